@@ -0,0 +1,172 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+use tracing::{info, warn};
+
+use crate::compiled::load_bytecode;
+use crate::decompiler::{DecompilationRequest, DecompileResult, Decompiler};
+use crate::error::{Error, Result};
+use crate::folder::{render_result, RenderOptions};
+use crate::rbxlx::{process_rbxlx, RbxlxOptions};
+
+pub fn is_zip(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Where the results go: a folder, or a new archive if the output ends in `.zip`
+enum Sink {
+    Folder(PathBuf),
+    Zip(Box<ZipWriter<File>>),
+}
+
+impl Sink {
+    fn create(output: &str) -> Result<Self> {
+        if is_zip(output) {
+            Ok(Sink::Zip(Box::new(ZipWriter::new(File::create(output)?))))
+        } else {
+            Ok(Sink::Folder(PathBuf::from(output)))
+        }
+    }
+
+    fn write(&mut self, name: &str, contents: &[u8]) -> Result<()> {
+        match self {
+            Sink::Folder(root) => {
+                let path = root.join(name);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(path, contents)?;
+            }
+            Sink::Zip(zip) => {
+                zip.start_file(name, SimpleFileOptions::default())?;
+                zip.write_all(contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        if let Sink::Zip(zip) = self {
+            zip.finish()?;
+        }
+        Ok(())
+    }
+}
+
+struct EntryJob {
+    name: String,
+    output_name: String,
+    bytecode: Arc<str>,
+    header: Option<String>,
+    rx: oneshot::Receiver<DecompileResult>,
+}
+
+/// Entry names come from the archive, so make sure they can't point outside the output folder
+fn entry_output_name(entry: &zip::read::ZipFile<'_, File>) -> Option<String> {
+    let path = entry.enclosed_name()?;
+    Some(path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Decompiles everything in a `.zip` without unpacking it: bytecode files
+/// become `.lua` files and `.rbxlx` places are processed as a whole
+pub async fn process_zip(
+    decompiler: &Decompiler,
+    input: &str,
+    output: &str,
+    options: &RbxlxOptions,
+) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(input)?)?;
+    let mut sink = Sink::create(output)?;
+    info!("found {} entries in {}", archive.len(), input);
+
+    let mut jobs = Vec::new();
+    let mut skipped = 0u32;
+    let mut places = 0u32;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let Some(name) = entry_output_name(&entry) else {
+            warn!("skipping {}, its path leaves the archive", entry.name()?);
+            skipped += 1;
+            continue;
+        };
+
+        let mut contents = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut contents)?;
+        drop(entry);
+
+        if name.to_ascii_lowercase().ends_with(".rbxlx") {
+            info!("processing {}", name);
+            places += 1;
+            let size = contents.len() as u64;
+            let processed = process_rbxlx(decompiler, Cursor::new(contents), size, Vec::new(), options).await?;
+            sink.write(&name, &processed)?;
+            continue;
+        }
+
+        let Ok(loaded) = load_bytecode(contents).await else {
+            skipped += 1;
+            continue;
+        };
+
+        let extension = if options.luau { "luau" } else { "lua" };
+        let output_name = Path::new(&name).with_extension(extension).to_string_lossy().to_string();
+
+        let (tx, rx) = oneshot::channel();
+        decompiler
+            .decompile_batch(vec![DecompilationRequest::with_hash(loaded.bytecode.clone(), loaded.hash, tx)])
+            .await?;
+        jobs.push(EntryJob {
+            name,
+            output_name,
+            bytecode: loaded.bytecode,
+            header: loaded.header,
+            rx,
+        });
+    }
+
+    info!(
+        "{} places processed, {} bytecode files queued for decompilation, {} skipped (not bytecode)",
+        places,
+        jobs.len(),
+        skipped
+    );
+
+    let mut failed = 0u32;
+    let total = jobs.len();
+    for job in jobs {
+        let result = job
+            .rx
+            .await
+            .unwrap_or_else(|_| Err("sender dropped".to_string().into()));
+        if result.is_err() {
+            failed += 1;
+        }
+        let render = RenderOptions {
+            format_lua: options.format_lua,
+            disasm: options.disasm,
+            // the scripts go into an archive, there's nowhere to put a `.disasm` next to them
+            with_disasm: false,
+            integrity: options.integrity,
+            size_comment: options.size_comment,
+            debug_names: options.debug_names,
+            luau: options.luau,
+        };
+        let result = render_result(&job.name, &job.bytecode, job.header, result, render);
+        sink.write(&job.output_name, result.as_bytes())?;
+    }
+
+    sink.finish()?;
+    info!("done. {} decompiled, {} failed, written to {}", total as u32 - failed, failed, output);
+    Error::check_failures(failed as usize, total, "files")
+}
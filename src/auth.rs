@@ -0,0 +1,134 @@
+use std::fmt;
+use std::io::{BufRead, IsTerminal};
+
+use keyring::Entry;
+use tracing::warn;
+
+use crate::error::Result;
+
+const KEYRING_SERVICE: &str = "oracle-postprocess";
+const KEYRING_USER: &str = "oracle-key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Argument,
+    Environment,
+    Keyring,
+    Config,
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeySource::Argument => "--key argument",
+            KeySource::Environment => "ORACLE_KEY env variable",
+            KeySource::Keyring => "os keyring",
+            KeySource::Config => "config file",
+        })
+    }
+}
+
+fn keyring_entry() -> keyring::Result<Entry> {
+    Entry::new(KEYRING_SERVICE, KEYRING_USER)
+}
+
+/// Reads the key saved by `auth login`, if there is one
+pub fn stored_key() -> keyring::Result<Option<String>> {
+    match keyring_entry()?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Picks the oracle key to use, in order of precedence:
+/// argument, env variable, os keyring, config file
+pub fn resolve_key(arg: Option<&str>, config_key: Option<&str>) -> Option<(String, KeySource)> {
+    if let Some(key) = arg {
+        return Some((key.to_string(), KeySource::Argument));
+    }
+
+    if let Ok(key) = std::env::var("ORACLE_KEY") {
+        return Some((key, KeySource::Environment));
+    }
+
+    match stored_key() {
+        Ok(Some(key)) => return Some((key, KeySource::Keyring)),
+        Ok(None) => {}
+        // the keyring being unavailable shouldn't get in the way of other sources
+        Err(e) if config_key.is_some() => {
+            warn!("couldn't read the os keyring: {}", e);
+        }
+        Err(_) => {}
+    }
+
+    config_key.map(|key| (key.to_string(), KeySource::Config))
+}
+
+fn read_key() -> Result<String> {
+    let key = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password("oracle key: ")?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        line
+    };
+
+    let key = key.trim().to_string();
+    if key.is_empty() {
+        return Err("no key entered".into());
+    }
+    Ok(key)
+}
+
+/// What `--cache-encrypt passphrase` derives the cache's key from: the
+/// ORACLE_CACHE_PASSPHRASE env variable, or a prompt
+pub fn cache_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var("ORACLE_CACHE_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err("no cache passphrase, set ORACLE_CACHE_PASSPHRASE".into());
+    }
+    let passphrase = rpassword::prompt_password("cache passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err("no passphrase entered".into());
+    }
+    Ok(passphrase)
+}
+
+pub fn login() -> Result<()> {
+    let key = read_key()?;
+    keyring_entry()?
+        .set_password(&key)
+        .map_err(|e| format!("failed to save key to the os keyring: {}", e))?;
+    println!("saved oracle key to the os keyring");
+    Ok(())
+}
+
+pub fn logout() -> Result<()> {
+    match keyring_entry()?.delete_credential() {
+        Ok(()) => println!("removed oracle key from the os keyring"),
+        Err(keyring::Error::NoEntry) => println!("no oracle key stored in the os keyring"),
+        Err(e) => return Err(format!("failed to remove key from the os keyring: {}", e).into()),
+    }
+    Ok(())
+}
+
+pub fn status(arg: Option<&str>, config_key: Option<&str>) -> Result<()> {
+    match stored_key() {
+        Ok(Some(_)) => println!("os keyring: key stored"),
+        Ok(None) => println!("os keyring: no key stored"),
+        Err(e) => println!("os keyring: unavailable ({})", e),
+    }
+
+    match resolve_key(arg, config_key) {
+        Some((key, source)) => {
+            let visible: String = key.chars().take(4).collect();
+            println!("using key {}… from {}", visible, source);
+        }
+        None => println!("no oracle key configured"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use arboard::Clipboard;
+
+use crate::error::Result;
+
+/// What's on the system clipboard, usually bytecode copied out of an executor
+pub fn read_clipboard() -> Result<String> {
+    Ok(Clipboard::new()?.get_text()?)
+}
+
+/// Replaces what's on the system clipboard with `text`. On Linux the
+/// clipboard belongs to whoever set it, so without a clipboard manager
+/// to take it over it's only there until the process exits.
+pub fn write_clipboard(text: &str) -> Result<()> {
+    Clipboard::new()?.set_text(text)?;
+    Ok(())
+}
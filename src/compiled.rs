@@ -1,3 +1,39 @@
+use std::sync::Arc;
+
+use crate::decompiler::hash_bytecode;
+use crate::error::Result;
+
+/// How many files to read and hash at once when going through a folder
+pub const LOAD_CONCURRENCY: usize = 16;
+
+/// Bytecode that's been read, decoded and hashed, ready for a `DecompilationRequest`
+pub struct LoadedBytecode {
+    pub bytecode: Arc<str>,
+    pub hash: String,
+    pub header: Option<String>,
+}
+
+impl LoadedBytecode {
+    fn new((bytecode, header): (String, Option<String>)) -> Self {
+        Self {
+            hash: hash_bytecode(&bytecode),
+            bytecode: Arc::from(bytecode),
+            header,
+        }
+    }
+}
+
+/// `get_bytecode_from_bytes` and the hash of what it finds, on the blocking
+/// pool. Base64 and SHA-256 of a multi-megabyte script would stall the runtime.
+pub async fn load_bytecode(contents: impl AsRef<[u8]> + Send + 'static) -> Result<LoadedBytecode> {
+    tokio::task::spawn_blocking(move || get_bytecode_from_bytes(contents.as_ref()).map(LoadedBytecode::new)).await?
+}
+
+/// `load_bytecode` for a file, see `get_bytecode_from_file`
+pub async fn load_bytecode_file(filename: String) -> Result<LoadedBytecode> {
+    tokio::task::spawn_blocking(move || get_bytecode_from_file(&filename).map(LoadedBytecode::new)).await?
+}
+
 pub fn is_bytecode(data: &[u8]) -> bool {
     if data.len() < 5 {
         return false;
@@ -14,48 +50,73 @@ pub fn is_bytecode(data: &[u8]) -> bool {
 
 pub fn get_bytecode_from_file(
     filename: &str,
-) -> Result<(String, Option<String>), Box<dyn std::error::Error>> {
-    use base64::{engine::general_purpose, Engine as _};
-    use std::fs;
+) -> Result<(String, Option<String>)> {
+    let file_contents = if filename == "-" {
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut contents)?;
+        contents
+    } else {
+        std::fs::read(filename)?
+    };
+
+    get_bytecode_from_bytes(&file_contents)
+}
+
+/// Like `get_bytecode_from_file`, but a text dump gives every blob in it
+pub fn get_all_bytecode_from_file(filename: &str) -> Result<Vec<(String, Option<String>)>> {
+    let file_contents = if filename == "-" {
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut contents)?;
+        contents
+    } else {
+        std::fs::read(filename)?
+    };
+
+    get_all_bytecode_from_bytes(&file_contents)
+}
 
-    let file_contents = fs::read(filename)?;
+/// Every bytecode blob in `file_contents`, each with the text between it and
+/// the one before as its header. Raw and base64 bytecode is a single blob.
+pub fn get_all_bytecode_from_bytes(file_contents: &[u8]) -> Result<Vec<(String, Option<String>)>> {
+    let file_string = String::from_utf8_lossy(file_contents);
+    let blobs = find_all_bytecode(&file_string);
+    if blobs.len() < 2 {
+        return get_bytecode_from_bytes(file_contents).map(|blob| vec![blob]);
+    }
+
+    let mut previous_end = 0;
+    let mut found = Vec::new();
+    for (start, end) in blobs {
+        let header = file_string[previous_end..start].trim_start_matches(['\r', '\n']);
+        found.push((file_string[start..end].to_string(), Some(header.to_string())));
+        previous_end = end;
+    }
+    Ok(found)
+}
+
+pub fn get_bytecode_from_bytes(
+    file_contents: &[u8],
+) -> Result<(String, Option<String>)> {
+    use base64::{engine::general_purpose, Engine as _};
 
     // check for direct bytecode
-    if is_bytecode(&file_contents) {
-        let bytecode = general_purpose::STANDARD.encode(&file_contents);
+    if is_bytecode(file_contents) {
+        let bytecode = general_purpose::STANDARD.encode(file_contents);
         return Ok((bytecode, None));
     }
 
     // try decoding as base64
-    if let Ok(decoded) = general_purpose::STANDARD.decode(&file_contents) {
+    if let Ok(decoded) = general_purpose::STANDARD.decode(file_contents) {
         if is_bytecode(&decoded) {
-            let bytecode = String::from_utf8_lossy(&file_contents).to_string();
+            let bytecode = String::from_utf8_lossy(file_contents).to_string();
             return Ok((bytecode, None));
         }
     }
 
     // try extracting from rbxlx-style header
-    let file_string = String::from_utf8_lossy(&file_contents);
-    
-    let bytecode_start_lf = "-- Bytecode (Base64):\n-- ";
-    let bytecode_start_crlf = "-- Bytecode (Base64):\r\n-- ";
-
-    let (position, bytecode_start_len) = file_string
-        .find(bytecode_start_lf)
-        .map(|pos| (pos, bytecode_start_lf.len()))
-        .or_else(|| file_string
-            .find(bytecode_start_crlf)
-            .map(|pos| (pos, bytecode_start_crlf.len())))
-        .unzip();
-
-    if let (Some(position), Some(bytecode_start_len)) = (position, bytecode_start_len) {
-        let start_pos = position + bytecode_start_len;
-
-        let bytecode_end = file_string[start_pos..]
-            .find(|c| c == '\n' || c == '\r')
-            .map(|idx| start_pos + idx)
-            .unwrap_or(file_string.len());
+    let file_string = String::from_utf8_lossy(file_contents);
 
+    if let Some((start_pos, bytecode_end)) = find_bytecode(&file_string) {
         let header = file_string[..start_pos].to_string();
         let bytecode = &file_string[start_pos..bytecode_end];
 
@@ -64,3 +125,132 @@ pub fn get_bytecode_from_file(
 
     Err("no bytecode found in file".into())
 }
+
+/// Lines of `text` with the byte offset each one starts at, without their line ending
+fn lines_with_offsets(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    text.split_inclusive('\n').map(move |line| {
+        let start = offset;
+        offset += line.len();
+        (start, line.trim_end_matches(['\n', '\r']))
+    })
+}
+
+/// If `line` is a `-- Bytecode (Base64):` header, returns what follows the colon.
+/// Spacing and case don't matter, since tooling doesn't agree on either.
+fn bytecode_header_rest(line: &str) -> Option<&str> {
+    let comment = line.trim_start().strip_prefix("--")?;
+    let (label, rest) = comment.split_once(':')?;
+    let label: String = label.chars().filter(|c| !c.is_whitespace()).collect();
+    label.eq_ignore_ascii_case("bytecode(base64)").then_some(rest)
+}
+
+/// Finds the base64 bytecode embedded after a `-- Bytecode (Base64):` header
+/// and returns its start and end offsets. Everything before the start is the header.
+///
+/// The bytecode is usually on the next line behind its own `-- `, but may also
+/// follow the colon directly. Line endings can be `\n` or `\r\n`, and blank
+/// lines and extra spaces around either part are skipped.
+pub fn find_bytecode(text: &str) -> Option<(usize, usize)> {
+    let mut lines = lines_with_offsets(text);
+    let (header_offset, header_line) = lines.find(|(_, line)| bytecode_header_rest(line).is_some())?;
+
+    let rest = bytecode_header_rest(header_line)?;
+    let (offset, candidate) = if rest.trim().is_empty() {
+        let (offset, line) = lines.find(|(_, line)| !line.trim().is_empty())?;
+        let line_start = line.len() - line.trim_start().len();
+        let comment = line.trim_start().strip_prefix("--")?;
+        (offset + line_start + 2, comment)
+    } else {
+        (header_offset + header_line.len() - rest.len(), rest)
+    };
+
+    let leading = candidate.len() - candidate.trim_start().len();
+    let start = offset + leading;
+    let end = start + candidate.trim().len();
+    (end > start).then_some((start, end))
+}
+
+/// Start and end offsets of every blob `find_bytecode` would find, in order
+pub fn find_all_bytecode(text: &str) -> Vec<(usize, usize)> {
+    let mut blobs = Vec::new();
+    let mut offset = 0;
+    while let Some((start, end)) = find_bytecode(&text[offset..]) {
+        blobs.push((offset + start, offset + end));
+        offset += end;
+    }
+    blobs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTECODE: &str = "BgMBAQAAAQA=";
+
+    /// The bytecode `find_bytecode` finds in `text`, if any
+    fn found(text: &str) -> Option<&str> {
+        find_bytecode(text).map(|(start, end)| &text[start..end])
+    }
+
+    #[test]
+    fn header_variants_are_recognized() {
+        assert_eq!(bytecode_header_rest("-- Bytecode (Base64):"), Some(""));
+        assert_eq!(bytecode_header_rest("  --  Bytecode  ( Base64 ) :  "), Some("  "));
+        assert_eq!(bytecode_header_rest("-- BYTECODE (base64):"), Some(""));
+        assert_eq!(bytecode_header_rest("-- bytecode(base64): abc"), Some(" abc"));
+        assert_eq!(bytecode_header_rest("-- Bytecode (Base32):"), None);
+        assert_eq!(bytecode_header_rest("Bytecode (Base64):"), None);
+    }
+
+    #[test]
+    fn bytecode_on_the_next_line_with_lf() {
+        let text = format!("-- Bytecode (Base64):\n-- {}\n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+    }
+
+    #[test]
+    fn bytecode_on_the_next_line_with_crlf() {
+        let text = format!("-- Bytecode (Base64):\r\n-- {}\r\n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+    }
+
+    #[test]
+    fn extra_spacing_is_skipped() {
+        let text = format!("   --   Bytecode ( Base64 )  :   \n    --    {}   \n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+    }
+
+    #[test]
+    fn letter_case_doesnt_matter() {
+        let text = format!("-- bytecode (BASE64):\n-- {}\n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+    }
+
+    #[test]
+    fn bytecode_on_the_same_line_as_the_colon() {
+        let text = format!("-- Bytecode (Base64): {}\n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+        let text = format!("-- Bytecode (Base64):{}\r\n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+    }
+
+    #[test]
+    fn blank_lines_before_the_bytecode_are_skipped() {
+        let text = format!("-- Bytecode (Base64):\n\n  \r\n\n-- {}\n", BYTECODE);
+        assert_eq!(found(&text), Some(BYTECODE));
+    }
+
+    #[test]
+    fn everything_before_the_bytecode_is_the_header() {
+        let text = format!("local x = 1\n-- Bytecode (Base64):\n-- {}\n", BYTECODE);
+        let (start, _) = find_bytecode(&text).unwrap();
+        assert!(text[..start].ends_with("-- Bytecode (Base64):\n-- "));
+    }
+
+    #[test]
+    fn no_header_no_bytecode() {
+        assert_eq!(found(&format!("-- {}\n", BYTECODE)), None);
+        assert_eq!(found("-- Bytecode (Base64):\n"), None);
+    }
+}
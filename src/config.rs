@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Deserialize;
+
+use crate::error::Result;
+use crate::decompiler::{options::DecompileOptions, CacheEncryption, Compression, Transport};
+use crate::extract::{BytecodeFormat, FileNaming};
+use crate::rbxlx::OversizePolicy;
+
+/// Settings read from `config.toml`. Anything passed on the command line wins.
+///
+/// ```toml
+/// key = "..."
+/// keys = ["...", "..."] # to spread big runs over several keys' quotas
+/// base_url = "wss://oracle.mshq.dev/v1/ws" # or a list, tried in order
+/// connections = 2
+/// # tried in order on scripts the oracle fails on or that don't parse,
+/// # each laid over the usual decompiler options
+/// retry_profiles = [{ ... }, { ... }]
+/// preset = "readable" # like --preset
+///
+/// [decompiler_options]
+/// # same shape as --decompiler-options, laid over the preset
+///
+/// [presets.mine] # for --preset mine, wins over a built-in one of the same name
+/// # same shape as --decompiler-options
+///
+/// [output]
+/// extract_format = "base64"
+///
+/// [cache] # decompilations kept to skip asking the oracle again
+/// dir = "..."
+/// max_size = 2048 # MiB
+/// encrypt = "oracle-key" # or "passphrase"
+/// remote_url = "https://cache.example.com/oracle" # shared with the team
+/// remote_token = "..."
+///
+/// [[profiles]] # decompiler options for some scripts only, first match wins
+/// classes = ["ModuleScript"]
+/// paths = ["ReplicatedStorage/**"]
+/// options = { ... }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub key: Option<String>,
+    /// More keys to spread runs over, like giving `--key` several times
+    pub keys: Vec<String>,
+    pub base_url: Option<BaseUrls>,
+    pub oracle_version: Option<u32>,
+    pub protocol: Option<u32>,
+    pub pre_process_cmd: Option<String>,
+    pub post_process_cmd: Option<String>,
+    pub key_command: Option<String>,
+    pub decompiler_options: Option<DecompileOptions>,
+    /// Like `--preset`
+    pub preset: Option<String>,
+    /// Named sets of decompiler options for `--preset`
+    pub presets: HashMap<String, DecompileOptions>,
+    pub retries: Option<u32>,
+    pub connections: Option<usize>,
+    pub transport: Option<Transport>,
+    /// Like `--compression`
+    pub compression: Option<Compression>,
+    pub max_rps: Option<f64>,
+    pub max_concurrent: Option<usize>,
+    pub max_in_flight: Option<u32>,
+    pub fixed_window: Option<bool>,
+    /// Like `--autotune`
+    pub autotune: Option<bool>,
+    /// Like `--gentle`
+    pub gentle: Option<bool>,
+    /// Like `--temp-dir`
+    pub temp_dir: Option<PathBuf>,
+    /// Like `--keep-temp`
+    pub keep_temp: Option<bool>,
+    pub retry_profiles: Vec<DecompileOptions>,
+    pub output: OutputConfig,
+    pub cache: CacheConfig,
+    pub profiles: Vec<ProfileConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BaseUrls {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl BaseUrls {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            BaseUrls::One(url) => vec![url.clone()],
+            BaseUrls::Many(urls) => urls.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub rbxlx: Option<String>,
+    pub single: Option<String>,
+    pub extract_format: Option<BytecodeFormat>,
+    pub extract_naming: Option<FileNaming>,
+    pub format_lua: Option<bool>,
+    pub disasm: Option<bool>,
+    pub with_disasm: Option<bool>,
+    pub integrity: Option<bool>,
+    pub size_comment: Option<bool>,
+    pub debug_names: Option<bool>,
+    pub luau: Option<bool>,
+    /// Like `--project-files`
+    pub project_files: Option<bool>,
+    pub write_buffer: Option<usize>,
+    pub strip_bytecode: Option<bool>,
+    /// In MiB, like `--max-script-size`
+    pub max_script_size: Option<f64>,
+    pub oversize: Option<OversizePolicy>,
+    /// Like `--template`
+    pub template: Option<PathBuf>,
+    /// In MiB, like `--spill-threshold`
+    pub spill_threshold: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Like `--cache-dir`
+    pub dir: Option<PathBuf>,
+    /// In MiB, like `--cache-max-size`
+    pub max_size: Option<f64>,
+    /// Like `--cache-encrypt`
+    pub encrypt: Option<CacheEncryption>,
+    /// Like `--cache-url`
+    pub remote_url: Option<String>,
+    /// Sent as a bearer token to `remote_url`
+    pub remote_token: Option<String>,
+}
+
+/// Scripts this applies to. Empty lists match everything, and both
+/// `classes` and `paths` have to match.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    #[serde(default)]
+    pub classes: Vec<String>,
+    /// Globs over instance paths, like `--include`
+    #[serde(default)]
+    pub paths: Vec<String>,
+    pub options: DecompileOptions,
+}
+
+/// `$XDG_CONFIG_HOME/oracle-postprocess/config.toml`, falling back to
+/// `~/.config` (or `%APPDATA%` on windows)
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+
+    Some(base.join("oracle-postprocess").join("config.toml"))
+}
+
+/// Loads the config at `path`, or at the default location if none is given.
+/// A missing file at the default location is not an error.
+pub fn load_config(path: Option<&Path>) -> Result<Config> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(Config::default()),
+        },
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Config::default());
+        }
+        Err(e) => {
+            return Err(format!("failed to read config file {}: {}", path.display(), e).into());
+        }
+    };
+
+    toml::from_str(&contents)
+        .map_err(|e| format!("invalid config file {}: {}", path.display(), e).into())
+}
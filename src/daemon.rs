@@ -0,0 +1,343 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, info, warn};
+
+use crate::compiled::{load_bytecode, load_bytecode_file};
+use crate::decompiler::{DecompileResult, Decompiler, FailureCategory};
+use crate::disasm::disassembly_comment;
+use crate::error::{Error, Result};
+use crate::folder::RenderOptions;
+use crate::integrity::with_integrity_line;
+use crate::luau;
+
+/// What clients send, one JSON object per line
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DaemonRequest {
+    /// Bytecode goes in `data` (base64 or raw, like a bytecode file), or is read from `path`
+    Decompile {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        data: Option<String>,
+        path: Option<String>,
+    },
+    Status {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+    },
+    Shutdown {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+    },
+}
+
+/// Responses echo the request's `id`, since decompilations finish in any order
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DaemonResponse {
+    DecompilationResult {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<serde_json::Value>,
+        success: bool,
+        data: String,
+        /// What kind of failure it was, when `success` is false
+        #[serde(skip_serializing_if = "Option::is_none")]
+        category: Option<FailureCategory>,
+    },
+    Status {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<serde_json::Value>,
+        uptime_secs: f64,
+        clients: usize,
+        in_progress: u64,
+        decompiled: u64,
+        failed: u64,
+        /// What the oracle last said the key has left
+        #[serde(skip_serializing_if = "Option::is_none")]
+        credits_remaining: Option<u64>,
+    },
+    Shutdown {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<serde_json::Value>,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<serde_json::Value>,
+        message: String,
+    },
+}
+
+struct Daemon {
+    decompiler: Decompiler,
+    render: RenderOptions,
+    started: Instant,
+    clients: AtomicUsize,
+    in_progress: AtomicU64,
+    decompiled: AtomicU64,
+    failed: AtomicU64,
+    shutdown_tx: watch::Sender<bool>,
+}
+
+/// `$XDG_RUNTIME_DIR/oracle-postprocess.sock`, or the same name in the temp folder
+#[cfg(unix)]
+pub fn default_socket_path() -> String {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.join("oracle-postprocess.sock").to_string_lossy().to_string()
+}
+
+#[cfg(windows)]
+pub fn default_socket_path() -> String {
+    r"\\.\pipe\oracle-postprocess".to_string()
+}
+
+impl Daemon {
+    fn status(&self, id: Option<serde_json::Value>) -> DaemonResponse {
+        DaemonResponse::Status {
+            id,
+            uptime_secs: self.started.elapsed().as_secs_f64(),
+            clients: self.clients.load(Ordering::Relaxed),
+            in_progress: self.in_progress.load(Ordering::Relaxed),
+            decompiled: self.decompiled.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            credits_remaining: self.decompiler.credits_remaining(),
+        }
+    }
+
+    fn render(&self, bytecode: &str, result: DecompileResult) -> DaemonResponse {
+        match result {
+            Ok(source) => {
+                self.decompiled.fetch_add(1, Ordering::Relaxed);
+                let source = if self.render.format_lua {
+                    luau::format_or_keep(source, &"request")
+                } else {
+                    source
+                };
+                let source = if self.render.integrity {
+                    with_integrity_line(source)
+                } else {
+                    source
+                };
+                DaemonResponse::DecompilationResult {
+                    id: None,
+                    success: true,
+                    data: source,
+                    category: None,
+                }
+            }
+            Err(e) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                let disassembly = if self.render.disasm {
+                    disassembly_comment(bytecode)
+                } else {
+                    String::new()
+                };
+                DaemonResponse::DecompilationResult {
+                    id: None,
+                    success: false,
+                    data: format!("{}{}", e, disassembly),
+                    category: Some(e.category),
+                }
+            }
+        }
+    }
+
+    /// Starts a decompilation; its result (or why it couldn't start) shows up
+    /// on `responses` once the oracle is done
+    fn decompile(
+        self: &Arc<Self>,
+        id: Option<serde_json::Value>,
+        data: Option<String>,
+        path: Option<String>,
+        responses: &mpsc::UnboundedSender<DaemonResponse>,
+    ) -> std::result::Result<(), String> {
+        // read and hashed off the runtime, so a big script doesn't hold up other clients
+        let loading = match (data, path) {
+            (Some(data), None) => tokio::spawn(load_bytecode(data.into_bytes())),
+            (None, Some(path)) => tokio::spawn(load_bytecode_file(path)),
+            _ => return Err("decompile needs exactly one of `data` and `path`".to_string()),
+        };
+
+        self.in_progress.fetch_add(1, Ordering::Relaxed);
+        let daemon = self.clone();
+        let responses = responses.clone();
+        tokio::spawn(async move {
+            let started = loading
+                .await
+                .map_err(Error::from)
+                .and_then(|loaded| loaded)
+                .and_then(|loaded| {
+                    let pending = daemon
+                        .decompiler
+                        .try_decompile_with_hash(loaded.bytecode.clone(), loaded.hash)?;
+                    Ok((loaded.bytecode, pending))
+                });
+            let response = match started {
+                Ok((bytecode, pending)) => {
+                    let result = pending.await.unwrap_or_else(|e| Err(e.to_string().into()));
+                    let mut response = daemon.render(&bytecode, result);
+                    if let DaemonResponse::DecompilationResult { id: response_id, .. } = &mut response {
+                        *response_id = id;
+                    }
+                    response
+                }
+                Err(e) => DaemonResponse::Error {
+                    id,
+                    message: e.to_string(),
+                },
+            };
+            daemon.in_progress.fetch_sub(1, Ordering::Relaxed);
+            // the client may have hung up in the meantime
+            let _ = responses.send(response);
+        });
+        Ok(())
+    }
+
+    async fn handle_client<S: AsyncRead + AsyncWrite + Send + 'static>(self: Arc<Self>, stream: S) -> Result<()> {
+        let (read, mut write) = tokio::io::split(stream);
+        let mut lines = BufReader::new(read).lines();
+        let (responses_tx, mut responses_rx) = mpsc::unbounded_channel();
+        let mut responses_tx = Some(responses_tx);
+
+        loop {
+            tokio::select! {
+                line = lines.next_line(), if responses_tx.is_some() => {
+                    let Some(line) = line? else {
+                        // no more requests, but the ones still running get answered
+                        responses_tx = None;
+                        continue;
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let response = match serde_json::from_str::<DaemonRequest>(&line) {
+                        Ok(DaemonRequest::Decompile { id, data, path }) => {
+                            match self.decompile(id.clone(), data, path, responses_tx.as_ref().unwrap()) {
+                                Ok(()) => continue,
+                                Err(message) => DaemonResponse::Error { id, message },
+                            }
+                        }
+                        Ok(DaemonRequest::Status { id }) => self.status(id),
+                        Ok(DaemonRequest::Shutdown { id }) => {
+                            write_response(&mut write, &DaemonResponse::Shutdown { id }).await?;
+                            info!("shutdown requested");
+                            self.shutdown_tx.send_replace(true);
+                            return Ok(());
+                        }
+                        Err(e) => DaemonResponse::Error {
+                            id: None,
+                            message: format!("invalid request: {}", e),
+                        },
+                    };
+                    write_response(&mut write, &response).await?;
+                }
+                response = responses_rx.recv() => {
+                    match response {
+                        Some(response) => write_response(&mut write, &response).await?,
+                        None => return Ok(()),
+                    }
+                }
+            }
+        }
+    }
+
+    fn spawn_client<S: AsyncRead + AsyncWrite + Send + 'static>(self: &Arc<Self>, stream: S) {
+        let daemon = self.clone();
+        tokio::spawn(async move {
+            let clients = daemon.clients.fetch_add(1, Ordering::Relaxed) + 1;
+            debug!("client connected ({} connected)", clients);
+            if let Err(e) = daemon.clone().handle_client(stream).await {
+                warn!("client connection failed: {}", e);
+            }
+            daemon.clients.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+async fn write_response<W: AsyncWrite + Unpin>(write: &mut W, response: &DaemonResponse) -> Result<()> {
+    let mut line = serde_json::to_string(response).unwrap();
+    line.push('\n');
+    write.write_all(line.as_bytes()).await?;
+    write.flush().await?;
+    Ok(())
+}
+
+/// Serves the decompiler over a local socket until a client asks it to shut down
+pub async fn run_daemon(decompiler: &Decompiler, socket: &str, render: RenderOptions) -> Result<()> {
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let daemon = Arc::new(Daemon {
+        decompiler: decompiler.clone(),
+        render,
+        started: Instant::now(),
+        clients: AtomicUsize::new(0),
+        in_progress: AtomicU64::new(0),
+        decompiled: AtomicU64::new(0),
+        failed: AtomicU64::new(0),
+        shutdown_tx,
+    });
+
+    serve(&daemon, socket, shutdown_rx).await
+}
+
+#[cfg(unix)]
+async fn serve(daemon: &Arc<Daemon>, socket: &str, mut shutdown_rx: watch::Receiver<bool>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    if std::path::Path::new(socket).exists() {
+        if UnixStream::connect(socket).await.is_ok() {
+            return Err(format!("a daemon is already listening on {}", socket).into());
+        }
+        // left behind by one that didn't get to clean up
+        std::fs::remove_file(socket)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    // anyone who can connect gets to use the key
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    info!("listening on {}", socket);
+
+    let result = loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => daemon.spawn_client(stream),
+                Err(e) => break Err(e.into()),
+            },
+            _ = shutdown_rx.changed() => break Ok(()),
+        }
+    };
+
+    let _ = std::fs::remove_file(socket);
+    result
+}
+
+#[cfg(windows)]
+async fn serve(daemon: &Arc<Daemon>, socket: &str, mut shutdown_rx: watch::Receiver<bool>) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(socket)
+        .map_err(|e| format!("couldn't create {} (is a daemon already running?): {}", socket, e))?;
+    info!("listening on {}", socket);
+
+    loop {
+        tokio::select! {
+            connected = server.connect() => {
+                connected?;
+                // the next client needs a fresh instance of the pipe
+                let client = std::mem::replace(&mut server, ServerOptions::new().create(socket)?);
+                daemon.spawn_client(client);
+            }
+            _ = shutdown_rx.changed() => return Ok(()),
+        }
+    }
+}
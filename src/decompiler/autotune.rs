@@ -0,0 +1,132 @@
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Windows tried while calibrating, smallest first, each capped by the limit
+const CANDIDATES: &[u32] = &[
+    256 * 1024,
+    512 * 1024,
+    1024 * 1024,
+    2 * 1024 * 1024,
+    4 * 1024 * 1024,
+    8 * 1024 * 1024,
+    16 * 1024 * 1024,
+];
+/// Answers measured at each window before trying the next
+const ANSWERS_PER_STEP: u32 = 8;
+/// A step ends after this long even if it hasn't had its answers, so a
+/// run with few scripts isn't stuck calibrating
+const MAX_STEP_TIME: Duration = Duration::from_secs(20);
+/// A bigger window has to be at least this much quicker to be picked over
+/// a smaller one, which is easier on the oracle
+const MIN_GAIN: f64 = 1.1;
+
+/// How one window did while calibrating
+struct Step {
+    window: u32,
+    /// Bytecode answered per second
+    throughput: f64,
+    average_latency: Duration,
+}
+
+/// `--autotune`: tries each of `CANDIDATES` in turn for a few answers at the
+/// start of the run, measuring how much bytecode the oracle gets through
+/// and how long it takes per script, then keeps the best window for the rest
+/// of the run
+pub(super) struct Autotune {
+    limit: u32,
+    candidates: Vec<u32>,
+    step: usize,
+    step_started: Instant,
+    bytes: u64,
+    answers: u32,
+    latency: Duration,
+    steps: Vec<Step>,
+    chosen: Option<u32>,
+    /// which connection this is, for the log
+    index: usize,
+}
+
+impl Autotune {
+    pub fn new(limit: u32, index: usize) -> Self {
+        let mut candidates: Vec<u32> = CANDIDATES.iter().copied().filter(|window| *window < limit).collect();
+        candidates.push(limit);
+        Self {
+            limit,
+            candidates,
+            step: 0,
+            step_started: Instant::now(),
+            bytes: 0,
+            answers: 0,
+            latency: Duration::ZERO,
+            steps: Vec::new(),
+            chosen: None,
+            index,
+        }
+    }
+
+    /// How much bytecode to keep in flight right now
+    pub fn size(&self) -> u32 {
+        self.chosen.unwrap_or_else(|| self.candidates[self.step]).min(self.limit)
+    }
+
+    pub fn set_limit(&mut self, limit: u32) {
+        self.limit = limit;
+    }
+
+    /// Counts an answer to `bytes` of bytecode sent `latency` ago
+    pub fn on_answer(&mut self, bytes: u32, latency: Duration) {
+        if self.chosen.is_some() {
+            return;
+        }
+        self.bytes += u64::from(bytes);
+        self.answers += 1;
+        self.latency += latency;
+        if self.answers < ANSWERS_PER_STEP && self.step_started.elapsed() < MAX_STEP_TIME {
+            return;
+        }
+
+        let step = Step {
+            window: self.candidates[self.step],
+            throughput: self.bytes as f64 / self.step_started.elapsed().as_secs_f64().max(0.001),
+            average_latency: self.latency / self.answers,
+        };
+        debug!(
+            "autotune on connection {}: {:.2} MiB in flight got {:.2} MiB/s through, {:.2?} per script",
+            self.index,
+            step.window as f64 / 1024.0 / 1024.0,
+            step.throughput / 1024.0 / 1024.0,
+            step.average_latency
+        );
+        // once a bigger window stops helping, the ones after it won't either
+        let stalled = self
+            .steps
+            .last()
+            .is_some_and(|previous| step.throughput < previous.throughput * MIN_GAIN);
+        self.steps.push(step);
+        self.step += 1;
+        self.step_started = Instant::now();
+        self.bytes = 0;
+        self.answers = 0;
+        self.latency = Duration::ZERO;
+        if stalled || self.step == self.candidates.len() {
+            self.choose();
+        }
+    }
+
+    /// Settles on the smallest window within `MIN_GAIN` of the best throughput
+    fn choose(&mut self) {
+        let best = self.steps.iter().map(|step| step.throughput).fold(0.0, f64::max);
+        let Some(step) = self.steps.iter().find(|step| step.throughput * MIN_GAIN >= best) else {
+            self.chosen = Some(self.limit);
+            return;
+        };
+        info!(
+            "autotune picked {:.2} MiB in flight on connection {} ({:.2} MiB/s, {:.2?} per script)",
+            step.window as f64 / 1024.0 / 1024.0,
+            self.index,
+            step.throughput / 1024.0 / 1024.0,
+            step.average_latency
+        );
+        self.chosen = Some(step.window);
+    }
+}
@@ -0,0 +1,781 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use clap::ValueEnum;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
+use tokio::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::decompiler::options::DecompileOptions;
+use crate::decompiler::{intercepted, DecompilationRequest};
+use crate::error::{Error, Result};
+use crate::studio::age;
+
+/// How long the remote cache gets to answer before the oracle is asked instead
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(10);
+/// What's in the cache folder besides the decompilations: when each was last used
+const INDEX_FILE_NAME: &str = "index.jsonl";
+/// Locked by a run compacting the index, shared by runs adding to it
+const LOCK_FILE_NAME: &str = "index.lock";
+/// Going over `--cache-max-size` evicts down to this much of it, so the
+/// next few writes don't each have to evict something again
+const EVICT_TO: f64 = 0.9;
+/// The index is written again from scratch once it has this many times
+/// more lines than there are scripts
+const COMPACT_AFTER: usize = 4;
+/// Half-written files younger than this could still be some run's
+const STALE_PARTIAL: Duration = Duration::from_secs(60 * 60);
+/// What a `--cache-encrypt` key is derived with, alongside the secret. Made
+/// once per cache folder, so the same secret gives each folder its own key.
+const SALT_FILE_NAME: &str = "salt";
+const SALT_LEN: usize = 16;
+/// What a cached script's file ends in, plain or encrypted
+const PLAIN_EXTENSION: &str = ".lua";
+const ENCRYPTED_EXTENSION: &str = ".lua.enc";
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+/// The key a file in the cache folder is for, if it's a cached script
+fn cached_key(file_name: &str) -> Option<&str> {
+    file_name
+        .strip_suffix(ENCRYPTED_EXTENSION)
+        .or_else(|| file_name.strip_suffix(PLAIN_EXTENSION))
+}
+
+/// Takes `key` out of the cache folder, whether it was cached encrypted or not
+fn remove_cached(dir: &Path, key: &str) {
+    for extension in [PLAIN_EXTENSION, ENCRYPTED_EXTENSION] {
+        let _ = std::fs::remove_file(dir.join(format!("{}{}", key, extension)));
+    }
+}
+
+/// Whether `key` is in the cache folder, encrypted or not
+fn is_cached(dir: &Path, key: &str) -> bool {
+    [PLAIN_EXTENSION, ENCRYPTED_EXTENSION]
+        .iter()
+        .any(|extension| dir.join(format!("{}{}", key, extension)).exists())
+}
+
+/// What `--cache-encrypt` derives the key for the cache folder from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheEncryption {
+    /// The oracle key the run uses. Switching keys starts the cache over
+    OracleKey,
+    /// The ORACLE_CACHE_PASSPHRASE env variable, or asked for when it isn't set
+    Passphrase,
+}
+
+/// Decompilations kept around to be handed out again instead of asking the
+/// oracle twice, in a folder on this machine, a key-value store shared over
+/// http, or both. The folder is looked in first, and what only the remote
+/// one had is copied into it.
+pub struct DecompilationCache {
+    local: Option<LocalCache>,
+    remote: Option<RemoteCache>,
+    /// Mixed into every key, so results from different oracle versions
+    /// don't get mixed up
+    version: Option<u32>,
+    hits: AtomicU64,
+}
+
+impl DecompilationCache {
+    pub fn new(local: Option<LocalCache>, remote: Option<RemoteCache>, version: Option<u32>) -> Self {
+        Self {
+            local,
+            remote,
+            version,
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    /// What a script is cached under: its hash, with a digest of the options
+    /// and oracle version after it when there are any
+    pub fn key(&self, hash: &str, options: Option<&DecompileOptions>) -> String {
+        if options.is_none() && self.version.is_none() {
+            return hash.to_string();
+        }
+        let mut digest = Sha256::new();
+        if let Some(options) = options {
+            digest.update(options.to_string().as_bytes());
+        }
+        if let Some(version) = self.version {
+            digest.update(format!("\nversion {}", version).as_bytes());
+        }
+        let digest = format!("{:x}", digest.finalize());
+        format!("{}-{}", hash, &digest[..16])
+    }
+
+    /// How many requests were answered from the cache so far
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub async fn get(&self, key: &str) -> Option<String> {
+        if let Some(local) = &self.local {
+            if let Some(source) = local.get(key).await {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(source);
+            }
+        }
+        let source = self.remote.as_ref()?.get(key).await?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        if let Some(local) = &self.local {
+            local.put(key, &source).await;
+        }
+        Some(source)
+    }
+
+    pub async fn put(&self, key: &str, source: &str) {
+        if let Some(local) = &self.local {
+            local.put(key, source).await;
+        }
+        if let Some(remote) = &self.remote {
+            remote.put(key, source).await;
+        }
+    }
+}
+
+/// When a cached script was last used, a line of JSON in the index
+#[derive(Serialize, Deserialize)]
+struct IndexLine {
+    key: String,
+    used: u64,
+}
+
+struct IndexEntry {
+    size: u64,
+    /// Seconds since the epoch
+    used: u64,
+}
+
+/// The last use of every key in the index file at `path`, and how many
+/// lines it has
+fn read_uses(path: &Path) -> (HashMap<String, u64>, usize) {
+    let mut uses = HashMap::new();
+    let mut lines = 0;
+    let Ok(file) = File::open(path) else {
+        return (uses, lines);
+    };
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        lines += 1;
+        // a line cut off halfway is skipped
+        let Ok(line) = serde_json::from_str::<IndexLine>(&line) else {
+            continue;
+        };
+        let used = uses.entry(line.key).or_insert(0);
+        *used = line.used.max(*used);
+    }
+    (uses, lines)
+}
+
+/// Every script in the cache folder, with its size and when it was last
+/// used. What's on disk is the truth, the index file only adds the last use,
+/// which file times can't be trusted with (think `noatime`). Uses are
+/// appended to it, so several runs can share a folder, and it's only
+/// written again from scratch under an exclusive lock, which appending waits on.
+struct CacheIndex {
+    path: PathBuf,
+    lock_path: PathBuf,
+    entries: HashMap<String, IndexEntry>,
+    total: u64,
+    lines: usize,
+}
+
+impl CacheIndex {
+    /// Reads what's in `dir`. A script the index doesn't know about counts
+    /// as last used when it was written.
+    fn load(dir: &Path) -> Self {
+        let mut entries = HashMap::new();
+        if let Ok(read_dir) = std::fs::read_dir(dir) {
+            for entry in read_dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let Some(key) = cached_key(&name) else {
+                    continue;
+                };
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                let used = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_secs());
+                entries.insert(key.to_string(), IndexEntry { size: metadata.len(), used });
+            }
+        }
+
+        let path = dir.join(INDEX_FILE_NAME);
+        let (uses, lines) = read_uses(&path);
+        for (key, used) in uses {
+            if let Some(entry) = entries.get_mut(&key) {
+                entry.used = entry.used.max(used);
+            }
+        }
+
+        let total = entries.values().map(|entry| entry.size).sum();
+        let mut index = Self {
+            path,
+            lock_path: dir.join(LOCK_FILE_NAME),
+            entries,
+            total,
+            lines,
+        };
+        if index.lines > index.entries.len() * COMPACT_AFTER {
+            index.compact();
+        }
+        index
+    }
+
+    /// Locks the index against other runs until the file that's handed back
+    /// is dropped. Without a folder yet there's nothing to lock, or to race on.
+    fn lock(&self, exclusive: bool) -> Option<File> {
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(&self.lock_path).ok()?;
+        let locked = if exclusive { file.lock() } else { file.lock_shared() };
+        if let Err(e) = locked {
+            debug!("failed to lock the cache index: {}", e);
+        }
+        Some(file)
+    }
+
+    /// Opened again every time, so a line never goes to an index another
+    /// run already replaced
+    fn append(&mut self, key: &str, used: u64) {
+        let _lock = self.lock(false);
+        let mut line = serde_json::to_string(&IndexLine { key: key.to_string(), used }).unwrap();
+        line.push('\n');
+        let appended = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(e) = appended {
+            debug!("failed to add {} to the cache index: {}", key, e);
+        }
+        self.lines += 1;
+    }
+
+    /// Notes `key` as used just now, `size` being how big it is if it was
+    /// just written
+    fn touch(&mut self, key: &str, size: Option<u64>) {
+        let used = now();
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.used = used;
+                if let Some(size) = size {
+                    self.total = self.total - entry.size + size;
+                    entry.size = size;
+                }
+            }
+            None => {
+                let size = size.unwrap_or(0);
+                self.total += size;
+                self.entries.insert(key.to_string(), IndexEntry { size, used });
+            }
+        }
+        self.append(key, used);
+    }
+
+    /// Takes the least recently used scripts out of the index until what's
+    /// left is at most `size`, giving back their keys to be removed
+    fn evict_to(&mut self, size: u64) -> Vec<String> {
+        if self.total <= size {
+            return Vec::new();
+        }
+        let mut by_use: Vec<(&String, &IndexEntry)> = self.entries.iter().collect();
+        by_use.sort_by_key(|(_, entry)| entry.used);
+        let mut total = self.total;
+        let mut evicted = Vec::new();
+        for (key, entry) in by_use {
+            if total <= size {
+                break;
+            }
+            total -= entry.size;
+            evicted.push(key.clone());
+        }
+        for key in &evicted {
+            self.entries.remove(key);
+        }
+        self.total = total;
+        evicted
+    }
+
+    /// Writes the index again with a line per script. What other runs added
+    /// since it was read is kept, theirs or not.
+    fn compact(&mut self) {
+        let _lock = self.lock(true);
+        let (mut uses, _) = read_uses(&self.path);
+        for (key, entry) in &mut self.entries {
+            if let Some(used) = uses.remove(key) {
+                entry.used = entry.used.max(used);
+            }
+        }
+        // what's left is other runs' scripts, unless they're gone since
+        if let Some(dir) = self.path.parent() {
+            uses.retain(|key, _| is_cached(dir, key));
+        }
+        let partial = self.path.with_extension(format!("jsonl.{}.partial", std::process::id()));
+        let mut contents = String::new();
+        let ours = self.entries.iter().map(|(key, entry)| (key, entry.used));
+        for (key, used) in ours.chain(uses.iter().map(|(key, used)| (key, *used))) {
+            contents.push_str(&serde_json::to_string(&IndexLine { key: key.clone(), used }).unwrap());
+            contents.push('\n');
+        }
+        let written = std::fs::write(&partial, contents).and_then(|()| std::fs::rename(&partial, &self.path));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&partial);
+            warn!("failed to compact the cache index at {}: {}", self.path.display(), e);
+            return;
+        }
+        self.lines = self.entries.len() + uses.len();
+    }
+}
+
+/// The salt in `dir`, made the first time it's needed
+fn salt(dir: &Path) -> Result<Vec<u8>> {
+    let path = dir.join(SALT_FILE_NAME);
+    match std::fs::read(&path) {
+        Ok(salt) if salt.len() == SALT_LEN => return Ok(salt),
+        Ok(_) => return Err(format!("the cache's salt at {} is damaged", path.display()).into()),
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        Err(_) => {}
+    }
+    let mut salt = vec![0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    std::fs::create_dir_all(dir)?;
+    // another run making one at the same time wins, and this one uses theirs
+    match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => {
+            file.write_all(&salt)?;
+            Ok(salt)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(std::fs::read(&path)?),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The cache's folder on this machine, `--cache-dir`. Once it's over
+/// `max_size`, the scripts used least recently are evicted. With
+/// `--cache-encrypt` the scripts are sealed with a key only the secret it
+/// was derived from opens, each under its own nonce and bound to its key so
+/// files can't be swapped around.
+pub struct LocalCache {
+    dir: PathBuf,
+    /// In bytes, no limit if `None`
+    max_size: Option<u64>,
+    /// Only touched in blocking tasks, it's all file system work
+    index: Arc<Mutex<CacheIndex>>,
+    cipher: Option<XChaCha20Poly1305>,
+    /// Whether a script that didn't decrypt was warned about already
+    warned: AtomicBool,
+}
+
+impl LocalCache {
+    /// `secret` is what to derive the encryption key from, the oracle key or a
+    /// passphrase. Without one, scripts are cached as they are.
+    pub fn open(dir: PathBuf, max_size: Option<u64>, secret: Option<&str>) -> Result<Self> {
+        let cipher = match secret {
+            Some(secret) => {
+                let mut key = [0; 32];
+                Argon2::default()
+                    .hash_password_into(secret.as_bytes(), &salt(&dir)?, &mut key)
+                    .map_err(|e| format!("failed to derive the cache's key: {}", e))?;
+                Some(XChaCha20Poly1305::new(&key.into()))
+            }
+            None => None,
+        };
+        let index = Arc::new(Mutex::new(CacheIndex::load(&dir)));
+        Ok(Self {
+            dir,
+            max_size,
+            index,
+            cipher,
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let extension = if self.cipher.is_some() { ENCRYPTED_EXTENSION } else { PLAIN_EXTENSION };
+        self.dir.join(format!("{}{}", key, extension))
+    }
+
+    /// The nonce, then the sealed source
+    fn seal(&self, key: &str, source: &str) -> Option<Vec<u8>> {
+        let Some(cipher) = &self.cipher else {
+            return Some(source.as_bytes().to_vec());
+        };
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, Payload { msg: source.as_bytes(), aad: key.as_bytes() })
+            .ok()?;
+        let mut contents = nonce.to_vec();
+        contents.extend(sealed);
+        Some(contents)
+    }
+
+    fn open_sealed(&self, key: &str, contents: Vec<u8>) -> Option<String> {
+        let Some(cipher) = &self.cipher else {
+            return String::from_utf8(contents).ok();
+        };
+        let nonce_len = XNonce::default().len();
+        if contents.len() < nonce_len {
+            return None;
+        }
+        let (nonce, sealed) = contents.split_at(nonce_len);
+        let opened = cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad: key.as_bytes() });
+        match opened {
+            Ok(source) => String::from_utf8(source).ok(),
+            Err(_) => {
+                if !self.warned.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "cached scripts in {} don't decrypt, they were cached with another key and will be \
+                         decompiled again",
+                        self.dir.display()
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    /// Runs `f` on the index in a blocking task, out of the way of the runtime
+    async fn with_index<T: Send + 'static>(&self, f: impl FnOnce(&mut CacheIndex) -> T + Send + 'static) -> Option<T> {
+        let index = self.index.clone();
+        tokio::task::spawn_blocking(move || f(&mut index.lock().unwrap())).await.ok()
+    }
+
+    async fn get(&self, key: &str) -> Option<String> {
+        let contents = tokio::fs::read(self.path(key)).await.ok()?;
+        let source = self.open_sealed(key, contents)?;
+        let key = key.to_string();
+        self.with_index(move |index| index.touch(&key, None)).await;
+        Some(source)
+    }
+
+    /// Written next to where it goes and moved there, so another run
+    /// reading the same folder never sees half of it
+    async fn put(&self, key: &str, source: &str) {
+        let Some(contents) = self.seal(key, source) else {
+            warn!("failed to encrypt {} for the cache", key);
+            return;
+        };
+        let partial = self.dir.join(format!("{}.{}.partial", key, std::process::id()));
+        let written = match tokio::fs::create_dir_all(&self.dir).await {
+            Ok(()) => match tokio::fs::write(&partial, &contents).await {
+                Ok(()) => tokio::fs::rename(&partial, self.path(key)).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            let _ = tokio::fs::remove_file(&partial).await;
+            warn!("failed to cache {} in {}: {}", key, self.dir.display(), e);
+            return;
+        }
+
+        let (key, size, dir, max_size) = (key.to_string(), contents.len() as u64, self.dir.clone(), self.max_size);
+        self.with_index(move |index| {
+            index.touch(&key, Some(size));
+            let Some(max_size) = max_size else {
+                return;
+            };
+            if index.total <= max_size {
+                return;
+            }
+            let evicted = index.evict_to((max_size as f64 * EVICT_TO) as u64);
+            for key in &evicted {
+                remove_cached(&dir, key);
+            }
+            index.compact();
+            debug!("evicted {} scripts from the cache to keep it under {} MiB", evicted.len(), max_size / 1024 / 1024);
+        })
+        .await;
+    }
+}
+
+/// The cache folder's scripts and how long since they were used, for `cache stats`
+pub fn print_cache_stats(dir: &Path, max_size: Option<u64>) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(format!("there's no cache at {}", dir.display()).into());
+    }
+    let index = CacheIndex::load(dir);
+    let mib = |size: u64| size as f64 / 1024.0 / 1024.0;
+    println!("cache:     {}", dir.display());
+    println!("scripts:   {}", index.entries.len());
+    match max_size {
+        Some(max_size) => println!("size:      {:.2} MiB of {:.2} MiB", mib(index.total), mib(max_size)),
+        None => println!("size:      {:.2} MiB", mib(index.total)),
+    }
+    let used = index.entries.values().map(|entry| entry.used);
+    if let (Some(newest), Some(oldest)) = (used.clone().max(), used.min()) {
+        let ago = |used: u64| age(UNIX_EPOCH + Duration::from_secs(used));
+        println!("last used: {} (least recently {})", ago(newest), ago(oldest));
+    }
+    Ok(())
+}
+
+/// `cache gc`: evicts scripts not used in `unused_for`, then the least
+/// recently used ones until the cache fits in `max_size`, and clears out
+/// what runs that were cut off left half-written
+pub fn collect_garbage(dir: &Path, max_size: Option<u64>, unused_for: Option<Duration>) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(format!("there's no cache at {}", dir.display()).into());
+    }
+    let mut index = CacheIndex::load(dir);
+    let before = (index.entries.len(), index.total);
+
+    let mut evicted = Vec::new();
+    if let Some(unused_for) = unused_for {
+        let cutoff = now().saturating_sub(unused_for.as_secs());
+        evicted.extend(index.entries.iter().filter(|(_, entry)| entry.used < cutoff).map(|(key, _)| key.clone()));
+        for key in &evicted {
+            if let Some(entry) = index.entries.remove(key) {
+                index.total -= entry.size;
+            }
+        }
+    }
+    if let Some(max_size) = max_size {
+        evicted.extend(index.evict_to(max_size));
+    }
+    for key in &evicted {
+        remove_cached(dir, key);
+    }
+
+    let mut partials = 0;
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > STALE_PARTIAL));
+        if stale && entry.file_name().to_string_lossy().ends_with(".partial") {
+            partials += usize::from(std::fs::remove_file(entry.path()).is_ok());
+        }
+    }
+    index.compact();
+
+    info!(
+        "evicted {} of {} scripts ({:.2} MiB), {:.2} MiB left",
+        evicted.len(),
+        before.0,
+        (before.1 - index.total) as f64 / 1024.0 / 1024.0,
+        index.total as f64 / 1024.0 / 1024.0
+    );
+    if partials > 0 {
+        info!("removed {} half-written files", partials);
+    }
+    Ok(())
+}
+
+/// A key-value store over http for a team to share decompilations through:
+/// `GET <url>/<key>` answers with one or a 404, `PUT <url>/<key>` stores
+/// one. A WebDAV folder, a bucket or a few lines in front of Redis will do.
+pub struct RemoteCache {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+    /// Whether a failure was warned about already, the rest only go to debug
+    warned: AtomicBool,
+}
+
+impl RemoteCache {
+    pub fn new(url: &str, token: Option<String>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(REMOTE_TIMEOUT)
+            .build()
+            .map_err(|e| Error::Connection(format!("failed to create http client: {}", e)))?;
+        Ok(Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, key: &str) -> reqwest::RequestBuilder {
+        let request = self.client.request(method, format!("{}/{}", self.url, key));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// A cache that can't be reached is a miss, the oracle is still there
+    fn failed(&self, what: &str, key: &str, e: impl std::fmt::Display) {
+        if self.warned.swap(true, Ordering::Relaxed) {
+            debug!("failed to {} {} on the remote cache: {}", what, key, e);
+        } else {
+            warn!("failed to {} {} on the remote cache at {}: {}", what, key, self.url, e);
+        }
+    }
+
+    async fn get(&self, key: &str) -> Option<String> {
+        let response = match self.request(reqwest::Method::GET, key).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                self.failed("look up", key, e);
+                return None;
+            }
+        };
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return None;
+        }
+        if !status.is_success() {
+            self.failed("look up", key, status);
+            return None;
+        }
+        match response.text().await {
+            Ok(source) => Some(source),
+            Err(e) => {
+                self.failed("look up", key, e);
+                None
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, source: &str) {
+        let sent = self
+            .request(reqwest::Method::PUT, key)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(source.to_string())
+            .send()
+            .await;
+        match sent {
+            Ok(response) if !response.status().is_success() => self.failed("store", key, response.status()),
+            Ok(_) => {}
+            Err(e) => self.failed("store", key, e),
+        }
+    }
+}
+
+/// Puts `cache` between the connection and whoever is waiting on `request`,
+/// so a successful answer is stored under `key` on its way to them
+pub(super) fn cached(cache: &Arc<DecompilationCache>, mut request: DecompilationRequest, key: String) -> DecompilationRequest {
+    let (tx, rx) = oneshot::channel();
+    let mut waiting = std::mem::replace(&mut request.tx, tx);
+    let cache = cache.clone();
+    let cancel = request.cancel_handle();
+    tokio::spawn(async move {
+        let Some(result) = intercepted(rx, &mut waiting, &cancel).await else {
+            return;
+        };
+        if let Ok(source) = &result {
+            cache.put(&key, source).await;
+        }
+        let _ = waiting.send(result);
+    });
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty folder for one test
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("oracle-postprocess-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// An index of `(key, size, used)` scripts, with nothing in the folder behind it
+    fn index(name: &str, scripts: &[(&str, u64, u64)]) -> CacheIndex {
+        let mut index = CacheIndex::load(&scratch(name));
+        for (key, size, used) in scripts {
+            index.entries.insert(key.to_string(), IndexEntry { size: *size, used: *used });
+            index.total += size;
+        }
+        index
+    }
+
+    fn sorted(mut keys: Vec<String>) -> Vec<String> {
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn nothing_is_evicted_at_exactly_the_limit() {
+        let mut index = index("at-limit", &[("a", 10, 1), ("b", 10, 2)]);
+        assert!(index.evict_to(20).is_empty());
+        assert_eq!((index.entries.len(), index.total), (2, 20));
+
+        assert_eq!(index.evict_to(19), ["a"]);
+        assert_eq!((index.entries.len(), index.total), (1, 10));
+    }
+
+    #[test]
+    fn the_least_recently_used_go_first() {
+        let mut index = index("lru-order", &[("new", 10, 30), ("old", 10, 10), ("middle", 10, 20)]);
+        assert_eq!(index.evict_to(15), ["old", "middle"]);
+        assert!(index.entries.contains_key("new"));
+        assert_eq!(index.total, 10);
+        // down to nothing once even the last one is too big
+        assert_eq!(index.evict_to(0), ["new"]);
+        assert_eq!(index.total, 0);
+    }
+
+    #[test]
+    fn sizes_follow_what_was_written_last() {
+        let mut index = index("sizes", &[]);
+        index.touch("a", Some(5));
+        index.touch("b", Some(7));
+        assert_eq!(index.total, 12);
+        // written again, bigger
+        index.touch("a", Some(8));
+        assert_eq!(index.total, 15);
+        // only read, the size stays
+        index.touch("b", None);
+        assert_eq!(index.total, 15);
+        assert_eq!(index.entries["b"].size, 7);
+    }
+
+    #[test]
+    fn uses_outlive_the_index_being_read_again() {
+        let dir = scratch("reload");
+        std::fs::write(dir.join("a.lua"), "a").unwrap();
+        std::fs::write(dir.join("b.lua"), "bb").unwrap();
+        let mut index = CacheIndex::load(&dir);
+        assert_eq!(index.total, 3);
+        index.append("a", u64::MAX);
+
+        let index = CacheIndex::load(&dir);
+        assert_eq!(index.entries["a"].used, u64::MAX);
+        assert_eq!(index.entries["b"].size, 2);
+    }
+
+    #[tokio::test]
+    async fn going_over_the_limit_evicts_down_to_evict_to() {
+        let dir = scratch("put");
+        let cache = LocalCache::open(dir.clone(), Some(100), None).unwrap();
+        for key in ["a", "b", "c", "d"] {
+            cache.put(key, &"x".repeat(30)).await;
+        }
+        let index = cache.index.lock().unwrap();
+        assert!(index.total <= (100.0 * EVICT_TO) as u64, "{} bytes left", index.total);
+        assert_eq!(index.total, index.entries.values().map(|entry| entry.size).sum::<u64>());
+
+        let on_disk: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .filter_map(|entry| cached_key(&entry.file_name().to_string_lossy()).map(str::to_string))
+            .collect();
+        assert_eq!(sorted(on_disk), sorted(index.entries.keys().cloned().collect()));
+    }
+}
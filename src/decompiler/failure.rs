@@ -0,0 +1,120 @@
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// What kind of failure the oracle reported, as far as can be told from what it said
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    /// The bytecode is from a luau version the oracle can't read
+    UnsupportedVersion,
+    /// The oracle gave up on the script partway through
+    Timeout,
+    /// Something went wrong on the oracle's side
+    Internal,
+    /// The key has run out of credits
+    Quota,
+    /// The oracle couldn't be reached, so the script was never answered
+    Unreachable,
+    /// Anything else, including failures that never reached the oracle
+    Other,
+}
+
+impl FailureCategory {
+    /// Looks for what the oracle's messages for each category say
+    fn classify(message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| message.contains(word));
+        if mentions(&["unsupported version", "bytecode version", "unsupported bytecode", "version mismatch"]) {
+            Self::UnsupportedVersion
+        } else if mentions(&["timed out", "timeout", "took too long", "deadline"]) {
+            Self::Timeout
+        } else if mentions(&["quota", "credits", "insufficient balance", "payment required"]) {
+            Self::Quota
+        } else if mentions(&["internal error", "internal server error", "panicked", "crashed"]) {
+            Self::Internal
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Whether the same request could turn out differently if it's sent again.
+    /// Another version or an empty key fails the same way every time, and
+    /// an oracle that can't be reached won't be for a while.
+    pub fn retryable(self) -> bool {
+        !matches!(self, Self::UnsupportedVersion | Self::Quota | Self::Unreachable)
+    }
+
+    /// How much longer than usual to wait before sending a failed request again.
+    /// A script the oracle timed out on is likely to time out again while it's busy.
+    pub fn backoff_factor(self) -> u32 {
+        match self {
+            Self::Timeout => 4,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnsupportedVersion => "unsupported version",
+            Self::Timeout => "timeout",
+            Self::Internal => "internal error",
+            Self::Quota => "quota",
+            Self::Unreachable => "unreachable",
+            Self::Other => "other",
+        })
+    }
+}
+
+/// Why a script wasn't decompiled, the oracle's message along with what kind of failure it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecompileError {
+    pub category: FailureCategory,
+    pub message: String,
+}
+
+/// What a server that reports failures as json sends in `data`
+#[derive(Deserialize)]
+struct StructuredFailure {
+    #[serde(alias = "code", alias = "kind")]
+    category: Option<String>,
+    #[serde(alias = "error")]
+    message: Option<String>,
+}
+
+impl DecompileError {
+    pub fn new(category: FailureCategory, message: impl Into<String>) -> Self {
+        Self {
+            category,
+            message: message.into(),
+        }
+    }
+
+    /// Makes sense of the `data` of a failed result. It's json with a category
+    /// and message on servers that send one, and just the message otherwise.
+    pub fn from_oracle(data: String) -> Self {
+        if let Ok(StructuredFailure { category, message }) = serde_json::from_str(&data) {
+            let category = category
+                .and_then(|category| serde_json::from_value(category.to_ascii_lowercase().into()).ok())
+                .or_else(|| message.as_deref().map(FailureCategory::classify))
+                .unwrap_or(FailureCategory::Other);
+            return Self::new(category, message.unwrap_or(data));
+        }
+        Self::new(FailureCategory::classify(&data), data)
+    }
+}
+
+impl fmt::Display for DecompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// For failures that never reached the oracle
+impl From<String> for DecompileError {
+    fn from(message: String) -> Self {
+        Self::new(FailureCategory::Other, message)
+    }
+}
@@ -0,0 +1,176 @@
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::{http, Error as TungsteniteError, Message};
+
+use crate::decompiler::{hash_bytecode, ConnectionRead, ConnectionWrite, DecompilerSettings};
+use crate::error::{Error, Result};
+
+/// Where servers that count credits put the ones left, if not in the body
+const CREDITS_REMAINING_HEADER: &str = "X-Credits-Remaining";
+
+/// How long `connect` waits for the server to answer at all
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Where to POST decompile requests. An http(s) base url is used as is,
+/// a websocket one has its `/ws` path swapped for `/decompile`.
+fn http_endpoint(endpoint: &str) -> String {
+    let (endpoint, query) = match endpoint.split_once('?') {
+        Some((endpoint, query)) => (endpoint, Some(query)),
+        None => (endpoint, None),
+    };
+
+    let endpoint = if let Some(rest) = endpoint.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = endpoint.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        return match query {
+            Some(query) => format!("{}?{}", endpoint, query),
+            None => endpoint.to_string(),
+        };
+    };
+
+    let endpoint = match endpoint.strip_suffix("/ws") {
+        Some(base) => format!("{}/decompile", base),
+        None => format!("{}/decompile", endpoint.trim_end_matches('/')),
+    };
+    match query {
+        Some(query) => format!("{}?{}", endpoint, query),
+        None => endpoint,
+    }
+}
+
+/// Turns an error status into the same error a failed websocket handshake would give
+fn status_error(status: reqwest::StatusCode, body: String) -> TungsteniteError {
+    let mut response = http::Response::new(Some(body.into_bytes()));
+    *response.status_mut() = http::StatusCode::from_u16(status.as_u16())
+        .unwrap_or(http::StatusCode::INTERNAL_SERVER_ERROR);
+    TungsteniteError::Http(response)
+}
+
+async fn post(
+    client: &reqwest::Client,
+    endpoint: &str,
+    auth_token: &str,
+    body: &serde_json::Value,
+) -> std::result::Result<String, TungsteniteError> {
+    let response = client
+        .post(endpoint)
+        .bearer_auth(auth_token)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| TungsteniteError::Io(std::io::Error::other(e)))?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        // the same thing a websocket server would say, so the request gets sent again later
+        let retry_after = response
+            .headers()
+            .get("Retry-After")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok());
+        let input_hash = body["data"][0].as_str().map(hash_bytecode);
+        return Ok(serde_json::json!({
+            "type": "rate_limited",
+            "retry_after": retry_after,
+            "input_hash": input_hash,
+        })
+        .to_string());
+    }
+
+    let credits_remaining = response
+        .headers()
+        .get(CREDITS_REMAINING_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok());
+    let text = response
+        .text()
+        .await
+        .map_err(|e| TungsteniteError::Io(std::io::Error::other(e)))?;
+
+    if !status.is_success() {
+        return Err(status_error(status, text));
+    }
+    // the body says it itself if it wants to, the header is only for what it leaves out
+    if let Some(credits_remaining) = credits_remaining {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&text) {
+            if let Some(message) = value.as_object_mut() {
+                message.entry("credits_remaining").or_insert(credits_remaining.into());
+                return Ok(value.to_string());
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// Opens a "connection" that speaks the websocket protocol over plain POST
+/// requests, for networks where websockets are blocked.
+///
+/// Every decompile message becomes its own request, carrying the options
+/// last sent over the connection, and each response body is handed back as
+/// if the server had sent it over the websocket.
+///
+/// There's nothing to hold open, so the endpoint is asked for once first.
+/// Any answer at all will do, but a server that isn't there fails here
+/// instead of on every script.
+pub(super) async fn connect(
+    settings: &DecompilerSettings,
+    endpoint: &str,
+) -> Result<(ConnectionWrite, ConnectionRead)> {
+    let client = reqwest::Client::builder()
+        .build()
+        .map_err(|e| Error::Connection(format!("failed to create http client: {}", e)))?;
+    let endpoint = http_endpoint(endpoint);
+    client
+        .get(&endpoint)
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| Error::Connection(format!("{} can't be reached: {}", endpoint, e)))?;
+    let auth_token = settings.auth_token.clone();
+
+    let (write_tx, mut write_rx) = mpsc::unbounded::<Message>();
+    let (read_tx, read_rx) = mpsc::unbounded::<std::result::Result<Message, TungsteniteError>>();
+
+    tokio::spawn(async move {
+        let mut options = None;
+
+        while let Some(message) = write_rx.next().await {
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Ping(data) => {
+                    let _ = read_tx.unbounded_send(Ok(Message::Pong(data)));
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let Ok(mut body) = serde_json::from_str::<serde_json::Value>(&text) else {
+                continue;
+            };
+            if body["type"] == "options" {
+                options = body.get("options").cloned();
+                continue;
+            }
+            // requests carrying their own options keep them
+            if let (Some(options), None) = (&options, body.get("options")) {
+                body["options"] = options.clone();
+            }
+
+            let client = client.clone();
+            let endpoint = endpoint.clone();
+            let auth_token = auth_token.clone();
+            let read_tx = read_tx.clone();
+            tokio::spawn(async move {
+                let response = post(&client, &endpoint, &auth_token, &body)
+                    .await
+                    .map(|text| Message::Text(text.into()));
+                let _ = read_tx.unbounded_send(response);
+            });
+        }
+    });
+
+    let write = write_tx.sink_map_err(|_| TungsteniteError::AlreadyClosed);
+    Ok((Box::pin(write), Box::pin(read_rx)))
+}
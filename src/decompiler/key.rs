@@ -0,0 +1,66 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+/// Comes up with a new key once the oracle stops taking the current one
+pub type KeyRefresh = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<String>> + Send>> + Send + Sync>;
+
+/// The keys connections authenticate with, each connection starting on the
+/// next one. A key the oracle stops taking is retired and its connections
+/// move on to the ones left. Once there's only one, whichever connection
+/// first hears that it expired gets the new one, the others pick it up.
+pub(super) struct SharedKey {
+    keys: Mutex<Vec<String>>,
+    refresh: Option<KeyRefresh>,
+}
+
+/// Enough of `key` to tell it apart in the log
+fn masked(key: &str) -> String {
+    let visible: String = key.chars().take(4).collect();
+    format!("{}…", visible)
+}
+
+impl SharedKey {
+    pub fn new(keys: Vec<String>, refresh: Option<KeyRefresh>) -> Self {
+        Self {
+            keys: Mutex::new(keys),
+            refresh,
+        }
+    }
+
+    /// Whether a connection using `key` has another key to move on to
+    pub async fn has_spare(&self, key: &str) -> bool {
+        let keys = self.keys.lock().await;
+        keys.len() > 1 || keys.first().is_some_and(|current| current != key)
+    }
+
+    /// A key other than `rejected`, or the reason there isn't one
+    pub async fn replace(&self, rejected: &str, reason: &str) -> Result<String> {
+        let mut keys = self.keys.lock().await;
+        let Some(position) = keys.iter().position(|key| key == rejected) else {
+            // another connection already retired or refreshed it
+            return keys.first().cloned().ok_or_else(|| Error::Auth(reason.to_string()));
+        };
+        if keys.len() > 1 {
+            keys.remove(position);
+            warn!("retiring key {} ({}), {} left", masked(rejected), reason, keys.len());
+            return Ok(keys[position % keys.len()].clone());
+        }
+        let Some(refresh) = &self.refresh else {
+            return Err(Error::Auth(reason.to_string()));
+        };
+
+        let key = refresh().await?;
+        let key = key.trim();
+        if key.is_empty() || key == rejected {
+            return Err(Error::Auth(format!("{}, and refreshing the key didn't come up with a new one", reason)));
+        }
+        keys[0] = key.to_string();
+        Ok(keys[0].clone())
+    }
+}
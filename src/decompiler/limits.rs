@@ -0,0 +1,30 @@
+use std::sync::Mutex;
+
+use tokio::time::{Duration, Instant};
+
+/// Spaces submissions out evenly so they never exceed `max_rps`,
+/// shared by every connection
+pub(super) struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(max_rps: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / max_rps),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Takes a slot if one is free now, otherwise says when the next one opens up
+    pub fn try_acquire(&self) -> Result<(), Instant> {
+        let mut next_slot = self.next_slot.lock().unwrap();
+        let now = Instant::now();
+        if *next_slot > now {
+            return Err(*next_slot);
+        }
+        *next_slot = now + self.interval;
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use tokio::time::Duration;
+
+/// Upper bounds of the latency histogram's buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+/// How many of the slowest answers are kept for the summary at the end of a run
+const SLOWEST_KEPT: usize = 10;
+
+/// How quickly the oracle answered over a whole run
+pub struct Latencies {
+    /// `None` when nothing was answered
+    pub average: Option<Duration>,
+    /// The slowest answers by bytecode hash, slowest first
+    pub slowest: Vec<(String, Duration)>,
+}
+
+/// How the connections to the oracle are doing, shared by all of them and
+/// written out in Prometheus' text format for `/metrics`
+pub(super) struct Metrics {
+    requests_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    results: AtomicU64,
+    failures: AtomicU64,
+    reconnects: AtomicU64,
+    /// One per connection, each only written by its own
+    bytes_in_flight: Box<[AtomicU32]>,
+    /// How many answers took at most each of `LATENCY_BUCKETS`, not cumulative
+    latency_buckets: Box<[AtomicU64]>,
+    latency_micros: AtomicU64,
+    /// At most `SLOWEST_KEPT`, slowest first
+    slowest: Mutex<Vec<(String, Duration)>>,
+}
+
+impl Metrics {
+    pub fn new(connections: usize) -> Self {
+        Self {
+            requests_sent: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            results: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            bytes_in_flight: (0..connections).map(|_| AtomicU32::new(0)).collect(),
+            latency_buckets: (0..=LATENCY_BUCKETS.len()).map(|_| AtomicU64::new(0)).collect(),
+            latency_micros: AtomicU64::new(0),
+            slowest: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn on_sent(&self, bytes: u32) {
+        self.requests_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(u64::from(bytes), Ordering::Relaxed);
+    }
+
+    /// The answer for `hash` came back `latency` after its request went out
+    pub fn on_answer(&self, hash: &str, latency: Duration, success: bool) {
+        self.results.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_micros
+            .fetch_add(latency.as_micros().min(u128::from(u64::MAX)) as u64, Ordering::Relaxed);
+
+        let mut slowest = self.slowest.lock().unwrap();
+        if slowest.len() < SLOWEST_KEPT || slowest.last().is_some_and(|(_, fastest)| latency > *fastest) {
+            let index = slowest.partition_point(|(_, kept)| *kept >= latency);
+            slowest.insert(index, (hash.to_string(), latency));
+            slowest.truncate(SLOWEST_KEPT);
+        }
+    }
+
+    pub fn latencies(&self) -> Latencies {
+        let results = self.results.load(Ordering::Relaxed);
+        let micros = self.latency_micros.load(Ordering::Relaxed);
+        Latencies {
+            average: (results > 0).then(|| Duration::from_micros(micros / results)),
+            slowest: self.slowest.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn on_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_bytes_in_flight(&self, connection: usize, bytes: u32) {
+        if let Some(slot) = self.bytes_in_flight.get(connection) {
+            slot.store(bytes, Ordering::Relaxed);
+        }
+    }
+
+    pub fn render(&self, credits_remaining: Option<u64>) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+        };
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        metric(
+            "oracle_requests_sent_total",
+            "counter",
+            "Scripts sent to the oracle, retries included",
+            load(&self.requests_sent),
+        );
+        metric(
+            "oracle_bytes_sent_total",
+            "counter",
+            "Bytecode sent to the oracle",
+            load(&self.bytes_sent),
+        );
+        metric(
+            "oracle_results_total",
+            "counter",
+            "Answers the oracle sent back",
+            load(&self.results),
+        );
+        metric(
+            "oracle_failures_total",
+            "counter",
+            "Answers saying the oracle couldn't decompile a script",
+            load(&self.failures),
+        );
+        metric(
+            "oracle_reconnects_total",
+            "counter",
+            "Times a lost connection to the oracle was made again",
+            load(&self.reconnects),
+        );
+        let in_flight = self
+            .bytes_in_flight
+            .iter()
+            .map(|slot| u64::from(slot.load(Ordering::Relaxed)))
+            .sum();
+        metric(
+            "oracle_bytes_in_flight",
+            "gauge",
+            "Bytecode sent and waiting on an answer, over all connections",
+            in_flight,
+        );
+        if let Some(credits_remaining) = credits_remaining {
+            metric(
+                "oracle_credits_remaining",
+                "gauge",
+                "What the oracle last said the key has left",
+                credits_remaining,
+            );
+        }
+
+        let name = "oracle_request_duration_seconds";
+        let _ = writeln!(out, "# HELP {} How long the oracle took to answer", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut count = 0;
+        for (i, bucket) in self.latency_buckets.iter().enumerate() {
+            count += load(bucket);
+            let _ = match LATENCY_BUCKETS.get(i) {
+                Some(bound) => writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count),
+                None => writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count),
+            };
+        }
+        let sum = load(&self.latency_micros) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{}_sum {}\n{}_count {}", name, sum, name, count);
+        out
+    }
+}
@@ -1,33 +1,130 @@
 use std::{
     collections::HashMap,
+    future::Future,
+    pin::Pin,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, Ordering},
         Arc,
     },
 };
 
-use futures::{SinkExt, StreamExt};
+use base64::{engine::general_purpose, Engine as _};
+use clap::ValueEnum;
+use futures::{Sink, SinkExt, Stream, StreamExt};
 use serde_derive::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Duration, Instant};
 use tokio_tungstenite::tungstenite::Error as TungsteniteError;
 use tokio_tungstenite::{
     connect_async_with_config,
-    tungstenite::{client::IntoClientRequest, protocol::WebSocketConfig, Bytes, Message},
-    MaybeTlsStream, WebSocketStream,
+    tungstenite::{
+        client::IntoClientRequest,
+        protocol::{frame::CloseFrame, WebSocketConfig},
+        Bytes, Message,
+    },
 };
+use tracing::{debug, warn};
 
+use crate::compiled::get_bytecode_from_bytes;
+use crate::decompiler::autotune::Autotune;
+pub use crate::decompiler::cache::{
+    collect_garbage, print_cache_stats, CacheEncryption, DecompilationCache, LocalCache, RemoteCache,
+};
+pub use crate::decompiler::key::KeyRefresh;
+pub use crate::decompiler::failure::{DecompileError, FailureCategory};
+pub use crate::decompiler::metrics::Latencies;
+pub use crate::decompiler::recording::{Recorder, Recording};
+use crate::decompiler::cache::cached;
+use crate::decompiler::key::SharedKey;
+use crate::decompiler::limits::RateLimiter;
+use crate::decompiler::metrics::Metrics;
 use crate::decompiler::options::DecompileOptions;
+use crate::decompiler::queue::RequestQueue;
+use crate::decompiler::quota::{Outstanding, Quota};
+use crate::decompiler::recording::recorded;
+use crate::decompiler::retry::{with_retry_profiles, RetryProfiles};
+use crate::decompiler::window::AdaptiveWindow;
+use crate::error::{Error, Result};
+use crate::hook::CommandHook;
 
-mod options;
+mod autotune;
+mod cache;
+mod failure;
+mod http;
+mod key;
+mod limits;
+mod metrics;
+pub mod options;
+mod queue;
+mod quota;
+mod recording;
+mod retry;
+mod window;
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
 enum WebsocketServerboundMessage {
     #[serde(rename = "decompile")]
-    Decompile { data: Vec<String> },
+    Decompile {
+        data: Vec<String>,
+        /// Only sent from protocol 2 on, older servers take options per connection
+        #[serde(skip_serializing_if = "Option::is_none")]
+        options: Option<DecompileOptions>,
+        /// Only sent from protocol 4 on, for the result to be matched up by
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+    },
     #[serde(rename = "options")]
     Options { options: DecompileOptions },
+    /// Asks for results to come compressed from here on
+    #[serde(rename = "compression")]
+    Compression { algorithm: String },
+}
+
+/// What goes ahead of the bytecode in a binary `decompile` frame, which is
+/// this header's length as a little-endian u32, the header as JSON, then the
+/// raw bytecode. The hash is of the base64 the text message would have carried,
+/// for the server to answer with, since it never sees that base64.
+#[derive(Debug, Serialize)]
+struct BinaryDecompileHeader<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    input_hash: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<&'a DecompileOptions>,
+    /// What the bytecode is compressed with, if anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compression: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+}
+
+/// `request` as a binary `decompile` frame, a quarter smaller than the text
+/// message and without the base64 going through JSON, and smaller still compressed
+fn binary_decompile_frame(
+    request: &DecompilationRequest,
+    options: Option<&DecompileOptions>,
+    compress: bool,
+    id: Option<u64>,
+) -> Option<Vec<u8>> {
+    let mut bytecode = general_purpose::STANDARD.decode(request.bytecode.as_bytes()).ok()?;
+    if compress {
+        bytecode = zstd::encode_all(bytecode.as_slice(), 0).ok()?;
+    }
+    let header = serde_json::to_vec(&BinaryDecompileHeader {
+        kind: "decompile",
+        input_hash: &request.bytecode_hash,
+        options,
+        compression: compress.then_some(ZSTD),
+        id,
+    })
+    .ok()?;
+    let mut frame = Vec::with_capacity(4 + header.len() + bytecode.len());
+    frame.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(&bytecode);
+    Some(frame)
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -38,232 +135,1767 @@ enum WebsocketClientboundMessage {
         success: bool,
         data: String,
         input_hash: String,
+        /// The id the request went out with, from servers on protocol 4
+        id: Option<u64>,
+        /// Credits the key has left after this one, from servers that say
+        credits_remaining: Option<f64>,
+    },
+    /// The server wants submissions paused for a while. If `input_hash` is
+    /// set, that request was dropped and has to be sent again.
+    #[serde(rename = "rate_limited", alias = "slow_down")]
+    RateLimited {
+        retry_after: Option<f64>,
+        input_hash: Option<String>,
+        id: Option<u64>,
+    },
+    /// Sent by servers that tell clients about their limits and
+    /// the protocol versions they speak when they connect
+    #[serde(rename = "hello")]
+    Hello {
+        max_bytes_in_flight: Option<u32>,
+        #[serde(default)]
+        protocols: Vec<u32>,
+        /// Compression the server can do, on top of binary frames
+        #[serde(default)]
+        compression: Vec<String>,
+        credits_remaining: Option<f64>,
     },
+    /// How many credits the key has left, whenever the server feels like saying
+    #[serde(rename = "quota")]
+    Quota { credits_remaining: Option<f64> },
 }
 
 pub struct DecompilationRequest {
     pub bytecode: Arc<str>,
     pub bytecode_hash: String,
     pub bytecode_len: u32,
-    pub tx: oneshot::Sender<Result<String, String>>,
+    pub tx: oneshot::Sender<DecompileResult>,
+    /// Higher goes first when requests have to wait for room in the window
+    pub priority: i32,
+    /// Sent along with the request on protocol 2, and ahead of it as an
+    /// `options` message before that if the connection is using different ones.
+    /// `None` means the connection's default `--decompiler-options`.
+    pub options: Option<Arc<DecompileOptions>>,
+    attempt: u32,
+    outstanding: Option<Outstanding>,
+    cancel: CancelHandle,
+}
+
+/// Calls off a request that hasn't been sent yet. Dropping the receiver does the
+/// same. One that was already sent is still answered, the answer just goes nowhere.
+#[derive(Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// What whoever cancelled a request gets, in case they're still listening
+const CANCELLED: &str = "cancelled";
+
+/// What a request gets back, the decompiled source or why there isn't one
+pub type DecompileResult = std::result::Result<String, DecompileError>;
+
+pub fn hash_bytecode(bytecode: &str) -> String {
+    format!("{:x}", Sha256::digest(bytecode.as_bytes()))
+}
+
+impl DecompilationRequest {
+    pub fn new(bytecode: Arc<str>, tx: oneshot::Sender<DecompileResult>) -> Self {
+        let bytecode_hash = hash_bytecode(&bytecode);
+        Self::with_hash(bytecode, bytecode_hash, tx)
+    }
+
+    /// Like `new`, for callers that already hashed the bytecode
+    pub fn with_hash(
+        bytecode: Arc<str>,
+        bytecode_hash: String,
+        tx: oneshot::Sender<DecompileResult>,
+    ) -> Self {
+        let bytecode_len = bytecode.len() as u32;
+
+        Self {
+            bytecode,
+            bytecode_hash,
+            bytecode_len,
+            tx,
+            priority: 0,
+            options: None,
+            attempt: 0,
+            outstanding: None,
+            cancel: CancelHandle::default(),
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_options(mut self, options: Option<Arc<DecompileOptions>>) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Whether nobody wants the result anymore
+    fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled() || self.tx.is_closed()
+    }
+}
+
+/// Waits for the result of a request whose `tx` was swapped out, so it could be
+/// looked at before `waiting` gets it. Cancels the request if `waiting` stops
+/// listening first, since that's invisible from the request's own `tx` now.
+async fn intercepted(
+    rx: oneshot::Receiver<DecompileResult>,
+    waiting: &mut oneshot::Sender<DecompileResult>,
+    cancel: &CancelHandle,
+) -> Option<DecompileResult> {
+    tokio::select! {
+        result = rx => result.ok(),
+        () = waiting.closed() => {
+            cancel.cancel();
+            None
+        }
+    }
+}
+
+struct PendingRequest {
+    requests: Vec<DecompilationRequest>,
+    /// What it went out with, from `REQUEST_IDS_PROTOCOL` on
+    id: Option<u64>,
+    byte_size: u32,
+    attempt: u32,
+    sent_at: Instant,
+}
+
+/// The two halves of a connection to the oracle. Both transports look like a
+/// websocket from here, so everything above them is shared.
+type ConnectionWrite = Pin<Box<dyn Sink<Message, Error = TungsteniteError> + Send>>;
+type ConnectionRead = Pin<Box<dyn Stream<Item = std::result::Result<Message, TungsteniteError>> + Send>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// One long-lived websocket per connection
+    Websocket,
+    /// A POST request per script, for networks that block websockets
+    Http,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Send and receive everything as it is
+    None,
+    /// Compress bytecode and results with zstd
+    Zstd,
 }
 
+/// What servers call zstd in their hello and compressed frames
+const ZSTD: &str = "zstd";
+
+/// A result the server sent compressed
+fn decompressed_text(frame: &[u8]) -> std::result::Result<String, String> {
+    let text = zstd::decode_all(frame).map_err(|e| e.to_string())?;
+    String::from_utf8(text).map_err(|e| e.to_string())
+}
+
+#[derive(Clone, Default)]
+pub struct DecompilerSettings {
+    /// Tried in order, later ones are only used when the ones before them are down
+    pub endpoints: Vec<String>,
+    pub auth_token: String,
+    /// More keys to spread the connections over, each connection starting on
+    /// the next one after `auth_token`. A key the oracle says is out of quota
+    /// is retired for the rest of the run.
+    pub extra_keys: Vec<String>,
+    /// `None` tries a websocket first, and http if the handshake fails but the server answers it
+    pub transport: Option<Transport>,
+    pub options: Option<DecompileOptions>,
+    pub max_retries: u32,
+    pub connections: usize,
+    /// Most requests per second to submit, over all connections
+    pub max_rps: Option<f64>,
+    /// Most distinct scripts in flight at once, over all connections
+    pub max_concurrent: Option<usize>,
+    /// Most bytecode in flight per connection. A lower limit from the server wins.
+    pub max_bytes_in_flight: Option<u32>,
+    /// Keep exactly `max_bytes_in_flight` in flight, instead of adapting how
+    /// much to how quickly the server answers
+    pub fixed_window: bool,
+    /// Try a few windows at the start and keep the quickest, instead of
+    /// adapting the whole way through
+    pub autotune: bool,
+    /// Protocol version to speak. From `PER_REQUEST_OPTIONS_PROTOCOL` on,
+    /// options go along with each request instead of being set per connection,
+    /// from `BINARY_FRAMES_PROTOCOL` on bytecode goes as raw bytes, and from
+    /// `REQUEST_IDS_PROTOCOL` on results are matched to requests by an id, and
+    /// from `DISASSEMBLY_PROTOCOL` on the oracle disassembles scripts too.
+    /// `None` picks the newest one the server's hello offers.
+    pub protocol: Option<u32>,
+    /// Hold `Decompiler::new` until every connection has had the server's
+    /// hello, or given up on it, so `disassembles` is right from the start
+    pub wait_for_hello: bool,
+    /// How to compress bytecode and results, which needs binary frames.
+    /// `None` uses zstd if the server's hello offers it.
+    pub compression: Option<Compression>,
+    /// Gets each script's raw bytecode on stdin before it's sent, and prints
+    /// what to send instead. Printing nothing sends the script as it was.
+    pub pre_process: Option<CommandHook>,
+    /// Gets each successful decompilation on stdin, and prints what to use
+    /// instead. Printing nothing, or failing, keeps the decompilation as it was.
+    pub post_process: Option<CommandHook>,
+    /// Asked for a new key when the oracle rejects `auth_token` partway
+    /// through a run. Without it, a rejected key ends the run.
+    pub refresh_key: Option<KeyRefresh>,
+    /// Options laid over the usual ones, tried in turn on a script the oracle
+    /// fails on or whose decompilation doesn't parse
+    pub retry_profiles: Vec<DecompileOptions>,
+    /// Once the connections gave up on the oracle, answer requests with an
+    /// `Unreachable` failure instead of refusing them, so a run can still
+    /// finish and queue those scripts for later
+    pub answer_unreachable: bool,
+    /// Writes down every answer from the oracle, for `replay`
+    pub record: Option<Arc<Recorder>>,
+    /// Answers requests from an earlier `record` instead of connecting to the oracle
+    pub replay: Option<Arc<Recording>>,
+    /// Where requests are looked up before they go to the oracle, and
+    /// successful answers kept
+    pub cache: Option<Arc<DecompilationCache>>,
+}
+
+/// Spoken when the server doesn't say what it supports
+const DEFAULT_PROTOCOL: u32 = 1;
+pub const PER_REQUEST_OPTIONS_PROTOCOL: u32 = 2;
+const BINARY_FRAMES_PROTOCOL: u32 = 3;
+const REQUEST_IDS_PROTOCOL: u32 = 4;
+/// Requests with `{"disassemble": true}` options are answered with a disassembly
+const DISASSEMBLY_PROTOCOL: u32 = 5;
+const SUPPORTED_PROTOCOLS: &[u32] = &[
+    DEFAULT_PROTOCOL,
+    PER_REQUEST_OPTIONS_PROTOCOL,
+    BINARY_FRAMES_PROTOCOL,
+    REQUEST_IDS_PROTOCOL,
+    DISASSEMBLY_PROTOCOL,
+];
+/// How long to wait for a hello before assuming the server doesn't send one
+const HELLO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The newest protocol both sides speak
+fn negotiate_protocol(offered: &[u32]) -> u32 {
+    offered
+        .iter()
+        .copied()
+        .filter(|protocol| SUPPORTED_PROTOCOLS.contains(protocol))
+        .max()
+        .unwrap_or(DEFAULT_PROTOCOL)
+}
+
+struct Connections {
+    decompile_txs: Vec<mpsc::UnboundedSender<DecompilationRequest>>,
+    websocket_handles: Vec<tokio::task::JoinHandle<Result<()>>>,
+    /// Like `DecompilerSettings::answer_unreachable`
+    answer_unreachable: bool,
+    record: Option<Arc<Recorder>>,
+    replay: Option<Arc<Recording>>,
+    cache: Option<Arc<DecompilationCache>>,
+    /// What requests without options of their own go out with
+    default_options: Option<Arc<DecompileOptions>>,
+}
+
+/// What requests are answered with once there's no connection to send them over
+const UNREACHABLE: &str = "the oracle can't be reached";
+
+impl Connections {
+    /// Whether requests can't be sent anymore, and won't be answered either
+    fn is_closed(&self) -> bool {
+        !self.answer_unreachable && self.replay.is_none() && self.decompile_txs.iter().all(|tx| tx.is_closed())
+    }
+
+    /// Looks the request up in the cache first, if there is one, and only
+    /// sends it on if it isn't there
+    fn send(self: &Arc<Self>, request: DecompilationRequest) -> Result<()> {
+        let options = request.options.clone().or_else(|| self.default_options.clone());
+        if let Some(replay) = &self.replay {
+            let _ = request.tx.send(replay.answer(&request.bytecode_hash, options.as_deref()));
+            return Ok(());
+        }
+        let Some(cache) = &self.cache else {
+            return self.forward(request, options);
+        };
+        if self.is_closed() {
+            return Err(Error::Connection("decompiler connection is closed".to_string()));
+        }
+
+        // a strong reference would keep the connections open past `shutdown`
+        let connections = Arc::downgrade(self);
+        let cache = cache.clone();
+        tokio::spawn(async move {
+            let key = cache.key(&request.bytecode_hash, options.as_deref());
+            if let Some(source) = cache.get(&key).await {
+                debug!("answered {} from the cache", request.bytecode_hash);
+                let _ = request.tx.send(Ok(source));
+                return;
+            }
+            let request = cached(&cache, request, key);
+            // closed connections drop the request, and with it `tx`
+            if let Some(connections) = connections.upgrade() {
+                let _ = connections.forward(request, options);
+            }
+        });
+        Ok(())
+    }
+
+    /// Requests with the same hash always go to the same connection,
+    /// so duplicates keep getting coalesced there
+    fn forward(&self, mut request: DecompilationRequest, options: Option<Arc<DecompileOptions>>) -> Result<()> {
+        if let Some(recorder) = &self.record {
+            request = recorded(recorder, request, options);
+        }
+        let sent = match self.decompile_txs.len() {
+            0 => Err(request),
+            connections => {
+                let index = u64::from_str_radix(&request.bytecode_hash[..16], 16).unwrap_or(0) % connections as u64;
+                self.decompile_txs[index as usize]
+                    .send(request)
+                    .map_err(|mpsc::error::SendError(request)| request)
+            }
+        };
+        match sent {
+            Ok(()) => Ok(()),
+            Err(request) if self.answer_unreachable => {
+                let _ = request
+                    .tx
+                    .send(Err(DecompileError::new(FailureCategory::Unreachable, UNREACHABLE)));
+                Ok(())
+            }
+            Err(_) => Err(Error::Connection("decompiler connection is closed".to_string())),
+        }
+    }
+}
+
+/// Swaps the request's bytecode for what `hook` makes of it. The hash is
+/// taken again, the server answers with the hash of what it was sent.
+async fn pre_process(
+    hook: &CommandHook,
+    request: DecompilationRequest,
+) -> std::result::Result<DecompilationRequest, (DecompilationRequest, String)> {
+    let Ok(raw) = general_purpose::STANDARD.decode(request.bytecode.as_bytes()) else {
+        return Ok(request);
+    };
+    let output = match hook.run(&raw).await {
+        Ok(output) => output,
+        Err(e) => return Err((request, format!("pre-process command failed: {}", e))),
+    };
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(request);
+    }
+    let bytecode = match get_bytecode_from_bytes(&output) {
+        Ok((bytecode, _)) => bytecode,
+        Err(_) => return Err((request, format!("`{}` didn't print luau bytecode", hook.command()))),
+    };
+
+    let DecompilationRequest { tx, priority, options, outstanding, cancel, .. } = request;
+    let mut request = DecompilationRequest::new(Arc::from(bytecode), tx)
+        .with_priority(priority)
+        .with_options(options);
+    request.outstanding = outstanding;
+    request.cancel = cancel;
+    Ok(request)
+}
+
+/// Puts `hook` between the connection and whoever is waiting on `request`,
+/// so it sees each successful result before they do
+fn post_process(hook: &CommandHook, mut request: DecompilationRequest) -> DecompilationRequest {
+    let (tx, rx) = oneshot::channel();
+    let mut waiting = std::mem::replace(&mut request.tx, tx);
+    let hook = hook.clone();
+    let hash = request.bytecode_hash.clone();
+    let cancel = request.cancel_handle();
+    tokio::spawn(async move {
+        let Some(result) = intercepted(rx, &mut waiting, &cancel).await else {
+            return;
+        };
+        let result = match result {
+            Ok(source) => Ok(match hook.run(source.as_bytes()).await {
+                Ok(output) if !output.iter().all(u8::is_ascii_whitespace) => {
+                    String::from_utf8_lossy(&output).into_owned()
+                }
+                Ok(_) => source,
+                Err(e) => {
+                    warn!("post-process command failed for {}, keeping the decompilation as is: {}", hash, e);
+                    source
+                }
+            }),
+            Err(e) => Err(e),
+        };
+        let _ = waiting.send(result);
+    });
+    request
+}
+
+/// A handle to the oracle connections. Clones are cheap and share the same
+/// connections, so any number of tasks can decompile through one of them.
+#[derive(Clone)]
 pub struct Decompiler {
-    decompile_tx: mpsc::UnboundedSender<DecompilationRequest>,
-    _websocket_handle: tokio::task::JoinHandle<()>,
+    connections: Arc<Connections>,
+    pre_process: Option<CommandHook>,
+    post_process: Option<CommandHook>,
+    retry: Option<RetryProfiles>,
+    quota: Arc<Quota>,
+    /// What the first connection was made to
+    endpoint: Arc<str>,
+    /// What each connection last agreed on with the server
+    protocols: Arc<[Arc<AtomicU32>]>,
+    metrics: Arc<Metrics>,
+}
+
+/// Priority of `decompile_single`, so someone waiting on one script
+/// isn't stuck behind a whole place
+pub const INTERACTIVE_PRIORITY: i32 = 100;
+
+/// Used until the server says otherwise, if the user didn't set a limit
+pub const DEFAULT_MAX_BYTES_IN_FLIGHT: u32 = 8 * 1024 * 1024; // 8 mib
+
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.min(5))
+        .min(RETRY_MAX_DELAY)
 }
 
-const MAX_BYTES_IN_FLIGHT: u32 = 8 * 1024 * 1024; // 8 mib
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+/// How long the server may stay completely silent, pongs included,
+/// before the connection is considered dead
+const PONG_TIMEOUT: Duration = Duration::from_secs(45);
+/// Reconnect attempts in a row without getting a single result back
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+/// How long to pause when the server asks to slow down without saying for how long
+const DEFAULT_SLOW_DOWN_DELAY: Duration = Duration::from_secs(5);
+
+/// Everything a single websocket connection is keeping track of.
+/// It outlives the websocket itself, so a reconnect can pick up where it left off.
+struct ConnectionState {
+    bytes_in_flight: u32,
+    max_bytes_in_flight: u32,
+    /// How much of `max_bytes_in_flight` to actually use, unless it's fixed
+    window: Option<AdaptiveWindow>,
+    /// Takes over from `window` with `--autotune`
+    autotune: Option<Autotune>,
+    /// by `pending_key`
+    pending_requests: HashMap<String, PendingRequest>,
+    /// the `pending_key` each id in flight was sent for
+    pending_ids: HashMap<u64, String>,
+    next_id: u64,
+    queued_requests: RequestQueue,
+    /// Bytecode sent ahead of the next queued request while it didn't fit
+    backfilled: u32,
+    // failed requests wait out their backoff in a separate task
+    // and come back through this channel
+    retry_tx: mpsc::UnboundedSender<Vec<DecompilationRequest>>,
+    retry_rx: mpsc::UnboundedReceiver<Vec<DecompilationRequest>>,
+    retrying: usize,
+    decompile_closed: bool,
+    /// whether a result came back since the last (re)connect
+    made_progress: bool,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    quota: Arc<Quota>,
+    max_concurrent: Option<usize>,
+    /// nothing new is sent before this, because of `--max-rps` or the server asking
+    paused_until: Option<Instant>,
+    default_options: Option<Arc<DecompileOptions>>,
+    per_request_options: bool,
+    /// bytecode goes as raw bytes in binary frames, not base64 in JSON
+    binary_frames: bool,
+    /// results say which request they're for by id, so the same script
+    /// asked for with different options isn't mixed up
+    request_ids: bool,
+    /// whether the server's hello offered zstd
+    zstd_offered: bool,
+    /// bytecode and results go zstd compressed
+    compress: bool,
+    /// what the last `options` message on this connection said
+    active_options: Option<Arc<DecompileOptions>>,
+    /// the protocol this connection speaks, read by the `Decompiler`
+    protocol: Arc<AtomicU32>,
+    metrics: Arc<Metrics>,
+    /// which of the connections this is, for `metrics`
+    index: usize,
+    /// told once the hello is through, see `DecompilerSettings::wait_for_hello`
+    hello_seen: Option<oneshot::Sender<()>>,
+}
+
+impl ConnectionState {
+    fn new(
+        settings: &DecompilerSettings,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        quota: Arc<Quota>,
+        max_concurrent: Option<usize>,
+        protocol: Arc<AtomicU32>,
+        metrics: Arc<Metrics>,
+        index: usize,
+    ) -> Self {
+        let max_bytes_in_flight = settings.max_bytes_in_flight.unwrap_or(DEFAULT_MAX_BYTES_IN_FLIGHT);
+        let per_request_options = protocol.load(Ordering::Relaxed) >= PER_REQUEST_OPTIONS_PROTOCOL;
+        let binary_frames = protocol.load(Ordering::Relaxed) >= BINARY_FRAMES_PROTOCOL;
+        let request_ids = protocol.load(Ordering::Relaxed) >= REQUEST_IDS_PROTOCOL;
+        let (retry_tx, retry_rx) = mpsc::unbounded_channel();
+        Self {
+            bytes_in_flight: 0,
+            max_bytes_in_flight,
+            window: (!settings.fixed_window).then(|| AdaptiveWindow::new(max_bytes_in_flight)),
+            autotune: None,
+            pending_requests: HashMap::new(),
+            pending_ids: HashMap::new(),
+            next_id: 0,
+            queued_requests: RequestQueue::default(),
+            backfilled: 0,
+            retry_tx,
+            retry_rx,
+            retrying: 0,
+            decompile_closed: false,
+            made_progress: false,
+            rate_limiter,
+            quota,
+            max_concurrent,
+            paused_until: None,
+            active_options: None,
+            default_options: settings.options.clone().map(Arc::new),
+            per_request_options,
+            binary_frames,
+            request_ids,
+            zstd_offered: false,
+            compress: false,
+            protocol,
+            metrics,
+            index,
+            hello_seen: None,
+        }
+    }
+
+    fn publish_metrics(&self) {
+        self.metrics.set_bytes_in_flight(self.index, self.bytes_in_flight);
+    }
+
+    /// Whether another request may go out right now, as far as
+    /// `--max-concurrent`, `--max-rps` and the server are concerned
+    fn can_send(&mut self) -> bool {
+        if let Some(paused_until) = self.paused_until {
+            if paused_until > Instant::now() {
+                return false;
+            }
+            self.paused_until = None;
+        }
+
+        if self
+            .max_concurrent
+            .is_some_and(|max| self.pending_requests.len() >= max)
+        {
+            return false;
+        }
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            if let Err(next_slot) = rate_limiter.try_acquire() {
+                self.paused_until = Some(next_slot);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn update_quota(&self, credits_remaining: Option<f64>) {
+        if let Some(credits_remaining) = credits_remaining {
+            self.quota.update(credits_remaining);
+        }
+    }
+
+    fn apply_hello(
+        &mut self,
+        settings: &DecompilerSettings,
+        max_bytes_in_flight: Option<u32>,
+        protocols: &[u32],
+        compression: &[String],
+    ) {
+        self.zstd_offered = compression.iter().any(|algorithm| algorithm == ZSTD);
+        if let Some(server_max) = max_bytes_in_flight {
+            self.max_bytes_in_flight = settings
+                .max_bytes_in_flight
+                .map_or(server_max, |max| max.min(server_max));
+            if let Some(window) = &mut self.window {
+                window.set_limit(self.max_bytes_in_flight);
+            }
+            if let Some(autotune) = &mut self.autotune {
+                autotune.set_limit(self.max_bytes_in_flight);
+            }
+        }
+        if settings.protocol.is_none() {
+            let protocol = negotiate_protocol(protocols);
+            self.protocol.store(protocol, Ordering::Relaxed);
+            self.per_request_options = protocol >= PER_REQUEST_OPTIONS_PROTOCOL;
+            self.binary_frames = protocol >= BINARY_FRAMES_PROTOCOL;
+            self.request_ids = protocol >= REQUEST_IDS_PROTOCOL;
+        }
+    }
+
+    /// What requests waiting on the same result are grouped by: the script,
+    /// and once results come with an id, the options it's wanted with too
+    fn pending_key(&self, request: &DecompilationRequest) -> String {
+        let options = request.options.as_ref().or(self.default_options.as_ref());
+        match options {
+            Some(options) if self.request_ids => format!("{} {}", request.bytecode_hash, options),
+            _ => request.bytecode_hash.clone(),
+        }
+    }
+
+    /// Which of `pending_requests` a result is for. The id wins when there
+    /// is one, servers from before `REQUEST_IDS_PROTOCOL` only give the hash.
+    /// Without a known id, a key with options after the hash still matches.
+    fn answered_key(&self, input_hash: Option<&str>, id: Option<u64>) -> Option<String> {
+        if let Some(key) = id.and_then(|id| self.pending_ids.get(&id)) {
+            return Some(key.clone());
+        }
+        let hash = input_hash?;
+        if self.pending_requests.contains_key(hash) {
+            return Some(hash.to_string());
+        }
+        self.pending_requests
+            .keys()
+            .find(|key| key.strip_prefix(hash).is_some_and(|rest| rest.starts_with(' ')))
+            .cloned()
+            .or_else(|| Some(hash.to_string()))
+    }
+
+    /// Takes the request a result is for out of `pending_requests`, and its
+    /// bytecode out of `bytes_in_flight`
+    fn take_pending(&mut self, key: &str) -> Option<PendingRequest> {
+        let pending = self.pending_requests.remove(key)?;
+        if let Some(id) = pending.id {
+            self.pending_ids.remove(&id);
+        }
+        self.bytes_in_flight -= pending.byte_size;
+        Some(pending)
+    }
+
+    /// Stops sending for `delay`, and takes back the request the server dropped, if any
+    fn slow_down(&mut self, delay: Duration, dropped: Option<String>) {
+        self.paused_until = Some(Instant::now() + delay);
+        if let Some(window) = &mut self.window {
+            window.on_congestion();
+        }
+
+        if let Some(pending) = dropped.and_then(|key| self.take_pending(&key)) {
+            self.queued_requests.extend(pending.requests);
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.pending_requests.is_empty() && self.queued_requests.is_empty()
+    }
+
+    /// Puts everything that was sent over a lost connection back in the queue
+    fn requeue_pending(&mut self) {
+        for (_, pending) in self.pending_requests.drain() {
+            self.queued_requests.extend(pending.requests);
+        }
+        self.pending_ids.clear();
+        self.bytes_in_flight = 0;
+    }
+
+    /// Answers every request this connection still owes a result
+    fn fail_all(&mut self, error: &DecompileError) {
+        let pending = self
+            .pending_requests
+            .drain()
+            .flat_map(|(_, pending)| pending.requests);
+        for request in pending.chain(self.queued_requests.drain()) {
+            let _ = request.tx.send(Err(error.clone()));
+        }
+        self.pending_ids.clear();
+        self.bytes_in_flight = 0;
+    }
+
+    async fn submit_request(&mut self, write: &mut ConnectionWrite, request: DecompilationRequest) -> Result<()> {
+        if request.is_cancelled() {
+            let _ = request.tx.send(Err(CANCELLED.to_string().into()));
+            return Ok(());
+        }
+
+        // check if there's already a pending request for this script
+        let key = self.pending_key(&request);
+        if let Some(existing) = self.pending_requests.get_mut(&key) {
+            existing.requests.push(request);
+            return Ok(());
+        }
+
+        // check if single request exceeds limit
+        if request.bytecode_len > self.max_bytes_in_flight {
+            self.reject_too_large(request);
+            return Ok(());
+        }
+
+        // everything goes through the queue, so it can't jump
+        // ahead of anything with a higher priority
+        self.queued_requests.push(request);
+        self.drain_queue(write).await
+    }
+
+    fn reject_too_large(&self, request: DecompilationRequest) {
+        // whoever asked may have given up on it already
+        let _ = request.tx.send(Err(format!("bytecode too large ({:.2} mb) exceeds {:.2}mb limit",
+            request.bytecode_len as f64 / 1024.0 / 1024.0,
+            self.max_bytes_in_flight as f64 / 1024.0 / 1024.0).into()));
+    }
+
+    /// Switches the connection over to the options `request` wants, if it isn't using them already
+    async fn switch_options(&mut self, write: &mut ConnectionWrite, request: &DecompilationRequest) -> Result<()> {
+        let wanted = request.options.as_ref().or(self.default_options.as_ref());
+        if wanted.map(|options| &**options) == self.active_options.as_deref() {
+            return Ok(());
+        }
+
+        // going back to defaults that were never set means no options at all
+        let options = wanted.map_or_else(|| serde_json::json!({}), |options| (**options).clone());
+        let message = serde_json::to_string(&WebsocketServerboundMessage::Options { options }).unwrap();
+        write.send(Message::Text(message.into())).await.map_err(|e| {
+            Error::Connection(format!("failed to send websocket message (connection lost): {}", e))
+        })?;
+        self.active_options = wanted.cloned();
+        Ok(())
+    }
+
+    async fn send_request(&mut self, write: &mut ConnectionWrite, request: DecompilationRequest) -> Result<()> {
+        // requeued or retried copies of a script that went out again in the
+        // meantime wait on that instead of being sent (and counted) twice
+        let key = self.pending_key(&request);
+        if let Some(existing) = self.pending_requests.get_mut(&key) {
+            existing.requests.push(request);
+            return Ok(());
+        }
+
+        let options = if self.per_request_options {
+            request.options.as_deref().cloned()
+        } else {
+            if let Err(e) = self.switch_options(write, &request).await {
+                self.queued_requests.push(request);
+                return Err(e);
+            }
+            None
+        };
+
+        let id = self.request_ids.then(|| {
+            self.next_id += 1;
+            self.next_id
+        });
+        let frame = self
+            .binary_frames
+            .then(|| binary_decompile_frame(&request, options.as_ref(), self.compress, id))
+            .flatten();
+        let message = match frame {
+            Some(frame) => Message::Binary(frame.into()),
+            None => Message::Text(
+                serde_json::to_string(&WebsocketServerboundMessage::Decompile {
+                    data: vec![request.bytecode.to_string()],
+                    options,
+                    id,
+                })
+                .unwrap()
+                .into(),
+            ),
+        };
+
+        if let Err(e) = write.send(message).await {
+            // keep it around for when the connection comes back
+            self.queued_requests.push(request);
+            return Err(Error::Connection(format!(
+                "failed to send websocket message (connection lost): {}",
+                e
+            )));
+        }
+
+        self.bytes_in_flight += request.bytecode_len;
+        self.metrics.on_sent(request.bytecode_len);
+        if let Some(id) = id {
+            self.pending_ids.insert(id, key.clone());
+        }
+        self.pending_requests.insert(
+            key,
+            PendingRequest {
+                id,
+                byte_size: request.bytecode_len,
+                attempt: request.attempt,
+                sent_at: Instant::now(),
+                requests: vec![request],
+            },
+        );
+
+        Ok(())
+    }
+
+    /// try to send queued requests now that we have space
+    async fn drain_queue(&mut self, write: &mut ConnectionWrite) -> Result<()> {
+        while let Some(next) = self.queued_requests.peek() {
+            // cancelled while it waited, it's taken out without being sent
+            if next.is_cancelled() {
+                let request = self.queued_requests.pop().unwrap();
+                let _ = request.tx.send(Err(CANCELLED.to_string().into()));
+                continue;
+            }
+
+            // nothing to send for a script that's already in flight, however full the window is
+            if self.pending_requests.contains_key(&self.pending_key(next)) {
+                let request = self.queued_requests.pop().unwrap();
+                self.send_request(write, request).await?;
+                continue;
+            }
+
+            // the limit can drop after a request was queued
+            if next.bytecode_len > self.max_bytes_in_flight {
+                let request = self.queued_requests.pop().unwrap();
+                self.reject_too_large(request);
+                continue;
+            }
+
+            // a script bigger than the window still goes out once nothing else is in flight
+            let limit = match (&self.autotune, &self.window) {
+                (Some(autotune), _) => autotune.size(),
+                (None, Some(window)) => window.size(),
+                (None, None) => self.max_bytes_in_flight,
+            };
+            let room = limit.saturating_sub(self.bytes_in_flight);
+            let fits = self.bytes_in_flight == 0 || next.bytecode_len <= room;
+
+            // while the next script doesn't fit yet, smaller ones fill the room,
+            // but no more of them than it's big, so it isn't held back for long
+            let backfill_room = room.min(next.bytecode_len.saturating_sub(self.backfilled));
+            if !fits && self.queued_requests.peek_fitting(backfill_room).is_none() {
+                break;
+            }
+            if !self.can_send() {
+                break;
+            }
+
+            let request = if fits {
+                self.backfilled = 0;
+                self.queued_requests.pop().unwrap()
+            } else {
+                let request = self.queued_requests.pop_fitting(backfill_room).unwrap();
+                if request.is_cancelled() {
+                    let _ = request.tx.send(Err(CANCELLED.to_string().into()));
+                    continue;
+                }
+                self.backfilled += request.bytecode_len;
+                request
+            };
+            self.send_request(write, request).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps a failed handshake or request to an auth error if the key was
+/// rejected, and to a connection error otherwise
+fn connection_error(e: TungsteniteError) -> Error {
+    let TungsteniteError::Http(response) = e else {
+        return e.into();
+    };
+
+    let message = response
+        .body()
+        .as_ref()
+        .and_then(|body| String::from_utf8(body.clone()).ok())
+        .unwrap_or_else(|| format!("http error: {:?}", response));
+
+    if matches!(response.status().as_u16(), 401 | 403) {
+        return Error::Auth(message);
+    }
+    Error::Connection(message)
+}
+
+/// Close codes servers use for a key they no longer take
+const AUTH_CLOSE_CODES: &[u16] = &[4001, 4003, 4401, 4403];
+
+/// Why the server closed the connection: an auth error if it was about
+/// the key, so it can be refreshed, and a connection error otherwise
+fn close_error(frame: Option<&CloseFrame>) -> Error {
+    let Some(frame) = frame else {
+        return Error::Connection("websocket connection closed by server".to_string());
+    };
+    let code = u16::from(frame.code);
+    let reason = frame.reason.to_ascii_lowercase();
+    let about_key = ["expired", "unauthorized", "invalid key", "invalid token"]
+        .iter()
+        .any(|word| reason.contains(word));
+    let message = format!("websocket connection closed by server ({}: {})", code, frame.reason);
+    if AUTH_CLOSE_CODES.contains(&code) || (code == 1008 && about_key) {
+        Error::Auth(message)
+    } else {
+        Error::Connection(message)
+    }
+}
 
 impl Decompiler {
-    pub async fn new(endpoint: &str, auth_token: &str, options: Option<DecompileOptions>) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut request = endpoint.into_client_request()?;
-        request
-            .headers_mut()
-            .insert("Authorization", format!("Bearer {}", auth_token).parse()?);
+    pub async fn new(settings: &DecompilerSettings) -> Result<Self> {
+        let mut decompile_txs = Vec::with_capacity(settings.connections);
+        let mut websocket_handles = Vec::with_capacity(settings.connections);
 
-        let ws_config = WebSocketConfig::default().max_frame_size(Some(512 * 1024 * 1024)).max_message_size(Some(512 * 1024 * 1024));
-        let ws_connect = connect_async_with_config(request, Some(ws_config), false).await;
+        if let Some(recording) = &settings.replay {
+            return Ok(Self::replaying(settings, recording.clone()));
+        }
 
-        let mut ws_stream = match ws_connect {
-            Ok((ws_stream, _)) => ws_stream,
-            Err(TungsteniteError::Http(e)) => {
-                if let Some(body) = e.body() {
-                    if let Ok(body_string) = String::from_utf8(body.clone()) {
-                        return Err(body_string.into());
+        let mut settings = settings.clone();
+        if settings.endpoints.is_empty() {
+            return Err(Error::Config("no oracle url configured".to_string()));
+        }
+
+        let mut first_connection = None;
+        if settings.transport.is_none() {
+            settings.transport = Some(Transport::Websocket);
+            match Self::connect(&settings, 0).await {
+                Ok(connection) => first_connection = Some(connection),
+                Err(Error::Connection(websocket_error)) => {
+                    settings.transport = Some(Transport::Http);
+                    match Self::connect(&settings, 0).await {
+                        Ok(connection) => {
+                            warn!("websocket handshake failed ({}), using http instead", websocket_error);
+                            first_connection = Some(connection);
+                        }
+                        // http doesn't get through either, so it's the server that isn't there
+                        Err(Error::Connection(_)) => return Err(Error::Connection(websocket_error)),
+                        Err(e) => return Err(e),
                     }
                 }
-                return Err(format!("http error: {:?}", e).into());
+                Err(e) => return Err(e),
             }
-            Err(e) => {
-                eprintln!("error: {:?}", e);
-                return Err(e.into());
+        }
+
+        let connections = settings.connections.max(1);
+        let rate_limiter = settings
+            .max_rps
+            .filter(|max_rps| *max_rps > 0.0)
+            .map(|max_rps| Arc::new(RateLimiter::new(max_rps)));
+        let max_concurrent = settings
+            .max_concurrent
+            .map(|max| max.div_ceil(connections).max(1));
+
+        let shared_settings = Arc::new(settings.clone());
+        let keys: Vec<String> = std::iter::once(&settings.auth_token)
+            .chain(&settings.extra_keys)
+            .cloned()
+            .collect();
+        let key = Arc::new(SharedKey::new(keys.clone(), settings.refresh_key.clone()));
+        let quota = Arc::new(Quota::new());
+        let metrics = Arc::new(Metrics::new(connections));
+        let mut protocols = Vec::with_capacity(connections);
+        let mut first_endpoint = None;
+        let mut hellos_seen = Vec::new();
+        for index in 0..connections {
+            // the first connection was made with `auth_token`, the first key
+            let connection_settings = match index % keys.len() {
+                0 => shared_settings.clone(),
+                key_index => Arc::new(DecompilerSettings {
+                    auth_token: keys[key_index].clone(),
+                    ..settings.clone()
+                }),
+            };
+            let (endpoint, connection) = match first_connection.take() {
+                Some(connection) => connection,
+                None => Self::connect(&connection_settings, 0).await?,
+            };
+            first_endpoint.get_or_insert(endpoint);
+            let (decompile_tx, decompile_rx) = mpsc::unbounded_channel::<DecompilationRequest>();
+            let protocol = Arc::new(AtomicU32::new(settings.protocol.unwrap_or(DEFAULT_PROTOCOL)));
+            protocols.push(protocol.clone());
+            let mut state = ConnectionState::new(
+                &settings,
+                rate_limiter.clone(),
+                quota.clone(),
+                max_concurrent,
+                protocol,
+                metrics.clone(),
+                index,
+            );
+            if settings.autotune {
+                state.autotune = Some(Autotune::new(state.max_bytes_in_flight, index));
             }
-        };
+            if settings.wait_for_hello {
+                let (tx, rx) = oneshot::channel();
+                state.hello_seen = Some(tx);
+                hellos_seen.push(rx);
+            }
+            let websocket_handle = tokio::spawn(Self::websocket_handler(
+                connection,
+                endpoint,
+                decompile_rx,
+                state,
+                connection_settings,
+                key.clone(),
+            ));
 
-        if let Some(options) = options {
-            let message = serde_json::to_string(&WebsocketServerboundMessage::Options { options }).unwrap();
-            ws_stream.send(Message::Text(message.into())).await?;
+            decompile_txs.push(decompile_tx);
+            websocket_handles.push(websocket_handle);
         }
-
-        let (decompile_tx, decompile_rx) = mpsc::unbounded_channel::<DecompilationRequest>();
-        let websocket_handle = tokio::spawn(Self::websocket_handler(ws_stream, decompile_rx));
+        // so `protocol` and `disassembles` already go by what the server
+        // offered. A connection that fails before then drops the sender.
+        futures::future::join_all(hellos_seen).await;
 
         Ok(Self {
-            decompile_tx,
-            _websocket_handle: websocket_handle,
+            connections: Arc::new(Connections {
+                decompile_txs,
+                websocket_handles,
+                answer_unreachable: settings.answer_unreachable,
+                record: settings.record.clone(),
+                replay: None,
+                cache: settings.cache.clone(),
+                default_options: settings.options.clone().map(Arc::new),
+            }),
+            pre_process: settings.pre_process.clone(),
+            post_process: settings.post_process.clone(),
+            retry: RetryProfiles::new(&settings.retry_profiles, settings.options.as_ref()),
+            quota,
+            endpoint: Arc::from(settings.endpoints[first_endpoint.unwrap_or(0)].as_str()),
+            protocols: protocols.into(),
+            metrics,
         })
     }
 
+    /// Stands in for the oracle when it can't be reached at all, answering
+    /// every request with an `Unreachable` failure right away
+    pub fn offline() -> Self {
+        Self {
+            connections: Arc::new(Connections {
+                decompile_txs: Vec::new(),
+                websocket_handles: Vec::new(),
+                answer_unreachable: true,
+                record: None,
+                replay: None,
+                cache: None,
+                default_options: None,
+            }),
+            pre_process: None,
+            post_process: None,
+            retry: None,
+            quota: Arc::new(Quota::new()),
+            endpoint: Arc::from(""),
+            protocols: Arc::new([Arc::new(AtomicU32::new(DEFAULT_PROTOCOL))]),
+            metrics: Arc::new(Metrics::new(0)),
+        }
+    }
+
+    /// Answers every request from `recording`, without connecting to the oracle
+    fn replaying(settings: &DecompilerSettings, recording: Arc<Recording>) -> Self {
+        Self {
+            connections: Arc::new(Connections {
+                decompile_txs: Vec::new(),
+                websocket_handles: Vec::new(),
+                answer_unreachable: false,
+                record: None,
+                replay: Some(recording),
+                cache: None,
+                default_options: settings.options.clone().map(Arc::new),
+            }),
+            pre_process: settings.pre_process.clone(),
+            post_process: settings.post_process.clone(),
+            retry: RetryProfiles::new(&settings.retry_profiles, settings.options.as_ref()),
+            quota: Arc::new(Quota::new()),
+            endpoint: Arc::from(""),
+            protocols: Arc::new([Arc::new(AtomicU32::new(settings.protocol.unwrap_or(DEFAULT_PROTOCOL)))]),
+            metrics: Arc::new(Metrics::new(0)),
+        }
+    }
+
+    /// Connects to the first endpoint that's up, starting at `first` and wrapping
+    /// around. Returns which one it ended up using.
+    async fn connect(
+        settings: &DecompilerSettings,
+        first: usize,
+    ) -> Result<(usize, (ConnectionWrite, ConnectionRead))> {
+        let count = settings.endpoints.len();
+        let mut last_error = None;
+        for index in (first..first + count).map(|index| index % count) {
+            let endpoint = &settings.endpoints[index];
+            match Self::connect_to(settings, endpoint).await {
+                Ok(connection) => return Ok((index, connection)),
+                Err(Error::Connection(e)) => {
+                    if count > 1 {
+                        warn!("failed to connect to {}: {}", endpoint, e);
+                    }
+                    last_error = Some(Error::Connection(e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap())
+    }
+
+    async fn connect_to(settings: &DecompilerSettings, endpoint: &str) -> Result<(ConnectionWrite, ConnectionRead)> {
+        let (mut write, read) = match settings.transport {
+            Some(Transport::Http) => http::connect(settings, endpoint).await?,
+            _ => Self::connect_websocket(settings, endpoint).await?,
+        };
+
+        if let Some(options) = &settings.options {
+            let message = serde_json::to_string(&WebsocketServerboundMessage::Options {
+                options: options.clone(),
+            })
+            .unwrap();
+            write.send(Message::Text(message.into())).await?;
+        }
+
+        Ok((write, read))
+    }
+
+    async fn connect_websocket(
+        settings: &DecompilerSettings,
+        endpoint: &str,
+    ) -> Result<(ConnectionWrite, ConnectionRead)> {
+        let mut request = endpoint.into_client_request()?;
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", settings.auth_token)
+                .parse()
+                .map_err(|_| Error::Auth("key contains characters not allowed in a header".to_string()))?,
+        );
+
+        let ws_config = WebSocketConfig::default().max_frame_size(Some(512 * 1024 * 1024)).max_message_size(Some(512 * 1024 * 1024));
+        let ws_connect = connect_async_with_config(request, Some(ws_config), false).await;
+
+        match ws_connect {
+            Ok((ws_stream, _)) => {
+                let (write, read) = ws_stream.split();
+                Ok((Box::pin(write), Box::pin(read)))
+            }
+            Err(e) => Err(connection_error(e)),
+        }
+    }
+
     async fn websocket_handler(
-        ws_stream: WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
+        mut connection: (ConnectionWrite, ConnectionRead),
+        mut endpoint: usize,
         mut decompile_rx: mpsc::UnboundedReceiver<DecompilationRequest>,
-    ) {
-        let bytes_in_flight = Arc::new(AtomicU32::new(0));
-        let (mut write, mut read) = ws_stream.split();
+        mut state: ConnectionState,
+        mut settings: Arc<DecompilerSettings>,
+        key: Arc<SharedKey>,
+    ) -> Result<()> {
+        let mut reconnect_attempts = 0;
+
+        let result = 'connection: loop {
+            let mut error = match Self::run_connection(connection, &mut decompile_rx, &mut state, &settings, &key).await {
+                Ok(()) => break Ok(()),
+                Err(e @ (Error::Connection(_) | Error::Auth(_))) => e,
+                Err(e) => break Err(e),
+            };
+
+            if state.made_progress {
+                reconnect_attempts = 0;
+            }
+            state.made_progress = false;
+            state.requeue_pending();
+            state.publish_metrics();
+
+            loop {
+                if let Error::Auth(reason) = &error {
+                    match key.replace(&settings.auth_token, reason).await {
+                        Ok(new_key) => {
+                            warn!("the oracle stopped taking the key ({}), reconnecting with another one", reason);
+                            settings = Arc::new(DecompilerSettings {
+                                auth_token: new_key,
+                                ..(*settings).clone()
+                            });
+                        }
+                        Err(e) => break 'connection Err(e),
+                    }
 
-        let mut pending_requests: HashMap<String, (Vec<DecompilationRequest>, u32)> =
-            HashMap::new();
-        let mut queued_requests: Vec<DecompilationRequest> = Vec::new();
+                    // nothing's wrong with the endpoint, no reason to wait before trying it again
+                    if reconnect_attempts < MAX_RECONNECT_ATTEMPTS {
+                        reconnect_attempts += 1;
+                        match Self::connect(&settings, endpoint).await {
+                            Ok((new_endpoint, new_connection)) => {
+                                state.metrics.on_reconnect();
+                                endpoint = new_endpoint;
+                                connection = new_connection;
+                                break;
+                            }
+                            Err(e @ (Error::Connection(_) | Error::Auth(_))) => {
+                                error = e;
+                                continue;
+                            }
+                            Err(e) => break 'connection Err(e),
+                        }
+                    }
+                }
+
+                let can_replace_key = settings.refresh_key.is_some() || key.has_spare(&settings.auth_token).await;
+                match Self::reconnect(&settings, &error, &mut reconnect_attempts, endpoint).await {
+                    Ok((new_endpoint, new_connection)) => {
+                        state.metrics.on_reconnect();
+                        endpoint = new_endpoint;
+                        connection = new_connection;
+                        break;
+                    }
+                    // the fresh key may not have been accepted either
+                    Err(e @ Error::Auth(_)) if can_replace_key => error = e,
+                    Err(e) => break 'connection Err(e),
+                }
+            }
+        };
+
+        if let Err(e) = &result {
+            // nothing more will be sent over this connection,
+            // so everyone still waiting gets the error instead
+            let category = match e {
+                Error::Connection(_) => FailureCategory::Unreachable,
+                _ => FailureCategory::Other,
+            };
+            let error = DecompileError::new(category, e.to_string());
+            state.fail_all(&error);
+            state.publish_metrics();
+            decompile_rx.close();
+            while let Ok(request) = decompile_rx.try_recv() {
+                let _ = request.tx.send(Err(error.clone()));
+            }
+        }
+
+        result
+    }
+
+    async fn reconnect(
+        settings: &DecompilerSettings,
+        error: &Error,
+        reconnect_attempts: &mut u32,
+        failed_endpoint: usize,
+    ) -> Result<(usize, (ConnectionWrite, ConnectionRead))> {
+        let mut last_error = error.to_string();
+        loop {
+            if *reconnect_attempts >= MAX_RECONNECT_ATTEMPTS {
+                return Err(Error::Connection(format!(
+                    "giving up after {} reconnect attempts: {}",
+                    MAX_RECONNECT_ATTEMPTS, last_error
+                )));
+            }
+
+            let delay = retry_delay(*reconnect_attempts);
+            *reconnect_attempts += 1;
+            warn!(
+                "{}, reconnecting in {:?} ({}/{})",
+                last_error, delay, reconnect_attempts, MAX_RECONNECT_ATTEMPTS
+            );
+            tokio::time::sleep(delay).await;
+
+            // the endpoint that just died goes last
+            match Self::connect(settings, failed_endpoint + 1).await {
+                Ok(connection) => return Ok(connection),
+                Err(e @ Error::Connection(_)) => last_error = e.to_string(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Gives the server a moment to say hello before anything is sent, so the
+    /// first requests already use the right protocol. Whatever else arrives
+    /// in the meantime is handed back for the connection to deal with.
+    async fn wait_for_hello(
+        mut read: ConnectionRead,
+        state: &mut ConnectionState,
+        settings: &DecompilerSettings,
+    ) -> Result<ConnectionRead> {
+        state.per_request_options = false;
+        state.binary_frames = false;
+        state.request_ids = false;
+        state.zstd_offered = false;
+        state.protocol.store(DEFAULT_PROTOCOL, Ordering::Relaxed);
+
+        let message = match tokio::time::timeout(HELLO_TIMEOUT, read.next()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(read),
+            Err(_) => {
+                debug!("no hello from the server, speaking protocol {}", DEFAULT_PROTOCOL);
+                return Ok(read);
+            }
+        };
 
-        let mut ping_interval = tokio::time::interval(tokio::time::Duration::from_secs(20));
+        if let Ok(Message::Text(text)) = &message {
+            if let Ok(WebsocketClientboundMessage::Hello { max_bytes_in_flight, protocols, compression, credits_remaining }) = serde_json::from_str(text) {
+                state.apply_hello(settings, max_bytes_in_flight, &protocols, &compression);
+                state.update_quota(credits_remaining);
+                debug!("server speaks protocols {:?}, using {}", protocols, negotiate_protocol(&protocols));
+                return Ok(read);
+            }
+        }
+
+        Ok(Box::pin(futures::stream::once(std::future::ready(message)).chain(read)))
+    }
+
+    async fn run_connection(
+        (mut write, mut read): (ConnectionWrite, ConnectionRead),
+        decompile_rx: &mut mpsc::UnboundedReceiver<DecompilationRequest>,
+        state: &mut ConnectionState,
+        settings: &DecompilerSettings,
+        key: &SharedKey,
+    ) -> Result<()> {
+        let mut ping_interval = tokio::time::interval(PING_INTERVAL);
         ping_interval.tick().await;
+        let mut last_seen = Instant::now();
+
+        // connecting sent the default options
+        state.active_options = state.default_options.clone();
+
+        if settings.protocol.is_none() && settings.transport != Some(Transport::Http) {
+            read = Self::wait_for_hello(read, state, settings).await?;
+        }
+        // the http transport only carries text
+        if settings.transport == Some(Transport::Http) {
+            state.binary_frames = false;
+        }
+        if let Some(hello_seen) = state.hello_seen.take() {
+            let _ = hello_seen.send(());
+        }
+        // settled once per connection, since results only come compressed once asked for
+        state.compress = state.binary_frames
+            && match settings.compression {
+                Some(Compression::None) => false,
+                Some(Compression::Zstd) => true,
+                None => state.zstd_offered,
+            };
+        if state.compress {
+            let message = serde_json::to_string(&WebsocketServerboundMessage::Compression {
+                algorithm: ZSTD.to_string(),
+            })
+            .unwrap();
+            write.send(Message::Text(message.into())).await.map_err(|e| {
+                Error::Connection(format!("failed to send websocket message (connection lost): {}", e))
+            })?;
+        }
+
+        // anything left over from a previous connection goes out first
+        state.drain_queue(&mut write).await?;
 
         loop {
+            state.publish_metrics();
+
+            // channel closed, check if we can exit
+            if state.decompile_closed && state.is_idle() && state.retrying == 0 {
+                return Ok(());
+            }
+
             tokio::select! {
                 _ = ping_interval.tick() => {
-                    if let Err(e) = write.send(Message::Ping(Bytes::from_static(b"ping"))).await {
-                        eprintln!("error: failed to send ping (connection lost): {}", e);
-                        std::process::exit(1);
+                    if last_seen.elapsed() > PONG_TIMEOUT {
+                        return Err(Error::Connection(format!(
+                            "no response from server in {:?}, connection is dead",
+                            last_seen.elapsed()
+                        )));
                     }
+
+                    write.send(Message::Ping(Bytes::from_static(b"ping"))).await.map_err(|e| {
+                        Error::Connection(format!("failed to send ping (connection lost): {}", e))
+                    })?;
                 }
                 message = read.next() => {
+                    if let Some(Ok(_)) = &message {
+                        last_seen = Instant::now();
+                    }
+
+                    let message = match message {
+                        Some(Ok(Message::Binary(frame))) if state.compress => match decompressed_text(&frame) {
+                            Ok(text) => Some(Ok(Message::Text(text.into()))),
+                            Err(e) => return Err(Error::Protocol(format!("server sent a frame that isn't zstd: {}", e))),
+                        },
+                        message => message,
+                    };
                     let text = match message {
                         Some(Ok(Message::Text(text))) => text,
-                        Some(Ok(Message::Close(_))) => {
-                            eprintln!("error: websocket connection closed by server");
-                            std::process::exit(1);
-                        },
+                        Some(Ok(Message::Close(frame))) => return Err(close_error(frame.as_ref())),
+                        Some(Err(e @ TungsteniteError::Http(_))) => return Err(connection_error(e)),
                         Some(Err(e)) => {
-                            eprintln!("error: websocket connection error: {}", e);
-                            std::process::exit(1);
+                            return Err(Error::Connection(format!("websocket connection error: {}", e)));
                         },
                         None => {
-                            eprintln!("error: websocket connection terminated unexpectedly");
-                            std::process::exit(1);
+                            return Err(Error::Connection("websocket connection terminated unexpectedly".to_string()));
                         },
                         _ => continue
                     };
 
-                    let Ok(response) = serde_json::from_str::<WebsocketClientboundMessage>(&text) else {
-                        println!("server sent something unknown: {:?}", &text);
+                    let value: serde_json::Value = serde_json::from_str(&text).map_err(|e| {
+                        Error::Protocol(format!("server sent invalid json ({}): {:?}", e, &text))
+                    })?;
+
+                    let Ok(response) = serde_json::from_value::<WebsocketClientboundMessage>(value) else {
+                        warn!("server sent something unknown: {:?}", &text);
                         continue;
                     };
 
-                    let WebsocketClientboundMessage::DecompilationResult { success, data, input_hash } = response;
+                    let (success, data, input_hash, id, credits_remaining) = match response {
+                        WebsocketClientboundMessage::DecompilationResult { success, data, input_hash, id, credits_remaining } => {
+                            (success, data, input_hash, id, credits_remaining)
+                        }
+                        WebsocketClientboundMessage::RateLimited { retry_after, input_hash, id } => {
+                            let delay = retry_after
+                                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                                .map(Duration::from_secs_f64)
+                                .unwrap_or(DEFAULT_SLOW_DOWN_DELAY);
+                            warn!("oracle asked to slow down, pausing submissions for {:?}", delay);
+                            let dropped = state.answered_key(input_hash.as_deref(), id);
+                            state.slow_down(delay, dropped);
+                            continue;
+                        }
+                        WebsocketClientboundMessage::Hello { max_bytes_in_flight, protocols, compression, credits_remaining } => {
+                            state.apply_hello(settings, max_bytes_in_flight, &protocols, &compression);
+                            state.update_quota(credits_remaining);
+                            state.drain_queue(&mut write).await?;
+                            continue;
+                        }
+                        WebsocketClientboundMessage::Quota { credits_remaining } => {
+                            state.update_quota(credits_remaining);
+                            continue;
+                        }
+                    };
 
-                    let Some((requests, byte_size)) = pending_requests.remove(&input_hash) else { continue; };
+                    let answered = state.answered_key(Some(&input_hash), id).unwrap_or_default();
+                    let Some(pending) = state.take_pending(&answered) else {
+                        state.update_quota(credits_remaining);
+                        continue;
+                    };
 
-                    bytes_in_flight.fetch_sub(byte_size, Ordering::Relaxed);
+                    state.made_progress = true;
+                    state.metrics.on_answer(&input_hash, pending.sent_at.elapsed(), success);
+                    if let Some(window) = &mut state.window {
+                        window.on_answer(pending.byte_size, pending.sent_at.elapsed());
+                    }
+                    if let Some(autotune) = &mut state.autotune {
+                        autotune.on_answer(pending.byte_size, pending.sent_at.elapsed());
+                    }
 
                     let result = if success {
                         Ok(data)
                     } else {
-                        Err(data)
+                        Err(DecompileError::from_oracle(data))
                     };
-
-                    for request in requests {
-                        // If the receiver was dropped, just ignore it
-                        let _ = request.tx.send(result.clone());
+                    if let Err(e) = &result {
+                        // another key may still have credits, so the script goes again on that
+                        if e.category == FailureCategory::Quota && key.has_spare(&settings.auth_token).await {
+                            let reason = format!("out of quota: {}", e);
+                            if let Some(id) = pending.id {
+                                state.pending_ids.insert(id, answered.clone());
+                            }
+                            state.bytes_in_flight += pending.byte_size;
+                            state.pending_requests.insert(answered, pending);
+                            return Err(Error::Auth(reason));
+                        }
                     }
-
-                    // try to send queued requests now that we have space
-                    let mut remaining_queue = Vec::with_capacity(queued_requests.len());
-                    while let Some(queued_request) = queued_requests.pop() {
-                        let current_bytes = bytes_in_flight.load(Ordering::Relaxed);
-
-                        if current_bytes + queued_request.bytecode_len > MAX_BYTES_IN_FLIGHT {
-                            remaining_queue.push(queued_request);
-                            continue;
+                    // a failure isn't retried once everyone who asked for it cancelled,
+                    // and submit_request leaves out the ones who did from a retry
+                    let wanted = pending.requests.iter().any(|request| !request.is_cancelled());
+                    let retry = match &result {
+                        Err(e) if pending.attempt < settings.max_retries && wanted => {
+                            e.category.retryable().then_some(e)
                         }
+                        _ => None,
+                    };
+                    if let Some(e) = retry {
+                        let delay = retry_delay(pending.attempt).saturating_mul(e.category.backoff_factor());
+                        warn!(
+                            "decompilation failed ({}), retrying in {:?} ({}/{}): {}",
+                            e.category, delay, pending.attempt + 1, settings.max_retries, e
+                        );
 
-                        let message = serde_json::to_string(&WebsocketServerboundMessage::Decompile {
-                            data: vec![queued_request.bytecode.to_string()]
-                        }).unwrap();
-
-                        if let Err(e) = write.send(Message::Text(message.into())).await {
-                            eprintln!("error: failed to send websocket message (connection lost): {}", e);
-                            std::process::exit(1);
+                        state.retrying += 1;
+                        let retry_tx = state.retry_tx.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            let _ = retry_tx.send(pending.requests);
+                        });
+                    } else {
+                        for request in pending.requests {
+                            // whoever asked may have given up on it, that's fine
+                            if request.tx.send(result.clone()).is_err() {
+                                debug!("nobody is waiting on {} anymore, dropping its result", input_hash);
+                            }
                         }
+                    }
+                    // once it's answered, so this one doesn't count as still waiting
+                    state.update_quota(credits_remaining);
 
-                        bytes_in_flight.fetch_add(queued_request.bytecode_len, Ordering::Relaxed);
-                        let bytecode_len = queued_request.bytecode_len;
-                        
-                        // Check if there's already a pending request for this hash (from duplicate)
-                        if let Some((existing_requests, _)) = pending_requests.get_mut(&queued_request.bytecode_hash) {
-                            existing_requests.push(queued_request);
-                        } else {
-                            pending_requests.insert(
-                                queued_request.bytecode_hash.clone(),
-                                (vec![queued_request], bytecode_len)
-                            );
-                        }
+                    state.drain_queue(&mut write).await?;
+                }
+                _ = tokio::time::sleep_until(state.paused_until.unwrap_or_else(Instant::now)), if state.paused_until.is_some() => {
+                    state.drain_queue(&mut write).await?;
+                }
+                retry = state.retry_rx.recv() => {
+                    let Some(requests) = retry else { continue; };
+                    state.retrying -= 1;
+
+                    for mut request in requests {
+                        request.attempt += 1;
+                        state.submit_request(&mut write, request).await?;
                     }
-                    queued_requests = remaining_queue;
                 }
-                decompile_request = decompile_rx.recv() => {
+                decompile_request = decompile_rx.recv(), if !state.decompile_closed => {
                     let Some(request) = decompile_request else {
-                        // channel closed, check if we can exit
-                        if pending_requests.is_empty() && queued_requests.is_empty() {
-                            break;
-                        }
+                        state.decompile_closed = true;
                         continue;
                     };
 
-                    // check if there's already a pending request for this script hash
-                    if let Some((existing_requests, _)) = pending_requests.get_mut(&request.bytecode_hash) {
-                        existing_requests.push(request);
-                        continue;
-                    }
+                    state.submit_request(&mut write, request).await?;
+                }
+            }
+        }
+    }
 
-                    // check if single request exceeds limit
-                    if request.bytecode_len > MAX_BYTES_IN_FLIGHT {
-                        request.tx.send(Err(format!("bytecode too large ({:.2} mb) exceeds 8mb limit",
-                            request.bytecode_len as f64 / 1024.0 / 1024.0))).unwrap();
-                        continue;
-                    }
+    fn send(&self, request: DecompilationRequest) -> Result<()> {
+        self.dispatch(request, true)
+    }
 
-                    let current_bytes = bytes_in_flight.load(Ordering::Relaxed);
+    /// Sends `request` on, through `--post-process-cmd` and the retry
+    /// profiles only if it's for a `decompilation`. A disassembly is neither
+    /// source to rewrite nor something other decompiler options would help.
+    fn dispatch(&self, mut request: DecompilationRequest, decompilation: bool) -> Result<()> {
+        request.outstanding = Some(self.quota.track());
+        let retry = self.retry.clone().filter(|_| decompilation);
+        if let (Some(hook), true) = (&self.post_process, decompilation) {
+            request = post_process(hook, request);
+        }
+        let Some(hook) = &self.pre_process else {
+            if let Some(retry) = &retry {
+                request = with_retry_profiles(retry, &self.connections, request);
+            }
+            return self.connections.send(request);
+        };
+        if self.connections.is_closed() {
+            return Err(Error::Connection("decompiler connection is closed".to_string()));
+        }
 
-                    if current_bytes + request.bytecode_len > MAX_BYTES_IN_FLIGHT {
-                        queued_requests.push(request);
-                        continue;
+        let hook = hook.clone();
+        let connections = self.connections.clone();
+        tokio::spawn(async move {
+            match pre_process(&hook, request).await {
+                Ok(mut request) => {
+                    if let Some(retry) = &retry {
+                        request = with_retry_profiles(retry, &connections, request);
                     }
+                    // a closed connection drops the request, and with it `tx`
+                    let _ = connections.send(request);
+                }
+                Err((request, e)) => {
+                    let _ = request.tx.send(Err(e.into()));
+                }
+            }
+        });
+        Ok(())
+    }
 
-                    let message = serde_json::to_string(&WebsocketServerboundMessage::Decompile {
-                        data: vec![request.bytecode.to_string()]
-                    }).unwrap();
+    /// Credits the oracle last said the key has left, if it ever did
+    pub fn credits_remaining(&self) -> Option<u64> {
+        self.quota.remaining()
+    }
 
-                    if let Err(e) = write.send(Message::Text(message.into())).await {
-                        eprintln!("error: failed to send websocket message (connection lost): {}", e);
-                        std::process::exit(1);
-                    }
+    /// How many requests the cache answered instead of the oracle
+    pub fn cache_hits(&self) -> u64 {
+        self.connections.cache.as_ref().map_or(0, |cache| cache.hits())
+    }
 
-                    bytes_in_flight.fetch_add(request.bytecode_len, Ordering::Relaxed);
-                    let bytecode_len = request.bytecode_len;
-                    pending_requests.insert(request.bytecode_hash.clone(), (vec![request], bytecode_len));
-                }
-            }
-        }
+    /// How the connections are doing, in Prometheus' text format
+    pub fn metrics(&self) -> String {
+        self.metrics.render(self.credits_remaining())
     }
 
-    pub async fn decompile_batch(
-        &self,
-        requests: Vec<DecompilationRequest>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    /// How quickly the oracle answered everything so far
+    pub fn latencies(&self) -> Latencies {
+        self.metrics.latencies()
+    }
+
+    /// The oracle url the first connection was made to
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The oldest protocol version any of the connections speaks, once
+    /// they've settled on one
+    pub fn protocol(&self) -> u32 {
+        self.protocols
+            .iter()
+            .map(|protocol| protocol.load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(DEFAULT_PROTOCOL)
+    }
+
+    /// Whether the oracle can be asked for disassemblies
+    pub fn disassembles(&self) -> bool {
+        self.protocol() >= DISASSEMBLY_PROTOCOL
+    }
+
+    /// Asks the oracle for a disassembly of `bytecode`, see `disassembles`
+    pub fn try_disassemble_with_hash(&self, bytecode: Arc<str>, bytecode_hash: String) -> Result<oneshot::Receiver<DecompileResult>> {
+        let (tx, rx) = oneshot::channel();
+        let options = Arc::new(serde_json::json!({ "disassemble": true }));
+        let request = DecompilationRequest::with_hash(bytecode, bytecode_hash, tx).with_options(Some(options));
+        self.dispatch(request, false)?;
+        Ok(rx)
+    }
+
+    pub async fn decompile_batch(&self, requests: Vec<DecompilationRequest>) -> Result<()> {
         for request in requests {
-            self.decompile_tx.send(request)?;
+            self.send(request)?;
         }
         Ok(())
     }
 
-    pub async fn decompile_single(
+    /// Sends `bytecode` off right away and returns a future for its result,
+    /// which doesn't borrow the decompiler and can be awaited from anywhere
+    pub fn try_decompile(
         &self,
         bytecode: &str,
-    ) -> Result<Result<String, String>, Box<dyn std::error::Error>> {
+    ) -> Result<impl Future<Output = Result<DecompileResult>> + Send + 'static> {
+        self.try_decompile_with_hash(Arc::from(bytecode), hash_bytecode(bytecode))
+    }
+
+    /// Like `try_decompile`, for callers that already hashed the bytecode
+    pub fn try_decompile_with_hash(
+        &self,
+        bytecode: Arc<str>,
+        bytecode_hash: String,
+    ) -> Result<impl Future<Output = Result<DecompileResult>> + Send + 'static> {
         let (tx, rx) = oneshot::channel();
-        let bytecode_hash = format!("{:x}", Sha256::digest(bytecode.as_bytes()));
-        let bytecode_len = bytecode.len() as u32;
+        let request = DecompilationRequest::with_hash(bytecode, bytecode_hash, tx).with_priority(INTERACTIVE_PRIORITY);
+        self.send(request)?;
 
-        let request = DecompilationRequest {
-            bytecode: Arc::from(bytecode),
-            bytecode_hash,
-            bytecode_len,
-            tx,
+        Ok(async move {
+            rx.await
+                .map_err(|_| Error::Connection("decompiler connection is closed".to_string()))
+        })
+    }
+
+    pub async fn decompile_single(&self, bytecode: &str) -> Result<DecompileResult> {
+        self.try_decompile(bytecode)?.await
+    }
+
+    /// Waits for every connection to finish its outstanding work, and returns
+    /// the first error any of them ran into. Only the last clone to shut down
+    /// waits, the others just let go of the connections.
+    pub async fn shutdown(self) -> Result<()> {
+        let Ok(connections) = Arc::try_unwrap(self.connections) else {
+            return Ok(());
         };
+        drop(connections.decompile_txs);
+
+        let mut result = Ok(());
+        for handle in connections.websocket_handles {
+            let connection_result = handle.await?;
+            if result.is_ok() {
+                result = connection_result;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+
+    /// A connection whose frames end up in the returned receiver
+    fn connection() -> (ConnectionWrite, UnboundedReceiver<Message>) {
+        let (tx, rx) = unbounded();
+        (Box::pin(tx.sink_map_err(|_| TungsteniteError::ConnectionClosed)), rx)
+    }
+
+    /// How many frames went out since the last time this was asked
+    fn sent(frames: &mut UnboundedReceiver<Message>) -> usize {
+        std::iter::from_fn(|| frames.try_next().ok().flatten()).count()
+    }
+
+    fn state(max_bytes_in_flight: u32) -> ConnectionState {
+        let settings = DecompilerSettings {
+            max_bytes_in_flight: Some(max_bytes_in_flight),
+            fixed_window: true,
+            ..Default::default()
+        };
+        ConnectionState::new(
+            &settings,
+            None,
+            Arc::new(Quota::new()),
+            None,
+            Arc::new(AtomicU32::new(DEFAULT_PROTOCOL)),
+            Arc::new(Metrics::new(1)),
+            0,
+        )
+    }
+
+    fn request(bytecode: &str) -> (DecompilationRequest, oneshot::Receiver<DecompileResult>) {
+        let (tx, rx) = oneshot::channel();
+        (DecompilationRequest::new(Arc::from(bytecode), tx), rx)
+    }
+
+    #[tokio::test]
+    async fn coalesced_requests_go_out_again_once() {
+        let (mut write, mut frames) = connection();
+        let mut state = state(1024);
+        let (first, _first) = request("AAAA");
+        let (second, _second) = request("AAAA");
+        let key = state.pending_key(&first);
+        state.submit_request(&mut write, first).await.unwrap();
+        state.submit_request(&mut write, second).await.unwrap();
+        assert_eq!(sent(&mut frames), 1);
+
+        // the connection is lost and made again
+        state.requeue_pending();
+        state.drain_queue(&mut write).await.unwrap();
+        assert_eq!(sent(&mut frames), 1);
+        assert_eq!(state.bytes_in_flight, 4);
+
+        // the server drops it
+        state.slow_down(Duration::ZERO, Some(key.clone()));
+        state.drain_queue(&mut write).await.unwrap();
+        assert_eq!(sent(&mut frames), 1);
+        assert_eq!(state.bytes_in_flight, 4);
+
+        let answered = state.take_pending(&key).unwrap();
+        assert_eq!(answered.requests.len(), 2);
+        assert_eq!(state.bytes_in_flight, 0);
+        assert!(state.is_idle());
+    }
+
+    #[tokio::test]
+    async fn retries_waiting_for_a_full_window_go_out_once() {
+        let (mut write, mut frames) = connection();
+        let mut state = state(4);
+        let (filling, _filling) = request("AAAA");
+        let filling_key = state.pending_key(&filling);
+        state.submit_request(&mut write, filling).await.unwrap();
+        assert_eq!(sent(&mut frames), 1);
+
+        // two failed copies of the same script come back from their backoff
+        let (first, _first) = request("BBBB");
+        let (second, _second) = request("BBBB");
+        let key = state.pending_key(&first);
+        for mut retried in [first, second] {
+            retried.attempt += 1;
+            state.submit_request(&mut write, retried).await.unwrap();
+        }
+        assert_eq!(sent(&mut frames), 0);
+
+        state.take_pending(&filling_key).unwrap();
+        state.drain_queue(&mut write).await.unwrap();
+        assert_eq!(sent(&mut frames), 1);
+        assert_eq!(state.bytes_in_flight, 4);
 
-        self.decompile_tx.send(request)?;
-        let result = rx.await?;
-        Ok(result)
+        let answered = state.take_pending(&key).unwrap();
+        assert_eq!(answered.requests.len(), 2);
+        assert_eq!(state.bytes_in_flight, 0);
+        assert!(state.is_idle());
     }
 }
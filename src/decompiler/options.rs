@@ -1 +1,68 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::error::{Error, Result};
+
 pub type DecompileOptions = serde_json::Value;
+
+/// What `--preset` can name without a `[presets]` entry in the config
+const BUILTIN_PRESETS: &[&str] = &["readable", "faithful", "minimal-renaming"];
+
+/// The oracle's v1 options each built-in preset stands for
+fn builtin_preset(name: &str) -> Option<DecompileOptions> {
+    Some(match name {
+        // names things after what they hold and tidies control flow up, for reading
+        "readable" => json!({
+            "rename_locals": true,
+            "rename_upvalues": true,
+            "inline_locals": true,
+            "simplify_control_flow": true,
+            "line_info": false,
+        }),
+        // stays as close to the bytecode as it can, for checking what it does
+        "faithful" => json!({
+            "rename_locals": false,
+            "rename_upvalues": false,
+            "inline_locals": false,
+            "simplify_control_flow": false,
+            "line_info": true,
+        }),
+        // tidied up like readable, but with the bytecode's own names, so
+        // decompilations of two versions of a script diff cleanly
+        "minimal-renaming" => json!({
+            "rename_locals": false,
+            "rename_upvalues": false,
+            "inline_locals": true,
+            "simplify_control_flow": true,
+            "line_info": false,
+        }),
+        _ => return None,
+    })
+}
+
+/// The options preset `name` stands for. The config's `[presets]` win over
+/// the built-in ones of the same name.
+pub fn preset(name: &str, presets: &HashMap<String, DecompileOptions>) -> Result<DecompileOptions> {
+    if let Some(options) = presets.get(name) {
+        return Ok(options.clone());
+    }
+    builtin_preset(name).ok_or_else(|| {
+        let mut names: Vec<&str> = presets.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names.extend(BUILTIN_PRESETS.iter().filter(|builtin| !presets.contains_key(**builtin)));
+        Error::Config(format!("no preset called {}, pick one of {}", name, names.join(", ")))
+    })
+}
+
+/// `over`, with whatever it leaves out taken from `base`
+pub fn layered(base: &DecompileOptions, over: &DecompileOptions) -> DecompileOptions {
+    match (base, over) {
+        (DecompileOptions::Object(base), DecompileOptions::Object(over)) => {
+            let mut options = base.clone();
+            options.extend(over.iter().map(|(key, value)| (key.clone(), value.clone())));
+            DecompileOptions::Object(options)
+        }
+        _ => over.clone(),
+    }
+}
@@ -0,0 +1,69 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use crate::decompiler::DecompilationRequest;
+
+/// Orders the queue, the greatest key goes first
+type QueueKey = (i32, u32, Reverse<u64>);
+
+/// Requests waiting for room in the in-flight window. Higher priorities go
+/// first, then bigger scripts, since the oracle takes longest over those and
+/// they'd otherwise hold up the end of a run, then whichever was queued earlier.
+#[derive(Default)]
+pub(super) struct RequestQueue {
+    requests: BTreeMap<QueueKey, DecompilationRequest>,
+    next_sequence: u64,
+}
+
+impl RequestQueue {
+    pub fn push(&mut self, request: DecompilationRequest) {
+        let key = (request.priority, request.bytecode_len, Reverse(self.next_sequence));
+        self.requests.insert(key, request);
+        self.next_sequence += 1;
+    }
+
+    pub fn peek(&self) -> Option<&DecompilationRequest> {
+        self.requests.last_key_value().map(|(_, request)| request)
+    }
+
+    pub fn pop(&mut self) -> Option<DecompilationRequest> {
+        self.requests.pop_last().map(|(_, request)| request)
+    }
+
+    /// The key of the biggest request no bigger than `room`, among those
+    /// with the same priority as the next one
+    fn fitting(&self, room: u32) -> Option<QueueKey> {
+        let (&(priority, _, _), _) = self.requests.last_key_value()?;
+        self.requests
+            .range((priority, 0, Reverse(u64::MAX))..=(priority, room, Reverse(0)))
+            .next_back()
+            .map(|(key, _)| *key)
+    }
+
+    /// What `pop_fitting` would take
+    pub fn peek_fitting(&self, room: u32) -> Option<&DecompilationRequest> {
+        self.fitting(room).and_then(|key| self.requests.get(&key))
+    }
+
+    /// Takes the biggest request that fits in `room`, to fill the window
+    /// while the next one is too big for what's left of it
+    pub fn pop_fitting(&mut self, room: u32) -> Option<DecompilationRequest> {
+        self.fitting(room).and_then(|key| self.requests.remove(&key))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = DecompilationRequest> + '_ {
+        std::mem::take(&mut self.requests).into_values()
+    }
+}
+
+impl Extend<DecompilationRequest> for RequestQueue {
+    fn extend<I: IntoIterator<Item = DecompilationRequest>>(&mut self, requests: I) {
+        for request in requests {
+            self.push(request);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tracing::warn;
+
+/// Stands in for "the server never said"
+const UNKNOWN: u64 = u64::MAX;
+
+/// What the oracle last said about the credits left on the key, next to
+/// how many scripts are still waiting on it, shared by every connection
+pub(super) struct Quota {
+    remaining: AtomicU64,
+    outstanding: Arc<AtomicUsize>,
+    warned: AtomicBool,
+}
+
+/// Counts a script as waiting on the oracle for as long as it's kept alive.
+/// It lives in the request, so it's gone once the request is answered.
+pub(super) struct Outstanding(Arc<AtomicUsize>);
+
+impl Drop for Outstanding {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Quota {
+    pub fn new() -> Self {
+        Self {
+            remaining: AtomicU64::new(UNKNOWN),
+            outstanding: Arc::new(AtomicUsize::new(0)),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn track(&self) -> Outstanding {
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+        Outstanding(self.outstanding.clone())
+    }
+
+    pub fn remaining(&self) -> Option<u64> {
+        Some(self.remaining.load(Ordering::Relaxed)).filter(|remaining| *remaining != UNKNOWN)
+    }
+
+    /// Takes in what the server said, warning the first time there are
+    /// more scripts left to decompile than credits to pay for them
+    pub fn update(&self, credits_remaining: f64) {
+        if !credits_remaining.is_finite() {
+            return;
+        }
+        let remaining = credits_remaining.max(0.0) as u64;
+        self.remaining.store(remaining, Ordering::Relaxed);
+
+        let outstanding = self.outstanding.load(Ordering::Relaxed) as u64;
+        if outstanding > remaining && !self.warned.swap(true, Ordering::Relaxed) {
+            warn!(
+                "the oracle says the key has {} credits left, but {} scripts are still waiting to be decompiled, \
+                 the run will likely run out before it's done",
+                remaining, outstanding
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+use crate::decompiler::failure::{DecompileError, FailureCategory};
+use crate::decompiler::options::DecompileOptions;
+use crate::decompiler::{intercepted, DecompilationRequest, DecompileResult, CANCELLED};
+use crate::error::Result;
+
+/// One request and what the oracle answered, a line of JSON in the recording
+#[derive(Serialize, Deserialize)]
+struct Exchange {
+    hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    options: Option<DecompileOptions>,
+    success: bool,
+    /// The decompiled source, or why there isn't one
+    data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<FailureCategory>,
+}
+
+/// Requests are told apart by their bytecode and the options they went with
+fn exchange_key(hash: &str, options: Option<&DecompileOptions>) -> (String, String) {
+    (hash.to_string(), options.map(DecompileOptions::to_string).unwrap_or_default())
+}
+
+/// Writes every answer the oracle gives to `--record`'s file
+pub struct Recorder {
+    file: Mutex<File>,
+    recorded: Mutex<HashSet<(String, String)>>,
+}
+
+impl Recorder {
+    /// Adds to the recording at `path`, starting one if there isn't one yet
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
+            recorded: Mutex::new(HashSet::new()),
+        })
+    }
+
+    fn record(&self, hash: &str, options: Option<&DecompileOptions>, result: &DecompileResult) {
+        // only what the oracle said is worth replaying
+        if let Err(e) = result {
+            if e.category == FailureCategory::Unreachable || e.message == CANCELLED {
+                return;
+            }
+        }
+        if !self.recorded.lock().unwrap().insert(exchange_key(hash, options)) {
+            return;
+        }
+        let exchange = Exchange {
+            hash: hash.to_string(),
+            options: options.cloned(),
+            success: result.is_ok(),
+            data: match result {
+                Ok(source) => source.clone(),
+                Err(e) => e.message.clone(),
+            },
+            category: result.as_ref().err().map(|e| e.category),
+        };
+        let mut line = serde_json::to_string(&exchange).unwrap();
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            warn!("failed to record the answer for {}: {}", hash, e);
+        }
+    }
+}
+
+/// Puts `recorder` between the connection and whoever is waiting on `request`,
+/// so the answer is recorded on its way to them. `options` is what the
+/// request goes out with, its own or the connection's.
+pub(super) fn recorded(
+    recorder: &Arc<Recorder>,
+    mut request: DecompilationRequest,
+    options: Option<Arc<DecompileOptions>>,
+) -> DecompilationRequest {
+    let (tx, rx) = oneshot::channel();
+    let mut waiting = std::mem::replace(&mut request.tx, tx);
+    let recorder = recorder.clone();
+    let hash = request.bytecode_hash.clone();
+    let cancel = request.cancel_handle();
+    tokio::spawn(async move {
+        let Some(result) = intercepted(rx, &mut waiting, &cancel).await else {
+            return;
+        };
+        recorder.record(&hash, options.as_deref(), &result);
+        let _ = waiting.send(result);
+    });
+    request
+}
+
+/// What `--replay` answers requests from instead of the oracle
+pub struct Recording {
+    exchanges: HashMap<(String, String), DecompileResult>,
+}
+
+impl Recording {
+    /// Reads back what a `Recorder` wrote. A line cut off halfway is skipped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut exchanges = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let Ok(exchange) = serde_json::from_str::<Exchange>(&line?) else {
+                continue;
+            };
+            let result = if exchange.success {
+                Ok(exchange.data)
+            } else {
+                Err(DecompileError::new(exchange.category.unwrap_or(FailureCategory::Other), exchange.data))
+            };
+            exchanges.insert(exchange_key(&exchange.hash, exchange.options.as_ref()), result);
+        }
+        Ok(Self { exchanges })
+    }
+
+    /// How many answers there are to replay
+    pub fn answers(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    /// What the oracle answered this request with when it was recorded
+    pub(super) fn answer(&self, hash: &str, options: Option<&DecompileOptions>) -> DecompileResult {
+        self.exchanges
+            .get(&exchange_key(hash, options))
+            .cloned()
+            .unwrap_or_else(|| Err("not in the recording".to_string().into()))
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::{Arc, Weak};
+
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+
+use crate::decompiler::options::{layered, DecompileOptions};
+use crate::decompiler::{intercepted, Connections, DecompilationRequest, DecompileResult};
+use crate::luau::check_syntax;
+
+/// The config's `retry_profiles`, tried in order on a script the oracle
+/// failed on or whose decompilation doesn't parse, before it's given up on
+#[derive(Clone)]
+pub(super) struct RetryProfiles {
+    profiles: Arc<[DecompileOptions]>,
+    /// `--decompiler-options`, which each profile is laid over
+    defaults: Option<Arc<DecompileOptions>>,
+}
+
+impl RetryProfiles {
+    pub fn new(profiles: &[DecompileOptions], defaults: Option<&DecompileOptions>) -> Option<Self> {
+        if profiles.is_empty() {
+            return None;
+        }
+        Some(Self {
+            profiles: profiles.into(),
+            defaults: defaults.cloned().map(Arc::new),
+        })
+    }
+
+    /// The options the request was going to use, with `profile`'s taking their place
+    fn options_for(&self, request_options: Option<&DecompileOptions>, profile: &DecompileOptions) -> DecompileOptions {
+        match request_options.or(self.defaults.as_deref()) {
+            Some(base) => layered(base, profile),
+            None => profile.clone(),
+        }
+    }
+}
+
+/// What's wrong with a result, if it's worth retrying. Other options won't
+/// help with a failure that isn't `retryable`, like an unsupported version.
+async fn problem_with(result: &DecompileResult) -> Option<String> {
+    match result {
+        Err(e) if e.category.retryable() => Some(e.to_string()),
+        Err(_) => None,
+        Ok(source) => {
+            let source = source.clone();
+            match tokio::task::spawn_blocking(move || check_syntax(&source)).await {
+                Ok(Err(e)) => Some(format!("doesn't parse: {}", e)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Puts the retry profiles between the connection and whoever is waiting on
+/// `request`, so a failed result is asked for again with each profile before
+/// they see it. Goes right in front of the connection, after any pre-processing,
+/// so retries send the same bytecode.
+pub(super) fn with_retry_profiles(
+    retry: &RetryProfiles,
+    connections: &Arc<Connections>,
+    mut request: DecompilationRequest,
+) -> DecompilationRequest {
+    let (tx, rx) = oneshot::channel();
+    let mut waiting = std::mem::replace(&mut request.tx, tx);
+    let cancel = request.cancel_handle();
+    let retry = retry.clone();
+    // a strong reference would keep the connections open past `shutdown`
+    let connections: Weak<Connections> = Arc::downgrade(connections);
+    let bytecode = request.bytecode.clone();
+    let hash = request.bytecode_hash.clone();
+    let priority = request.priority;
+    let request_options = request.options.clone();
+
+    tokio::spawn(async move {
+        let Some(mut result) = intercepted(rx, &mut waiting, &cancel).await else {
+            return;
+        };
+        let mut problem = problem_with(&result).await;
+        for (i, profile) in retry.profiles.iter().enumerate() {
+            let Some(reason) = problem.take() else {
+                break;
+            };
+            if cancel.is_cancelled() {
+                return;
+            }
+            debug!("{}: {}, retrying with retry profile {}", hash, reason, i + 1);
+
+            let options = retry.options_for(request_options.as_deref(), profile);
+            let (tx, rx) = oneshot::channel();
+            let mut request = DecompilationRequest::with_hash(bytecode.clone(), hash.clone(), tx)
+                .with_priority(priority)
+                .with_options(Some(Arc::new(options)));
+            request.cancel = cancel.clone();
+            let Some(live) = connections.upgrade() else {
+                break;
+            };
+            let sent = live.send(request);
+            drop(live);
+            if sent.is_err() {
+                break;
+            }
+            let Some(retried) = intercepted(rx, &mut waiting, &cancel).await else {
+                break;
+            };
+
+            problem = problem_with(&retried).await;
+            if problem.is_none() {
+                info!("{} decompiled with retry profile {}", hash, i + 1);
+                result = retried;
+                break;
+            }
+            // a decompilation that doesn't parse still beats an error
+            if retried.is_ok() || result.is_err() {
+                result = retried;
+            }
+        }
+        let _ = waiting.send(result);
+    });
+    request
+}
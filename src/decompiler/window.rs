@@ -0,0 +1,97 @@
+use tokio::time::{Duration, Instant};
+use tracing::debug;
+
+/// Where the window starts, before anything is known about the server
+const INITIAL_WINDOW: u32 = 1024 * 1024;
+/// The window never shrinks below this
+const MIN_WINDOW: u32 = 256 * 1024;
+/// Scripts smaller than this take about as long as this, so latency is
+/// compared per byte of at least this much bytecode
+const MIN_SAMPLE_BYTES: u32 = 64 * 1024;
+/// How much slower than the fastest answer seen answers may get before the
+/// server is taken to be queueing them
+const QUEUEING_FACTOR: f64 = 2.0;
+/// Weight of each new answer in the smoothed latency
+const SMOOTHING: f64 = 0.125;
+
+/// How much bytecode a connection keeps in flight, adjusted as answers come
+/// back: doubling every round trip at first, then growing by `MIN_WINDOW`
+/// per window answered while latency holds, and halving when answers slow
+/// down or the server asks to. `limit` from the user or server is never passed.
+pub(super) struct AdaptiveWindow {
+    size: u32,
+    limit: u32,
+    /// Until the first sign of congestion
+    slow_start: bool,
+    /// Seconds per byte of the fastest answer seen
+    min_latency: Option<f64>,
+    smoothed_latency: Option<f64>,
+    /// Smoothed time an answer takes, so one slowdown only halves the window once
+    round_trip: Duration,
+    last_decrease: Option<Instant>,
+}
+
+impl AdaptiveWindow {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            size: INITIAL_WINDOW.min(limit),
+            limit,
+            slow_start: true,
+            min_latency: None,
+            smoothed_latency: None,
+            round_trip: Duration::ZERO,
+            last_decrease: None,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn set_limit(&mut self, limit: u32) {
+        self.limit = limit;
+        self.size = self.size.min(limit);
+    }
+
+    /// Counts an answer to `bytes` of bytecode sent `latency` ago
+    pub fn on_answer(&mut self, bytes: u32, latency: Duration) {
+        let per_byte = latency.as_secs_f64() / bytes.max(MIN_SAMPLE_BYTES) as f64;
+        let min_latency = self.min_latency.map_or(per_byte, |min| min.min(per_byte));
+        let smoothed = self
+            .smoothed_latency
+            .map_or(per_byte, |smoothed| smoothed + SMOOTHING * (per_byte - smoothed));
+        self.min_latency = Some(min_latency);
+        self.smoothed_latency = Some(smoothed);
+        self.round_trip = if self.round_trip.is_zero() {
+            latency
+        } else {
+            self.round_trip.mul_f64(1.0 - SMOOTHING) + latency.mul_f64(SMOOTHING)
+        };
+
+        if smoothed > min_latency * QUEUEING_FACTOR {
+            self.on_congestion();
+            return;
+        }
+
+        let growth = if self.slow_start {
+            bytes as u64
+        } else {
+            (MIN_WINDOW as u64 * bytes as u64 / self.size.max(1) as u64).max(1)
+        };
+        self.size = (self.size as u64 + growth).min(self.limit as u64) as u32;
+    }
+
+    /// Halves the window, at most once per round trip
+    pub fn on_congestion(&mut self) {
+        self.slow_start = false;
+        if self
+            .last_decrease
+            .is_some_and(|last| last.elapsed() < self.round_trip)
+        {
+            return;
+        }
+        self.last_decrease = Some(Instant::now());
+        self.size = (self.size / 2).max(MIN_WINDOW).min(self.limit);
+        debug!("in-flight window down to {:.2} MiB", self.size as f64 / 1024.0 / 1024.0);
+    }
+}
@@ -0,0 +1,42 @@
+/// Luau's `--!` comment directives, which only count at the top of a file
+const DIRECTIVES: &[&str] = &["strict", "nonstrict", "nocheck", "native", "optimize", "nolint"];
+
+/// The directive on `line`, if it is one
+fn directive(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let name = line.strip_prefix("--!")?;
+    let name = name.split_whitespace().next()?;
+    DIRECTIVES.contains(&name).then_some(line)
+}
+
+/// Moves the `--!strict`, `--!native`, ... directives in the comments a
+/// script starts with up to its first lines, where Luau looks for them.
+/// The oracle echoes them after its own header, and the headers written in
+/// front of a decompilation push them down further. Anything after the
+/// first line of code is left alone.
+pub fn hoist_directives(contents: &str) -> String {
+    let mut directives: Vec<&str> = Vec::new();
+    let mut rest = String::with_capacity(contents.len());
+    let mut in_header = true;
+    for line in contents.split_inclusive('\n') {
+        if in_header {
+            let trimmed = line.trim();
+            if let Some(found) = directive(trimmed) {
+                if !directives.contains(&found) {
+                    directives.push(found);
+                }
+                continue;
+            }
+            in_header = trimmed.is_empty() || trimmed.starts_with("--");
+        }
+        rest.push_str(line);
+    }
+
+    if directives.is_empty() {
+        return rest;
+    }
+    let mut hoisted = directives.join("\n");
+    hoisted.push('\n');
+    hoisted.push_str(&rest);
+    hoisted
+}
@@ -0,0 +1,825 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::{engine::general_purpose, Engine as _};
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+use crate::decompiler::{DecompileError, DecompileResult, Decompiler};
+
+/// `(name, has an aux word)`, indexed by opcode
+const OPCODES: &[(&str, bool)] = &[
+    ("NOP", false),
+    ("BREAK", false),
+    ("LOADNIL", false),
+    ("LOADB", false),
+    ("LOADN", false),
+    ("LOADK", false),
+    ("MOVE", false),
+    ("GETGLOBAL", true),
+    ("SETGLOBAL", true),
+    ("GETUPVAL", false),
+    ("SETUPVAL", false),
+    ("CLOSEUPVALS", false),
+    ("GETIMPORT", true),
+    ("GETTABLE", false),
+    ("SETTABLE", false),
+    ("GETTABLEKS", true),
+    ("SETTABLEKS", true),
+    ("GETTABLEN", false),
+    ("SETTABLEN", false),
+    ("NEWCLOSURE", false),
+    ("NAMECALL", true),
+    ("CALL", false),
+    ("RETURN", false),
+    ("JUMP", false),
+    ("JUMPBACK", false),
+    ("JUMPIF", false),
+    ("JUMPIFNOT", false),
+    ("JUMPIFEQ", true),
+    ("JUMPIFLE", true),
+    ("JUMPIFLT", true),
+    ("JUMPIFNOTEQ", true),
+    ("JUMPIFNOTLE", true),
+    ("JUMPIFNOTLT", true),
+    ("ADD", false),
+    ("SUB", false),
+    ("MUL", false),
+    ("DIV", false),
+    ("MOD", false),
+    ("POW", false),
+    ("ADDK", false),
+    ("SUBK", false),
+    ("MULK", false),
+    ("DIVK", false),
+    ("MODK", false),
+    ("POWK", false),
+    ("AND", false),
+    ("OR", false),
+    ("ANDK", false),
+    ("ORK", false),
+    ("CONCAT", false),
+    ("NOT", false),
+    ("MINUS", false),
+    ("LENGTH", false),
+    ("NEWTABLE", true),
+    ("DUPTABLE", false),
+    ("SETLIST", true),
+    ("FORNPREP", false),
+    ("FORNLOOP", false),
+    ("FORGLOOP", true),
+    ("FORGPREP_INEXT", false),
+    ("FASTCALL3", true),
+    ("FORGPREP_NEXT", false),
+    ("NATIVECALL", false),
+    ("GETVARARGS", false),
+    ("DUPCLOSURE", false),
+    ("PREPVARARGS", false),
+    ("LOADKX", true),
+    ("JUMPX", false),
+    ("FASTCALL", false),
+    ("COVERAGE", false),
+    ("CAPTURE", false),
+    ("SUBRK", false),
+    ("DIVRK", false),
+    ("FASTCALL1", false),
+    ("FASTCALL2", true),
+    ("FASTCALL2K", true),
+    ("FORGPREP", false),
+    ("JUMPXEQKNIL", true),
+    ("JUMPXEQKB", true),
+    ("JUMPXEQKN", true),
+    ("JUMPXEQKS", true),
+    ("IDIV", false),
+    ("IDIVK", false),
+];
+
+const OP_PREPVARARGS: u8 = 65;
+
+/// Roblox ships bytecode with every opcode multiplied by 227 (mod 256);
+/// multiplying by 203 undoes that
+const ENCODED_OP_UNDO: u8 = 203;
+const ENCODED_OP: u8 = 227;
+
+enum Constant {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Option<String>),
+    Import(u32),
+    Table(Vec<u32>),
+    Closure(u32),
+    Vector([f32; 4]),
+    TableWithConstants(Vec<(u32, i32)>),
+}
+
+struct Proto {
+    max_stack: u8,
+    num_params: u8,
+    num_upvalues: u8,
+    is_vararg: bool,
+    code: Vec<u32>,
+    constants: Vec<Constant>,
+    children: Vec<u32>,
+    line_defined: u32,
+    debug_name: Option<String>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| format!("bytecode ends early (at byte {})", self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u32, String> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            result |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(format!("varint too long at byte {}", self.pos))
+    }
+
+    /// A count that's about to size an allocation, checked against what's left
+    fn count(&mut self) -> Result<usize, String> {
+        let count = self.varint()? as usize;
+        if count > self.data.len() - self.pos {
+            return Err(format!("count {} at byte {} is larger than the bytecode", count, self.pos));
+        }
+        Ok(count)
+    }
+
+    fn string_ref(&mut self, strings: &[String]) -> Result<Option<String>, String> {
+        match self.varint()? as usize {
+            0 => Ok(None),
+            index => strings
+                .get(index - 1)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| format!("string reference {} out of range", index)),
+        }
+    }
+}
+
+fn read_proto(reader: &mut Reader, version: u8, strings: &[String]) -> Result<Proto, String> {
+    let max_stack = reader.u8()?;
+    let num_params = reader.u8()?;
+    let num_upvalues = reader.u8()?;
+    let is_vararg = reader.u8()? != 0;
+
+    if version >= 4 {
+        let _flags = reader.u8()?;
+        let type_size = reader.count()?;
+        reader.bytes(type_size)?;
+    }
+
+    let code_size = reader.count()?;
+    let code = (0..code_size).map(|_| reader.u32()).collect::<Result<Vec<_>, _>>()?;
+
+    let constant_count = reader.count()?;
+    let mut constants = Vec::with_capacity(constant_count);
+    for _ in 0..constant_count {
+        let constant = match reader.u8()? {
+            0 => Constant::Nil,
+            1 => Constant::Boolean(reader.u8()? != 0),
+            2 => Constant::Number(f64::from_le_bytes(reader.bytes(8)?.try_into().unwrap())),
+            3 => Constant::String(reader.string_ref(strings)?),
+            4 => Constant::Import(reader.u32()?),
+            5 => {
+                let keys = reader.count()?;
+                Constant::Table((0..keys).map(|_| reader.varint()).collect::<Result<_, _>>()?)
+            }
+            6 => Constant::Closure(reader.varint()?),
+            7 => {
+                let mut vector = [0f32; 4];
+                for component in &mut vector {
+                    *component = f32::from_le_bytes(reader.bytes(4)?.try_into().unwrap());
+                }
+                Constant::Vector(vector)
+            }
+            8 => {
+                let keys = reader.count()?;
+                let mut entries = Vec::with_capacity(keys);
+                for _ in 0..keys {
+                    entries.push((reader.varint()?, reader.u32()? as i32));
+                }
+                Constant::TableWithConstants(entries)
+            }
+            kind => return Err(format!("unknown constant type {} at byte {}", kind, reader.pos - 1)),
+        };
+        constants.push(constant);
+    }
+
+    let child_count = reader.count()?;
+    let children = (0..child_count).map(|_| reader.varint()).collect::<Result<_, _>>()?;
+
+    let line_defined = reader.varint()?;
+    let debug_name = reader.string_ref(strings)?;
+
+    if reader.u8()? != 0 {
+        let line_gap_log2 = reader.u8()?;
+        let intervals = ((code_size.saturating_sub(1)) >> line_gap_log2) + 1;
+        reader.bytes(code_size)?;
+        reader.bytes(intervals * 4)?;
+    }
+
+    if reader.u8()? != 0 {
+        let locals = reader.count()?;
+        for _ in 0..locals {
+            reader.varint()?;
+            reader.varint()?;
+            reader.varint()?;
+            reader.u8()?;
+        }
+        let upvalues = reader.count()?;
+        for _ in 0..upvalues {
+            reader.varint()?;
+        }
+    }
+
+    Ok(Proto {
+        max_stack,
+        num_params,
+        num_upvalues,
+        is_vararg,
+        code,
+        constants,
+        children,
+        line_defined,
+        debug_name,
+    })
+}
+
+fn constant_string(constants: &[Constant], index: usize) -> String {
+    match constants.get(index) {
+        Some(Constant::Nil) => "nil".to_string(),
+        Some(Constant::Boolean(value)) => value.to_string(),
+        Some(Constant::Number(value)) => value.to_string(),
+        Some(Constant::String(Some(value))) => format!("{:?}", value),
+        Some(Constant::String(None)) => "<null string>".to_string(),
+        Some(Constant::Import(id)) => import_path(constants, *id),
+        Some(Constant::Table(keys)) => format!("{{{} keys}}", keys.len()),
+        Some(Constant::Closure(proto)) => format!("closure of proto {}", proto),
+        Some(Constant::Vector([x, y, z, w])) => format!("vector({}, {}, {}, {})", x, y, z, w),
+        Some(Constant::TableWithConstants(entries)) => format!("{{{} entries}}", entries.len()),
+        None => format!("<K{} out of range>", index),
+    }
+}
+
+/// A string constant as a bare name, for globals
+fn constant_name(constants: &[Constant], index: usize) -> String {
+    match constants.get(index) {
+        Some(Constant::String(Some(name))) => name.clone(),
+        _ => format!("K{}", index),
+    }
+}
+
+/// Imports pack up to three constant indices, e.g. `game.Players.LocalPlayer`
+fn import_path(constants: &[Constant], id: u32) -> String {
+    let count = (id >> 30) as usize;
+    (0..count)
+        .map(|i| constant_name(constants, ((id >> (20 - 10 * i)) & 1023) as usize))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn write_proto(out: &mut String, chunk: &Chunk, id: usize) {
+    let proto = &chunk.protos[id];
+    let name = chunk.proto_name(id);
+    let _ = writeln!(
+        out,
+        "proto {} ({}), line {}: {} params{}, {} upvalues, stack {}",
+        id,
+        name,
+        proto.line_defined,
+        proto.num_params,
+        if proto.is_vararg { " + vararg" } else { "" },
+        proto.num_upvalues,
+        proto.max_stack
+    );
+    if !proto.children.is_empty() {
+        let children: Vec<String> = proto.children.iter().map(u32::to_string).collect();
+        let _ = writeln!(out, "  child protos: {}", children.join(", "));
+    }
+
+    if !proto.constants.is_empty() {
+        let _ = writeln!(out, "  constants:");
+        for index in 0..proto.constants.len() {
+            let _ = writeln!(out, "    K{} = {}", index, constant_string(&proto.constants, index));
+        }
+    }
+
+    let _ = writeln!(out, "  instructions:");
+    let mut pc = 0;
+    while pc < proto.code.len() {
+        let insn = proto.code[pc];
+        let op = chunk.op(insn);
+        let a = (insn >> 8) & 0xff;
+        let b = (insn >> 16) & 0xff;
+        let c = insn >> 24;
+        let d = (insn as i32) >> 16;
+        let e = (insn as i32) >> 8;
+        let Some(&(op_name, has_aux)) = OPCODES.get(op as usize) else {
+            let _ = writeln!(out, "    {:>4}  <unknown opcode {}> {:08x}", pc, op, insn);
+            pc += 1;
+            continue;
+        };
+        let aux = has_aux.then(|| proto.code.get(pc + 1).copied()).flatten();
+        let jump_to = |offset: i32| pc as i64 + 1 + offset as i64;
+        let k = |index: u32| constant_string(&proto.constants, index as usize);
+
+        let operands = match op_name {
+            "LOADN" => format!("R{} {}", a, d),
+            "LOADK" => format!("R{} K{}  ; {}", a, d, k(d as u32)),
+            "LOADKX" => format!("R{} K{}  ; {}", a, aux.unwrap_or(0), k(aux.unwrap_or(0))),
+            "LOADB" => format!("R{} {} +{}", a, b != 0, c),
+            "GETGLOBAL" | "SETGLOBAL" => format!("R{} K{}  ; {}", a, aux.unwrap_or(0), k(aux.unwrap_or(0))),
+            "GETIMPORT" => format!("R{} K{}  ; {}", a, d, import_path(&proto.constants, aux.unwrap_or(0))),
+            "GETTABLEKS" | "SETTABLEKS" | "NAMECALL" => {
+                format!("R{} R{} K{}  ; {}", a, b, aux.unwrap_or(0), k(aux.unwrap_or(0)))
+            }
+            "ADDK" | "SUBK" | "MULK" | "DIVK" | "MODK" | "POWK" | "ANDK" | "ORK" | "IDIVK" => {
+                format!("R{} R{} K{}  ; {}", a, b, c, k(c))
+            }
+            "SUBRK" | "DIVRK" => format!("R{} K{} R{}  ; {}", a, b, c, k(b)),
+            "GETUPVAL" | "SETUPVAL" => format!("R{} U{}", a, b),
+            "NEWCLOSURE" => format!("R{} P{}", a, d),
+            "DUPCLOSURE" | "DUPTABLE" => format!("R{} K{}  ; {}", a, d, k(d as u32)),
+            "JUMP" | "JUMPBACK" => format!("to {}", jump_to(d)),
+            "JUMPX" => format!("to {}", jump_to(e)),
+            "JUMPIF" | "JUMPIFNOT" | "FORNPREP" | "FORNLOOP" | "FORGPREP" | "FORGPREP_INEXT"
+            | "FORGPREP_NEXT" => format!("R{} to {}", a, jump_to(d)),
+            "JUMPIFEQ" | "JUMPIFLE" | "JUMPIFLT" | "JUMPIFNOTEQ" | "JUMPIFNOTLE" | "JUMPIFNOTLT" => {
+                format!("R{} R{} to {}", a, aux.unwrap_or(0), jump_to(d))
+            }
+            "JUMPXEQKNIL" | "JUMPXEQKB" | "JUMPXEQKN" | "JUMPXEQKS" => {
+                let aux = aux.unwrap_or(0);
+                let not = if aux >> 31 != 0 { "not " } else { "" };
+                let compared = match op_name {
+                    "JUMPXEQKNIL" => "nil".to_string(),
+                    "JUMPXEQKB" => ((aux & 1) != 0).to_string(),
+                    _ => k(aux & 0xffffff),
+                };
+                format!("R{} {}{} to {}", a, not, compared, jump_to(d))
+            }
+            "CALL" => format!("R{} {} args, {} results", a, b as i32 - 1, c as i32 - 1),
+            "RETURN" => format!("R{} {} values", a, b as i32 - 1),
+            "COVERAGE" => format!("{}", e),
+            "NOP" | "BREAK" => String::new(),
+            "PREPVARARGS" => format!("{}", a),
+            "NOT" | "MINUS" | "LENGTH" | "MOVE" => format!("R{} R{}", a, b),
+            "LOADNIL" | "CLOSEUPVALS" => format!("R{}", a),
+            "ADD" | "SUB" | "MUL" | "DIV" | "MOD" | "POW" | "IDIV" | "AND" | "OR" | "GETTABLE" | "SETTABLE"
+            | "CONCAT" => format!("R{} R{} R{}", a, b, c),
+            "GETTABLEN" | "SETTABLEN" => format!("R{} R{} [{}]", a, b, c + 1),
+            "NEWTABLE" => format!("R{} hash {}, array {}", a, if b == 0 { 0 } else { 1 << (b - 1) }, aux.unwrap_or(0)),
+            "SETLIST" => format!("R{} R{} {} values from [{}]", a, b, c as i32 - 1, aux.unwrap_or(0)),
+            "FORGLOOP" => format!("R{} to {}", a, jump_to(d)),
+            "GETVARARGS" => format!("R{} {}", a, b as i32 - 1),
+            "CAPTURE" => format!("{} {}", ["val", "ref", "upval"].get(a as usize).unwrap_or(&"?"), b),
+            _ => format!("{} {} {}", a, b, c),
+        };
+        let _ = writeln!(out, "    {:>4}  {} {}", pc, op_name, operands.trim_end());
+        pc += if has_aux { 2 } else { 1 };
+    }
+}
+
+struct Chunk {
+    version: u8,
+    types_version: u8,
+    protos: Vec<Proto>,
+    main: usize,
+    /// Whether the opcodes went through Roblox's encoding
+    encoded: bool,
+}
+
+impl Chunk {
+    fn op(&self, insn: u32) -> u8 {
+        if self.encoded {
+            (insn as u8).wrapping_mul(ENCODED_OP_UNDO)
+        } else {
+            insn as u8
+        }
+    }
+
+    fn proto_name(&self, id: usize) -> String {
+        match (&self.protos[id].debug_name, id == self.main) {
+            (Some(name), _) => name.clone(),
+            (None, true) => "main".to_string(),
+            (None, false) => "anonymous".to_string(),
+        }
+    }
+}
+
+fn parse(bytecode: &[u8]) -> Result<Chunk, String> {
+    let mut reader = Reader { data: bytecode, pos: 0 };
+    let version = reader.u8()?;
+    if version == 0 {
+        let message = String::from_utf8_lossy(&bytecode[1..]);
+        return Err(format!("bytecode holds a compile error: {}", message));
+    }
+    if !(3..=6).contains(&version) {
+        return Err(format!("unsupported bytecode version {}", version));
+    }
+    let types_version = if version >= 4 { reader.u8()? } else { 0 };
+
+    let string_count = reader.count()?;
+    let mut strings = Vec::with_capacity(string_count);
+    for _ in 0..string_count {
+        let len = reader.count()?;
+        strings.push(String::from_utf8_lossy(reader.bytes(len)?).into_owned());
+    }
+
+    if types_version == 3 {
+        // names of userdata types, only there for the type checker
+        while reader.u8()? != 0 {
+            reader.varint()?;
+        }
+    }
+
+    let proto_count = reader.count()?;
+    let protos = (0..proto_count)
+        .map(|_| read_proto(&mut reader, version, &strings))
+        .collect::<Result<Vec<_>, _>>()?;
+    let main = reader.varint()? as usize;
+    if main >= protos.len() {
+        return Err(format!("main proto {} out of range", main));
+    }
+
+    let encoded = protos[main]
+        .code
+        .first()
+        .is_some_and(|insn| (*insn as u8) == OP_PREPVARARGS.wrapping_mul(ENCODED_OP));
+
+    Ok(Chunk {
+        version,
+        types_version,
+        protos,
+        main,
+        encoded,
+    })
+}
+
+/// Turns raw Luau bytecode into a readable listing of its constants,
+/// instructions and function tree. Doesn't need the oracle, so it still
+/// works when the oracle can't decompile a script.
+pub fn disassemble(bytecode: &[u8]) -> Result<String, String> {
+    let chunk = parse(bytecode)?;
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "luau bytecode version {}, types version {}, {} protos, main is {}{}",
+        chunk.version,
+        chunk.types_version,
+        chunk.protos.len(),
+        chunk.main,
+        if chunk.encoded { ", roblox-encoded opcodes" } else { "" }
+    );
+    for id in 0..chunk.protos.len() {
+        out.push('\n');
+        write_proto(&mut out, &chunk, id);
+    }
+    Ok(out)
+}
+
+/// The string constants and globals one function refers to
+pub struct ProtoStrings {
+    pub id: usize,
+    pub name: String,
+    /// Globals read or written, and imports like `math.floor`, in order of first use
+    pub globals: Vec<String>,
+    pub strings: Vec<String>,
+}
+
+/// Collects what every function in raw Luau bytecode refers to by name,
+/// without disassembling all of it
+pub fn strings(bytecode: &[u8]) -> Result<Vec<ProtoStrings>, String> {
+    let chunk = parse(bytecode)?;
+
+    let mut result = Vec::with_capacity(chunk.protos.len());
+    for (id, proto) in chunk.protos.iter().enumerate() {
+        let mut globals: Vec<String> = Vec::new();
+        let mut pc = 0;
+        while pc < proto.code.len() {
+            let op = chunk.op(proto.code[pc]);
+            let Some(&(op_name, has_aux)) = OPCODES.get(op as usize) else {
+                pc += 1;
+                continue;
+            };
+            let aux = proto.code.get(pc + 1).copied().unwrap_or(0);
+            let global = match op_name {
+                "GETGLOBAL" | "SETGLOBAL" => Some(constant_name(&proto.constants, aux as usize)),
+                "GETIMPORT" => Some(import_path(&proto.constants, aux)),
+                _ => None,
+            };
+            if let Some(global) = global.filter(|global| !globals.contains(global)) {
+                globals.push(global);
+            }
+            pc += if has_aux { 2 } else { 1 };
+        }
+
+        let mut strings: Vec<String> = Vec::new();
+        for constant in &proto.constants {
+            if let Constant::String(Some(value)) = constant {
+                if !strings.contains(value) {
+                    strings.push(value.clone());
+                }
+            }
+        }
+
+        result.push(ProtoStrings {
+            id,
+            name: chunk.proto_name(id),
+            globals,
+            strings,
+        });
+    }
+    Ok(result)
+}
+
+/// What `roundtrip-check` compares between two compilations of a script
+pub struct Fingerprint {
+    pub protos: usize,
+    /// Every constant as text, except closures, which only number protos
+    pub constants: Vec<String>,
+}
+
+/// The protos and constants of raw Luau bytecode
+pub fn fingerprint(bytecode: &[u8]) -> Result<Fingerprint, String> {
+    let chunk = parse(bytecode)?;
+    let constants = chunk
+        .protos
+        .iter()
+        .flat_map(|proto| {
+            (0..proto.constants.len())
+                .filter(|index| !matches!(proto.constants[*index], Constant::Closure(_)))
+                .map(|index| constant_string(&proto.constants, index))
+        })
+        .collect();
+    Ok(Fingerprint {
+        protos: chunk.protos.len(),
+        constants,
+    })
+}
+
+/// What a script's bytecode says it's called, when it was compiled with debug info
+#[derive(Debug, Default)]
+pub struct DebugNames {
+    /// The main function's name. Roblox leaves it empty, but some compilers
+    /// put the chunk name there, e.g. `=ReplicatedStorage.Modules.Inventory`
+    pub chunk: Option<String>,
+    /// Names of the functions defined in the script, in order, without repeats
+    pub functions: Vec<String>,
+}
+
+/// How many function names a header line lists before it just counts them
+const HEADER_FUNCTIONS: usize = 8;
+
+impl DebugNames {
+    /// The script's name going by the chunk name: its last segment, without
+    /// the `=`/`@` prefix or a `.lua`/`.luau` extension
+    pub fn script_name(&self) -> Option<&str> {
+        let chunk = self.chunk.as_deref()?;
+        let chunk = chunk.trim_start_matches(['=', '@']);
+        let chunk = chunk.strip_suffix(".luau").or_else(|| chunk.strip_suffix(".lua")).unwrap_or(chunk);
+        let name = chunk.rsplit(['.', '/', '\\']).next()?.trim();
+        (!name.is_empty()).then_some(name)
+    }
+
+    /// `-- Debug names: chunk Inventory, functions new, add, remove` for the
+    /// script's header, `None` if the bytecode didn't have any
+    pub fn header_line(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(name) = self.script_name() {
+            parts.push(format!("chunk {}", name));
+        }
+        if !self.functions.is_empty() {
+            let mut functions = self.functions.iter().take(HEADER_FUNCTIONS).cloned().collect::<Vec<_>>().join(", ");
+            if self.functions.len() > HEADER_FUNCTIONS {
+                functions.push_str(&format!(" and {} more", self.functions.len() - HEADER_FUNCTIONS));
+            }
+            parts.push(format!("functions {}", functions));
+        }
+        (!parts.is_empty()).then(|| format!("-- Debug names: {}", parts.join(", ")))
+    }
+}
+
+/// The names base64 `bytecode` kept of its chunk and functions. `None` if
+/// it can't be read.
+pub fn debug_names(bytecode: &str) -> Option<DebugNames> {
+    let raw = general_purpose::STANDARD.decode(bytecode).ok()?;
+    let chunk = parse(&raw).ok()?;
+
+    let mut names = DebugNames {
+        chunk: chunk.protos[chunk.main].debug_name.clone().filter(|name| !name.is_empty()),
+        functions: Vec::new(),
+    };
+    for (id, proto) in chunk.protos.iter().enumerate() {
+        let Some(name) = proto.debug_name.as_ref().filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        if id != chunk.main && !names.functions.contains(name) {
+            names.functions.push(name.clone());
+        }
+    }
+    Some(names)
+}
+
+/// A disassembly of base64 `bytecode` as Lua comments, to append to a failed script
+pub fn disassembly_comment(bytecode: &str) -> String {
+    let listing = general_purpose::STANDARD
+        .decode(bytecode)
+        .map_err(|e| format!("invalid base64: {}", e))
+        .and_then(|raw| disassemble(&raw));
+    match listing {
+        Ok(listing) => {
+            let mut out = String::from("\n\n-- disassembly:\n");
+            for line in listing.lines() {
+                out.push_str("-- ");
+                out.push_str(line);
+                out.push('\n');
+            }
+            out
+        }
+        Err(e) => format!("\n\n-- disassembly failed: {}\n", e),
+    }
+}
+
+/// `--with-disasm`: asks for a disassembly of base64 `bytecode` to go next to
+/// its decompilation, from the oracle if it does them and locally otherwise
+pub fn request_disassembly(
+    decompiler: &Decompiler,
+    bytecode: Arc<str>,
+    bytecode_hash: String,
+) -> crate::error::Result<oneshot::Receiver<DecompileResult>> {
+    if decompiler.disassembles() {
+        return decompiler.try_disassemble_with_hash(bytecode, bytecode_hash);
+    }
+    let (tx, rx) = oneshot::channel();
+    let listing = general_purpose::STANDARD
+        .decode(bytecode.as_bytes())
+        .map_err(|e| format!("invalid base64: {}", e))
+        .and_then(|raw| disassemble(&raw));
+    let _ = tx.send(listing.map_err(DecompileError::from));
+    Ok(rx)
+}
+
+/// What the `.disasm` file gets from `request_disassembly`'s answer, which
+/// isn't waited for past `deadline`
+pub async fn disassembly_listing(rx: oneshot::Receiver<DecompileResult>, deadline: Option<Instant>) -> String {
+    let answer = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, rx).await.ok(),
+        None => Some(rx.await),
+    };
+    match answer {
+        Some(Ok(Ok(listing))) => listing,
+        Some(Ok(Err(e))) => format!("disassembly failed: {}\n", e),
+        Some(Err(_)) => "disassembly failed: sender dropped\n".to_string(),
+        None => "disassembly failed: the oracle didn't answer in time\n".to_string(),
+    }
+}
+
+/// Writes the disassembly `rx` gets next to `script`, as `<script>.disasm`
+pub async fn write_disassembly(
+    script: &Path,
+    rx: oneshot::Receiver<DecompileResult>,
+    deadline: Option<Instant>,
+) -> std::io::Result<()> {
+    let listing = disassembly_listing(rx, deadline).await;
+    tokio::fs::write(script.with_extension("disasm"), listing).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREPVARARGS: u8 = 65;
+    const GETIMPORT: u8 = 12;
+    const RETURN: u8 = 22;
+
+    fn insn(op: u8, a: u32, b: u32, c: u32) -> u32 {
+        op as u32 | a << 8 | b << 16 | c << 24
+    }
+
+    /// Version 3 bytecode with one vararg main proto that runs `code`, with
+    /// `"print"` and `"game"` as K0 and K1 and an import of `print` as K2
+    fn chunk(code: &[u32]) -> Vec<u8> {
+        let mut bytes = vec![3, 2];
+        for string in ["print", "game"] {
+            bytes.push(string.len() as u8);
+            bytes.extend(string.as_bytes());
+        }
+        bytes.extend([1, 1, 0, 0, 1]);
+        bytes.push(code.len() as u8);
+        for insn in code {
+            bytes.extend(insn.to_le_bytes());
+        }
+        bytes.extend([3, 3, 1, 3, 2, 4]);
+        bytes.extend((1u32 << 30).to_le_bytes());
+        // no children, line 0, no name, no line or debug info, main is 0
+        bytes.extend([0, 0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    fn print_call() -> Vec<u32> {
+        vec![
+            insn(PREPVARARGS, 0, 0, 0),
+            insn(GETIMPORT, 0, 2, 0),
+            1 << 30,
+            insn(RETURN, 0, 1, 0),
+        ]
+    }
+
+    #[test]
+    fn constants_and_instructions_are_listed() {
+        let listing = disassemble(&chunk(&print_call())).unwrap();
+        assert!(listing.starts_with("luau bytecode version 3, types version 0, 1 protos, main is 0\n"));
+        assert!(listing.contains("proto 0 (main), line 0: 0 params + vararg, 0 upvalues, stack 1"));
+        assert!(listing.contains("K0 = \"print\""));
+        assert!(listing.contains("K2 = print"));
+        assert!(listing.contains("   1  GETIMPORT R0 K2  ; print\n"));
+        assert!(listing.contains("   3  RETURN R0 0 values\n"));
+    }
+
+    #[test]
+    fn roblox_encoded_opcodes_are_decoded() {
+        let encoded: Vec<u32> = print_call()
+            .into_iter()
+            .enumerate()
+            // the aux word after GETIMPORT isn't an instruction
+            .map(|(pc, insn)| if pc == 2 { insn } else { insn & !0xff | (insn as u8).wrapping_mul(ENCODED_OP) as u32 })
+            .collect();
+        let listing = disassemble(&chunk(&encoded)).unwrap();
+        assert!(listing.contains(", roblox-encoded opcodes"));
+        assert!(listing.contains("GETIMPORT R0 K2  ; print"));
+        assert_eq!(
+            listing.lines().skip(1).collect::<Vec<_>>(),
+            disassemble(&chunk(&print_call())).unwrap().lines().skip(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_are_shown_raw() {
+        let listing = disassemble(&chunk(&[insn(PREPVARARGS, 0, 0, 0), 0xff])).unwrap();
+        assert!(listing.contains("   1  <unknown opcode 255> 000000ff\n"));
+    }
+
+    #[test]
+    fn a_compile_error_is_reported_as_one() {
+        let error = disassemble(b"\0:1: Expected identifier").unwrap_err();
+        assert_eq!(error, "bytecode holds a compile error: :1: Expected identifier");
+    }
+
+    #[test]
+    fn unsupported_versions_are_refused() {
+        assert_eq!(disassemble(&[9]).unwrap_err(), "unsupported bytecode version 9");
+        assert_eq!(disassemble(&[]).unwrap_err(), "bytecode ends early (at byte 0)");
+    }
+
+    #[test]
+    fn truncated_bytecode_is_an_error_at_every_length() {
+        let whole = chunk(&print_call());
+        for len in 1..whole.len() {
+            assert!(disassemble(&whole[..len]).is_err(), "{} of {} bytes read fine", len, whole.len());
+        }
+    }
+
+    #[test]
+    fn counts_past_the_end_are_refused_before_allocating() {
+        // a string count of u32::MAX
+        let error = disassemble(&[3, 0xff, 0xff, 0xff, 0xff, 0x0f]).unwrap_err();
+        assert!(error.contains("is larger than the bytecode"), "{}", error);
+    }
+
+    #[test]
+    fn disassembly_comments_are_lua_comments() {
+        let comment = disassembly_comment(&general_purpose::STANDARD.encode(chunk(&print_call())));
+        assert!(comment.starts_with("\n\n-- disassembly:\n-- luau bytecode version 3"));
+        assert!(comment.lines().skip(2).all(|line| line.starts_with("-- ")));
+        assert!(disassembly_comment("not base64!").starts_with("\n\n-- disassembly failed: invalid base64"));
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::compiled::{load_bytecode_file, LOAD_CONCURRENCY};
+use crate::decompiler::{DecompilationRequest, DecompileResult, Decompiler};
+use crate::error::{Error, Result};
+use crate::directives::hoist_directives;
+use crate::disasm::{debug_names, disassembly_comment, request_disassembly, write_disassembly};
+use crate::folder::{collect_files, RenderOptions};
+use crate::instance::InstancePath;
+use crate::integrity::with_integrity_line;
+use crate::luau;
+use crate::naming::ScriptFileNamer;
+use crate::profile::OptionProfiles;
+use crate::sizes::with_size_line;
+use crate::sourcemap::Sourcemap;
+
+/// Index files dumpers write next to the scripts, describing all of them at once
+const INDEX_FILE_NAMES: &[&str] = &["metadata.json", "manifest.json", "index.json"];
+
+/// Scripts without metadata are kept under this folder, mirroring the dump
+const UNSORTED_DIR: &str = "_unsorted";
+
+/// An instance path, either as `game.Workspace.Script` or as a list of names
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum FullName {
+    Dotted(String),
+    Segments(Vec<String>),
+}
+
+impl FullName {
+    fn to_instance_path(&self) -> InstancePath {
+        let mut segments = match self {
+            FullName::Dotted(name) => name.split('.').map(str::to_string).collect(),
+            FullName::Segments(segments) => segments.clone(),
+        };
+        if segments.len() > 1 && segments[0] == "game" {
+            segments.remove(0);
+        }
+        InstancePath::new(segments)
+    }
+}
+
+/// What a dumper recorded about one script. Dumpers disagree on the
+/// field names, so the common spellings are all accepted.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ScriptMetadata {
+    /// The dumped file this entry is about, only used in index files
+    #[serde(alias = "filename", alias = "file_name", alias = "bytecode")]
+    file: Option<String>,
+    #[serde(alias = "path", alias = "fullName", alias = "FullName")]
+    full_name: Option<FullName>,
+    #[serde(alias = "class", alias = "className", alias = "ClassName")]
+    class_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Index {
+    List(Vec<ScriptMetadata>),
+    Map(HashMap<String, ScriptMetadata>),
+}
+
+/// Metadata from the dump's index files, by file path relative to the
+/// dump (with `/` separators) and by bare file name
+#[derive(Default)]
+struct DumpIndex {
+    by_path: HashMap<String, ScriptMetadata>,
+    by_name: HashMap<String, ScriptMetadata>,
+}
+
+impl DumpIndex {
+    fn load(dump_dir: &Path) -> Result<Self> {
+        let mut index = Self::default();
+        for name in INDEX_FILE_NAMES {
+            let path = dump_dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+
+            let entries = match serde_json::from_slice::<Index>(&std::fs::read(&path)?) {
+                Ok(Index::List(entries)) => entries,
+                Ok(Index::Map(entries)) => entries
+                    .into_iter()
+                    .map(|(file, mut metadata)| {
+                        metadata.file.get_or_insert(file);
+                        metadata
+                    })
+                    .collect(),
+                Err(e) => {
+                    warn!("ignoring {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            for metadata in entries {
+                let Some(file) = metadata.file.clone() else {
+                    continue;
+                };
+                let file = file.replace('\\', "/");
+                if let Some(name) = file.rsplit('/').next() {
+                    index.by_name.insert(name.to_string(), metadata.clone());
+                }
+                index.by_path.insert(file, metadata);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Looks `file` up in the index, or in a `<name>.json` / `<name>.bin.json`
+    /// sidecar next to it
+    fn metadata_for(&self, file: &Path, relative: &str) -> Option<ScriptMetadata> {
+        for sidecar in [file.with_extension("json"), PathBuf::from(format!("{}.json", file.display()))] {
+            let Ok(contents) = std::fs::read(&sidecar) else {
+                continue;
+            };
+            match serde_json::from_slice(&contents) {
+                Ok(metadata) => return Some(metadata),
+                Err(e) => warn!("ignoring {}: {}", sidecar.display(), e),
+            }
+        }
+
+        let name = file.file_name()?.to_string_lossy();
+        self.by_path
+            .get(relative)
+            .or_else(|| self.by_name.get(name.as_ref()))
+            .cloned()
+    }
+}
+
+struct DumpJob {
+    input_path: PathBuf,
+    output_path: PathBuf,
+    bytecode: Arc<str>,
+    full_name: Option<InstancePath>,
+    class_name: Option<String>,
+    /// `-- Debug names: ...`, with `--debug-names`
+    debug_line: Option<String>,
+    rx: oneshot::Receiver<DecompileResult>,
+    /// For the `.disasm` file, with `--with-disasm`
+    disassembly: Option<oneshot::Receiver<DecompileResult>>,
+}
+
+/// Decompiles a dumper's output folder (`.bin` files with JSON metadata)
+/// into a tree that mirrors the game, e.g. `ServerScriptService/Main.lua`
+pub async fn process_dump(
+    decompiler: &Decompiler,
+    input_dir: &str,
+    output_dir: &str,
+    options: RenderOptions,
+    profiles: &OptionProfiles,
+) -> Result<()> {
+    let input_path = Path::new(input_dir).canonicalize()?;
+    let index = DumpIndex::load(&input_path)?;
+    let mut namer = ScriptFileNamer::new(Path::new(output_dir), options.extension());
+
+    let files: Vec<PathBuf> = collect_files(&input_path)
+        .into_iter()
+        .filter(|file| file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("bin")))
+        .collect();
+    info!("found {} .bin files in {}", files.len(), input_dir);
+
+    let mut jobs = Vec::new();
+    let mut skipped = 0u32;
+    let mut without_metadata = 0u32;
+
+    let mut loads = futures::stream::iter(files)
+        .map(|file| async move {
+            let loaded = load_bytecode_file(file.to_string_lossy().to_string()).await;
+            (file, loaded)
+        })
+        .buffered(LOAD_CONCURRENCY);
+
+    while let Some((file, loaded)) = loads.next().await {
+        let Ok(loaded) = loaded else {
+            skipped += 1;
+            continue;
+        };
+
+        let relative = file.strip_prefix(&input_path)?;
+        let relative_str = relative.to_string_lossy().replace('\\', "/");
+        let metadata = index.metadata_for(&file, &relative_str).unwrap_or_default();
+        let full_name = metadata.full_name.as_ref().map(FullName::to_instance_path);
+
+        let names = options.debug_names.then(|| debug_names(&loaded.bytecode)).flatten();
+        let output_path = match (&full_name, names.as_ref().and_then(|names| names.script_name())) {
+            (Some(full_name), Some(name)) => namer.path_named(full_name, name),
+            (Some(full_name), None) => namer.path_for(full_name),
+            (None, _) => {
+                without_metadata += 1;
+                let mut segments = vec![UNSORTED_DIR.to_string()];
+                segments.extend(
+                    relative
+                        .with_extension("")
+                        .iter()
+                        .map(|segment| segment.to_string_lossy().to_string()),
+                );
+                namer.path_for(&InstancePath::new(segments))
+            }
+        };
+
+        let (tx, rx) = oneshot::channel();
+        let bytecode = loaded.bytecode;
+        let request = DecompilationRequest::with_hash(bytecode.clone(), loaded.hash.clone(), tx)
+            .with_options(profiles.options_for(full_name.as_ref(), metadata.class_name.as_deref()));
+        decompiler.decompile_batch(vec![request]).await?;
+        let disassembly = if options.with_disasm {
+            Some(request_disassembly(decompiler, bytecode.clone(), loaded.hash)?)
+        } else {
+            None
+        };
+
+        jobs.push(DumpJob {
+            input_path: file,
+            output_path,
+            bytecode,
+            full_name,
+            class_name: metadata.class_name,
+            debug_line: names.and_then(|names| names.header_line()),
+            rx,
+            disassembly,
+        });
+    }
+
+    info!(
+        "{} scripts queued for decompilation, {} skipped (not bytecode), {} without metadata",
+        jobs.len(),
+        skipped,
+        without_metadata
+    );
+
+    let mut sourcemap = Sourcemap::new(Path::new(output_dir));
+    let total = jobs.len();
+    let mut failed = 0u32;
+    for (done, job) in jobs.into_iter().enumerate() {
+        let result = match job.rx.await {
+            Ok(Ok(source)) => {
+                let source = if options.format_lua {
+                    luau::format_or_keep(source, &job.input_path.display())
+                } else {
+                    source
+                };
+                let source = if options.size_comment {
+                    with_size_line(source, &job.bytecode)
+                } else {
+                    source
+                };
+                let source = if options.integrity {
+                    with_integrity_line(source)
+                } else {
+                    source
+                };
+                format!("-- decompilation:\n{}", source)
+            }
+            Ok(Err(e)) => {
+                failed += 1;
+                warn!("couldn't decompile {}: {}", job.input_path.display(), e);
+                let disassembly = if options.disasm {
+                    disassembly_comment(&job.bytecode)
+                } else {
+                    String::new()
+                };
+                format!("-- decompilation failed:\n-- {}{}", e, disassembly)
+            }
+            Err(_) => {
+                failed += 1;
+                warn!("couldn't decompile {}: sender dropped", job.input_path.display());
+                "-- decompilation failed:\n-- sender dropped".to_string()
+            }
+        };
+
+        let mut header = String::new();
+        if let Some(full_name) = &job.full_name {
+            header.push_str(&format!("-- Path: game.{}\n", full_name));
+        }
+        if let Some(class_name) = &job.class_name {
+            header.push_str(&format!("-- ClassName: {}\n", class_name));
+        }
+        if let Some(debug_line) = &job.debug_line {
+            header.push_str(debug_line);
+            header.push('\n');
+        }
+
+        if let Some(parent) = job.output_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = format!("{}{}\n", header, result);
+        let contents = if options.luau { hoist_directives(&contents) } else { contents };
+        std::fs::write(&job.output_path, contents)?;
+        if let Some(disassembly) = job.disassembly {
+            write_disassembly(&job.output_path, disassembly, None).await?;
+        }
+
+        if let Some(full_name) = &job.full_name {
+            // without a class there's no telling, but requiring it is the likeliest use
+            let class_name = job.class_name.unwrap_or_else(|| "ModuleScript".to_string());
+            sourcemap.add(full_name, &[class_name], &job.output_path);
+        }
+
+        if (done + 1) % 100 == 0 {
+            info!("decompiling: {}/{} | {} failed", done + 1, total, failed);
+        }
+    }
+
+    sourcemap.write()?;
+    namer.write_manifest()?;
+    info!("done. {} decompiled, {} failed", total as u32 - failed, failed);
+    Error::check_failures(failed as usize, total, "scripts")
+}
@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use tracing::{debug, warn};
+
+/// How much of the start of a place is looked at for a byte order mark and declaration
+const SNIFF_LEN: usize = 1024;
+
+/// The longest declaration that gets rewritten, anything longer isn't one
+const MAX_DECLARATION_LEN: usize = 4 * 1024;
+
+/// What 0x80 to 0x9f stand for in windows-1252, the rest of it is Latin-1.
+/// The five bytes it leaves undefined become the control characters of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+impl SourceEncoding {
+    /// Looks up a declared encoding name. Like browsers, Latin-1 and ASCII are
+    /// read as windows-1252, which covers both. UTF-16 isn't here, a place in
+    /// it is found by its byte order mark since its declaration can't be read
+    /// as ASCII.
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(Self::Utf8),
+            "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1"
+            | "l1" | "cp819" | "ibm819" | "us-ascii" | "ascii" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+}
+
+/// Where the encoding name is in the `<?xml ... ?>` declaration at the start of `bytes`
+fn declared_encoding(bytes: &[u8]) -> Option<Range<usize>> {
+    let declaration = bytes.strip_prefix(b"<?xml")?;
+    let end = declaration.windows(2).position(|it| it == b"?>")?;
+    let declaration = &declaration[..end];
+
+    let key = declaration.windows(8).position(|it| it == b"encoding")?;
+    let skip_whitespace = |mut i: usize| {
+        while declaration.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        i
+    };
+    let equals = skip_whitespace(key + 8);
+    if declaration.get(equals) != Some(&b'=') {
+        return None;
+    }
+    let quote_at = skip_whitespace(equals + 1);
+    let quote = *declaration.get(quote_at).filter(|it| matches!(it, b'"' | b'\''))?;
+    let start = quote_at + 1;
+    let len = declaration[start..].iter().position(|it| *it == quote)?;
+    Some(5 + start..5 + start + len)
+}
+
+/// Works out what a place is encoded in from its first bytes, returning the
+/// encoding and how long its byte order mark is. A byte order mark wins over
+/// the declaration, and anything unrecognised is read as UTF-8.
+pub fn detect_encoding(start: &[u8]) -> (SourceEncoding, usize) {
+    match start {
+        [0xef, 0xbb, 0xbf, ..] => (SourceEncoding::Utf8, 3),
+        [0xff, 0xfe, ..] => (SourceEncoding::Utf16Le, 2),
+        [0xfe, 0xff, ..] => (SourceEncoding::Utf16Be, 2),
+        [b'<', 0, b'?', 0, ..] => (SourceEncoding::Utf16Le, 0),
+        [0, b'<', 0, b'?', ..] => (SourceEncoding::Utf16Be, 0),
+        _ => {
+            let encoding = declared_encoding(start).map_or(SourceEncoding::Utf8, |range| {
+                let label = String::from_utf8_lossy(&start[range]);
+                SourceEncoding::from_label(&label).unwrap_or_else(|| {
+                    warn!("the place declares an unsupported encoding {:?}, reading it as utf-8", label);
+                    SourceEncoding::Utf8
+                })
+            });
+            (encoding, 0)
+        }
+    }
+}
+
+/// Reads a place as UTF-8 whatever it was encoded in, with its declaration
+/// rewritten to say so. Everything else passes through as it was.
+pub struct DecodingReader<R: Read> {
+    inner: R,
+    /// Not known until enough of the start has been read
+    encoding: Option<SourceEncoding>,
+    /// Bytes that haven't been decoded yet, the start while it's being
+    /// looked at or the first half of a character split between reads
+    raw: Vec<u8>,
+    output: VecDeque<u8>,
+    /// Whether the declaration may still need rewriting
+    in_prolog: bool,
+    eof: bool,
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R, bytes_read: Arc<AtomicU64>) -> Self {
+        Self {
+            inner,
+            encoding: None,
+            raw: Vec::new(),
+            output: VecDeque::new(),
+            in_prolog: true,
+            eof: false,
+            bytes_read,
+        }
+    }
+
+    fn fill_raw(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 64 * 1024];
+        let read = self.inner.read(&mut buf)?;
+        if read == 0 {
+            self.eof = true;
+        } else {
+            self.bytes_read.fetch_add(read as u64, Ordering::Relaxed);
+            self.raw.extend_from_slice(&buf[..read]);
+        }
+        Ok(())
+    }
+
+    fn push_char(&mut self, c: char) {
+        let mut utf8 = [0u8; 4];
+        self.output.extend(c.encode_utf8(&mut utf8).as_bytes());
+    }
+
+    fn decode(&mut self) {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => {
+                if self.raw.len() < SNIFF_LEN && !self.eof {
+                    return;
+                }
+                let (encoding, bom_len) = detect_encoding(&self.raw);
+                if encoding != SourceEncoding::Utf8 {
+                    debug!("transcoding the place from {:?}", encoding);
+                }
+                self.raw.drain(..bom_len);
+                self.encoding = Some(encoding);
+                encoding
+            }
+        };
+
+        match encoding {
+            SourceEncoding::Utf8 => self.output.extend(self.raw.drain(..)),
+            SourceEncoding::Windows1252 => {
+                let raw = std::mem::take(&mut self.raw);
+                for b in raw {
+                    self.push_char(match b {
+                        0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                        _ => b as char,
+                    });
+                }
+            }
+            SourceEncoding::Utf16Le | SourceEncoding::Utf16Be => {
+                let whole = self.raw.len() / 2 * 2;
+                let mut units: Vec<u16> = self.raw[..whole]
+                    .chunks_exact(2)
+                    .map(|pair| match encoding {
+                        SourceEncoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                // a surrogate pair split between reads waits for its second half
+                let mut kept = 0;
+                if !self.eof && units.last().is_some_and(|unit| (0xd800..0xdc00).contains(unit)) {
+                    units.pop();
+                    kept = 2;
+                }
+                self.raw.drain(..whole - kept);
+                for c in char::decode_utf16(units) {
+                    self.push_char(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                if self.eof && !self.raw.is_empty() {
+                    // half a code unit at the very end
+                    self.raw.clear();
+                    self.push_char(char::REPLACEMENT_CHARACTER);
+                }
+            }
+        }
+    }
+
+    /// Points the declaration at UTF-8 once all of it has been decoded
+    fn rewrite_declaration(&mut self) {
+        let output = self.output.make_contiguous();
+        let prefix_len = output.len().min(5);
+        if output[..prefix_len] != b"<?xml"[..prefix_len] {
+            self.in_prolog = false;
+            return;
+        }
+        let closed = output.windows(2).any(|it| it == b"?>");
+        if !closed && !self.eof && output.len() < MAX_DECLARATION_LEN {
+            return;
+        }
+        self.in_prolog = false;
+
+        let Some(range) = declared_encoding(output) else {
+            return;
+        };
+        if SourceEncoding::from_label(&String::from_utf8_lossy(&output[range.clone()])) == Some(SourceEncoding::Utf8) {
+            return;
+        }
+        let mut rewritten = Vec::with_capacity(output.len());
+        rewritten.extend_from_slice(&output[..range.start]);
+        rewritten.extend_from_slice(b"UTF-8");
+        rewritten.extend_from_slice(&output[range.end..]);
+        self.output = rewritten.into();
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while (self.output.is_empty() || self.in_prolog) && !self.eof {
+            self.fill_raw()?;
+            self.decode();
+            if self.in_prolog {
+                self.rewrite_declaration();
+            }
+        }
+
+        let (front, _) = self.output.as_slices();
+        let written = front.len().min(out.len());
+        out[..written].copy_from_slice(&front[..written]);
+        self.output.drain(..written);
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A place with characters that need more than ASCII, and one outside the BMP
+    const PLACE: &str = "<?xml version=\"1.0\" encoding=\"{}\"?>\n<roblox><Item class=\"Script\">\
+                         <string name=\"Name\">Caf\u{e9} \u{20ac}5 \u{2014} \u{1f600}</string></Item></roblox>\n";
+
+    fn place(encoding: &str) -> String {
+        PLACE.replace("{}", encoding)
+    }
+
+    /// Reads `bytes` through a `DecodingReader`, `chunk` bytes at a time from the inner reader
+    fn decoded(bytes: Vec<u8>, chunk: usize) -> String {
+        struct Chunked(io::Cursor<Vec<u8>>, usize);
+        impl Read for Chunked {
+            fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+                let len = out.len().min(self.1);
+                self.0.read(&mut out[..len])
+            }
+        }
+        let mut reader = DecodingReader::new(Chunked(io::Cursor::new(bytes), chunk), Arc::default());
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    fn utf16(text: &str, big_endian: bool, bom: bool) -> Vec<u8> {
+        let units = bom.then_some(0xfeff).into_iter().chain(text.encode_utf16());
+        units
+            .flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() })
+            .collect()
+    }
+
+    #[test]
+    fn utf16_with_a_bom_comes_out_as_utf8() {
+        for big_endian in [false, true] {
+            let bytes = utf16(&place("UTF-16"), big_endian, true);
+            assert_eq!(detect_encoding(&bytes).1, 2);
+            assert_eq!(decoded(bytes, 64 * 1024), place("UTF-8"));
+        }
+    }
+
+    #[test]
+    fn utf16_without_a_bom_is_found_by_its_declaration() {
+        for big_endian in [false, true] {
+            let bytes = utf16(&place("UTF-16"), big_endian, false);
+            let expected = if big_endian { SourceEncoding::Utf16Be } else { SourceEncoding::Utf16Le };
+            assert_eq!(detect_encoding(&bytes), (expected, 0));
+            assert_eq!(decoded(bytes, 64 * 1024), place("UTF-8"));
+        }
+    }
+
+    #[test]
+    fn utf16_split_between_reads_at_every_byte() {
+        // long enough to be decoded as it comes in rather than all at the end,
+        // so odd reads split code units, and some of them surrogate pairs
+        let long = |encoding| place(encoding).replace("Caf", &format!("{}Caf", "x".repeat(SNIFF_LEN)));
+        for chunk in [1, 3, 7] {
+            assert_eq!(decoded(utf16(&long("UTF-16"), false, true), chunk), long("UTF-8"));
+        }
+    }
+
+    #[test]
+    fn windows_1252_comes_out_as_utf8() {
+        // the emoji has no windows-1252 byte, so this place goes without it
+        let text = place("windows-1252").replace(" \u{1f600}", "");
+        let bytes: Vec<u8> = text
+            .chars()
+            .map(|c| match c {
+                '\u{20ac}' => 0x80,
+                '\u{2014}' => 0x97,
+                c => u8::try_from(u32::from(c)).unwrap(),
+            })
+            .collect();
+        assert_eq!(detect_encoding(&bytes), (SourceEncoding::Windows1252, 0));
+        let expected = place("UTF-8").replace(" \u{1f600}", "");
+        assert_eq!(decoded(bytes.clone(), 64 * 1024), expected);
+        assert_eq!(decoded(bytes, 5), expected);
+    }
+
+    #[test]
+    fn latin1_labels_are_read_as_windows_1252() {
+        assert_eq!(SourceEncoding::from_label("ISO-8859-1"), Some(SourceEncoding::Windows1252));
+        assert_eq!(SourceEncoding::from_label(" us-ascii "), Some(SourceEncoding::Windows1252));
+        assert_eq!(SourceEncoding::from_label("utf-16"), None);
+    }
+
+    #[test]
+    fn utf8_passes_through_untouched() {
+        let text = place("UTF-8");
+        assert_eq!(decoded(text.clone().into_bytes(), 3), text);
+    }
+}
@@ -0,0 +1,145 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The oracle couldn't be reached, or the connection to it was lost
+    #[error("connection error: {0}")]
+    Connection(String),
+
+    /// The oracle rejected the key
+    #[error("authentication failed: {0}")]
+    Auth(String),
+
+    /// The oracle sent something this client doesn't understand
+    #[error("protocol error: {0}")]
+    Protocol(String),
+
+    /// The oracle answered, but couldn't decompile the script, or any of them
+    #[error("decompilation failed: {0}")]
+    OracleFailure(String),
+
+    /// Some scripts couldn't be decompiled, the rest were written
+    #[error("some decompilations failed: {0}")]
+    PartialFailure(String),
+
+    #[error("timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("xml error: {0}")]
+    Xml(#[from] xml::reader::Error),
+
+    #[error("xml write error: {0}")]
+    XmlWrite(#[from] xml::writer::Error),
+
+    #[error("rbxl error: {0}")]
+    Rbxl(#[from] rbx_binary::DecodeError),
+
+    #[error("rbxl write error: {0}")]
+    RbxlWrite(#[from] rbx_binary::EncodeError),
+
+    /// The processed place couldn't be loaded to convert it
+    #[error("rbxlx load error: {0}")]
+    RbxlxLoad(#[from] rbx_xml::DecodeError),
+
+    #[error("rbxlx save error: {0}")]
+    RbxlxSave(#[from] rbx_xml::EncodeError),
+
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+
+    #[error("clipboard error: {0}")]
+    Clipboard(#[from] arboard::Error),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// What the process exits with, so wrappers can tell outcomes apart
+    /// without parsing messages. 2 is shared with clap's usage errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Other(_) | Error::Keyring(_) | Error::Clipboard(_) => 1,
+            Error::Config(_) => 2,
+            Error::Auth(_) => 3,
+            Error::Connection(_) | Error::Protocol(_) | Error::Timeout(_) => 4,
+            Error::PartialFailure(_) => 5,
+            Error::OracleFailure(_) => 6,
+            Error::Io(_)
+            | Error::Xml(_)
+            | Error::XmlWrite(_)
+            | Error::Rbxl(_)
+            | Error::RbxlWrite(_)
+            | Error::RbxlxLoad(_)
+            | Error::RbxlxSave(_)
+            | Error::Sqlite(_)
+            | Error::Zip(_) => 7,
+        }
+    }
+
+    /// `PartialFailure` if some of `total` scripts or files failed, `OracleFailure` if all of them did
+    pub fn check_failures(failed: usize, total: usize, what: &str) -> Result<()> {
+        match failed {
+            0 => Ok(()),
+            _ if failed == total => Err(Error::OracleFailure(format!("all {} {} failed", total, what))),
+            _ => Err(Error::PartialFailure(format!("{} of {} {} failed", failed, total, what))),
+        }
+    }
+
+    /// The outcome named in the summary line, alongside `exit_code`
+    pub fn status(&self) -> &'static str {
+        match self.exit_code() {
+            2 => "config_error",
+            3 => "auth_failed",
+            4 => "connection_lost",
+            5 => "partial_failure",
+            6 => "all_failed",
+            7 => "io_error",
+            _ => "error",
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Other(message.to_string())
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::Connection(e.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Error::Other(format!("background task failed: {}", e))
+    }
+}
+
+impl From<std::path::StripPrefixError> for Error {
+    fn from(e: std::path::StripPrefixError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
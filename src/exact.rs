@@ -0,0 +1,696 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use futures::future::FutureExt;
+use tokio::sync::{mpsc, oneshot};
+use tracing::info;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::compiled::find_bytecode;
+use crate::decompiler::{hash_bytecode, DecompilationRequest, DecompileResult, Decompiler};
+use crate::disasm::debug_names;
+use crate::error::{Error, Result};
+use crate::filter::ScriptFilter;
+use crate::instance::{InstancePath, InstanceTracker};
+use crate::integrity::with_integrity_line;
+use crate::luau;
+use crate::obfuscation::detect_obfuscator;
+use crate::progress::{emit, ProgressEvent};
+use crate::rbxlx::{
+    save_failure, RbxlxOptions, SharedResult, Utf8BoundaryReader, CLOUD_SOURCE_PROPERTIES, READ_CHANNEL_CAPACITY,
+};
+use crate::report::{Reused, ScriptReport};
+use crate::sizes::with_size_line;
+use crate::spool::spooled;
+use crate::template::{OutputTemplate, TemplateFields};
+
+/// What an element found by `find_spans` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpanKind {
+    /// A `<ProtectedString name="Source">`
+    Source,
+    /// One of the `CLOUD_SOURCE_PROPERTIES`, and whether it has an element
+    /// (`LinkedSource`'s `<url>`) inside
+    CloudSource { has_children: bool },
+}
+
+/// Where the contents of an element are in the input, between its tags
+struct Span {
+    kind: SpanKind,
+    range: Range<u64>,
+}
+
+/// What a `<` in the input turned out to start
+enum Markup {
+    Start {
+        name: String,
+        /// Its `name` attribute, the property's name
+        property: Option<String>,
+        self_closing: bool,
+    },
+    End,
+    /// A comment, CDATA section, processing instruction or doctype
+    Other,
+}
+
+/// Reads the input a byte at a time, keeping track of where it is
+struct ByteScanner<R: BufRead> {
+    input: R,
+    position: u64,
+}
+
+impl<R: BufRead> ByteScanner<R> {
+    fn next(&mut self) -> io::Result<Option<u8>> {
+        let Some(&byte) = self.input.fill_buf()?.first() else {
+            return Ok(None);
+        };
+        self.input.consume(1);
+        self.position += 1;
+        Ok(Some(byte))
+    }
+
+    fn expect_next(&mut self) -> io::Result<u8> {
+        self.next()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the place ends in the middle of an element"))
+    }
+
+    /// Reads up to and including the next `end`
+    fn skip_past(&mut self, end: &[u8]) -> io::Result<()> {
+        let mut window = Vec::with_capacity(end.len());
+        while window != end {
+            if window.len() == end.len() {
+                window.remove(0);
+            }
+            window.push(self.expect_next()?);
+        }
+        Ok(())
+    }
+
+    /// Reads what follows a `<`, up to and including its closing `>`
+    fn markup(&mut self) -> io::Result<Markup> {
+        match self.expect_next()? {
+            b'!' => {
+                match self.expect_next()? {
+                    b'-' => self.skip_past(b"-->")?,
+                    b'[' => self.skip_past(b"]]>")?,
+                    _ => self.skip_past(b">")?,
+                }
+                Ok(Markup::Other)
+            }
+            b'?' => {
+                self.skip_past(b"?>")?;
+                Ok(Markup::Other)
+            }
+            b'/' => {
+                self.skip_past(b">")?;
+                Ok(Markup::End)
+            }
+            first => {
+                // `>` may appear unescaped in attribute values
+                let mut tag = vec![first];
+                let mut quote = None;
+                loop {
+                    let byte = self.expect_next()?;
+                    match (byte, quote) {
+                        (b'>', None) => break,
+                        (b'"' | b'\'', None) => quote = Some(byte),
+                        (_, Some(open)) if byte == open => quote = None,
+                        _ => {}
+                    }
+                    tag.push(byte);
+                }
+                let self_closing = tag.last() == Some(&b'/');
+                let tag = String::from_utf8_lossy(&tag);
+                let name = tag
+                    .split(|c: char| c.is_ascii_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default();
+                // prefixes are ignored, the same as the parser's local names
+                let name = name.rsplit(':').next().unwrap_or(name).to_string();
+                Ok(Markup::Start {
+                    name,
+                    property: name_attribute(&tag),
+                    self_closing,
+                })
+            }
+        }
+    }
+
+    /// Reads the contents of the element just started, up to its end tag.
+    /// Returns where the end tag starts and whether there was an element inside.
+    fn element_end(&mut self) -> io::Result<(u64, bool)> {
+        let mut depth = 0u32;
+        let mut has_children = false;
+        loop {
+            if self.expect_next()? != b'<' {
+                continue;
+            }
+            let start = self.position - 1;
+            match self.markup()? {
+                Markup::Start { self_closing, .. } => {
+                    has_children = true;
+                    if !self_closing {
+                        depth += 1;
+                    }
+                }
+                Markup::End if depth == 0 => return Ok((start, has_children)),
+                Markup::End => depth -= 1,
+                Markup::Other => {}
+            }
+        }
+    }
+}
+
+/// The value of the `name` attribute in the inside of a start tag
+fn name_attribute(tag: &str) -> Option<String> {
+    let mut rest = tag.split_once(|c: char| c.is_ascii_whitespace())?.1;
+    loop {
+        rest = rest.trim_start();
+        let (attribute, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+        let quote = after.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let (value, after) = after[1..].split_once(quote)?;
+        if attribute.trim() == "name" {
+            return Some(value.to_string());
+        }
+        rest = after;
+    }
+}
+
+/// Finds the contents of every `<ProtectedString name="Source">` and every
+/// `CLOUD_SOURCE_PROPERTIES` property in the place, in the order they appear.
+/// Only the markup is looked at, so offsets are into the file as it is on disk.
+fn find_spans(input: impl Read) -> io::Result<Vec<Span>> {
+    let mut scanner = ByteScanner {
+        input: BufReader::with_capacity(8 * 1024 * 1024, input),
+        position: 0,
+    };
+    let mut spans = Vec::new();
+    while let Some(byte) = scanner.next()? {
+        if byte != b'<' {
+            continue;
+        }
+        let Markup::Start { name, property, self_closing } = scanner.markup()? else {
+            continue;
+        };
+        let kind = match property.as_deref() {
+            Some("Source") if name == "ProtectedString" => SpanKind::Source,
+            Some(property) if CLOUD_SOURCE_PROPERTIES.contains(&property) => {
+                SpanKind::CloudSource { has_children: false }
+            }
+            _ => continue,
+        };
+        let start = scanner.position;
+        if self_closing {
+            // still counted, so the sources line up with the parser's
+            if kind == SpanKind::Source {
+                spans.push(Span { kind, range: start..start });
+            }
+            continue;
+        }
+        let (end, has_children) = scanner.element_end()?;
+        let kind = match kind {
+            SpanKind::CloudSource { .. } => SpanKind::CloudSource { has_children },
+            kind => kind,
+        };
+        spans.push(Span { kind, range: start..end });
+    }
+    Ok(spans)
+}
+
+/// A script found by `read_sources`
+struct ExactScript {
+    /// Which `<ProtectedString name="Source">` of the place it is, counting from 0
+    source_index: usize,
+    /// What comes before the bytecode in the source
+    header: String,
+    bytecode: Arc<str>,
+    bytecode_hash: String,
+    path: InstancePath,
+    classes: Vec<String>,
+    obfuscator: Option<&'static str>,
+}
+
+/// Parses the place for the scripts to decompile, numbering every
+/// `<ProtectedString name="Source">` the way `find_spans` does.
+/// Returns how many there were and how many scripts the filter skipped.
+fn read_sources(input: impl Read, filter: &ScriptFilter, tx: &mpsc::Sender<ExactScript>) -> Result<(usize, u32)> {
+    let file = BufReader::with_capacity(8 * 1024 * 1024, input);
+    let parser = EventReader::new(Utf8BoundaryReader::new(file, Arc::new(AtomicU64::new(0))));
+
+    let mut tracker = InstanceTracker::default();
+    let mut sources = 0usize;
+    let mut filtered_scripts = 0u32;
+    // the source being read: its index, whether it's a script's, and its text so far
+    let mut current: Option<(usize, bool, String)> = None;
+    let mut obfuscators: HashMap<String, Option<&'static str>> = HashMap::new();
+    for e in parser {
+        let e = e?;
+        match &e {
+            XmlEvent::StartElement { name, attributes, .. }
+                if name.local_name == "ProtectedString"
+                    && attributes
+                        .iter()
+                        .any(|attr| attr.name.local_name == "name" && attr.value == "Source") =>
+            {
+                tracker.observe(&e);
+                current = Some((sources, tracker.in_script_source(), String::new()));
+                sources += 1;
+                continue;
+            }
+            XmlEvent::CData(text) | XmlEvent::Characters(text) => {
+                if let Some((_, _, source)) = &mut current {
+                    source.push_str(text);
+                    continue;
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                if let Some((source_index, true, source)) = current.take() {
+                    if let Some((start, end)) = find_bytecode(&source) {
+                        let path = tracker.path();
+                        if filter.allows(&path, tracker.class_name()) {
+                            let bytecode_hash = hash_bytecode(&source[start..end]);
+                            let obfuscator = *obfuscators
+                                .entry(bytecode_hash.clone())
+                                .or_insert_with(|| detect_obfuscator(&source[start..end]));
+                            let script = ExactScript {
+                                source_index,
+                                header: source[..start].to_string(),
+                                bytecode: Arc::from(&source[start..end]),
+                                bytecode_hash,
+                                path,
+                                classes: tracker.class_names(),
+                                obfuscator,
+                            };
+                            // the receiver only goes away if submission failed, and it reports that itself
+                            if tx.blocking_send(script).is_err() {
+                                break;
+                            }
+                        } else {
+                            filtered_scripts += 1;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        tracker.observe(&e);
+    }
+    Ok((sources, filtered_scripts))
+}
+
+/// Copies the input to the output as it is, apart from the spans replaced along the way
+struct SpliceWriter {
+    input: File,
+    output: File,
+    /// How far into the input has been written out
+    copied_to: u64,
+}
+
+impl SpliceWriter {
+    /// Copies the input up to `range` and writes `replacement` instead of what's in it
+    fn splice(&mut self, range: &Range<u64>, replacement: &[u8]) -> io::Result<()> {
+        let length = range.start - self.copied_to;
+        if io::copy(&mut (&mut self.input).take(length), &mut self.output)? != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the place got shorter while it was processed"));
+        }
+        self.output.write_all(replacement)?;
+        self.input.seek(SeekFrom::Start(range.end))?;
+        self.copied_to = range.end;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        io::copy(&mut self.input, &mut self.output)?;
+        self.output.flush()
+    }
+}
+
+/// What an emptied cloud source property is left with, the same as the event
+/// by event writer leaves it
+fn cleared_cloud_source(has_children: bool) -> &'static [u8] {
+    if has_children {
+        b"<null></null>"
+    } else {
+        b""
+    }
+}
+
+/// Processes a place by copying it byte for byte and only rewriting the
+/// sources of the scripts being decompiled (and the emptied cloud source
+/// properties), instead of parsing and writing every event. Attribute order,
+/// whitespace and self-closing tags stay exactly as they were.
+pub async fn process_rbxlx_exact(
+    decompiler: &Decompiler,
+    input_file: &str,
+    output_file: &str,
+    options: &RbxlxOptions,
+) -> Result<()> {
+    let spans_input = File::open(input_file)?;
+    let spans_handle = tokio::task::spawn_blocking(move || find_spans(spans_input));
+
+    let (tx, mut rx) = mpsc::channel::<ExactScript>(READ_CHANNEL_CAPACITY);
+    let reader_input = File::open(input_file)?;
+    let filter = options.filter.clone();
+    let reader_handle = tokio::task::spawn_blocking(move || read_sources(reader_input, &filter, &tx));
+
+    let mut scripts = Vec::new();
+    let mut duplicate_scripts = 0u32;
+    // first occurrence of every bytecode, as in `process_rbxlx`
+    let mut seen: HashMap<String, (SharedResult, InstancePath)> = HashMap::new();
+    while let Some(script) = rx.recv().await {
+        if let Some((rx, first_path)) = seen.get(&script.bytecode_hash) {
+            duplicate_scripts += 1;
+            let duplicate_of = options.annotate_duplicates.then(|| first_path.clone());
+            scripts.push((script, rx.clone(), duplicate_of, Some(Reused::Duplicate)));
+            continue;
+        }
+
+        let (dec_tx, dec_rx) = oneshot::channel::<DecompileResult>();
+        let mut rx = spooled(options.spool.clone(), dec_rx).shared();
+        let asked_already = options.shared_results.as_ref().and_then(|shared| {
+            let mut shared = shared.lock().unwrap();
+            let existing = shared.get(&script.bytecode_hash).cloned();
+            if existing.is_none() {
+                shared.insert(script.bytecode_hash.clone(), rx.clone());
+            }
+            existing
+        });
+        let reused = match asked_already {
+            Some(existing) => {
+                rx = existing;
+                Some(Reused::OtherPlace)
+            }
+            None => {
+                let request =
+                    DecompilationRequest::with_hash(script.bytecode.clone(), script.bytecode_hash.clone(), dec_tx)
+                        .with_options(
+                            options
+                                .profiles
+                                .options_for(Some(&script.path), script.classes.last().map(String::as_str)),
+                        );
+                emit(&ProgressEvent::ScriptStarted {
+                    place: options.label.as_deref(),
+                    path: &script.path.to_string(),
+                    hash: &script.bytecode_hash,
+                });
+                decompiler.decompile_batch(vec![request]).await?;
+                None
+            }
+        };
+        seen.insert(script.bytecode_hash.clone(), (rx.clone(), script.path.clone()));
+        scripts.push((script, rx, None, reused));
+    }
+    let (sources, filtered_scripts) = reader_handle.await??;
+    let spans = spans_handle.await??;
+
+    let source_spans: Vec<&Range<u64>> = spans
+        .iter()
+        .filter(|span| span.kind == SpanKind::Source)
+        .map(|span| &span.range)
+        .collect();
+    if source_spans.len() != sources {
+        return Err(Error::from(format!(
+            "found {} sources in the markup of {} but {} parsing it, run without --exact",
+            source_spans.len(),
+            input_file,
+            sources
+        )));
+    }
+    let mut cloud_sources = spans
+        .iter()
+        .filter_map(|span| match span.kind {
+            SpanKind::CloudSource { has_children } if !options.keep_linked_source => {
+                Some((&span.range, cleared_cloud_source(has_children)))
+            }
+            _ => None,
+        })
+        .peekable();
+
+    let template = options
+        .template
+        .clone()
+        .unwrap_or_else(|| OutputTemplate::default_for(options.strip_bytecode));
+    let total = scripts.len();
+    let mut failed = 0usize;
+    let mut cleared = 0u32;
+    let mut writer = SpliceWriter {
+        input: File::open(input_file)?,
+        output: File::create(output_file)?,
+        copied_to: 0,
+    };
+    for (done, (script, rx, duplicate_of, reused)) in scripts.into_iter().enumerate() {
+        let range = source_spans[script.source_index];
+        while let Some((cloud_range, replacement)) =
+            cloud_sources.next_if(|(cloud_range, _)| cloud_range.start < range.start)
+        {
+            writer.splice(cloud_range, replacement)?;
+            cleared += 1;
+        }
+
+        let result = rx
+            .await
+            .unwrap_or_else(|_| Err("oracle-postprocess error: sender dropped".to_string().into()))
+            .and_then(|spooled| spooled.load());
+        failed += usize::from(result.is_err());
+        let path = script.path.to_string();
+        emit(&match &result {
+            Ok(_) => ProgressEvent::ScriptFinished {
+                place: options.label.as_deref(),
+                path: &path,
+                hash: &script.bytecode_hash,
+            },
+            Err(e) => ProgressEvent::ScriptFailed {
+                place: options.label.as_deref(),
+                path: &path,
+                hash: &script.bytecode_hash,
+                error: &e.message,
+                category: e.category,
+            },
+        });
+        if let Some(report) = &options.report {
+            report.lock().unwrap().push(ScriptReport {
+                path,
+                class_name: script.classes.last().cloned().unwrap_or_default(),
+                hash: script.bytecode_hash.clone(),
+                bytecode_size: script.bytecode.len(),
+                output_size: result.as_ref().map_or(0, String::len),
+                success: result.is_ok(),
+                error: result.as_ref().err().map(ToString::to_string),
+                error_category: result.as_ref().err().map(|e| e.category),
+                obfuscator: script.obfuscator.map(str::to_string),
+                place: options.label.clone(),
+                reused,
+            });
+        }
+
+        let decompilation = match result {
+            Ok(source) => {
+                let source = if options.format_lua {
+                    luau::format_or_keep(source, &format_args!("game.{}", script.path))
+                } else {
+                    source
+                };
+                let source = if options.size_comment {
+                    with_size_line(source, &script.bytecode)
+                } else {
+                    source
+                };
+                let source = if options.integrity { with_integrity_line(source) } else { source };
+                format!("-- decompilation:\n{}", source)
+            }
+            Err(e) => {
+                let failed_bytecode = script.bytecode.clone();
+                let failed_hash = script.bytecode_hash.clone();
+                let disasm = options.disasm;
+                let failures_dir = options.run.failures_dir();
+                let disassembly = tokio::task::spawn_blocking(move || {
+                    save_failure(&failed_bytecode, &failed_hash, disasm, &failures_dir)
+                })
+                .await
+                .unwrap_or_default();
+                format!("-- decompilation failed:\n-- {}{}", e, disassembly)
+            }
+        };
+        let decompilation = match duplicate_of {
+            Some(first_path) => format!("-- duplicate of game.{}\n{}", first_path, decompilation),
+            None => decompilation,
+        };
+        let decompilation = match script.obfuscator {
+            Some(obfuscator) => format!("-- Obfuscator: {} (detected)\n{}", obfuscator, decompilation),
+            None => decompilation,
+        };
+        let names = options.debug_names.then(|| debug_names(&script.bytecode)).flatten();
+        let decompilation = match names.and_then(|names| names.header_line()) {
+            Some(line) => format!("{}\n{}", line, decompilation),
+            None => decompilation,
+        };
+        let source = template.render(&TemplateFields {
+            header: &script.header,
+            bytecode: &script.bytecode,
+            decompilation: &decompilation,
+            path: &script.path.to_string(),
+            class_name: script.classes.last().map_or("", String::as_str),
+            hash: &script.bytecode_hash,
+        });
+        let cdata = format!("<![CDATA[{}]]>", source.replace("]]>", "]]]]><![CDATA[>"));
+        writer.splice(range, cdata.as_bytes())?;
+
+        if (done + 1) % 100 == 0 {
+            info!("decompiling: {}/{} | {} failed", done + 1, total, failed);
+            emit(&ProgressEvent::QueueDepth {
+                place: options.label.as_deref(),
+                found: total as u32,
+                done: done as u32 + 1,
+                waiting: (total - done - 1) as u32,
+            });
+        }
+    }
+    for (cloud_range, replacement) in cloud_sources {
+        writer.splice(cloud_range, replacement)?;
+        cleared += 1;
+    }
+    writer.finish()?;
+
+    if filtered_scripts > 0 {
+        info!("{} scripts skipped by --root/--include/--exclude/--only-class", filtered_scripts);
+    }
+    if duplicate_scripts > 0 {
+        info!("{} duplicate scripts reused an earlier result", duplicate_scripts);
+    }
+    if cleared > 0 {
+        info!("emptied {} LinkedSource and ScriptGuid properties, so Studio keeps the decompiled sources", cleared);
+    }
+    if total == 0 {
+        info!("no scripts found to decompile");
+    }
+    info!("{} scripts rewritten, everything else copied as it was", total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "-- Bytecode (Base64):\n-- BgMBAQAAAQA=\n";
+
+    fn script(class: &str, name: &str, source: &str) -> String {
+        format!(
+            "<Item class=\"{}\" referent=\"RBX{}\"><Properties>\
+             <string name=\"Name\">{}</string>\
+             <ProtectedString name=\"Source\"><![CDATA[{}]]></ProtectedString>\
+             </Properties></Item>",
+            class, name, name, source
+        )
+    }
+
+    fn spans(place: &str) -> Vec<Span> {
+        find_spans(place.as_bytes()).unwrap()
+    }
+
+    /// The contents of every span, as text
+    fn contents<'a>(place: &'a str, spans: &[Span]) -> Vec<&'a str> {
+        spans
+            .iter()
+            .map(|span| &place[span.range.start as usize..span.range.end as usize])
+            .collect()
+    }
+
+    #[test]
+    fn the_name_attribute_is_found_wherever_it_is() {
+        assert_eq!(name_attribute("string name=\"Source\""), Some("Source".to_string()));
+        assert_eq!(name_attribute("string name='Source'"), Some("Source".to_string()));
+        assert_eq!(name_attribute("string  name = \"Source\" "), Some("Source".to_string()));
+        assert_eq!(name_attribute("string a=\"name=x\" name=\"Source\""), Some("Source".to_string()));
+        assert_eq!(name_attribute("string names=\"Source\""), None);
+        assert_eq!(name_attribute("string name=Source"), None);
+        assert_eq!(name_attribute("string"), None);
+    }
+
+    #[test]
+    fn spans_cover_exactly_what_is_between_the_tags() {
+        let place = format!("<roblox>{}{}</roblox>", script("Script", "A", SOURCE), script("ModuleScript", "B", "x"));
+        let spans = spans(&place);
+        assert_eq!(
+            contents(&place, &spans),
+            [format!("<![CDATA[{}]]>", SOURCE).as_str(), "<![CDATA[x]]>"]
+        );
+        assert!(spans.iter().all(|span| span.kind == SpanKind::Source));
+    }
+
+    #[test]
+    fn markup_that_only_looks_like_a_source_is_skipped() {
+        let place = "<roblox><!-- <ProtectedString name=\"Source\">no</ProtectedString> -->\
+                     <?pi <ProtectedString name=\"Source\"> ?>\
+                     <string name=\"Source\">no</string>\
+                     <ProtectedString name=\"Sources\">no</ProtectedString>\
+                     <ProtectedString a=\"1 > 0\" name=\"Source\">yes</ProtectedString></roblox>";
+        assert_eq!(contents(place, &spans(place)), ["yes"]);
+    }
+
+    #[test]
+    fn prefixed_and_self_closing_sources_are_still_counted() {
+        let place = "<roblox><x:ProtectedString name=\"Source\">a</x:ProtectedString>\
+                     <ProtectedString name=\"Source\"/>\
+                     <ProtectedString name=\"Source\">c</ProtectedString></roblox>";
+        let spans = spans(place);
+        assert_eq!(contents(place, &spans), ["a", "", "c"]);
+    }
+
+    #[test]
+    fn cloud_sources_say_whether_they_hold_an_element() {
+        let place = "<roblox><Content name=\"LinkedSource\"><url>rbxassetid://1</url></Content>\
+                     <string name=\"ScriptGuid\">{guid}</string>\
+                     <Content name=\"LinkedSource\"><null></null></Content></roblox>";
+        let kinds: Vec<SpanKind> = spans(place).iter().map(|span| span.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                SpanKind::CloudSource { has_children: true },
+                SpanKind::CloudSource { has_children: false },
+                SpanKind::CloudSource { has_children: true },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_place_cut_off_inside_an_element_is_an_error() {
+        let error = find_spans("<roblox><ProtectedString name=\"Source\">abc".as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn sources_are_numbered_the_same_as_spans() {
+        let place = format!(
+            "<roblox>{}<ProtectedString name=\"Source\"/>{}{}</roblox>",
+            script("Script", "Plain", "print(1)"),
+            script("LocalScript", "First", SOURCE),
+            script("ModuleScript", "Second", SOURCE),
+        );
+        let (tx, mut rx) = mpsc::channel(8);
+        let (sources, filtered) = read_sources(place.as_bytes(), &ScriptFilter::default(), &tx).unwrap();
+        drop(tx);
+        assert_eq!((sources, filtered), (spans(&place).len(), 0));
+
+        let mut found = Vec::new();
+        while let Ok(script) = rx.try_recv() {
+            found.push((script.source_index, script.path.to_string(), script.header));
+        }
+        assert_eq!(
+            found,
+            [
+                (2, "First".to_string(), "-- Bytecode (Base64):\n-- ".to_string()),
+                (3, "Second".to_string(), "-- Bytecode (Base64):\n-- ".to_string()),
+            ]
+        );
+        let spans = spans(&place);
+        assert!(contents(&place, &spans)[2].contains("BgMBAQAAAQA="));
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::AtomicU64, Arc};
+
+use base64::{engine::general_purpose, Engine as _};
+use clap::ValueEnum;
+use rbx_dom_weak::{types::Variant, ustr};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+use xml::reader::{EventReader, XmlEvent};
+use tracing::{info, warn};
+
+use crate::error::Result;
+use crate::compiled::{find_bytecode, get_bytecode_from_file};
+use crate::folder::collect_files;
+use crate::instance::{InstancePath, InstanceTracker};
+use crate::naming::ScriptFileNamer;
+use crate::rbxlx::Utf8BoundaryReader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BytecodeFormat {
+    /// Decoded bytecode, as produced by the compiler
+    Raw,
+    /// Base64 text, as embedded in the place file
+    Base64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileNaming {
+    /// Mirror the instance tree, e.g. ServerScriptService/Main.bin
+    Path,
+    /// Name each file after the sha256 of its bytecode
+    Hash,
+}
+
+enum InputKind {
+    Rbxl,
+    Rbxlx,
+    Bytecode,
+}
+
+fn detect_input_kind(path: &Path) -> std::io::Result<InputKind> {
+    let mut header = [0u8; 16];
+    let read = File::open(path)?.read(&mut header)?;
+    let header = &header[..read];
+
+    if header.starts_with(b"<roblox!") {
+        Ok(InputKind::Rbxl)
+    } else if header.trim_ascii_start().starts_with(b"<") {
+        Ok(InputKind::Rbxlx)
+    } else {
+        Ok(InputKind::Bytecode)
+    }
+}
+
+struct Extractor {
+    format: BytecodeFormat,
+    naming: FileNaming,
+    namer: ScriptFileNamer,
+    written: u32,
+    duplicates: u32,
+    failed: u32,
+}
+
+impl Extractor {
+    fn output_path(&mut self, path: &InstancePath, hash: &str) -> Option<PathBuf> {
+        if self.naming == FileNaming::Hash || path.segments().is_empty() {
+            return self.namer.path_for_hash(path, hash);
+        }
+        Some(self.namer.path_for(path))
+    }
+
+    fn write(&mut self, path: &InstancePath, bytecode: &str) -> Result<()> {
+        let hash = format!("{:x}", Sha256::digest(bytecode.as_bytes()));
+
+        let Some(out) = self.output_path(path, &hash) else {
+            self.duplicates += 1;
+            return Ok(());
+        };
+
+        let contents = match self.format {
+            BytecodeFormat::Base64 => bytecode.as_bytes().to_vec(),
+            BytecodeFormat::Raw => match general_purpose::STANDARD.decode(bytecode.as_bytes()) {
+                Ok(raw) => raw,
+                Err(e) => {
+                    warn!("couldn't extract {}: invalid base64: {}", path, e);
+                    self.failed += 1;
+                    return Ok(());
+                }
+            },
+        };
+
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&out, contents)?;
+        self.written += 1;
+
+        Ok(())
+    }
+}
+
+/// A script `for_each_script` came across
+pub struct FoundScript<'a> {
+    pub path: &'a InstancePath,
+    /// `None` if the input doesn't say, as for loose bytecode files
+    pub class_name: Option<&'a str>,
+    /// Base64, the way the place embeds it
+    pub bytecode: &'a str,
+    /// Whatever the source has after the bytecode, such as an earlier decompilation
+    pub rest: &'a str,
+}
+
+impl FoundScript<'_> {
+    /// The decompilation a processed place has after the bytecode, if it has one
+    pub fn decompilation(&self) -> Option<&str> {
+        let start = self.rest.find(DECOMPILATION_MARKER)? + DECOMPILATION_MARKER.len();
+        Some(self.rest[start..].trim_end_matches('\n'))
+    }
+}
+
+/// What comes before a decompilation in a processed place
+const DECOMPILATION_MARKER: &str = "-- decompilation:\n";
+
+type ScriptVisitor<'a> = dyn FnMut(&FoundScript) -> Result<()> + 'a;
+
+fn visit_rbxlx(input: &Path, visit: &mut ScriptVisitor) -> Result<()> {
+    let file = BufReader::with_capacity(8 * 1024 * 1024, File::open(input)?);
+    let utf8_reader = Utf8BoundaryReader::new(file, Arc::new(AtomicU64::new(0)));
+    let parser = EventReader::new(utf8_reader);
+    let mut tracker = InstanceTracker::default();
+
+    for e in parser {
+        let e = e?;
+        if let XmlEvent::CData(cdata_string) = &e {
+            if !tracker.in_script_source() {
+                continue;
+            }
+            if let Some((start, end)) = find_bytecode(cdata_string) {
+                let classes = tracker.class_names();
+                visit(&FoundScript {
+                    path: &tracker.path(),
+                    class_name: classes.last().map(String::as_str),
+                    bytecode: &cdata_string[start..end],
+                    rest: &cdata_string[end..],
+                })?;
+            }
+        }
+        tracker.observe(&e);
+    }
+
+    Ok(())
+}
+
+fn visit_rbxl(input: &Path, visit: &mut ScriptVisitor) -> Result<()> {
+    let file = BufReader::with_capacity(8 * 1024 * 1024, File::open(input)?);
+    let dom = rbx_binary::from_reader(file)?;
+
+    for instance in dom.descendants() {
+        let Some(Variant::String(source)) = instance.properties.get(&ustr("Source")) else {
+            continue;
+        };
+        let Some((start, end)) = find_bytecode(source) else {
+            continue;
+        };
+
+        let path = InstancePath::new(
+            dom.full_path_of(instance.referent(), "\0")
+                .split('\0')
+                .map(str::to_string)
+                .collect(),
+        );
+        visit(&FoundScript {
+            path: &path,
+            class_name: Some(instance.class.as_str()),
+            bytecode: &source[start..end],
+            rest: &source[end..],
+        })?;
+    }
+
+    Ok(())
+}
+
+fn visit_folder(input: &Path, visit: &mut ScriptVisitor) -> Result<()> {
+    let input = input.canonicalize()?;
+
+    for file in collect_files(&input) {
+        let Ok((bytecode, _)) = get_bytecode_from_file(&file.to_string_lossy()) else {
+            continue;
+        };
+
+        let rel = file.strip_prefix(&input)?.with_extension("");
+        let path = InstancePath::new(
+            rel.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect(),
+        );
+        visit(&FoundScript {
+            path: &path,
+            class_name: None,
+            bytecode: &bytecode,
+            rest: "",
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Calls `visit` with every script in a .rbxlx or .rbxl place,
+/// a bytecode file, or a folder of bytecode files
+pub fn for_each_script(
+    input: &str,
+    mut visit: impl FnMut(&FoundScript) -> Result<()>,
+) -> Result<()> {
+    let input_path = Path::new(input);
+    if input_path.is_dir() {
+        return visit_folder(input_path, &mut visit);
+    }
+
+    match detect_input_kind(input_path)? {
+        InputKind::Rbxl => visit_rbxl(input_path, &mut visit),
+        InputKind::Rbxlx => visit_rbxlx(input_path, &mut visit),
+        InputKind::Bytecode => {
+            let (bytecode, _) = get_bytecode_from_file(input)?;
+            let name = input_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            visit(&FoundScript {
+                path: &InstancePath::new(vec![name]),
+                class_name: None,
+                bytecode: &bytecode,
+                rest: "",
+            })
+        }
+    }
+}
+
+pub fn extract_bytecode(
+    input: &str,
+    output_dir: &str,
+    format: BytecodeFormat,
+    naming: FileNaming,
+) -> Result<()> {
+    let extension = match format {
+        BytecodeFormat::Raw => "bin",
+        BytecodeFormat::Base64 => "b64",
+    };
+    let mut extractor = Extractor {
+        format,
+        naming,
+        namer: ScriptFileNamer::new(Path::new(output_dir), extension),
+        written: 0,
+        duplicates: 0,
+        failed: 0,
+    };
+
+    for_each_script(input, |script| extractor.write(script.path, script.bytecode))?;
+    extractor.namer.write_manifest()?;
+
+    info!(
+        "extracted {} scripts to {} ({} duplicates, {} failed)",
+        extractor.written, output_dir, extractor.duplicates, extractor.failed
+    );
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use crate::instance::InstancePath;
+
+/// A `/`-separated glob over instance paths.
+///
+/// `*` and `?` match within a single name, `**` matches any number of names.
+/// A pattern without a `/` matches an instance with that name anywhere in the
+/// tree, and everything below it.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    segments: Vec<String>,
+    anchored: bool,
+}
+
+fn matches_segment(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_segment(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && matches_segment(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && matches_segment(rest, &name[1..]),
+    }
+}
+
+/// Whether `name` matches `pattern`, a single name with `*` and `?` in it
+pub fn matches_name(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_segment(&pattern, &name)
+}
+
+fn matches_segments(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment == "**" => {
+            (0..=path.len()).any(|skip| matches_segments(rest, &path[skip..]))
+        }
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                let segment: Vec<char> = segment.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                matches_segment(&segment, &name) && matches_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let pattern = pattern.trim_matches('/');
+        Self {
+            segments: pattern.split('/').map(str::to_string).collect(),
+            anchored: pattern.contains('/'),
+        }
+    }
+
+    pub fn matches(&self, path: &InstancePath) -> bool {
+        let path = path.segments();
+        if self.anchored {
+            return matches_segments(&self.segments, path);
+        }
+
+        let segment: Vec<char> = self.segments[0].chars().collect();
+        path.iter().any(|name| {
+            let name: Vec<char> = name.chars().collect();
+            matches_segment(&segment, &name)
+        })
+    }
+}
+
+/// Decides which scripts get decompiled based on `--root`, `--include`,
+/// `--exclude` and `--only-class`
+#[derive(Debug, Clone, Default)]
+pub struct ScriptFilter {
+    /// Instance subtrees scripts have to be in, if any are given
+    roots: Vec<Vec<String>>,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    /// Classes scripts have to be, if any are given
+    classes: Vec<String>,
+}
+
+impl ScriptFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        Self {
+            include: include.iter().map(|pattern| Glob::new(pattern)).collect(),
+            exclude: exclude.iter().map(|pattern| Glob::new(pattern)).collect(),
+            roots: Vec::new(),
+            classes: Vec::new(),
+        }
+    }
+
+    /// Only allows scripts at or below one of `roots`, given as `.`-separated
+    /// instance paths like `Workspace.Map`, with or without `game.` in front
+    pub fn with_roots(mut self, roots: &[String]) -> Self {
+        self.roots = roots
+            .iter()
+            .map(|root| {
+                let root = root.strip_prefix("game.").unwrap_or(root);
+                root.split('.').map(str::to_string).collect()
+            })
+            .collect();
+        self
+    }
+
+    /// Only allows scripts of one of `classes`, like `LocalScript`
+    pub fn with_classes(mut self, classes: &[String]) -> Self {
+        self.classes = classes.to_vec();
+        self
+    }
+
+    pub fn allows(&self, path: &InstancePath, class_name: Option<&str>) -> bool {
+        if !self.classes.is_empty()
+            && !class_name.is_some_and(|class_name| self.classes.iter().any(|class| class == class_name))
+        {
+            return false;
+        }
+        if !self.roots.is_empty() && !self.roots.iter().any(|root| path.segments().starts_with(root)) {
+            return false;
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|glob| glob.matches(path)) {
+            return false;
+        }
+        !self.exclude.iter().any(|glob| glob.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> InstancePath {
+        InstancePath::new(path.split('.').map(str::to_string).collect())
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn wildcards_stay_within_a_name() {
+        assert!(matches_name("Gui*", "GuiHandler"));
+        assert!(matches_name("*Handler", "GuiHandler"));
+        assert!(matches_name("G?i*r", "GuiHandler"));
+        assert!(matches_name("*", ""));
+        assert!(!matches_name("?", ""));
+        assert!(!matches_name("Gui", "GuiHandler"));
+        assert!(matches_name("Sk?pt", "Sk\u{e9}pt"));
+        assert!(!Glob::new("Workspace/*").matches(&path("Workspace.Map.Script")));
+    }
+
+    #[test]
+    fn double_stars_match_any_number_of_names() {
+        let glob = Glob::new("Workspace/**/Script");
+        assert!(glob.matches(&path("Workspace.Script")));
+        assert!(glob.matches(&path("Workspace.Map.Doors.Script")));
+        assert!(!glob.matches(&path("ReplicatedStorage.Script")));
+        assert!(Glob::new("**").matches(&path("Workspace.Map")));
+    }
+
+    #[test]
+    fn a_name_without_slashes_matches_anywhere_below_it() {
+        let glob = Glob::new("Map");
+        assert!(glob.matches(&path("Workspace.Map")));
+        assert!(glob.matches(&path("Workspace.Map.Doors.Script")));
+        assert!(!glob.matches(&path("Workspace.Maps.Script")));
+        // slashes around the pattern don't anchor it
+        assert!(Glob::new("/Map/").matches(&path("Workspace.Map.Script")));
+    }
+
+    #[test]
+    fn anchored_globs_match_the_whole_path() {
+        let glob = Glob::new("Workspace/Map");
+        assert!(glob.matches(&path("Workspace.Map")));
+        assert!(!glob.matches(&path("Workspace.Map.Script")));
+        assert!(!glob.matches(&path("Game.Workspace.Map")));
+    }
+
+    #[test]
+    fn excludes_win_over_includes() {
+        let filter = ScriptFilter::new(&patterns(&["Workspace/**"]), &patterns(&["Doors"]));
+        assert!(filter.allows(&path("Workspace.Map.Script"), None));
+        assert!(!filter.allows(&path("Workspace.Doors.Script"), None));
+        assert!(!filter.allows(&path("ServerScriptService.Script"), None));
+        assert!(ScriptFilter::default().allows(&path("Anything"), None));
+    }
+
+    #[test]
+    fn roots_take_whole_names_with_or_without_game() {
+        let filter = ScriptFilter::default().with_roots(&patterns(&["game.Workspace.Map", "ReplicatedStorage"]));
+        assert!(filter.allows(&path("Workspace.Map"), None));
+        assert!(filter.allows(&path("Workspace.Map.Doors.Script"), None));
+        assert!(filter.allows(&path("ReplicatedStorage.Module"), None));
+        assert!(!filter.allows(&path("Workspace.Maps.Script"), None));
+        assert!(!filter.allows(&path("Workspace.Script"), None));
+    }
+
+    #[test]
+    fn roots_and_globs_both_have_to_allow_a_script() {
+        let filter = ScriptFilter::new(&patterns(&["*Handler"]), &[]).with_roots(&patterns(&["Workspace"]));
+        assert!(filter.allows(&path("Workspace.GuiHandler"), None));
+        assert!(!filter.allows(&path("StarterGui.GuiHandler"), None));
+        assert!(!filter.allows(&path("Workspace.Script"), None));
+    }
+
+    #[test]
+    fn classes_have_to_match_exactly() {
+        let filter = ScriptFilter::default().with_classes(&patterns(&["LocalScript", "ModuleScript"]));
+        assert!(filter.allows(&path("StarterGui.Gui"), Some("LocalScript")));
+        assert!(filter.allows(&path("ReplicatedStorage.Module"), Some("ModuleScript")));
+        assert!(!filter.allows(&path("Workspace.Script"), Some("Script")));
+        assert!(!filter.allows(&path("Workspace.Script"), Some("localscript")));
+        // without a class there's nothing to match
+        assert!(!filter.allows(&path("Workspace.Script"), None));
+    }
+
+    #[test]
+    fn classes_combine_with_roots_and_globs() {
+        let filter = ScriptFilter::new(&[], &patterns(&["Debug*"]))
+            .with_roots(&patterns(&["StarterGui"]))
+            .with_classes(&patterns(&["LocalScript"]));
+        assert!(filter.allows(&path("StarterGui.Menu"), Some("LocalScript")));
+        assert!(!filter.allows(&path("StarterGui.DebugMenu"), Some("LocalScript")));
+        assert!(!filter.allows(&path("StarterPlayer.Menu"), Some("LocalScript")));
+        assert!(!filter.allows(&path("StarterGui.Menu"), Some("ModuleScript")));
+    }
+}
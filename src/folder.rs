@@ -1,22 +1,44 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
-use sha2::{Digest, Sha256};
+use futures::StreamExt;
 use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
 
-use crate::compiled::get_bytecode_from_file;
-use crate::decompiler::{DecompilationRequest, Decompiler};
+use crate::error::{Error, Result};
+use crate::compiled::{load_bytecode_file, LOAD_CONCURRENCY};
+use crate::decompiler::{DecompilationRequest, DecompileError, DecompileResult, Decompiler, FailureCategory};
+use crate::directives::hoist_directives;
+use crate::disasm::{debug_names, disassembly_comment, request_disassembly, write_disassembly};
+use crate::integrity::with_integrity_line;
+use crate::luau;
+use crate::sizes::with_size_line;
 
 struct FileJob {
     input_path: PathBuf,
     output_path: PathBuf,
     bytecode: Arc<str>,
     header: Option<String>,
-    rx: oneshot::Receiver<Result<String, String>>,
+    /// When to stop waiting for the oracle, if ever
+    deadline: Option<Instant>,
+    rx: oneshot::Receiver<DecompileResult>,
+    /// For the `.disasm` file, with `--with-disasm`
+    disassembly: Option<oneshot::Receiver<DecompileResult>>,
 }
 
-fn collect_files(dir: &Path) -> Vec<PathBuf> {
+/// What went wrong in a batch of files. Timed out files count as failed too.
+#[derive(Default)]
+struct FileCounts {
+    /// Files that were bytecode and sent off, skipped ones aren't counted
+    queued: u32,
+    failed: u32,
+    timed_out: u32,
+}
+
+pub fn collect_files(dir: &Path) -> Vec<PathBuf> {
     let mut files = Vec::new();
     let mut stack = vec![dir.to_path_buf()];
     while let Some(current) = stack.pop() {
@@ -37,67 +59,265 @@ fn collect_files(dir: &Path) -> Vec<PathBuf> {
     files
 }
 
+/// How results are turned into `.lua` files
+#[derive(Clone, Copy, Default)]
+pub struct RenderOptions {
+    /// Run decompiled scripts through StyLua
+    pub format_lua: bool,
+    /// Append a local disassembly to scripts the oracle couldn't decompile
+    pub disasm: bool,
+    /// Write each script's disassembly next to it, as a `.disasm` file
+    pub with_disasm: bool,
+    /// Put an integrity line in front of every decompiled script
+    pub integrity: bool,
+    /// Put a line comparing bytecode and decompiled sizes in front of every decompiled script
+    pub size_comment: bool,
+    /// Put the names kept in the bytecode's debug info in front of every
+    /// decompiled script, and name dumped scripts after their chunk name
+    pub debug_names: bool,
+    /// Write `.luau` files, with the script's `--!` directives at the top
+    pub luau: bool,
+}
+
+impl RenderOptions {
+    /// What written scripts end in
+    pub fn extension(&self) -> &'static str {
+        if self.luau {
+            "luau"
+        } else {
+            "lua"
+        }
+    }
+}
+
+/// Turns an oracle result into the contents of a `.lua` file, keeping the
+/// rbxlx-style header and bytecode in front of it if the input had them
+pub fn render_result(
+    name: &dyn std::fmt::Display,
+    bytecode: &str,
+    header: Option<String>,
+    result: DecompileResult,
+    options: RenderOptions,
+) -> String {
+    let rendered = render_contents(name, bytecode, header, result, options);
+    if options.luau {
+        hoist_directives(&rendered)
+    } else {
+        rendered
+    }
+}
+
+fn render_contents(
+    name: &dyn std::fmt::Display,
+    bytecode: &str,
+    header: Option<String>,
+    result: DecompileResult,
+    options: RenderOptions,
+) -> String {
+    match result {
+        Ok(source) => {
+            let source = if options.format_lua {
+                luau::format_or_keep(source, name)
+            } else {
+                source
+            };
+            let source = match options.debug_names.then(|| debug_names(bytecode)).flatten().and_then(|names| names.header_line()) {
+                Some(line) => format!("{}\n{}", line, source),
+                None => source,
+            };
+            let source = if options.size_comment {
+                with_size_line(source, bytecode)
+            } else {
+                source
+            };
+            let source = if options.integrity {
+                with_integrity_line(source)
+            } else {
+                source
+            };
+            match header {
+                Some(header) => {
+                    format!("{}{}\n\n-- decompilation:\n{}", header, bytecode, source)
+                }
+                None => source,
+            }
+        }
+        Err(err) => {
+            warn!("couldn't decompile {}: {}", name, err);
+            let disassembly = if options.disasm {
+                disassembly_comment(bytecode)
+            } else {
+                String::new()
+            };
+            match header {
+                Some(header) => format!(
+                    "{}{}\n\n-- decompilation failed:\n-- {}{}",
+                    header, bytecode, err, disassembly
+                ),
+                None => format!("-- decompilation failed:\n-- {}{}", err, disassembly),
+            }
+        }
+    }
+}
+
+/// Decompiles every blob found in one file at once and renders them one
+/// after the other, each behind a `-- bytecode i of n` line when there's
+/// more than one. Gives up on all of them after `timeout`. The reasons the
+/// ones that failed did so are returned along with it.
+pub async fn render_blobs(
+    decompiler: &Decompiler,
+    name: &str,
+    blobs: Vec<(String, Option<String>)>,
+    options: RenderOptions,
+    timeout: Option<Duration>,
+) -> Result<(String, Vec<String>)> {
+    let results = blobs
+        .iter()
+        .map(|(bytecode, _)| decompiler.try_decompile(bytecode))
+        .collect::<Result<Vec<_>>>()?;
+    let results = futures::future::join_all(results);
+    let results = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, results)
+            .await
+            .map_err(|_| Error::Timeout(timeout))?,
+        None => results.await,
+    };
+
+    let count = blobs.len();
+    let mut rendered = Vec::new();
+    let mut failures = Vec::new();
+    for (i, ((bytecode, header), result)) in blobs.into_iter().zip(results).enumerate() {
+        let result = result?;
+        if let Err(e) = &result {
+            failures.push(e.to_string());
+        }
+        if count == 1 {
+            rendered.push(render_result(&name, &bytecode, header, result, options));
+        } else {
+            let rendered_blob = render_result(&format_args!("{} ({})", name, i + 1), &bytecode, header, result, options);
+            rendered.push(format!("-- bytecode {} of {}\n{}", i + 1, count, rendered_blob));
+        }
+    }
+    Ok((rendered.join("\n\n"), failures))
+}
+
 pub async fn process_folder(
     decompiler: &Decompiler,
     input_dir: &str,
     output_dir: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+    options: RenderOptions,
+) -> Result<()> {
     let input_path = Path::new(input_dir).canonicalize()?;
     let output_path = Path::new(output_dir);
 
     let all_files = collect_files(&input_path);
-    println!("found {} files in {}", all_files.len(), input_dir);
+    info!("found {} files in {}", all_files.len(), input_dir);
+
+    let mut files = Vec::new();
+    for file in all_files {
+        let rel = file.strip_prefix(&input_path)?;
+        let mut out = output_path.join(rel);
+        out.set_extension(options.extension());
+        files.push((file, out));
+    }
+
+    let counts = decompile_files(decompiler, files, options, None).await?;
+    Error::check_failures(counts.failed as usize, counts.queued as usize, "files")
+}
 
+/// Decompiles each of `inputs` into a `.lua` file named after it in
+/// `output_dir`. Inputs that end up with the same name get `_2`, `_3`, ...
+/// suffixes. Any of them failing or timing out is an error, once they're
+/// all written.
+pub async fn process_files(
+    decompiler: &Decompiler,
+    inputs: &[String],
+    output_dir: &str,
+    options: RenderOptions,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let output_path = Path::new(output_dir);
+    let mut used_names = HashSet::new();
+    let mut files = Vec::new();
+    for input in inputs {
+        let stem = Path::new(input)
+            .file_stem()
+            .map_or_else(|| "_".to_string(), |stem| stem.to_string_lossy().to_string());
+        let mut name = format!("{}.{}", stem, options.extension());
+        let mut n = 2;
+        while !used_names.insert(name.to_lowercase()) {
+            name = format!("{}_{}.{}", stem, n, options.extension());
+            n += 1;
+        }
+        files.push((PathBuf::from(input), output_path.join(name)));
+    }
+
+    let counts = decompile_files(decompiler, files, options, timeout).await?;
+    if let (Some(timeout), true) = (timeout, counts.timed_out > 0) {
+        return Err(Error::Timeout(timeout));
+    }
+    Error::check_failures(counts.failed as usize, counts.queued as usize, "files")
+}
+
+/// Sends every bytecode file in `files` off at once and writes each result
+/// to the `.lua` file it's paired with. Files that aren't bytecode are
+/// skipped, results that take longer than `timeout` count as failures.
+async fn decompile_files(
+    decompiler: &Decompiler,
+    files: Vec<(PathBuf, PathBuf)>,
+    options: RenderOptions,
+    timeout: Option<Duration>,
+) -> Result<FileCounts> {
     let mut jobs: Vec<FileJob> = Vec::new();
     let mut skipped = 0u32;
 
-    for file in &all_files {
-        let file_str = file.to_string_lossy();
-        let Ok((bytecode, header)) = get_bytecode_from_file(&file_str) else {
+    let mut loads = futures::stream::iter(files)
+        .map(|(file, out)| async move {
+            let loaded = load_bytecode_file(file.to_string_lossy().to_string()).await;
+            (file, out, loaded)
+        })
+        .buffered(LOAD_CONCURRENCY);
+
+    while let Some((file, out, loaded)) = loads.next().await {
+        let Ok(loaded) = loaded else {
             skipped += 1;
             continue;
         };
 
-        let rel = file.strip_prefix(&input_path)?;
-        let mut out = output_path.join(rel);
-        out.set_extension("lua");
-
         if let Some(parent) = out.parent() {
             std::fs::create_dir_all(parent)?;
         }
 
         let (tx, rx) = oneshot::channel();
-        let bytecode_arc: Arc<str> = Arc::from(bytecode.as_str());
-        let bytecode_hash = format!("{:x}", Sha256::digest(bytecode.as_bytes()));
-        let bytecode_len = bytecode.len() as u32;
-
-        let request = DecompilationRequest {
-            bytecode: bytecode_arc.clone(),
-            bytecode_hash,
-            bytecode_len,
-            tx,
-        };
+        let request = DecompilationRequest::with_hash(loaded.bytecode.clone(), loaded.hash.clone(), tx);
 
         decompiler.decompile_batch(vec![request]).await?;
+        let disassembly = if options.with_disasm {
+            Some(request_disassembly(decompiler, loaded.bytecode.clone(), loaded.hash)?)
+        } else {
+            None
+        };
 
         jobs.push(FileJob {
-            input_path: file.clone(),
+            input_path: file,
             output_path: out,
-            bytecode: bytecode_arc,
-            header,
+            bytecode: loaded.bytecode,
+            header: loaded.header,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
             rx,
+            disassembly,
         });
     }
 
     let total = jobs.len() as u32;
-    println!(
+    info!(
         "{} bytecode files queued for decompilation, {} skipped (not bytecode)",
         total, skipped
     );
 
     if total == 0 {
-        println!("nothing to decompile");
-        return Ok(());
+        info!("nothing to decompile");
+        return Ok(FileCounts::default());
     }
 
     let decompiled = Arc::new(AtomicU32::new(0));
@@ -112,7 +332,7 @@ pub async fn process_folder(
             let done = decompiled_progress.load(Ordering::Relaxed);
             let fail = failed_progress.load(Ordering::Relaxed);
             let pct = (done as f64 / total as f64) * 100.0;
-            println!(
+            info!(
                 "decompiling: {:.1}% ({}/{}) | {} failed",
                 pct, done, total, fail
             );
@@ -122,46 +342,45 @@ pub async fn process_folder(
         }
     });
 
+    let mut timed_out = 0;
     for job in jobs {
-        let result = match job.rx.await {
-            Ok(Ok(source)) => {
-                decompiled.fetch_add(1, Ordering::Relaxed);
-                match job.header {
-                    Some(header) => {
-                        format!("{}{}\n\n-- decompilation:\n{}", header, job.bytecode, source)
-                    }
-                    None => source,
-                }
-            }
-            Ok(Err(err)) => {
-                failed.fetch_add(1, Ordering::Relaxed);
-                eprintln!("failed: {} — {}", job.input_path.display(), err);
-                match job.header {
-                    Some(header) => format!(
-                        "{}{}\n\n-- decompilation failed:\n-- {}",
-                        header, job.bytecode, err
-                    ),
-                    None => format!("-- decompilation failed:\n-- {}", err),
+        let result = match (job.deadline, timeout) {
+            (Some(deadline), Some(timeout)) => match tokio::time::timeout_at(deadline, job.rx).await {
+                Ok(result) => result,
+                Err(_) => {
+                    timed_out += 1;
+                    Ok(Err(DecompileError::new(
+                        FailureCategory::Timeout,
+                        format!("the oracle didn't answer within {:?}", timeout),
+                    )))
                 }
-            }
-            Err(_) => {
-                failed.fetch_add(1, Ordering::Relaxed);
-                eprintln!(
-                    "failed: {} — sender dropped",
-                    job.input_path.display()
-                );
-                format!("-- decompilation failed:\n-- sender dropped")
-            }
+            },
+            _ => job.rx.await,
+        };
+        let result = result.unwrap_or_else(|_| Err("sender dropped".to_string().into()));
+        match &result {
+            Ok(_) => decompiled.fetch_add(1, Ordering::Relaxed),
+            Err(_) => failed.fetch_add(1, Ordering::Relaxed),
         };
+        let result = render_result(
+            &job.input_path.display(),
+            &job.bytecode,
+            job.header,
+            result,
+            options,
+        );
 
         std::fs::write(&job.output_path, result)?;
+        if let Some(disassembly) = job.disassembly {
+            write_disassembly(&job.output_path, disassembly, job.deadline).await?;
+        }
     }
 
     progress_handle.await?;
 
     let ok = decompiled.load(Ordering::Relaxed);
     let fail = failed.load(Ordering::Relaxed);
-    println!("done. {} decompiled, {} failed", ok, fail);
+    info!("done. {} decompiled, {} failed", ok, fail);
 
-    Ok(())
+    Ok(FileCounts { queued: total, failed: fail, timed_out })
 }
@@ -0,0 +1,80 @@
+use std::process::Stdio;
+use std::sync::Arc;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+/// An external command scripts are piped through, run with the shell so
+/// it can be a whole pipeline. Only so many copies run at once, a place
+/// has far more scripts than there are cores to run them on.
+#[derive(Clone)]
+pub struct CommandHook {
+    command: Arc<str>,
+    slots: Arc<Semaphore>,
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+impl CommandHook {
+    pub fn new(command: &str) -> Self {
+        let parallelism = std::thread::available_parallelism().map_or(4, |count| count.get());
+        Self {
+            command: Arc::from(command),
+            slots: Arc::new(Semaphore::new(parallelism)),
+        }
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Feeds `input` to the command and returns what it printed. Exiting
+    /// with an error is a failure, with whatever it wrote to stderr.
+    pub async fn run(&self, input: &[u8]) -> Result<Vec<u8>, String> {
+        let _slot = self.slots.acquire().await.unwrap();
+        let mut child = shell(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("couldn't run `{}`: {}", self.command, e))?;
+
+        // written alongside reading the output, so a command that prints as
+        // it goes can't fill its stdout pipe and stall
+        let mut stdin = child.stdin.take().unwrap();
+        let input = input.to_vec();
+        let writer = tokio::spawn(async move {
+            // the command doesn't have to read all of it
+            let _ = stdin.write_all(&input).await;
+        });
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| format!("couldn't run `{}`: {}", self.command, e))?;
+        let _ = writer.await;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let mut message = format!("`{}` failed ({})", self.command, output.status);
+            if !stderr.trim().is_empty() {
+                message.push_str(&format!(": {}", stderr.trim()));
+            }
+            return Err(message);
+        }
+        Ok(output.stdout)
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use reqwest::header::USER_AGENT;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::error::{Error, Result};
+use crate::filter::matches_name;
+
+/// How many downloaded chunks may wait for the parser before the download pauses
+const DOWNLOAD_CHANNEL_CAPACITY: usize = 64;
+
+pub fn is_url(input: &str) -> bool {
+    let lowercase = input.to_ascii_lowercase();
+    lowercase.starts_with("http://") || lowercase.starts_with("https://")
+}
+
+/// Whether `input` names a file on disk, rather than stdin or a url
+pub fn is_local_file(input: &str) -> bool {
+    input != "-" && !is_url(input)
+}
+
+pub fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?'])
+}
+
+/// Collects the files under `dir` that the rest of a glob matches, in order
+fn expand_glob_in(dir: PathBuf, segments: &[&str], files: &mut Vec<PathBuf>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        if dir.is_file() {
+            files.push(dir);
+        }
+        return;
+    };
+    if !is_glob(segment) {
+        return expand_glob_in(dir.join(segment), rest, files);
+    }
+
+    let listed = if dir.as_os_str().is_empty() { Path::new(".") } else { &dir };
+    let Ok(entries) = std::fs::read_dir(listed) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| dir.join(entry.file_name())).collect();
+    entries.sort();
+
+    if *segment == "**" {
+        // any number of folders, none included
+        expand_glob_in(dir, rest, files);
+        for entry in entries.into_iter().filter(|entry| entry.is_dir()) {
+            expand_glob_in(entry, segments, files);
+        }
+        return;
+    }
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+        // like a shell, `*` doesn't pick up hidden files unless asked to
+        if name.starts_with('.') && !segment.starts_with('.') {
+            continue;
+        }
+        if matches_name(segment, &name) {
+            expand_glob_in(entry, rest, files);
+        }
+    }
+}
+
+/// Turns `*`, `?` and `**` patterns into the files they match, for shells
+/// that pass them along as is. Other inputs are kept as they are. A file
+/// matched more than once is only listed the first time.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    let mut seen = HashSet::new();
+    for input in inputs {
+        if !is_glob(input) {
+            if seen.insert(input.clone()) {
+                expanded.push(input.clone());
+            }
+            continue;
+        }
+
+        // the folders before the first pattern are taken as they are
+        let mut base = PathBuf::new();
+        let mut segments = Vec::new();
+        for component in Path::new(input).components() {
+            match component {
+                Component::Normal(name) if !segments.is_empty() || is_glob(&name.to_string_lossy()) => {
+                    segments.push(name.to_str().ok_or_else(|| format!("`{}` isn't valid utf-8", input))?);
+                }
+                component => base.push(component),
+            }
+        }
+
+        let mut files = Vec::new();
+        expand_glob_in(base, &segments, &mut files);
+        if files.is_empty() {
+            return Err(Error::Config(format!("`{}` didn't match any files", input)));
+        }
+        for file in files {
+            let file = file.to_string_lossy().to_string();
+            if seen.insert(file.clone()) {
+                expanded.push(file);
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Hands what a download task receives to a blocking reader, chunk by chunk
+struct DownloadReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl Read for DownloadReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.current.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.current = chunk?;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.current.len() - self.position);
+        buf[..read].copy_from_slice(&self.current[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Starts downloading `url`, streaming the body as it arrives. The reader
+/// blocks, so it has to be read off the runtime.
+async fn download(url: &str) -> Result<(DownloadReader, u64)> {
+    let mut response = reqwest::Client::new()
+        .get(url)
+        .header(USER_AGENT, concat!("oracle-postprocess/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("couldn't download {}: {}", url, e))?;
+    let size = response.content_length().unwrap_or(0);
+    info!("downloading {}", url);
+
+    let (chunks_tx, chunks_rx) = mpsc::channel(DOWNLOAD_CHANNEL_CAPACITY);
+    tokio::spawn(async move {
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => Ok(chunk.to_vec()),
+                Ok(None) => return,
+                Err(e) => Err(io::Error::other(format!("download failed: {}", e))),
+            };
+            let failed = chunk.is_err();
+            // the reader hung up, or got told about the error
+            if chunks_tx.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok((
+        DownloadReader {
+            chunks: chunks_rx,
+            current: Vec::new(),
+            position: 0,
+        },
+        size,
+    ))
+}
+
+/// Opens a place to read from: a file, `-` for stdin, or an `http(s)://` url
+/// that's streamed as it downloads. The size is 0 when it isn't known up front.
+pub async fn open_input(input: &str) -> Result<(Box<dyn Read + Send>, u64)> {
+    if input == "-" {
+        return Ok((Box::new(io::stdin()), 0));
+    }
+    if is_url(input) {
+        let (reader, size) = download(input).await?;
+        return Ok((Box::new(reader), size));
+    }
+    let file = File::open(input)?;
+    let size = file.metadata()?.len();
+    Ok((Box::new(file), size))
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use xml::reader::XmlEvent;
+
+/// Names of the instances leading from the place root down to a script,
+/// e.g. `["ServerScriptService", "Main", "Module"]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct InstancePath(Vec<String>);
+
+impl InstancePath {
+    pub fn new(segments: Vec<String>) -> Self {
+        Self(segments)
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn join(&self, separator: &str) -> String {
+        self.0.join(separator)
+    }
+}
+
+impl fmt::Display for InstancePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.join("."))
+    }
+}
+
+struct TrackedItem {
+    class_name: String,
+    name: Option<String>,
+}
+
+/// The property element currently open, e.g. `<ProtectedString name="Source">`
+struct TrackedProperty {
+    kind: String,
+    name: String,
+}
+
+/// Follows `<Item>` nesting in an rbxlx event stream, so that whoever is
+/// looking at a property can tell which instance it belongs to.
+///
+/// Names come from the `Name` property, which Studio writes before `Source`.
+/// If a name hasn't been seen yet, the class name stands in for it.
+#[derive(Default)]
+pub struct InstanceTracker {
+    items: Vec<TrackedItem>,
+    property: Option<TrackedProperty>,
+}
+
+impl InstanceTracker {
+    pub fn observe(&mut self, event: &XmlEvent) {
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                if name.local_name == "Item" {
+                    let class_name = attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == "class")
+                        .map(|attr| attr.value.clone())
+                        .unwrap_or_default();
+                    self.items.push(TrackedItem {
+                        class_name,
+                        name: None,
+                    });
+                    self.property = None;
+                } else if !self.items.is_empty() {
+                    if let Some(attr) = attributes.iter().find(|attr| attr.name.local_name == "name") {
+                        self.property = Some(TrackedProperty {
+                            kind: name.local_name.clone(),
+                            name: attr.value.clone(),
+                        });
+                    }
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "Item" {
+                    self.items.pop();
+                }
+                self.property = None;
+            }
+            XmlEvent::Characters(text)
+                if self.property.as_ref().is_some_and(|property| property.name == "Name") =>
+            {
+                if let Some(item) = self.items.last_mut() {
+                    item.name.get_or_insert_with(String::new).push_str(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether the element being read is a script's `<ProtectedString name="Source">`.
+    /// Other strings, like a StringValue's, can contain the bytecode header too.
+    pub fn in_script_source(&self) -> bool {
+        self.property
+            .as_ref()
+            .is_some_and(|property| property.kind == "ProtectedString" && property.name == "Source")
+    }
+
+    /// Class of every instance along `path`, the innermost (e.g. `ModuleScript`) last
+    pub fn class_names(&self) -> Vec<String> {
+        self.items.iter().map(|item| item.class_name.clone()).collect()
+    }
+
+    /// Class of the instance being read, e.g. `LocalScript`
+    pub fn class_name(&self) -> Option<&str> {
+        self.items.last().map(|item| item.class_name.as_str())
+    }
+
+    pub fn path(&self) -> InstancePath {
+        InstancePath(
+            self.items
+                .iter()
+                .map(|item| item.name.clone().unwrap_or_else(|| item.class_name.clone()))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,16 @@
+use sha2::{Digest, Sha256};
+
+use crate::template::utc_timestamp;
+
+/// Puts `-- oracle: sha256=<hash> ts=<time> tool=<version>` in front of a
+/// decompiled script. The hash covers everything after that line, so an
+/// edit made to the script later no longer matches it.
+pub fn with_integrity_line(source: String) -> String {
+    format!(
+        "-- oracle: sha256={:x} ts={} tool=oracle-postprocess/{}\n{}",
+        Sha256::digest(source.as_bytes()),
+        utc_timestamp(),
+        env!("CARGO_PKG_VERSION"),
+        source
+    )
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// One finished decompilation, a line of JSON in the journal
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    hash: String,
+    source: String,
+}
+
+/// Saves successful decompilations by bytecode hash, so a later `--resume`
+/// doesn't have to decompile them again. Failures aren't kept, those are
+/// worth another try.
+pub fn write_journal<'a>(path: &Path, results: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut written = 0;
+    for (hash, source) in results {
+        let entry = JournalEntry {
+            hash: hash.to_string(),
+            source: source.to_string(),
+        };
+        serde_json::to_writer(&mut out, &entry).map_err(|e| format!("failed to write the journal: {}", e))?;
+        out.write_all(b"\n")?;
+        written += 1;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+/// Reads back what `write_journal` saved. A line cut off halfway, as a
+/// crash while writing leaves behind, is skipped.
+pub fn load_journal(path: &Path) -> Result<HashMap<String, String>> {
+    let mut results = HashMap::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let Ok(entry) = serde_json::from_str::<JournalEntry>(&line?) else {
+            continue;
+        };
+        results.insert(entry.hash, entry.source);
+    }
+    Ok(results)
+}
@@ -0,0 +1,59 @@
+use full_moon::LuaVersion;
+use stylua_lib::{Config, OutputVerification};
+use tracing::warn;
+
+/// full_moon recurses once per nesting level, and decompiled code can nest
+/// a lot deeper than the default thread stack allows
+const PARSER_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn with_parser_stack<T: Send>(f: impl FnOnce() -> Result<T, String> + Send) -> Result<T, String> {
+    std::thread::scope(|scope| {
+        let parser = std::thread::Builder::new()
+            .stack_size(PARSER_STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .map_err(|e| format!("failed to start the parser: {}", e))?;
+
+        parser
+            .join()
+            .unwrap_or_else(|_| Err("the parser crashed".to_string()))
+    })
+}
+
+/// Parses `source` as Luau, returning the first syntax error if there is one
+pub fn check_syntax(source: &str) -> Result<(), String> {
+    with_parser_stack(|| {
+        let result = full_moon::parse_fallible(source, LuaVersion::luau());
+        match result.errors().first() {
+            Some(error) => Err(error.to_string()),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Runs `source` through StyLua with its default style
+pub fn format(source: &str) -> Result<String, String> {
+    with_parser_stack(|| {
+        let config = Config {
+            syntax: stylua_lib::LuaVersion::Luau,
+            ..Config::default()
+        };
+        stylua_lib::format_code(source, config, None, OutputVerification::None).map_err(|e| match e {
+            stylua_lib::Error::ParseError(errors) if !errors.is_empty() => {
+                format!("doesn't parse as luau: {}", errors[0])
+            }
+            e => e.to_string(),
+        })
+    })
+}
+
+/// `format`, keeping the source as it was (with a warning naming `script`)
+/// if StyLua can't handle it
+pub fn format_or_keep(source: String, script: &dyn std::fmt::Display) -> String {
+    match format(&source) {
+        Ok(formatted) => formatted,
+        Err(e) => {
+            warn!("couldn't format {}: {}", script, e);
+            source
+        }
+    }
+}
@@ -1,36 +1,183 @@
 use clap::{Parser, Subcommand};
-use std::{env, path::PathBuf, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+mod archive;
+mod auth;
+mod clipboard;
 mod compiled;
+mod config;
+mod daemon;
 mod decompiler;
+mod directives;
+mod disasm;
+mod dump;
+mod encoding;
+mod error;
+mod exact;
+mod extract;
+mod filter;
 mod folder;
+mod hook;
+mod input;
+mod journal;
+mod instance;
+mod integrity;
+mod logging;
+mod luau;
+mod meta;
+#[cfg(feature = "mock-oracle")]
+mod mock;
+mod naming;
+mod obfuscation;
+mod offline;
+mod profile;
+mod progress;
+mod project;
 mod rbxlx;
+mod report;
+mod roundtrip;
+mod run;
+mod serve;
+mod sizes;
+mod sourcemap;
+mod spool;
+mod sqlite;
+mod stats;
+mod strings;
+mod studio;
+mod template;
+mod verify;
 
-use decompiler::Decompiler;
-use folder::process_folder;
-use rbxlx::process_rbxlx_file;
+use archive::{is_zip, process_zip};
+use clipboard::{read_clipboard, write_clipboard};
+use config::{load_config, Config};
+use decompiler::options::{layered, preset};
+use decompiler::{
+    collect_garbage, hash_bytecode, print_cache_stats, CacheEncryption, Compression, DecompilationCache,
+    Decompiler, DecompilerSettings, FailureCategory, KeyRefresh, LocalCache, Recorder, Recording, RemoteCache,
+    Transport, DEFAULT_MAX_BYTES_IN_FLIGHT,
+};
+use error::{Error, Result};
+use extract::{extract_bytecode, BytecodeFormat, FileNaming};
+use daemon::{default_socket_path, run_daemon};
+use disasm::{disassembly_listing, request_disassembly};
+use dump::process_dump;
+use folder::{process_files, process_folder, render_blobs, RenderOptions};
+use hook::CommandHook;
+use input::{expand_inputs, is_glob, is_local_file};
+use journal::load_journal;
+use offline::{append_queue, load_queue, save_queue, QueuedScript};
+use meta::{MetaRecorder, META_FILE_NAME};
+#[cfg(feature = "mock-oracle")]
+use mock::{MockBehaviour, MockOracle, DEFAULT_MOCK_LISTEN_ADDRESS};
+use filter::ScriptFilter;
+use profile::OptionProfiles;
+use project::write_project_files;
+use rbxlx::{
+    dry_run_rbxlx_file, patch_rbxlx_file, process_rbxlx_file, process_rbxlx_in_place, OutputCompression, OversizePolicy,
+    PlaceFormat, RbxlxOptions, ScriptSizeLimit, SharedResults,
+};
+use report::{DuplicatesReport, Report, ReportSink, Summary};
+use roundtrip::roundtrip_check;
+use run::RunContext;
+use serve::{serve, serve_metrics, ServeOptions, DEFAULT_CACHE_ENTRIES, DEFAULT_LISTEN_ADDRESS};
+use spool::{Spool, DEFAULT_SPILL_THRESHOLD_MIB};
+use sqlite::process_sqlite;
+use stats::{write_stats, StatsFormat};
+use strings::print_strings;
+use studio::{convert_rbxl_to_rbxlx, find_studio_places, pick_place, print_places};
+use template::OutputTemplate;
+use verify::verify_rbxlx;
+
+use tracing::{error, info, warn};
+
+/// Listed under `--help`, the codes come from `Error::exit_code`
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  everything decompiled
+  1  any other error
+  2  invalid arguments or configuration
+  3  the oracle rejected the key
+  4  the connection to the oracle failed, dropped or timed out
+  5  some scripts or files failed to decompile, the rest were written
+  6  nothing decompiled
+  7  reading or writing a file failed, or it wasn't valid";
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 #[command(propagate_version = true)]
 struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
 
+    /// Path to a config file
+    /// Defaults to ~/.config/oracle-postprocess/config.toml
+    #[arg(long, verbatim_doc_comment)]
+    config: Option<PathBuf>,
+
     /// Oracle key
-    /// You can also set it with the ORACLE_KEY env variable
-    /// If both are provided, one from the argument is used
+    /// You can also set it with the ORACLE_KEY env variable, `auth login`
+    /// or in the config file
+    /// If several are provided, they're used in that order
+    /// Give it several times to spread a run over several keys, one per
+    /// connection in turn (connections default to one per key). A key
+    /// that runs out of quota is retired and its scripts go to the others
     #[arg(short, long, verbatim_doc_comment)]
-    key: Option<String>,
+    key: Vec<String>,
 
     /// Oracle decompiler url
-    #[arg(long, default_value = "wss://oracle.mshq.dev/v1/ws")]
-    base_url: String,
+    /// Can be given multiple times, later urls are failed over to
+    /// when the ones before them are down
+    /// Defaults to wss://oracle.mshq.dev/v1/ws
+    #[arg(long, verbatim_doc_comment)]
+    base_url: Vec<String>,
 
     /// Oracle API version
-    #[arg(short = 'v', long)]
+    /// No longer -v, which is short for --verbose now
+    #[arg(long, verbatim_doc_comment)]
     oracle_version: Option<u32>,
 
+    /// Version of the websocket protocol to speak
+    /// 2 sends decompiler options along with each request, so scripts
+    /// matched by different [[profiles]] can share a connection
+    /// 3 also sends bytecode as raw bytes in binary frames instead of
+    /// base64 in JSON, a quarter less to upload
+    /// 4 also tags each request with an id that its result comes back
+    /// with, so the same script wanted with different options can't
+    /// get the wrong answer
+    /// 5 also lets --with-disasm ask the oracle for disassemblies
+    /// Defaults to the newest one the server offers when it connects,
+    /// or 1 if it doesn't say
+    #[arg(long, verbatim_doc_comment)]
+    protocol: Option<u32>,
+
+    /// Shell command to run each script's bytecode through before it's sent,
+    /// e.g. an unpacker for VM-protected scripts
+    /// It gets the raw bytecode on stdin and prints the bytecode to send
+    /// instead (raw or base64), or nothing to send the script as it is
+    #[arg(long, verbatim_doc_comment)]
+    pre_process_cmd: Option<String>,
+
+    /// Shell command to run each decompiled script through before it's
+    /// written, e.g. a renamer or deobfuscation pass
+    /// It gets the source on stdin and prints the source to write instead,
+    /// or nothing to keep it as it is. Runs before --format-lua
+    #[arg(long, verbatim_doc_comment)]
+    post_process_cmd: Option<String>,
+
+    /// Shell command that prints a fresh oracle key, run when the oracle
+    /// rejects the current one partway through a run (e.g. it expired)
+    /// Without it, the key is looked up again the usual way
+    #[arg(long, verbatim_doc_comment)]
+    key_command: Option<String>,
+
     /// Decompiler options as a JSON string
     #[arg(long, conflicts_with = "decompiler_options_file")]
     decompiler_options: Option<String>,
@@ -38,33 +185,482 @@ struct Args {
     /// Path to a JSON file containing decompiler options
     #[arg(long, conflicts_with = "decompiler_options")]
     decompiler_options_file: Option<PathBuf>,
+
+    /// Write every answer the oracle gives to this file, for --replay
+    /// Added to the end of the file if it's already there
+    #[arg(long, verbatim_doc_comment, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Answer requests from a file written by --record instead of asking
+    /// the oracle, to process something again offline without spending
+    /// quota. Scripts the recording doesn't have fail
+    #[arg(long, verbatim_doc_comment)]
+    replay: Option<PathBuf>,
+
+    /// Keep successful decompilations in this folder, and answer scripts
+    /// found in it from there instead of asking the oracle again
+    #[arg(long, verbatim_doc_comment)]
+    cache_dir: Option<PathBuf>,
+
+    /// Most MiB to let --cache-dir grow to. Past it, the scripts used
+    /// least recently are evicted. `cache gc` evicts down to it too
+    /// Defaults to no limit
+    #[arg(long, verbatim_doc_comment)]
+    cache_max_size: Option<f64>,
+
+    /// Encrypt what goes in --cache-dir, so other users on the machine
+    /// can't read the decompilations in it. The key is derived from the
+    /// oracle key, or with --cache-encrypt=passphrase from a passphrase in
+    /// the ORACLE_CACHE_PASSPHRASE env variable, asked for if it's not set
+    /// Scripts cached with another key are decompiled again
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "oracle-key",
+        require_equals = true,
+        verbatim_doc_comment
+    )]
+    cache_encrypt: Option<CacheEncryption>,
+
+    /// Share decompilations with a team through a key-value store over
+    /// http: GET <url>/<key> to look one up, PUT <url>/<key> to store one.
+    /// Scripts anyone already decompiled don't cost the others credits.
+    /// Looked in after --cache-dir. The config's cache.remote_token goes
+    /// along as a bearer token
+    #[arg(long, verbatim_doc_comment)]
+    cache_url: Option<String>,
+
+    /// Don't look in or add to the cache the config file sets up
+    #[arg(long, conflicts_with_all = ["cache_dir", "cache_max_size", "cache_encrypt", "cache_url"])]
+    no_cache: bool,
+
+    /// Start from a named set of decompiler options: readable, faithful,
+    /// minimal-renaming or one of the config file's [presets]
+    /// --decompiler-options are laid over it
+    #[arg(long, verbatim_doc_comment)]
+    preset: Option<String>,
+
+    /// How many times to retry a failed decompilation
+    /// Retries back off exponentially, starting at 1 second
+    /// Defaults to 0
+    #[arg(long, verbatim_doc_comment)]
+    retries: Option<u32>,
+
+    /// How many websocket connections to open to the oracle
+    /// Defaults to 1
+    #[arg(long, verbatim_doc_comment)]
+    connections: Option<usize>,
+
+    /// How to talk to the oracle
+    /// Defaults to websocket, falling back to http if the handshake fails but the server answers over http
+    #[arg(long, value_enum, verbatim_doc_comment)]
+    transport: Option<Transport>,
+
+    /// How to compress bytecode and results on the websocket: zstd or none
+    /// Needs protocol 3. zstd is used whenever the server offers it by
+    /// default, and even when it doesn't if asked for
+    #[arg(long, value_enum, verbatim_doc_comment)]
+    compression: Option<Compression>,
+
+    /// Most requests per second to send to the oracle
+    /// Defaults to no limit
+    #[arg(long, verbatim_doc_comment)]
+    max_rps: Option<f64>,
+
+    /// Most distinct scripts waiting on the oracle at once
+    /// Spread evenly over --connections. Defaults to no limit,
+    /// other than the 8 MiB of bytecode in flight per connection
+    #[arg(long, verbatim_doc_comment)]
+    max_concurrent: Option<usize>,
+
+    /// Most bytecode to have waiting on the oracle per connection, in MiB
+    /// The server can lower this when it connects
+    /// Defaults to 8
+    #[arg(long, verbatim_doc_comment)]
+    max_in_flight: Option<u32>,
+
+    /// Always keep --max-in-flight worth of bytecode waiting on the oracle
+    /// Without it, how much is kept in flight grows while the oracle keeps
+    /// up and shrinks when its answers slow down, up to --max-in-flight
+    #[arg(long, verbatim_doc_comment)]
+    fixed_window: bool,
+
+    /// Start the run by trying a few amounts of bytecode in flight, from
+    /// 256 KiB up to --max-in-flight, and keep whichever the oracle got
+    /// through quickest for the rest of it, instead of adapting all along
+    #[arg(long, verbatim_doc_comment, conflicts_with = "fixed_window")]
+    autotune: bool,
+
+    /// Go easy on an oracle key other people are using too: one connection,
+    /// at most 4 scripts in flight and 2 requests a second, so a big run
+    /// doesn't starve anyone decompiling interactively. Lower limits given
+    /// with the flags above still apply.
+    #[arg(long, verbatim_doc_comment)]
+    gentle: bool,
+
+    /// Run decompiled scripts through StyLua before writing them
+    #[arg(long)]
+    format_lua: bool,
+
+    /// Append a local bytecode disassembly to scripts the oracle
+    /// couldn't decompile, instead of just the error
+    #[arg(long, verbatim_doc_comment)]
+    disasm: bool,
+
+    /// Write each script's disassembly next to its decompilation, as a
+    /// .disasm file, to check tricky decompilations against the
+    /// instructions. The oracle disassembles when it speaks protocol 5,
+    /// the built-in disassembler does otherwise. Places need --scripts-dir.
+    #[arg(long, verbatim_doc_comment)]
+    with_disasm: bool,
+
+    /// Put a `-- oracle: sha256=<hash> ts=<time> tool=<version>` line in
+    /// front of every decompiled script, hashing the source after it, so
+    /// later hand edits can be detected
+    #[arg(long, verbatim_doc_comment)]
+    integrity: bool,
+
+    /// Put a `-- sizes: ...` line in front of every decompiled script with
+    /// its bytecode size, decompiled size and line count, to help spot
+    /// decompilations that came out suspiciously small
+    #[arg(long, verbatim_doc_comment)]
+    size_comment: bool,
+
+    /// Decode the function and chunk names scripts were compiled with and
+    /// put them in front of each script, for when obfuscators renamed the
+    /// instances. Scripts written to folders by dump or --scripts-dir are
+    /// named after their chunk name when the bytecode has one
+    #[arg(long, verbatim_doc_comment)]
+    debug_names: bool,
+
+    /// Write decompiled scripts to folders as .luau instead of .lua, with
+    /// any --!strict, --!native, ... directives the oracle echoed moved to
+    /// the top of the file, so type-checking keeps them
+    #[arg(long, verbatim_doc_comment)]
+    luau: bool,
+
+    /// Write a selene.toml and .luaurc into folders decompiled scripts are
+    /// written to (folder, dump, sqlite and rbxlx --scripts-dir), so they can be
+    /// linted and analyzed in an editor straight away
+    #[arg(long, verbatim_doc_comment)]
+    project_files: bool,
+
+    /// Folder to give each run a folder of its own in, for temp files,
+    /// partial outputs, journals and the bytecode of failed scripts
+    /// Defaults to oracle-postprocess in the system's temp folder
+    #[arg(long, verbatim_doc_comment)]
+    temp_dir: Option<PathBuf>,
+
+    /// Keep the run's folder once it's done. It's only kept by default
+    /// when something failed, for the journal and failed bytecode in it
+    #[arg(long, verbatim_doc_comment)]
+    keep_temp: bool,
+
+    /// Also print debug output, give twice for even more
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print warnings and errors, give twice for errors only
+    #[arg(short, long, action = clap::ArgAction::Count, verbatim_doc_comment)]
+    quiet: u8,
+
+    /// Log JSON lines instead of plain text, for log collectors
+    #[arg(long)]
+    log_json: bool,
+
+    /// Also write progress to stderr as JSON lines, for frontends that
+    /// show their own: script_started, script_finished and script_failed
+    /// for every script in a place, and queue_depth as it goes
+    /// Add -q to leave out the usual progress lines
+    #[arg(long, verbatim_doc_comment)]
+    progress_json: bool,
 }
 
+const DEFAULT_BASE_URL: &str = "wss://oracle.mshq.dev/v1/ws";
+
 #[derive(Subcommand)]
+// only ever parsed once, boxing Rbxlx would just make matching on it noisier
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Process a .rbxlx file
+    /// Several places, or glob patterns like games/*.rbxlx, are processed
+    /// at the same time over the same connection
+    #[command(verbatim_doc_comment)]
     Rbxlx {
-        /// Input file path
-        input: String,
+        /// Input file paths
+        /// Can also be an http(s):// url, which is processed as it downloads,
+        /// or - to read from stdin
+        #[arg(value_name = "INPUT", required = true, verbatim_doc_comment)]
+        inputs: Vec<String>,
 
         /// Output file path
-        /// Defaults to out.rbxlx
-        #[arg(short, long, verbatim_doc_comment, default_value = "processed.rbxlx")]
-        output: String,
+        /// Defaults to processed.rbxlx
+        /// With more than one input it's the folder each place is written
+        /// to under its own name, defaulting to processed
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+
+        /// Only decompile scripts whose instance path matches this glob
+        /// e.g. `ServerScriptService/**`. Can be given multiple times
+        /// A pattern without a / matches an instance name at any depth
+        #[arg(long, verbatim_doc_comment)]
+        include: Vec<String>,
+
+        /// Only decompile scripts at or below this instance, e.g.
+        /// `Workspace.Map`. The rest of the place is passed through as is
+        /// Can be given multiple times
+        #[arg(long, verbatim_doc_comment)]
+        root: Vec<String>,
+
+        /// Skip scripts whose instance path matches this glob
+        /// Can be given multiple times, and wins over --include
+        #[arg(long, verbatim_doc_comment)]
+        exclude: Vec<String>,
+
+        /// Only decompile scripts of these classes, e.g.
+        /// `LocalScript,ModuleScript` for the client side
+        /// Scripts of other classes are passed through as is
+        #[arg(long, value_delimiter = ',', verbatim_doc_comment)]
+        only_class: Vec<String>,
+
+        /// Count scripts, bytecode size and duplicates and estimate
+        /// how long a run would take, without contacting the oracle
+        #[arg(long, verbatim_doc_comment)]
+        dry_run: bool,
+
+        /// Add a `-- duplicate of <path>` line to scripts whose bytecode
+        /// is identical to an earlier script's
+        #[arg(long, verbatim_doc_comment)]
+        annotate_duplicates: bool,
+
+        /// Also write every decompiled script to this folder
+        /// as <instance path>.lua, e.g. ServerScriptService/Main.lua,
+        /// along with a Rojo sourcemap.json for luau-lsp
+        #[arg(long, verbatim_doc_comment)]
+        scripts_dir: Option<PathBuf>,
+
+        /// Check that every decompiled script parses as Luau, and
+        /// mark the ones that don't with a warning
+        #[arg(long, verbatim_doc_comment)]
+        validate: bool,
+
+        /// Decompiler options as a JSON string to retry scripts that
+        /// don't parse with, over a separate connection. Implies --validate
+        #[arg(long, verbatim_doc_comment)]
+        fallback_options: Option<String>,
+
+        /// How many XML events may wait for the output file to catch up
+        /// before reading pauses. Defaults to 16384
+        #[arg(long, verbatim_doc_comment)]
+        write_buffer: Option<usize>,
+
+        /// Write a JSON report of how every script turned out to this file
+        #[arg(long, verbatim_doc_comment)]
+        report: Option<PathBuf>,
+
+        /// Write an oracle-meta.json next to the output recording the tool
+        /// version, oracle url, protocol, options and command line used,
+        /// how long the run took and how every bytecode hash turned out
+        #[arg(long, verbatim_doc_comment, conflicts_with = "dry_run")]
+        meta: bool,
+
+        /// Write a JSON report to this file grouping scripts with identical
+        /// bytecode, listing every instance path in each group, to spot
+        /// loaders and modules copied around the place
+        #[arg(long, verbatim_doc_comment, conflicts_with_all = ["dry_run", "retry_failures"])]
+        duplicates_report: Option<PathBuf>,
+
+        /// Read a report from an earlier --report run, decompile only the
+        /// scripts that failed in it and patch them into the existing output
+        /// file, instead of processing the whole place again.
+        /// The report is updated to match
+        #[arg(long, verbatim_doc_comment, conflicts_with_all = ["dry_run", "report", "gzip", "zstd", "strip_bytecode"])]
+        retry_failures: Option<PathBuf>,
+
+        /// Gzip the output file as it's written, adding .gz to the
+        /// default output name
+        #[arg(long, verbatim_doc_comment, conflicts_with = "zstd")]
+        gzip: bool,
+
+        /// Compress the output file with zstd as it's written, adding
+        /// .zst to the default output name
+        #[arg(long, verbatim_doc_comment)]
+        zstd: bool,
+
+        /// Format to save the processed place in: rbxlx, or rbxl for
+        /// the binary format, often 5-10x smaller and much quicker to open.
+        /// rbxl needs the whole place in memory at the end to convert it
+        /// Defaults to rbxlx
+        #[arg(long, value_enum, verbatim_doc_comment, conflicts_with_all = ["retry_failures", "in_place", "gzip", "zstd"])]
+        output_format: Option<PlaceFormat>,
+
+        /// Write only the decompiled source into each script, without the
+        /// original header and base64 bytecode, so the place opens quickly
+        /// in Studio. The output can't be used with verify or --retry-failures
+        #[arg(long, verbatim_doc_comment)]
+        strip_bytecode: bool,
+
+        /// Keep scripts' LinkedSource and ScriptGuid properties
+        /// They're emptied by default: with them set, Studio loads the
+        /// script's source from the cloud or a Team Create draft and
+        /// replaces the decompiled one with it
+        #[arg(long, verbatim_doc_comment)]
+        keep_linked_source: bool,
+
+        /// Keep going when the place isn't well-formed, e.g. a truncated
+        /// dump: unknown entities are replaced, and at the first error that
+        /// can't be read past, the elements still open are closed and
+        /// everything found before it is decompiled as usual
+        #[arg(long, verbatim_doc_comment)]
+        lenient: bool,
+
+        /// Copy the place byte for byte and only rewrite the scripts'
+        /// sources, so nothing else about the file changes (attribute order,
+        /// whitespace, self-closing tags) and writing is much quicker.
+        /// The input has to be a UTF-8 rbxlx file
+        #[arg(
+            long,
+            verbatim_doc_comment,
+            conflicts_with_all = [
+                "dry_run", "retry_failures", "gzip", "zstd", "output_format", "scripts_dir",
+                "validate", "fallback_options", "max_script_size", "journal", "resume", "lenient",
+            ]
+        )]
+        exact: bool,
+
+        /// File with a template for each processed script's source, with
+        /// {header}, {bytecode}, {decompilation}, {path}, {class_name},
+        /// {hash} and {timestamp} placeholders in whatever order it needs.
+        /// Leaving out {header}{bytecode} at the start has the same
+        /// caveats as --strip-bytecode
+        #[arg(long, verbatim_doc_comment, conflicts_with = "strip_bytecode")]
+        template: Option<PathBuf>,
+
+        /// Replace the input file with the processed place instead of
+        /// writing a separate one. It's written to <input>.processing first
+        /// and only renamed over the input once processing succeeded
+        #[arg(long, verbatim_doc_comment, conflicts_with_all = ["output", "dry_run", "retry_failures", "gzip", "zstd"])]
+        in_place: bool,
+
+        /// With --in-place, keep the original file as <input>.bak
+        #[arg(long, verbatim_doc_comment, requires = "in_place")]
+        backup: bool,
+
+        /// Largest decompilation to put into the place, in MiB. Scripts
+        /// tens of MiB big can make Studio choke on the whole place
+        /// What happens to bigger ones is up to --oversize
+        #[arg(long, verbatim_doc_comment)]
+        max_script_size: Option<f64>,
+
+        /// What to do with decompilations over --max-script-size:
+        /// truncate (keep what fits, with a note), sidecar (write them to
+        /// <output>_oversized/ and say so in the script) or skip
+        /// Defaults to truncate
+        #[arg(long, verbatim_doc_comment, requires = "max_script_size")]
+        oversize: Option<OversizePolicy>,
+
+        /// Where to save the decompilations that finished if writing the
+        /// output fails partway (e.g. the disk filled up), for --resume
+        /// Defaults to a file in the run's folder, see --temp-dir
+        #[arg(long, verbatim_doc_comment)]
+        journal: Option<PathBuf>,
+
+        /// Read a journal left behind by a run whose output couldn't be
+        /// written, and use the decompilations in it instead of asking
+        /// the oracle for them again
+        #[arg(long, verbatim_doc_comment, conflicts_with_all = ["dry_run", "retry_failures"])]
+        resume: Option<PathBuf>,
+
+        /// If the oracle can't be reached, process the place anyway and
+        /// add the scripts it couldn't decompile to this queue file
+        /// (hash, size, path and output), instead of giving up
+        /// `flush-queue` decompiles them once the oracle is back
+        #[arg(
+            long,
+            verbatim_doc_comment,
+            conflicts_with_all = ["dry_run", "retry_failures", "strip_bytecode", "output_format", "gzip", "zstd"]
+        )]
+        offline_queue: Option<PathBuf>,
+
+        /// Decompilations bigger than this many MiB wait on disk instead
+        /// of in memory until they're written, so a few giant scripts
+        /// don't decide how much memory the run takes
+        /// Defaults to 8, 0 spills everything
+        #[arg(long, verbatim_doc_comment)]
+        spill_threshold: Option<f64>,
     },
     /// Process a single bytecode file
+    /// A text dump with several `-- Bytecode (Base64):` blobs in it has
+    /// each of them decompiled, one after the other in the output
+    #[command(verbatim_doc_comment)]
     Single {
-        /// Input file path
-        input: String,
+        /// Input file paths, or glob patterns like dumps/*.bin
+        /// Use - to read from stdin
+        #[arg(value_name = "INPUT", required_unless_present = "clipboard", verbatim_doc_comment)]
+        inputs: Vec<String>,
 
         /// Output file path
-        /// Defaults to out.rbxlx
-        #[arg(short, long, verbatim_doc_comment, default_value = "decompiled.lua")]
-        output: String,
+        /// Use - to write to stdout
+        /// Defaults to decompiled.lua
+        /// With more than one input, a glob or an existing folder, it's the
+        /// folder each input is written to as <name>.lua, defaulting to decompiled
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+
+        /// Decompile the bytecode on the clipboard and put the decompiled
+        /// source back on it, instead of reading a file
+        /// It's only written to a file as well if --output is given
+        #[arg(long, verbatim_doc_comment, conflicts_with = "inputs")]
+        clipboard: bool,
+
+        /// Give up if the oracle hasn't answered within this many seconds
+        /// Waits indefinitely by default
+        #[arg(long, verbatim_doc_comment)]
+        timeout: Option<f64>,
     },
     /// Process all bytecode files in a folder
     Folder {
         /// Input folder path
+        /// A .zip is read without unpacking it, decompiling bytecode
+        /// files and processing .rbxlx places inside it
+        #[arg(verbatim_doc_comment)]
+        input: String,
+
+        /// Output folder path
+        /// Defaults to <input>_decompiled
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+    },
+    /// Write each script's bytecode to its own file without decompiling
+    /// Accepts .rbxlx and .rbxl places, bytecode files and folders of them
+    #[command(verbatim_doc_comment)]
+    Extract {
+        /// Input file or folder path
+        input: String,
+
+        /// Output folder path
+        /// Defaults to <input>_bytecode, without the file extension
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+
+        /// How to encode the extracted bytecode
+        /// Defaults to raw
+        #[arg(long, value_enum, verbatim_doc_comment)]
+        format: Option<BytecodeFormat>,
+
+        /// How to name the extracted files
+        /// Defaults to path
+        #[arg(long, value_enum, verbatim_doc_comment)]
+        naming: Option<FileNaming>,
+    },
+    /// Decompile a script dumper's output folder (.bin files with JSON metadata)
+    /// into a tree that mirrors the game, with a Rojo sourcemap.json. Metadata is read
+    /// from metadata.json, manifest.json or index.json in the folder, or a <name>.json
+    /// next to each file
+    #[command(verbatim_doc_comment)]
+    Dump {
+        /// Dump folder path
         input: String,
 
         /// Output folder path
@@ -72,79 +668,1195 @@ enum Commands {
         #[arg(short, long, verbatim_doc_comment)]
         output: Option<String>,
     },
+    /// Decompile the scripts in a dumper's SQLite database (a table with
+    /// name, parent and bytecode columns) into a tree that mirrors the game,
+    /// or into a .rbxlx with the scripts put back where they were
+    #[command(verbatim_doc_comment)]
+    Sqlite {
+        /// Database file path
+        input: String,
+
+        /// Output folder path, or a .rbxlx file to rebuild a place
+        /// Defaults to <input>_decompiled, without the file extension
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+
+        /// Table the scripts are in
+        /// Defaults to the first one with name, parent and bytecode columns
+        #[arg(long, verbatim_doc_comment)]
+        table: Option<String>,
+    },
+    /// List the globals and string constants each script refers to, decoded
+    /// locally without contacting the oracle
+    /// Accepts .rbxlx and .rbxl places, bytecode files and folders of them
+    #[command(verbatim_doc_comment)]
+    Strings {
+        /// Input file or folder path
+        input: String,
+    },
+    /// Write per-script statistics (path, class, bytecode and decompiled
+    /// size, duplicate group) for spreadsheets or pandas, without contacting
+    /// the oracle. Decompiled sizes come from a processed place, and
+    /// --disasm adds a proto count
+    #[command(verbatim_doc_comment)]
+    Stats {
+        /// Input file or folder path
+        input: String,
+
+        /// Output file path
+        /// Defaults to stdout, which only works for csv
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+
+        /// Output format
+        /// Defaults to parquet for .parquet outputs and csv otherwise
+        #[arg(long, value_enum, verbatim_doc_comment)]
+        format: Option<StatsFormat>,
+    },
+    /// Recompile every decompilation in a processed place with the bundled
+    /// Luau compiler and compare its protos and constants with the original
+    /// bytecode, scoring how faithful each decompilation is from 0 to 1
+    #[command(verbatim_doc_comment)]
+    RoundtripCheck {
+        /// The processed .rbxlx or .rbxl
+        input: String,
+
+        /// Write each script's score to this JSON file
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+    /// Keep a connection to the oracle open and take requests from other
+    /// programs over a local socket (a named pipe on windows), one JSON
+    /// object per line:
+    ///   {"type":"decompile","id":1,"data":"<base64 bytecode>"}
+    ///   {"type":"decompile","id":2,"path":"script.bin"}
+    ///   {"type":"status"}
+    ///   {"type":"shutdown"}
+    #[command(verbatim_doc_comment)]
+    Daemon {
+        /// Where to listen
+        /// Defaults to $XDG_RUNTIME_DIR/oracle-postprocess.sock
+        /// (\\.\pipe\oracle-postprocess on windows)
+        #[arg(long, verbatim_doc_comment)]
+        socket: Option<String>,
+
+        /// Also serve Prometheus metrics over http at this address,
+        /// under GET /metrics, e.g. 127.0.0.1:9100
+        #[arg(long, verbatim_doc_comment)]
+        metrics_listen: Option<String>,
+    },
+    /// Serve decompilations over http to anyone who can reach the address:
+    ///   POST /decompile  bytecode in, decompiled source out
+    ///   POST /rbxlx      a .rbxlx place in, the processed place out
+    ///   GET  /metrics    how the oracle connections are doing, for Prometheus
+    #[command(verbatim_doc_comment)]
+    Serve {
+        /// Address to listen on
+        /// Defaults to 127.0.0.1:8080
+        #[arg(long, verbatim_doc_comment)]
+        listen: Option<String>,
+
+        /// How many /decompile results to keep in memory, 0 to turn it off
+        /// Defaults to 10000
+        #[arg(long, verbatim_doc_comment)]
+        cache_entries: Option<usize>,
+    },
+    /// Run a stand-in for the oracle on a local address, answering every
+    /// script with a placeholder decompilation, to test against without
+    /// a key or network. Prints its url on the first line of stdout
+    #[cfg(feature = "mock-oracle")]
+    #[command(verbatim_doc_comment)]
+    MockOracle {
+        /// Address to listen on
+        /// Defaults to 127.0.0.1 on a free port
+        #[arg(long, verbatim_doc_comment)]
+        listen: Option<String>,
+
+        /// Milliseconds to wait before answering each script
+        #[arg(long)]
+        delay_ms: Option<u64>,
+
+        /// Fail every nth script received with an internal error
+        #[arg(long)]
+        fail_every: Option<usize>,
+
+        /// Answer scripts in a different order than they came in
+        #[arg(long)]
+        out_of_order: bool,
+
+        /// Answer every script twice
+        #[arg(long)]
+        duplicate: bool,
+
+        /// Leave the id off the answer to every nth script received
+        #[arg(long)]
+        drop_id_every: Option<usize>,
+
+        /// Turn away connections that don't bring this key
+        #[arg(long)]
+        require_key: Option<String>,
+
+        /// Protocol versions to offer when a client connects
+        /// Sends no hello by default, like older servers
+        #[arg(long, verbatim_doc_comment)]
+        protocol: Vec<u32>,
+
+        /// Compression to offer when a client connects, only zstd
+        #[arg(long)]
+        compression: Vec<String>,
+
+        /// Append a line to this file for every script received, saying
+        /// how it came (text, binary or binary zstd) and its hash
+        #[arg(long, verbatim_doc_comment)]
+        frame_log: Option<PathBuf>,
+    },
+    /// List the places Studio saved (its Documents\ROBLOX folder and
+    /// autosaves), most recent first, and process the one picked
+    /// A binary .rbxl is converted to .rbxlx first
+    #[command(verbatim_doc_comment)]
+    StudioPlaces {
+        /// Only list the places, without asking which one to process
+        #[arg(long)]
+        list: bool,
+
+        /// Output file path
+        /// Defaults to processed.rbxlx
+        #[arg(short, long, verbatim_doc_comment)]
+        output: Option<String>,
+    },
+    /// Decompile the scripts an --offline-queue run left behind and
+    /// patch them into the places they're in
+    /// Scripts that still fail stay in the queue
+    #[command(verbatim_doc_comment)]
+    FlushQueue {
+        /// The queue file given to --offline-queue
+        queue: PathBuf,
+    },
+    /// Check a processed .rbxlx against the original
+    /// Only script sources may differ, and each must have been decompiled or marked failed
+    #[command(verbatim_doc_comment)]
+    Verify {
+        /// The original .rbxlx
+        input: String,
+
+        /// The processed .rbxlx
+        /// Defaults to processed.rbxlx
+        #[arg(verbatim_doc_comment)]
+        output: Option<String>,
+    },
+    /// Manage the oracle key stored in the os keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    /// Look after the --cache-dir folder
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+#[derive(Subcommand)]
+enum AuthAction {
+    /// Save a key to the os keyring
+    /// The key is read from a prompt, or from stdin when piped
+    #[command(verbatim_doc_comment)]
+    Login,
+    /// Remove the stored key from the os keyring
+    Logout,
+    /// Show which key would be used and where it comes from
+    Status,
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Show how many scripts are cached, how much room they take and
+    /// how long since they were used
+    #[command(verbatim_doc_comment)]
+    Stats,
+    /// Evict scripts until the cache fits in --cache-max-size, least
+    /// recently used first, and clear out files runs that were cut off
+    /// left half-written
+    #[command(verbatim_doc_comment)]
+    Gc {
+        /// Evict scripts that weren't used in this many days too
+        #[arg(long)]
+        unused_for: Option<u64>,
+    },
+}
+
+/// What `--gentle` holds a run to
+const GENTLE_CONNECTIONS: usize = 1;
+const GENTLE_MAX_CONCURRENT: usize = 4;
+const GENTLE_MAX_RPS: f64 = 2.0;
+
+/// `limit`, lowered to `cap` when `gentle`
+fn gentle_limit<T: PartialOrd + Copy>(limit: Option<T>, cap: T, gentle: bool) -> Option<T> {
+    if !gentle {
+        return limit;
+    }
+    Some(limit.filter(|limit| *limit < cap).unwrap_or(cap))
+}
+
+fn cache_dir(args: &Args, config: &Config) -> Option<PathBuf> {
+    args.cache_dir.clone().or_else(|| config.cache.dir.clone())
+}
+
+/// In bytes
+fn cache_max_size(args: &Args, config: &Config) -> Option<u64> {
+    args.cache_max_size
+        .or(config.cache.max_size)
+        .map(|mib| (mib * 1024.0 * 1024.0) as u64)
+}
+
+fn max_bytes_in_flight(args: &Args, config: &Config) -> Option<u32> {
+    args.max_in_flight
+        .or(config.max_in_flight)
+        .map(|mib| mib.saturating_mul(1024 * 1024))
+}
+
+/// Where a new key comes from once the oracle rejects the one a run started
+/// with: `--key-command` if set, otherwise the env, keyring and config file
+/// are read again, in case the key was replaced there in the meantime
+fn key_refresh(args: &Args, config: &Config) -> KeyRefresh {
+    if let Some(command) = args.key_command.as_deref().or(config.key_command.as_deref()) {
+        let hook = CommandHook::new(command);
+        return Arc::new(move || {
+            let hook = hook.clone();
+            Box::pin(async move {
+                let key = hook.run(&[]).await.map_err(Error::Auth)?;
+                Ok(String::from_utf8_lossy(&key).trim().to_string())
+            })
+        });
+    }
+
+    let key_arg = args.key.first().cloned();
+    let config_path = args.config.clone();
+    Arc::new(move || {
+        let key_arg = key_arg.clone();
+        let config_path = config_path.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let config = load_config(config_path.as_deref())?;
+                match auth::resolve_key(key_arg.as_deref(), config.key.as_deref()) {
+                    Some((key, _)) => Ok(key),
+                    None => Err(Error::Auth("oracle key not provided".to_string())),
+                }
+            })
+            .await?
+        })
+    })
+}
 
+fn decompiler_settings(args: &Args, config: &Config) -> Result<DecompilerSettings> {
     let key = {
-        let env = env::var("ORACLE_KEY").ok();
-        let arg = args.key;
-        match arg.or(env) {
-            Some(key) => key,
+        let config_key = config.key.as_deref().or(config.keys.first().map(String::as_str));
+        match auth::resolve_key(args.key.first().map(String::as_str), config_key) {
+            Some((key, _)) => key,
+            // the recording doesn't need one
+            None if args.replay.is_some() => String::new(),
             None => {
-                return Err(format!(
+                return Err(Error::Auth(format!(
                     "oracle key not provided. try `{} help`",
                     env::args().next().unwrap()
-                )
-                .into());
+                )));
             }
         }
     };
 
-    let decompiler_options = match (args.decompiler_options, args.decompiler_options_file) {
+    let decompiler_options = match (&args.decompiler_options, &args.decompiler_options_file) {
         (Some(json_str), _) => {
-            let value: serde_json::Value = serde_json::from_str(&json_str)
-                .map_err(|e| format!("invalid decompiler options json: {}", e))?;
+            let value: serde_json::Value = serde_json::from_str(json_str)
+                .map_err(|e| Error::Config(format!("invalid decompiler options json: {}", e)))?;
             Some(value)
         }
         (_, Some(path)) => {
-            let contents = std::fs::read_to_string(&path)
-                .map_err(|e| format!("failed to read decompiler options file: {}", e))?;
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| Error::Config(format!("failed to read decompiler options file: {}", e)))?;
             let value: serde_json::Value = serde_json::from_str(&contents)
-                .map_err(|e| format!("invalid json in decompiler options file: {}", e))?;
+                .map_err(|e| Error::Config(format!("invalid json in decompiler options file: {}", e)))?;
             Some(value)
         }
-        _ => None,
+        _ => config.decompiler_options.clone(),
+    };
+    let preset_options = match args.preset.as_deref().or(config.preset.as_deref()) {
+        Some(name) => Some(preset(name, &config.presets)?),
+        None => None,
+    };
+    let decompiler_options = match (preset_options, decompiler_options) {
+        (Some(base), Some(options)) => Some(layered(&base, &options)),
+        (base, options) => options.or(base),
+    };
+
+    let base_urls = if !args.base_url.is_empty() {
+        args.base_url.clone()
+    } else {
+        match &config.base_url {
+            Some(base_urls) => base_urls.to_vec(),
+            None => vec![DEFAULT_BASE_URL.to_string()],
+        }
+    };
+    let version = args.oracle_version.or(config.oracle_version);
+    let urls = base_urls
+        .into_iter()
+        .map(|base_url| match version {
+            Some(v) => format!("{}?version={}", base_url, v),
+            None => base_url,
+        })
+        .collect();
+
+    // the rest of the --key values, or the config's keys, to spread the run over
+    let pool = if args.key.is_empty() { &config.keys } else { &args.key };
+    let mut extra_keys: Vec<String> = Vec::new();
+    for other in pool {
+        if *other != key && !extra_keys.contains(other) {
+            extra_keys.push(other.clone());
+        }
+    }
+
+    let replay = match &args.replay {
+        Some(path) => {
+            let recording = Recording::load(path)?;
+            info!("replaying {} answers from {}", recording.answers(), path.display());
+            Some(Arc::new(recording))
+        }
+        None => None,
+    };
+
+    let cache = if args.no_cache || args.replay.is_some() {
+        None
+    } else {
+        let local = match cache_dir(args, config) {
+            Some(dir) => {
+                // only the folder is encrypted, so there's nothing to ask for without one
+                let secret = match args.cache_encrypt.or(config.cache.encrypt) {
+                    Some(CacheEncryption::OracleKey) => Some(key.clone()),
+                    Some(CacheEncryption::Passphrase) => Some(auth::cache_passphrase()?),
+                    None => None,
+                };
+                Some(LocalCache::open(dir, cache_max_size(args, config), secret.as_deref())?)
+            }
+            None => None,
+        };
+        let remote = match args.cache_url.as_deref().or(config.cache.remote_url.as_deref()) {
+            Some(url) => Some(RemoteCache::new(url, config.cache.remote_token.clone())?),
+            None => None,
+        };
+        (local.is_some() || remote.is_some()).then(|| Arc::new(DecompilationCache::new(local, remote, version)))
     };
 
-    let url = match args.oracle_version {
-        Some(v) => format!("{}?version={}", args.base_url, v),
-        None => args.base_url,
+    let gentle = args.gentle || config.gentle.unwrap_or(false);
+    let connections = args.connections.or(config.connections);
+    let connections = gentle_limit(connections, GENTLE_CONNECTIONS, gentle).unwrap_or(1 + extra_keys.len());
+    Ok(DecompilerSettings {
+        endpoints: urls,
+        auth_token: key,
+        extra_keys,
+        transport: args.transport.or(config.transport),
+        options: decompiler_options,
+        max_retries: args.retries.or(config.retries).unwrap_or(0),
+        connections,
+        max_rps: gentle_limit(args.max_rps.or(config.max_rps), GENTLE_MAX_RPS, gentle),
+        max_concurrent: gentle_limit(args.max_concurrent.or(config.max_concurrent), GENTLE_MAX_CONCURRENT, gentle),
+        max_bytes_in_flight: max_bytes_in_flight(args, config),
+        fixed_window: args.fixed_window || config.fixed_window.unwrap_or(false),
+        autotune: args.autotune || config.autotune.unwrap_or(false),
+        protocol: args.protocol.or(config.protocol),
+        // only `--with-disasm` needs to know what the oracle speaks up front
+        wait_for_hello: args.with_disasm || config.output.with_disasm.unwrap_or(false),
+        compression: args.compression.or(config.compression),
+        pre_process: args
+            .pre_process_cmd
+            .as_deref()
+            .or(config.pre_process_cmd.as_deref())
+            .map(CommandHook::new),
+        post_process: args
+            .post_process_cmd
+            .as_deref()
+            .or(config.post_process_cmd.as_deref())
+            .map(CommandHook::new),
+        refresh_key: Some(key_refresh(args, config)),
+        retry_profiles: config.retry_profiles.clone(),
+        answer_unreachable: false,
+        record: args.record.as_deref().map(Recorder::open).transpose()?.map(Arc::new),
+        replay,
+        cache,
+    })
+}
+
+async fn connect(args: &Args, config: &Config) -> Result<Decompiler> {
+    Decompiler::new(&decompiler_settings(args, config)?).await
+}
+
+/// What every command that writes places shares, from the output flags.
+/// The rest is up to each command.
+fn rbxlx_options(render: RenderOptions, profiles: &OptionProfiles) -> RbxlxOptions {
+    RbxlxOptions {
+        format_lua: render.format_lua,
+        disasm: render.disasm,
+        with_disasm: render.with_disasm,
+        integrity: render.integrity,
+        size_comment: render.size_comment,
+        debug_names: render.debug_names,
+        luau: render.luau,
+        profiles: profiles.clone(),
+        ..RbxlxOptions::default()
+    }
+}
+
+/// Sets up the folder this run's temp files go in
+fn run_context(args: &Args, config: &Config) -> Result<RunContext> {
+    let base = args.temp_dir.as_deref().or(config.temp_dir.as_deref());
+    RunContext::create(base, args.keep_temp || config.keep_temp.unwrap_or(false))
+}
+
+/// Shuts the decompiler down after `result` was produced with it. A connection
+/// error wins over `result`'s own error, since it's usually what caused it.
+async fn finish<T>(decompiler: Decompiler, result: Result<T>) -> Result<T> {
+    if let Some(credits) = decompiler.credits_remaining() {
+        info!("{} credits left on the key", credits);
+    }
+    let cache_hits = decompiler.cache_hits();
+    if cache_hits > 0 {
+        info!("{} scripts answered from the cache", cache_hits);
+    }
+    decompiler.shutdown().await?;
+    result
+}
+
+#[tokio::main]
+async fn main() -> std::process::ExitCode {
+    let args = Args::parse();
+    logging::init(args.verbose as i8 - args.quiet as i8, args.log_json);
+    if args.progress_json {
+        progress::enable();
+    }
+
+    let (status, exit_code) = match run(args).await {
+        Ok(()) => ("ok", 0),
+        Err(e) => {
+            error!("{}", e);
+            (e.status(), e.exit_code())
+        }
     };
-    let decompiler = Decompiler::new(&url, &key, decompiler_options).await?;
+    // one line with the outcome, for wrappers that read the log
+    info!(status = %status, exit_code, "finished");
+    std::process::ExitCode::from(exit_code)
+}
+
+async fn run(args: Args) -> Result<()> {
+    let config = load_config(args.config.as_deref())?;
 
     let processing_start = Instant::now();
+    let format_lua = args.format_lua || config.output.format_lua.unwrap_or(false);
+    let disasm = args.disasm || config.output.disasm.unwrap_or(false);
+    let with_disasm = args.with_disasm || config.output.with_disasm.unwrap_or(false);
+    let integrity = args.integrity || config.output.integrity.unwrap_or(false);
+    let size_comment = args.size_comment || config.output.size_comment.unwrap_or(false);
+    let debug_names = args.debug_names || config.output.debug_names.unwrap_or(false);
+    let project_files = args.project_files || config.output.project_files.unwrap_or(false);
+    let luau = args.luau || config.output.luau.unwrap_or(false);
+    let render = RenderOptions {
+        format_lua,
+        disasm,
+        with_disasm,
+        integrity,
+        size_comment,
+        debug_names,
+        luau,
+    };
+    let profiles = OptionProfiles::new(&config.profiles);
 
     match &args.command {
-        Some(Commands::Rbxlx { input, output }) => {
-            process_rbxlx_file(&decompiler, input, output).await?;
-        }
-        Some(Commands::Single { input, output }) => {
-            let (bytecode, header) = compiled::get_bytecode_from_file(input)?;
-            let mut result = decompiler.decompile_single(&bytecode).await??;
+        Some(Commands::Rbxlx {
+            inputs,
+            output,
+            include,
+            root,
+            exclude,
+            only_class,
+            dry_run,
+            annotate_duplicates,
+            scripts_dir,
+            validate,
+            fallback_options,
+            write_buffer,
+            report,
+            meta,
+            duplicates_report,
+            retry_failures,
+            gzip,
+            zstd,
+            output_format,
+            strip_bytecode,
+            keep_linked_source,
+            lenient,
+            exact,
+            template,
+            in_place,
+            backup,
+            max_script_size,
+            oversize,
+            journal,
+            resume,
+            offline_queue,
+            spill_threshold,
+        }) => {
+            let compression = match (gzip, zstd) {
+                (true, _) => Some(OutputCompression::Gzip),
+                (_, true) => Some(OutputCompression::Zstd),
+                _ => None,
+            };
+            let output_format = output_format.unwrap_or_default();
+            let extension = match compression {
+                Some(compression) => format!("{}.{}", output_format.extension(), compression.extension()),
+                None => output_format.extension().to_string(),
+            };
+            let inputs = expand_inputs(inputs)?;
+            let several = inputs.len() > 1;
+            if several && inputs.iter().any(|input| input == "-") {
+                return Err(Error::Config("stdin can only be used with a single input".to_string()));
+            }
+            if several && retry_failures.is_some() {
+                return Err(Error::Config("--retry-failures works on one place at a time".to_string()));
+            }
+            if (*in_place || *dry_run || *exact) && !inputs.iter().all(|input| is_local_file(input)) {
+                let flag = if *in_place {
+                    "--in-place"
+                } else if *dry_run {
+                    "--dry-run"
+                } else {
+                    "--exact"
+                };
+                return Err(Error::Config(format!("{} needs the input to be a file", flag)));
+            }
+
+            // (input, output, label) for every place, the label telling them apart when there are several
+            let mut places = Vec::new();
+            if several {
+                let output_dir = Path::new(output.as_deref().unwrap_or("processed"));
+                let mut used_names = HashSet::new();
+                for input in &inputs {
+                    let stem = Path::new(input)
+                        .file_stem()
+                        .map_or_else(|| "place".to_string(), |stem| stem.to_string_lossy().to_string());
+                    let mut name = stem.clone();
+                    let mut n = 2;
+                    while !used_names.insert(name.to_lowercase()) {
+                        name = format!("{}_{}", stem, n);
+                        n += 1;
+                    }
+                    let place_output = output_dir.join(format!("{}.{}", name, extension));
+                    places.push((input.clone(), place_output.to_string_lossy().to_string(), Some(name)));
+                }
+                if !*in_place && !*dry_run {
+                    std::fs::create_dir_all(output_dir)?;
+                }
+            } else {
+                let output = match output.as_deref().or(config.output.rbxlx.as_deref()) {
+                    Some(output) => output.to_string(),
+                    None => format!("processed.{}", extension),
+                };
+                places.push((inputs[0].clone(), output, None));
+            }
+
+            let mut options = RbxlxOptions {
+                filter: ScriptFilter::new(include, exclude)
+                    .with_roots(root)
+                    .with_classes(only_class),
+                annotate_duplicates: *annotate_duplicates,
+                scripts_dir: None,
+                validate: *validate || fallback_options.is_some(),
+                write_buffer: write_buffer.or(config.output.write_buffer),
+                compression,
+                output_format,
+                strip_bytecode: *strip_bytecode || config.output.strip_bytecode.unwrap_or(false),
+                keep_linked_source: *keep_linked_source,
+                lenient: *lenient,
+                exact: *exact,
+                template: template
+                    .as_deref()
+                    .or(config.output.template.as_deref())
+                    .map(OutputTemplate::load)
+                    .transpose()?,
+                shared_results: several.then(SharedResults::default),
+                ..rbxlx_options(render, &profiles)
+            };
+
+            if let Some(path) = resume {
+                let resumed = load_journal(path)?;
+                info!("{} decompilations to pick up from {}", resumed.len(), path.display());
+                options.resume = Some(Arc::new(resumed));
+            }
+
+            if retry_failures.is_some() && options.template.as_ref().is_some_and(|template| !template.keeps_bytecode()) {
+                return Err(Error::Config(
+                    "--retry-failures needs a template starting with {header}{bytecode}".to_string(),
+                ));
+            }
+            if offline_queue.is_some() && options.template.as_ref().is_some_and(|template| !template.keeps_bytecode()) {
+                return Err(Error::Config(
+                    "--offline-queue needs a template starting with {header}{bytecode}".to_string(),
+                ));
+            }
+
+            let mut previous_report = None;
+            if let Some(path) = retry_failures {
+                let previous = Report::load(path)?;
+                let failed = previous.failed_hashes();
+                if failed.is_empty() {
+                    info!("nothing failed in {}, leaving {} alone", path.display(), places[0].1);
+                    return Ok(());
+                }
+                info!("retrying {} failed scripts from {}", failed.len(), path.display());
+                options.only_hashes = Some(Arc::new(failed));
+                previous_report = Some((path, previous));
+            }
+            // always collected, it's what tells a partial failure from a full one
+            let report_sink = ReportSink::default();
+            options.report = Some(report_sink.clone());
 
-            if let Some(header) = header {
-                result = format!("{}{}\n\n-- decompilation:\n{}", header, bytecode, result);
+            // made once, so a cache passphrase is asked for once and the
+            // fallback decompiler shares the same cache
+            let settings = if *dry_run { None } else { Some(decompiler_settings(&args, &config)?) };
+            if let Some(settings) = &settings {
+                if let Some(json_str) = fallback_options {
+                    let mut settings = settings.clone();
+                    settings.options = Some(serde_json::from_str(json_str).map_err(|e| {
+                        Error::Config(format!("invalid fallback options json: {}", e))
+                    })?);
+                    settings.connections = 1;
+                    options.fallback = Some(Decompiler::new(&settings).await?);
+                }
             }
 
-            std::fs::write(output, result)?;
+            let run = if *dry_run { RunContext::default() } else { run_context(&args, &config)? };
+            options.run = run.clone();
+            let spill_threshold = spill_threshold
+                .or(config.output.spill_threshold)
+                .unwrap_or(DEFAULT_SPILL_THRESHOLD_MIB);
+            options.spool = Some(Arc::new(Spool::new(
+                run.spool_dir(),
+                (spill_threshold * 1024.0 * 1024.0) as usize,
+            )));
+
+            let place_options: Vec<RbxlxOptions> = places
+                .iter()
+                .map(|(input, output, label)| {
+                    let replaced = if *in_place { input.as_str() } else { output.as_str() };
+                    let stem = Path::new(replaced).with_extension("");
+                    let mut place_options = options.clone();
+                    place_options.label = label.clone();
+                    place_options.scripts_dir = scripts_dir.as_ref().map(|dir| match label {
+                        Some(label) => dir.join(label),
+                        None => dir.clone(),
+                    });
+                    place_options.max_script_size = max_script_size.or(config.output.max_script_size).map(|mib| {
+                        ScriptSizeLimit {
+                            max_bytes: (mib * 1024.0 * 1024.0) as usize,
+                            policy: oversize.or(config.output.oversize).unwrap_or(OversizePolicy::Truncate),
+                            sidecar_dir: PathBuf::from(format!("{}_oversized", stem.display())),
+                        }
+                    });
+                    // with several places, --journal is the folder their journals go in
+                    place_options.journal = Some(match (journal, label) {
+                        (Some(journal), Some(label)) => journal.join(format!("{}.journal", label)),
+                        (Some(journal), None) => journal.clone(),
+                        (None, label) => run.journal(label.as_deref()),
+                    });
+                    place_options
+                })
+                .collect();
+
+            if *dry_run {
+                let connections = args.connections.or(config.connections).unwrap_or(1);
+                let max_bytes_in_flight =
+                    max_bytes_in_flight(&args, &config).unwrap_or(DEFAULT_MAX_BYTES_IN_FLIGHT);
+                for ((input, _, _), options) in places.iter().zip(&place_options) {
+                    dry_run_rbxlx_file(input, options, connections, max_bytes_in_flight)?;
+                }
+            } else {
+                if let (Some(journal), true) = (journal, several) {
+                    std::fs::create_dir_all(journal)?;
+                }
+                if let (Some(scripts_dir), true) = (scripts_dir, project_files) {
+                    write_project_files(scripts_dir)?;
+                }
+                let Some(mut settings) = settings else {
+                    unreachable!("settings are made for everything but dry runs");
+                };
+                settings.answer_unreachable = offline_queue.is_some();
+                let recorder = meta.then(MetaRecorder::start);
+                let decompiler = match (Decompiler::new(&settings).await, offline_queue) {
+                    (Err(Error::Connection(e)), Some(queue)) => {
+                        warn!("{}, queueing every script to {}", e, queue.display());
+                        Decompiler::offline()
+                    }
+                    (decompiler, _) => decompiler?,
+                };
+                let runs = places.iter().zip(&place_options).map(|((input, output, _), options)| {
+                    let decompiler = &decompiler;
+                    let previous_report = &previous_report;
+                    async move {
+                        let result = match previous_report {
+                            Some(_) => patch_rbxlx_file(decompiler, output, options).await,
+                            None if *in_place => process_rbxlx_in_place(decompiler, input, options, *backup).await,
+                            None => process_rbxlx_file(decompiler, input, output, options).await,
+                        };
+                        if let (Err(e), true) = (&result, several) {
+                            error!("{}: {}", input, e);
+                        }
+                        result
+                    }
+                });
+                let results = futures::future::join_all(runs).await;
+                if let Some(fallback) = options.fallback.take() {
+                    fallback.shutdown().await?;
+                }
+                let failed_places = results.iter().filter(|result| result.is_err()).count();
+                if several && failed_places > 0 {
+                    error!("{} of {} places couldn't be processed", failed_places, places.len());
+                }
+                // the first place that failed decides how the run ends
+                let result = results.into_iter().find(Result::is_err).unwrap_or(Ok(()));
+                let new_report = Report::from_sink(&report_sink);
+                let latencies = decompiler.latencies();
+                run.finish(result.is_ok() && new_report.scripts.iter().all(|script| script.success));
+                let provenance = recorder
+                    .map(|recorder| recorder.finish(&decompiler, settings.options.clone(), &inputs, &new_report));
+
+                let mut queued = 0;
+                if let Some(queue) = offline_queue {
+                    // the place each script's failure went into, which flush-queue patches
+                    let outputs: HashMap<Option<&str>, PathBuf> = places
+                        .iter()
+                        .map(|(input, output, label)| {
+                            let written = Path::new(if *in_place { input } else { output });
+                            let written = std::fs::canonicalize(written).unwrap_or_else(|_| written.to_path_buf());
+                            (label.as_deref(), written)
+                        })
+                        .collect();
+                    let scripts: Vec<QueuedScript> = new_report
+                        .scripts
+                        .iter()
+                        .filter(|script| script.error_category == Some(FailureCategory::Unreachable))
+                        .filter_map(|script| {
+                            Some(QueuedScript {
+                                hash: script.hash.clone(),
+                                size: script.bytecode_size,
+                                path: script.path.clone(),
+                                output: outputs.get(&script.place.as_deref())?.clone(),
+                            })
+                        })
+                        .collect();
+                    queued = scripts.len();
+                    if queued > 0 {
+                        let added = append_queue(queue, &scripts)?;
+                        info!(
+                            "{} scripts queued to {} ({} new), run flush-queue once the oracle is back",
+                            queued,
+                            queue.display(),
+                            added
+                        );
+                    }
+                }
+                match finish(decompiler, result).await {
+                    Err(Error::Connection(e)) if queued > 0 => warn!("lost the oracle partway: {}", e),
+                    finished => finished?,
+                }
+
+                if let Some(provenance) = provenance {
+                    let meta_dir = if several {
+                        PathBuf::from(output.as_deref().unwrap_or("processed"))
+                    } else {
+                        let written = if *in_place { &places[0].0 } else { &places[0].1 };
+                        Path::new(written).parent().map(Path::to_path_buf).unwrap_or_default()
+                    };
+                    provenance.save(&meta_dir)?;
+                    info!("wrote {}", meta_dir.join(META_FILE_NAME).display());
+                }
+                // queued scripts aren't failures, they're waiting on the oracle
+                let failed = new_report.scripts.iter().filter(|script| !script.success).count() - queued;
+                let total = new_report.scripts.len() - queued;
+                match previous_report {
+                    Some((path, mut previous)) => {
+                        info!("{} of {} retried scripts decompiled this time", total - failed, total);
+                        previous.update(&new_report);
+                        previous.save(path)?;
+                    }
+                    None => {
+                        if let Some(path) = report {
+                            new_report.save(path)?;
+                        }
+                        if let Some(path) = duplicates_report {
+                            let duplicates = DuplicatesReport::new(&new_report);
+                            let copies: usize = duplicates.groups.iter().map(|group| group.paths.len()).sum();
+                            info!(
+                                "{} scripts share {} bytecodes between them, see {}",
+                                copies,
+                                duplicates.groups.len(),
+                                path.display()
+                            );
+                            duplicates.save(path)?;
+                        }
+                    }
+                }
+                Summary::new(&new_report, latencies).log();
+                Error::check_failures(failed, total, "scripts")?;
+            }
+        }
+        Some(Commands::Single { inputs, output, timeout, clipboard }) => {
+            let to_folder = !*clipboard
+                && (inputs.len() > 1
+                    || inputs.iter().any(|input| is_glob(input))
+                    || output.as_deref().is_some_and(|output| Path::new(output).is_dir()));
+            if to_folder {
+                if inputs.iter().any(|input| input == "-") || output.as_deref() == Some("-") {
+                    return Err(Error::Config(
+                        "stdin and stdout can only be used with a single input".to_string(),
+                    ));
+                }
+                let inputs = expand_inputs(inputs)?;
+                let output = output.as_deref().unwrap_or("decompiled");
+                let timeout = timeout.map(Duration::from_secs_f64);
+                let decompiler = connect(&args, &config).await?;
+                match process_files(&decompiler, &inputs, output, render, timeout).await {
+                    // shutting down would wait for the oracle after all
+                    Err(Error::Timeout(timeout)) => return Err(Error::Timeout(timeout)),
+                    result => finish(decompiler, result).await?,
+                }
+            } else {
+                let input = if *clipboard { "the clipboard" } else { inputs[0].as_str() };
+                // the clipboard is where the result goes, a file only if asked for
+                let output = match output.as_deref() {
+                    Some(output) => Some(output),
+                    None if *clipboard => None,
+                    None => Some(config.output.single.as_deref().unwrap_or("decompiled.lua")),
+                };
+                // a text dump can have any number of scripts in it
+                let blobs = if *clipboard {
+                    compiled::get_all_bytecode_from_bytes(read_clipboard()?.as_bytes())?
+                } else {
+                    compiled::get_all_bytecode_from_file(input)?
+                };
+                let count = blobs.len();
+                let timeout = timeout.map(Duration::from_secs_f64);
+                let decompiler = connect(&args, &config).await?;
+                let disassemblies = if with_disasm && output.is_some_and(|output| output != "-") {
+                    blobs
+                        .iter()
+                        .map(|(bytecode, _)| request_disassembly(&decompiler, Arc::from(bytecode.as_str()), hash_bytecode(bytecode)))
+                        .collect::<Result<Vec<_>>>()?
+                } else {
+                    Vec::new()
+                };
+                let (rendered, failures) = match render_blobs(&decompiler, input, blobs, render, timeout).await {
+                    // shutting down would wait for the oracle after all
+                    Err(Error::Timeout(timeout)) => return Err(Error::Timeout(timeout)),
+                    result => finish(decompiler, result).await?,
+                };
+                if *clipboard {
+                    write_clipboard(&rendered)?;
+                    info!("the decompiled source is on the clipboard");
+                }
+                match output {
+                    Some("-") => std::io::stdout().write_all(rendered.as_bytes())?,
+                    Some(output) => std::fs::write(output, rendered)?,
+                    None => {}
+                }
+                if let (Some(output), false) = (output, disassemblies.is_empty()) {
+                    let mut listings = Vec::with_capacity(count);
+                    for (i, rx) in disassemblies.into_iter().enumerate() {
+                        let listing = disassembly_listing(rx, None).await;
+                        if count == 1 {
+                            listings.push(listing);
+                        } else {
+                            listings.push(format!("bytecode {} of {}\n{}", i + 1, count, listing));
+                        }
+                    }
+                    std::fs::write(Path::new(output).with_extension("disasm"), listings.join("\n"))?;
+                }
+                match failures.as_slice() {
+                    [] => {}
+                    [e] if count == 1 => return Err(Error::OracleFailure(e.clone())),
+                    _ if failures.len() == count => {
+                        return Err(Error::OracleFailure(format!("all {} scripts in {} failed", count, input)))
+                    }
+                    _ => {
+                        return Err(Error::PartialFailure(format!(
+                            "{} of the {} scripts in {} failed",
+                            failures.len(),
+                            count,
+                            input
+                        )))
+                    }
+                }
+            }
         }
         Some(Commands::Folder { input, output }) => {
+            let output = output.clone().unwrap_or_else(|| {
+                let trimmed = input.trim_end_matches('/');
+                let trimmed = if is_zip(trimmed) { &trimmed[..trimmed.len() - 4] } else { trimmed };
+                format!("{}_decompiled", trimmed)
+            });
+            if project_files {
+                write_project_files(Path::new(&output))?;
+            }
+            let decompiler = connect(&args, &config).await?;
+            let result = if is_zip(input) {
+                let options = rbxlx_options(render, &profiles);
+                process_zip(&decompiler, input, &output, &options).await
+            } else {
+                process_folder(&decompiler, input, &output, render).await
+            };
+            finish(decompiler, result).await?;
+        }
+        Some(Commands::Dump { input, output }) => {
             let output = output.clone().unwrap_or_else(|| {
                 let trimmed = input.trim_end_matches('/');
                 format!("{}_decompiled", trimmed)
             });
-            process_folder(&decompiler, input, &output).await?;
+            if project_files {
+                write_project_files(Path::new(&output))?;
+            }
+            let decompiler = connect(&args, &config).await?;
+            let result = process_dump(&decompiler, input, &output, render, &profiles).await;
+            finish(decompiler, result).await?;
+        }
+        Some(Commands::Sqlite { input, output, table }) => {
+            let output = output.clone().unwrap_or_else(|| {
+                format!("{}_decompiled", Path::new(input).with_extension("").display())
+            });
+            let to_folder = !output.to_ascii_lowercase().ends_with(".rbxlx");
+            if project_files && to_folder {
+                write_project_files(Path::new(&output))?;
+            }
+            let decompiler = connect(&args, &config).await?;
+            let result = process_sqlite(&decompiler, input, table.as_deref(), &output, render, &profiles).await;
+            finish(decompiler, result).await?;
+        }
+        Some(Commands::Extract {
+            input,
+            output,
+            format,
+            naming,
+        }) => {
+            let output = output.clone().unwrap_or_else(|| {
+                let trimmed = Path::new(input.trim_end_matches('/'));
+                let stem = if trimmed.is_dir() {
+                    trimmed.to_path_buf()
+                } else {
+                    trimmed.with_extension("")
+                };
+                format!("{}_bytecode", stem.display())
+            });
+            let format = format
+                .or(config.output.extract_format)
+                .unwrap_or(BytecodeFormat::Raw);
+            let naming = naming
+                .or(config.output.extract_naming)
+                .unwrap_or(FileNaming::Path);
+            extract_bytecode(input, &output, format, naming)?;
+        }
+        Some(Commands::StudioPlaces { list, output }) => {
+            let places = find_studio_places();
+            if places.is_empty() {
+                return Err(Error::Other("no saved places found in Studio's folders".to_string()));
+            }
+            if *list {
+                print_places(&places);
+                return Ok(());
+            }
+            let Some(place) = pick_place(&places)? else {
+                return Ok(());
+            };
+
+            let output = output
+                .as_deref()
+                .or(config.output.rbxlx.as_deref())
+                .unwrap_or("processed.rbxlx");
+            let binary = place.path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("rbxl"));
+            let run = run_context(&args, &config)?;
+            let input = if binary {
+                let converted = run.temp_file(None, "rbxlx");
+                convert_rbxl_to_rbxlx(&place.path, &converted)?;
+                converted.to_string_lossy().to_string()
+            } else {
+                place.path.to_string_lossy().to_string()
+            };
+
+            let options = RbxlxOptions {
+                report: Some(ReportSink::default()),
+                run: run.clone(),
+                ..rbxlx_options(render, &profiles)
+            };
+            let decompiler = connect(&args, &config).await?;
+            let result = process_rbxlx_file(&decompiler, &input, output, &options).await;
+            if binary {
+                let _ = std::fs::remove_file(&input);
+            }
+            let latencies = decompiler.latencies();
+            let result = finish(decompiler, result).await;
+
+            let report = Report::from_sink(options.report.as_ref().unwrap());
+            Summary::new(&report, latencies).log();
+            let failed = report.scripts.iter().filter(|script| !script.success).count();
+            run.finish(result.is_ok() && failed == 0);
+            result?;
+            Error::check_failures(failed, report.scripts.len(), "scripts")?;
+        }
+        Some(Commands::FlushQueue { queue }) => {
+            let mut scripts = load_queue(queue)?;
+            if scripts.is_empty() {
+                info!("nothing queued in {}", queue.display());
+                return Ok(());
+            }
+            // every output with the hashes waiting on it, in the order they were queued
+            let mut outputs: Vec<(PathBuf, HashSet<String>)> = Vec::new();
+            for script in &scripts {
+                match outputs.iter_mut().find(|(output, _)| *output == script.output) {
+                    Some((_, hashes)) => {
+                        hashes.insert(script.hash.clone());
+                    }
+                    None => outputs.push((script.output.clone(), HashSet::from([script.hash.clone()]))),
+                }
+            }
+            info!("decompiling {} queued scripts in {} places", scripts.len(), outputs.len());
+
+            let template = config.output.template.as_deref().map(OutputTemplate::load).transpose()?;
+            let run = run_context(&args, &config)?;
+            let decompiler = connect(&args, &config).await?;
+            let mut decompiled = HashSet::new();
+            let mut result = Ok(());
+            for (output, hashes) in outputs {
+                if !output.exists() {
+                    warn!("{} is gone, leaving its scripts in the queue", output.display());
+                    continue;
+                }
+                let sink = ReportSink::default();
+                let options = RbxlxOptions {
+                    report: Some(sink.clone()),
+                    only_hashes: Some(Arc::new(hashes)),
+                    template: template.clone(),
+                    run: run.clone(),
+                    ..rbxlx_options(render, &profiles)
+                };
+                if let Err(e) = patch_rbxlx_file(&decompiler, &output.to_string_lossy(), &options).await {
+                    error!("{}: {}", output.display(), e);
+                    result = Err(e);
+                    continue;
+                }
+                for script in Report::from_sink(&sink).scripts.into_iter().filter(|script| script.success) {
+                    decompiled.insert((script.hash, output.clone()));
+                }
+            }
+
+            let total = scripts.len();
+            scripts.retain(|script| !decompiled.contains(&(script.hash.clone(), script.output.clone())));
+            save_queue(queue, &scripts)?;
+            run.finish(result.is_ok() && scripts.is_empty());
+            finish(decompiler, result).await?;
+            match scripts.len() {
+                0 => info!("all {} queued scripts decompiled, removed {}", total, queue.display()),
+                left => info!("{} of {} queued scripts decompiled, {} left in {}", total - left, total, left, queue.display()),
+            }
+            Error::check_failures(scripts.len(), total, "queued scripts")?;
+        }
+        Some(Commands::Verify { input, output }) => {
+            let output = output
+                .as_deref()
+                .or(config.output.rbxlx.as_deref())
+                .unwrap_or("processed.rbxlx");
+            verify_rbxlx(input, output)?;
+        }
+        Some(Commands::Strings { input }) => {
+            print_strings(input)?;
+        }
+        Some(Commands::Stats { input, output, format }) => {
+            write_stats(input, output.as_deref(), *format, disasm)?;
+        }
+        Some(Commands::RoundtripCheck { input, report }) => {
+            roundtrip_check(input, report.as_deref())?;
+        }
+        Some(Commands::Daemon { socket, metrics_listen }) => {
+            let socket = socket.clone().unwrap_or_else(default_socket_path);
+            let decompiler = connect(&args, &config).await?;
+            let result = match metrics_listen {
+                Some(listen) => tokio::select! {
+                    result = run_daemon(&decompiler, &socket, render) => result,
+                    result = serve_metrics(&decompiler, listen) => result,
+                },
+                None => run_daemon(&decompiler, &socket, render).await,
+            };
+            finish(decompiler, result).await?;
+        }
+        Some(Commands::Serve { listen, cache_entries }) => {
+            let listen = listen.as_deref().unwrap_or(DEFAULT_LISTEN_ADDRESS);
+            let options = ServeOptions {
+                render,
+                profiles,
+                cache_entries: cache_entries.unwrap_or(DEFAULT_CACHE_ENTRIES),
+            };
+            let decompiler = connect(&args, &config).await?;
+            let result = serve(&decompiler, listen, options).await;
+            finish(decompiler, result).await?;
+        }
+        #[cfg(feature = "mock-oracle")]
+        Some(Commands::MockOracle {
+            listen,
+            delay_ms,
+            fail_every,
+            out_of_order,
+            duplicate,
+            drop_id_every,
+            require_key,
+            protocol,
+            compression,
+            frame_log,
+        }) => {
+            let behaviour = MockBehaviour {
+                delay: Duration::from_millis(delay_ms.unwrap_or(0)),
+                fail_every: *fail_every,
+                out_of_order: *out_of_order,
+                duplicate: *duplicate,
+                drop_id_every: *drop_id_every,
+                key: require_key.clone(),
+                protocols: protocol.clone(),
+                compression: compression.clone(),
+                frame_log: frame_log.clone(),
+            };
+            let oracle = MockOracle::start(listen.as_deref().unwrap_or(DEFAULT_MOCK_LISTEN_ADDRESS), behaviour).await?;
+            println!("{}", oracle.url());
+            std::io::stdout().flush()?;
+            oracle.wait().await?;
+        }
+        Some(Commands::Auth { action }) => {
+            match action {
+                AuthAction::Login => auth::login()?,
+                AuthAction::Logout => auth::logout()?,
+                AuthAction::Status => auth::status(args.key.first().map(String::as_str), config.key.as_deref())?,
+            }
+            return Ok(());
+        }
+        Some(Commands::Cache { action }) => {
+            let Some(dir) = cache_dir(&args, &config) else {
+                return Err(Error::Config("no cache folder, see --cache-dir".to_string()));
+            };
+            match action {
+                CacheAction::Stats => print_cache_stats(&dir, cache_max_size(&args, &config))?,
+                CacheAction::Gc { unused_for } => collect_garbage(
+                    &dir,
+                    cache_max_size(&args, &config),
+                    unused_for.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+                )?,
+            }
+            return Ok(());
         }
         None => {
             println!("Try passing in --help")
         }
     }
 
-    drop(decompiler);
-
-    println!("time: {:?}", processing_start.elapsed());
+    info!("time: {:?}", processing_start.elapsed());
     Ok(())
 }
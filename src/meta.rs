@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::decompiler::{options::DecompileOptions, Decompiler};
+use crate::error::Result;
+use crate::report::Report;
+use crate::template::utc_timestamp;
+
+/// Written next to the output by `--meta`
+pub const META_FILE_NAME: &str = "oracle-meta.json";
+
+/// How one distinct bytecode turned out
+#[derive(Debug, Serialize)]
+pub struct HashOutcome {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How many scripts have this bytecode
+    pub scripts: usize,
+}
+
+/// What produced a processed place, so a decompilation can be traced back
+/// to the tool, oracle and options behind it
+#[derive(Debug, Serialize)]
+pub struct ProvenanceMeta {
+    pub tool: &'static str,
+    pub tool_version: &'static str,
+    pub endpoint: String,
+    pub protocol: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decompiler_options: Option<DecompileOptions>,
+    /// The command line, with the key left out
+    pub arguments: Vec<String>,
+    pub inputs: Vec<String>,
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_secs: f64,
+    /// By bytecode hash
+    pub outcomes: BTreeMap<String, HashOutcome>,
+}
+
+/// Started before a run, and turned into a `ProvenanceMeta` once it's done
+pub struct MetaRecorder {
+    started_at: String,
+    started: Instant,
+}
+
+impl MetaRecorder {
+    pub fn start() -> Self {
+        Self {
+            started_at: utc_timestamp(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn finish(
+        self,
+        decompiler: &Decompiler,
+        decompiler_options: Option<DecompileOptions>,
+        inputs: &[String],
+        report: &Report,
+    ) -> ProvenanceMeta {
+        let mut outcomes: BTreeMap<String, HashOutcome> = BTreeMap::new();
+        for script in &report.scripts {
+            outcomes
+                .entry(script.hash.clone())
+                .and_modify(|outcome| outcome.scripts += 1)
+                .or_insert_with(|| HashOutcome {
+                    success: script.success,
+                    error: script.error.clone(),
+                    scripts: 1,
+                });
+        }
+
+        ProvenanceMeta {
+            tool: env!("CARGO_PKG_NAME"),
+            tool_version: env!("CARGO_PKG_VERSION"),
+            endpoint: decompiler.endpoint().to_string(),
+            protocol: decompiler.protocol(),
+            decompiler_options,
+            arguments: redacted_arguments(),
+            inputs: inputs.to_vec(),
+            started_at: self.started_at,
+            finished_at: utc_timestamp(),
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            outcomes,
+        }
+    }
+}
+
+impl ProvenanceMeta {
+    /// Writes `oracle-meta.json` into `dir`
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("failed to serialize {}: {}", META_FILE_NAME, e))?;
+        std::fs::write(dir.join(META_FILE_NAME), json)?;
+        Ok(())
+    }
+}
+
+/// The command line the tool was run with, minus the value of `--key`
+fn redacted_arguments() -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut redact_next = false;
+    for argument in std::env::args() {
+        if redact_next {
+            arguments.push("<redacted>".to_string());
+            redact_next = false;
+        } else if argument == "-k" || argument == "--key" {
+            arguments.push(argument);
+            redact_next = true;
+        } else if argument.starts_with("--key=") {
+            arguments.push("--key=<redacted>".to_string());
+        } else if argument.starts_with("-k") && argument.len() > 2 {
+            arguments.push("-k<redacted>".to_string());
+        } else {
+            arguments.push(argument);
+        }
+    }
+    arguments
+}
@@ -0,0 +1,284 @@
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose, Engine as _};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info};
+
+use crate::decompiler::hash_bytecode;
+use crate::error::{Error, Result};
+
+pub const DEFAULT_MOCK_LISTEN_ADDRESS: &str = "127.0.0.1:0";
+
+/// How long out-of-order answers wait at least, when no delay is given
+const OUT_OF_ORDER_STEP: Duration = Duration::from_millis(10);
+
+/// How the mock oracle misbehaves. The default answers every script right away.
+#[derive(Debug, Clone, Default)]
+pub struct MockBehaviour {
+    /// How long to wait before answering each script
+    pub delay: Duration,
+    /// Fail every nth script received with an internal error, counting from 1
+    pub fail_every: Option<usize>,
+    /// Answer scripts in a different order than they came in
+    pub out_of_order: bool,
+    /// Answer every script twice
+    pub duplicate: bool,
+    /// Leave the id off the answer to every nth script received, counting from 1
+    pub drop_id_every: Option<usize>,
+    /// Turn away connections that don't bring this key
+    pub key: Option<String>,
+    /// Protocols to offer in a hello when a client connects. Empty sends no
+    /// hello, like the servers from before there was one.
+    pub protocols: Vec<u32>,
+    /// Compression to offer in the hello, `zstd` being the only one it does
+    pub compression: Vec<String>,
+    /// Appends a line to this file for every script received, saying how
+    /// it came (`text`, `binary` or `binary zstd`) and its hash
+    pub frame_log: Option<PathBuf>,
+}
+
+/// A script a client sent, and how to answer it
+struct Received {
+    /// What it's answered with
+    hash: String,
+    id: Option<u64>,
+    disassemble: bool,
+    /// How it came, for `frame_log`
+    frame: &'static str,
+    /// Whether the bytecode in a binary frame matched its hash, once
+    /// decompressed. Text frames are hashed as they are, so always match.
+    intact: bool,
+}
+
+/// What the mock oracle answers a script with: valid luau that says which
+/// bytecode it's for, so it's easy to tell whether answers got mixed up
+pub fn mock_decompilation(hash: &str) -> String {
+    format!("-- decompiled by the mock oracle\nreturn \"{}\"\n", hash)
+}
+
+/// What the mock oracle answers a request for a disassembly with
+pub fn mock_disassembly(hash: &str) -> String {
+    format!("disassembled by the mock oracle\n{}\n", hash)
+}
+
+/// A local stand-in for the oracle speaking its websocket protocol, for
+/// testing against without a key or network
+pub struct MockOracle {
+    address: SocketAddr,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl MockOracle {
+    /// Starts listening on `listen`, which can have port 0 to pick a free one
+    pub async fn start(listen: &str, behaviour: MockBehaviour) -> Result<Self> {
+        let listener = TcpListener::bind(listen).await?;
+        let address = listener.local_addr()?;
+        info!("mock oracle listening on ws://{}", address);
+        let handle = tokio::spawn(accept(listener, Arc::new(behaviour)));
+        Ok(Self { address, handle })
+    }
+
+    /// What to pass as `--base-url`
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /// Runs until accepting a connection fails
+    pub async fn wait(self) -> Result<()> {
+        self.handle.await?
+    }
+}
+
+async fn accept(listener: TcpListener, behaviour: Arc<MockBehaviour>) -> Result<()> {
+    // counted over all connections, so --fail-every doesn't depend on how many there are
+    let received = Arc::new(AtomicUsize::new(0));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let behaviour = behaviour.clone();
+        let received = received.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, behaviour, received).await {
+                debug!("mock oracle connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(stream: TcpStream, behaviour: Arc<MockBehaviour>, received: Arc<AtomicUsize>) -> Result<()> {
+    let key = behaviour.key.clone();
+    // tungstenite decides what the callback returns, error response and all
+    #[allow(clippy::result_large_err)]
+    let check_key = move |request: &Request, response: Response| {
+        let Some(key) = key else {
+            return Ok(response);
+        };
+        let given = request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if given == Some(key.as_str()) {
+            return Ok(response);
+        }
+        let mut rejected = ErrorResponse::new(Some("invalid key".to_string()));
+        *rejected.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(rejected)
+    };
+    let websocket = tokio_tungstenite::accept_hdr_async(stream, check_key).await?;
+    let (mut write, mut read) = websocket.split();
+
+    // answers are sent from here, so they can finish in any order
+    let (answer_tx, mut answer_rx) = mpsc::unbounded_channel::<String>();
+    let compress = Arc::new(AtomicBool::new(false));
+    let writer_compress = compress.clone();
+    let writer = tokio::spawn(async move {
+        while let Some(answer) = answer_rx.recv().await {
+            let message = if writer_compress.load(Ordering::Relaxed) {
+                Message::Binary(zstd::encode_all(answer.as_bytes(), 0)?.into())
+            } else {
+                Message::Text(answer.into())
+            };
+            write.send(message).await?;
+        }
+        Ok::<_, Error>(())
+    });
+
+    if !behaviour.protocols.is_empty() {
+        let hello = json!({ "type": "hello", "protocols": behaviour.protocols, "compression": behaviour.compression });
+        let _ = answer_tx.send(hello.to_string());
+    }
+
+    while let Some(message) = read.next().await {
+        let scripts: Vec<Received> = match message? {
+            Message::Text(text) => {
+                let Ok(message) = serde_json::from_str::<Value>(&text) else {
+                    continue;
+                };
+                if message["type"] == "compression" && message["algorithm"] == "zstd" {
+                    compress.store(true, Ordering::Relaxed);
+                }
+                // options other than disassemble are taken and ignored,
+                // every script is answered the same way
+                if message["type"] != "decompile" {
+                    continue;
+                }
+                let id = message["id"].as_u64();
+                let disassemble = message["options"]["disassemble"] == true;
+                let scripts = message["data"].as_array().cloned().unwrap_or_default();
+                scripts
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(|script| Received {
+                        hash: hash_bytecode(script),
+                        id,
+                        disassemble,
+                        frame: "text",
+                        intact: true,
+                    })
+                    .collect()
+            }
+            Message::Binary(frame) => match binary_frame(&frame) {
+                Some(script) => vec![script],
+                None => continue,
+            },
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        for script in scripts {
+            if let Some(path) = &behaviour.frame_log {
+                let logged = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut log| writeln!(log, "{} {}", script.frame, script.hash));
+                if let Err(e) = logged {
+                    debug!("failed to log a frame to {}: {}", path.display(), e);
+                }
+            }
+            let n = received.fetch_add(1, Ordering::Relaxed) + 1;
+            let answer = answer(&script, n, &behaviour);
+            // 2, 3, 1, 2, 3, 1... steps when out of order, so each third script overtakes the two before it
+            let delay = if behaviour.out_of_order {
+                behaviour.delay.max(OUT_OF_ORDER_STEP) * (1 + (n as u32 % 3))
+            } else {
+                behaviour.delay
+            };
+            let copies = if behaviour.duplicate { 2 } else { 1 };
+            let answer_tx = answer_tx.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                for _ in 0..copies {
+                    let _ = answer_tx.send(answer.clone());
+                }
+            });
+        }
+    }
+
+    drop(answer_tx);
+    writer.await?
+}
+
+/// The script in a binary `decompile` frame: its header, then its bytecode,
+/// compressed if the header says so
+fn binary_frame(frame: &[u8]) -> Option<Received> {
+    let length = u32::from_le_bytes(frame.get(..4)?.try_into().ok()?) as usize;
+    let header: Value = serde_json::from_slice(frame.get(4..4 + length)?).ok()?;
+    if header["type"] != "decompile" {
+        return None;
+    }
+    let hash = header["input_hash"].as_str()?.to_string();
+    let payload = &frame[4 + length..];
+    let (frame, bytecode) = match header["compression"].as_str() {
+        Some("zstd") => ("binary zstd", zstd::decode_all(payload).ok()),
+        Some(_) => ("binary", None),
+        None => ("binary", Some(payload.to_vec())),
+    };
+    let intact = bytecode.is_some_and(|bytecode| hash_bytecode(&general_purpose::STANDARD.encode(bytecode)) == hash);
+    Some(Received {
+        hash,
+        id: header["id"].as_u64(),
+        disassemble: header["options"]["disassemble"] == true,
+        frame,
+        intact,
+    })
+}
+
+/// The `decompilation_result` for the `n`th script received, with the id the
+/// request came with if it had one and `drop_id_every` doesn't drop it
+fn answer(script: &Received, n: usize, behaviour: &MockBehaviour) -> String {
+    let told_to_fail = behaviour.fail_every.is_some_and(|every| every > 0 && n.is_multiple_of(every));
+    let fails = told_to_fail || !script.intact;
+    let hash = script.hash.as_str();
+    let data = if told_to_fail {
+        "internal error: the mock oracle was told to fail this one".to_string()
+    } else if !script.intact {
+        "internal error: the bytecode doesn't match its hash".to_string()
+    } else if script.disassemble {
+        mock_disassembly(hash)
+    } else {
+        mock_decompilation(hash)
+    };
+    let mut answer = json!({
+        "type": "decompilation_result",
+        "success": !fails,
+        "data": data,
+        "input_hash": hash,
+    });
+    let drops_id = behaviour.drop_id_every.is_some_and(|every| every > 0 && n.is_multiple_of(every));
+    if let Some(id) = script.id.filter(|_| !drops_id) {
+        answer["id"] = id.into();
+    }
+    answer.to_string()
+}
@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::Result;
+use crate::instance::InstancePath;
+
+/// Written next to the exported scripts, saying which instance went where
+pub const MANIFEST_FILE_NAME: &str = "script-manifest.json";
+
+/// Scripts whose path would get too long for windows end up flat in here
+const LONG_PATHS_DIR: &str = "_long_paths";
+
+/// Windows gives up at 260 characters, with some room for `_2` suffixes
+const MAX_PATH_LEN: usize = 240;
+
+/// Most file systems stop at 255 bytes a name, with some room for suffixes and the extension
+const MAX_NAME_BYTES: usize = 200;
+
+/// What's left of an instance name in the name of a file under `LONG_PATHS_DIR`
+const MAX_LONG_PATH_NAME_BYTES: usize = 64;
+
+/// Names windows won't create a file under, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn truncate_to_bytes(name: &str, max: usize) -> &str {
+    let mut end = name.len().min(max);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+/// Makes an instance name usable as a file name on every platform
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    let trimmed = truncate_to_bytes(&sanitized, MAX_NAME_BYTES).trim_end_matches(['.', ' ']);
+    // windows only looks at what comes before the first dot, `CON.txt` is out too
+    let stem = trimmed.split('.').next().unwrap_or_default();
+    if trimmed.is_empty() {
+        "_".to_string()
+    } else if RESERVED_NAMES.iter().any(|reserved| stem.trim_end().eq_ignore_ascii_case(reserved)) {
+        format!("{}_{}", stem, &trimmed[stem.len()..])
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Windows and macOS don't tell `Script` and `script` apart, so neither do we
+fn folded(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    /// Instance names from the place root down, as they are in the place
+    path: Vec<String>,
+    /// Relative to the export folder, with `/` separators
+    file: String,
+}
+
+/// Hands out one file per script under `root`, mirroring the instance tree.
+/// Siblings whose names end up the same (even only case-insensitively) get
+/// `_2`, `_3`, ... suffixes, and scripts nested too deep for windows go flat
+/// into `_long_paths`. Every file handed out is recorded for the manifest.
+pub struct ScriptFileNamer {
+    root: PathBuf,
+    extension: &'static str,
+    /// Case-folded paths of every file and folder handed out so far
+    used_paths: HashSet<String>,
+    /// The folder each instance ended up in, by its parent's folder and its own name
+    folders: HashMap<(PathBuf, String), PathBuf>,
+    manifest: Vec<ManifestEntry>,
+}
+
+impl ScriptFileNamer {
+    pub fn new(root: &Path, extension: &'static str) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            extension,
+            used_paths: HashSet::new(),
+            folders: HashMap::new(),
+            manifest: Vec::new(),
+        }
+    }
+
+    /// A free name for `name` in `parent`, `<name><suffix>` or `<name>_2<suffix>` and so on
+    fn claim(&mut self, parent: &Path, name: &str, suffix: &str) -> PathBuf {
+        let mut candidate = parent.join(format!("{}{}", name, suffix));
+        let mut n = 2;
+        while self.used_paths.contains(&folded(&candidate)) {
+            candidate = parent.join(format!("{}_{}{}", name, n, suffix));
+            n += 1;
+        }
+        self.used_paths.insert(folded(&candidate));
+        candidate
+    }
+
+    fn folder_for(&mut self, parent: PathBuf, name: &str) -> PathBuf {
+        if let Some(folder) = self.folders.get(&(parent.clone(), name.to_string())) {
+            return folder.clone();
+        }
+        let folder = self.claim(&parent, &sanitize_file_name(name), "");
+        self.folders.insert((parent, name.to_string()), folder.clone());
+        folder
+    }
+
+    pub fn path_for(&mut self, path: &InstancePath) -> PathBuf {
+        let name = path.segments().last().map_or("_", String::as_str);
+        self.path_named(path, name)
+    }
+
+    /// Like `path_for`, but the file is named `name` instead of after the
+    /// script. The manifest still has the script's own path.
+    pub fn path_named(&mut self, path: &InstancePath, name: &str) -> PathBuf {
+        let ancestors = path.segments().split_last().map_or(&[][..], |(_, ancestors)| ancestors);
+        let extension = format!(".{}", self.extension);
+
+        let mut parent = self.root.clone();
+        for ancestor in ancestors {
+            parent = self.folder_for(parent, ancestor);
+        }
+        let stem = sanitize_file_name(name);
+
+        let file = if parent.join(format!("{}{}", stem, extension)).as_os_str().len() > MAX_PATH_LEN {
+            // named after the whole path, so it's stable between runs
+            let hash = format!("{:x}", Sha256::digest(path.join("\0").as_bytes()));
+            let stem = format!("{}_{}", truncate_to_bytes(&stem, MAX_LONG_PATH_NAME_BYTES), &hash[..16]);
+            self.claim(&self.root.join(LONG_PATHS_DIR), &stem, &extension)
+        } else {
+            self.claim(&parent, &stem, &extension)
+        };
+        self.record(path, &file);
+        file
+    }
+
+    /// Like `path_for`, but files are named after `hash` and
+    /// `None` is returned if that hash was already given a file
+    pub fn path_for_hash(&mut self, path: &InstancePath, hash: &str) -> Option<PathBuf> {
+        let out = self.root.join(format!("{}.{}", hash, self.extension));
+        self.record(path, &out);
+        self.used_paths.insert(folded(&out)).then_some(out)
+    }
+
+    fn record(&mut self, path: &InstancePath, file: &Path) {
+        let file = file.strip_prefix(&self.root).unwrap_or(file);
+        let file: Vec<String> = file
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        self.manifest.push(ManifestEntry {
+            path: path.segments().to_vec(),
+            file: file.join("/"),
+        });
+    }
+
+    /// Writes `script-manifest.json` to `root`, listing every file handed out
+    /// in order, along with the instance it's for
+    pub fn write_manifest(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.manifest)
+            .map_err(|e| format!("failed to serialize the manifest: {}", e))?;
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(self.root.join(MANIFEST_FILE_NAME), json)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[&str]) -> InstancePath {
+        InstancePath::new(segments.iter().map(|segment| segment.to_string()).collect())
+    }
+
+    /// Where `namer` put a file, relative to its root and with `/` separators
+    fn relative(namer: &ScriptFileNamer, file: &Path) -> String {
+        file.strip_prefix(&namer.root).unwrap().to_string_lossy().replace('\\', "/")
+    }
+
+    #[test]
+    fn empty_and_dotted_names_still_get_a_file() {
+        assert_eq!(sanitize_file_name(""), "_");
+        assert_eq!(sanitize_file_name("..."), "_");
+        assert_eq!(sanitize_file_name("  "), "_");
+        assert_eq!(sanitize_file_name("Script. "), "Script");
+    }
+
+    #[test]
+    fn characters_windows_refuses_are_replaced() {
+        assert_eq!(sanitize_file_name("a/b\\c:d*e?f\"g<h>i|j"), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(sanitize_file_name("tab\there\n"), "tab_here_");
+    }
+
+    #[test]
+    fn reserved_names_are_changed_whatever_their_case_or_extension() {
+        assert_eq!(sanitize_file_name("CON"), "CON_");
+        assert_eq!(sanitize_file_name("con.server"), "con_.server");
+        assert_eq!(sanitize_file_name("Lpt1"), "Lpt1_");
+        assert_eq!(sanitize_file_name("CONSOLE"), "CONSOLE");
+    }
+
+    #[test]
+    fn unicode_names_are_kept_and_cut_on_a_character_boundary() {
+        assert_eq!(sanitize_file_name("Café ☕ 脚本"), "Café ☕ 脚本");
+        let long = "脚".repeat(100);
+        let sanitized = sanitize_file_name(&long);
+        assert!(sanitized.len() <= MAX_NAME_BYTES);
+        assert_eq!(sanitized, "脚".repeat(MAX_NAME_BYTES / "脚".len()));
+    }
+
+    #[test]
+    fn duplicate_names_get_numbered_ignoring_case() {
+        let mut namer = ScriptFileNamer::new(Path::new("out"), "lua");
+        let files: Vec<String> = [
+            path(&["Workspace", "Script"]),
+            path(&["Workspace", "Script"]),
+            path(&["Workspace", "script"]),
+            path(&["Workspace", "Script_2"]),
+        ]
+        .iter()
+        .map(|script| {
+            let file = namer.path_for(script);
+            relative(&namer, &file)
+        })
+        .collect();
+        assert_eq!(
+            files,
+            ["Workspace/Script.lua", "Workspace/Script_2.lua", "Workspace/script_3.lua", "Workspace/Script_2_2.lua"]
+        );
+    }
+
+    #[test]
+    fn names_that_collide_once_sanitized_are_told_apart() {
+        let mut namer = ScriptFileNamer::new(Path::new("out"), "lua");
+        let first = namer.path_for(&path(&["a/b", "Script"]));
+        let second = namer.path_for(&path(&["a:b", "Script"]));
+        // the same instance keeps its folder
+        let third = namer.path_for(&path(&["a/b", "Other"]));
+        assert_eq!(relative(&namer, &first), "a_b/Script.lua");
+        assert_eq!(relative(&namer, &second), "a_b_2/Script.lua");
+        assert_eq!(relative(&namer, &third), "a_b/Other.lua");
+    }
+
+    #[test]
+    fn paths_too_long_for_windows_go_flat_and_stay_put() {
+        let deep: Vec<String> = (0..30).map(|i| format!("Folder{:02}", i)).chain(["Script".to_string()]).collect();
+        let deep = InstancePath::new(deep);
+        let file = ScriptFileNamer::new(Path::new("out"), "lua").path_for(&deep);
+        let again = ScriptFileNamer::new(Path::new("out"), "lua").path_for(&deep);
+        assert_eq!(file, again);
+        assert!(file.starts_with(Path::new("out").join(LONG_PATHS_DIR)));
+        assert!(file.file_name().unwrap().to_string_lossy().starts_with("Script_"));
+    }
+
+    #[test]
+    fn a_hash_only_gets_one_file() {
+        let mut namer = ScriptFileNamer::new(Path::new("out"), "lua");
+        assert_eq!(namer.path_for_hash(&path(&["A"]), "abc"), Some(Path::new("out").join("abc.lua")));
+        assert_eq!(namer.path_for_hash(&path(&["B"]), "abc"), None);
+        // both are in the manifest, pointing at the one file
+        assert_eq!(namer.manifest.len(), 2);
+        assert!(namer.manifest.iter().all(|entry| entry.file == "abc.lua"));
+    }
+}
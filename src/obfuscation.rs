@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+
+use base64::{engine::general_purpose, Engine as _};
+
+use crate::disasm::{self, ProtoStrings};
+
+/// Watermarks and leftovers that only show up in one obfuscator's output,
+/// matched case-insensitively against string constants and globals
+const SIGNATURES: &[(&str, &[&str])] = &[
+    ("Luraph", &["luraph obfuscator", "lph_obfuscated", "lph_jit", "lph_no_virtualize"]),
+    ("Ironbrew", &["ironbrew"]),
+    ("PSU", &["psu|", "perthsecurity", "psu obfuscator"]),
+    ("Moonsec", &["moonsec"]),
+    ("Prometheus", &["prometheus obfuscator"]),
+    ("Luarmor", &["luarmor"]),
+    ("WeAreDevs", &["wearedevs"]),
+];
+
+/// What scripts carrying a packed VM payload get tagged with when no signature matches
+const UNKNOWN_VM: &str = "unknown VM";
+
+/// VM payloads are long string constants, real scripts rarely have anything that size
+const PAYLOAD_MIN_LEN: usize = 4096;
+
+/// What VM loaders need to unpack and run their payload
+const LOADER_GLOBALS: &[&str] = &["getfenv", "setfenv", "loadstring", "string.byte", "string.char", "bit32.bxor"];
+
+fn matches_signature(protos: &[ProtoStrings], markers: &[&str]) -> bool {
+    protos
+        .iter()
+        .flat_map(|proto| proto.strings.iter().chain(&proto.globals))
+        .any(|text| {
+            let text = text.to_ascii_lowercase();
+            markers.iter().any(|marker| text.contains(marker))
+        })
+}
+
+/// A loader is a handful of functions holding one huge string, and
+/// reaching for the globals that decode it
+fn looks_like_vm_loader(protos: &[ProtoStrings]) -> bool {
+    let has_payload = protos
+        .iter()
+        .flat_map(|proto| &proto.strings)
+        .any(|string| string.len() >= PAYLOAD_MIN_LEN);
+    let loader_globals: HashSet<&str> = protos
+        .iter()
+        .flat_map(|proto| &proto.globals)
+        .map(String::as_str)
+        .filter(|global| LOADER_GLOBALS.contains(global))
+        .collect();
+    has_payload && loader_globals.len() >= 2
+}
+
+/// Guesses which obfuscator produced a script from its base64 bytecode.
+/// `None` if it looks like a regular script, or can't be read.
+pub fn detect_obfuscator(bytecode: &str) -> Option<&'static str> {
+    let raw = general_purpose::STANDARD.decode(bytecode).ok()?;
+    let protos = disasm::strings(&raw).ok()?;
+
+    SIGNATURES
+        .iter()
+        .find(|(_, markers)| matches_signature(&protos, markers))
+        .map(|(name, _)| *name)
+        .or_else(|| looks_like_vm_loader(&protos).then_some(UNKNOWN_VM))
+}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// A script left for when the oracle is back, a line of JSON in the queue
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QueuedScript {
+    pub hash: String,
+    /// Size of the bytecode, in bytes
+    pub size: usize,
+    /// Instance path of the script
+    pub path: String,
+    /// The processed place the script's failure was written to, which
+    /// `flush-queue` patches the decompilation into
+    pub output: PathBuf,
+}
+
+/// Adds `scripts` to the queue at `path`, leaving out ones already in it
+pub fn append_queue(path: &Path, scripts: &[QueuedScript]) -> Result<usize> {
+    let queued: HashSet<(String, PathBuf)> = if path.exists() {
+        load_queue(path)?
+            .into_iter()
+            .map(|script| (script.hash, script.output))
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let mut out = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+    let mut written = 0;
+    for script in scripts {
+        if queued.contains(&(script.hash.clone(), script.output.clone())) {
+            continue;
+        }
+        serde_json::to_writer(&mut out, script).map_err(|e| format!("failed to write the queue: {}", e))?;
+        out.write_all(b"\n")?;
+        written += 1;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+/// Reads back what `append_queue` saved. A line cut off halfway is skipped.
+pub fn load_queue(path: &Path) -> Result<Vec<QueuedScript>> {
+    let mut scripts = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let Ok(script) = serde_json::from_str::<QueuedScript>(&line?) else {
+            continue;
+        };
+        scripts.push(script);
+    }
+    Ok(scripts)
+}
+
+/// Writes the queue over with what's left of it, removing it once it's empty
+pub fn save_queue(path: &Path, scripts: &[QueuedScript]) -> Result<()> {
+    if scripts.is_empty() {
+        std::fs::remove_file(path)?;
+        return Ok(());
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    for script in scripts {
+        serde_json::to_writer(&mut out, script).map_err(|e| format!("failed to write the queue: {}", e))?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
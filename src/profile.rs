@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use crate::config::ProfileConfig;
+use crate::decompiler::options::DecompileOptions;
+use crate::filter::Glob;
+use crate::instance::InstancePath;
+
+struct Profile {
+    classes: Vec<String>,
+    paths: Vec<Glob>,
+    options: Arc<DecompileOptions>,
+}
+
+impl Profile {
+    fn matches(&self, path: Option<&InstancePath>, class_name: Option<&str>) -> bool {
+        if !self.classes.is_empty()
+            && !class_name.is_some_and(|class_name| self.classes.iter().any(|class| class == class_name))
+        {
+            return false;
+        }
+        if !self.paths.is_empty() && !path.is_some_and(|path| self.paths.iter().any(|glob| glob.matches(path))) {
+            return false;
+        }
+        true
+    }
+}
+
+/// Picks the decompiler options for a script from the `[[profiles]]` in the config.
+/// The first profile whose classes and paths both match wins; scripts no
+/// profile matches get the regular `--decompiler-options`.
+#[derive(Default, Clone)]
+pub struct OptionProfiles {
+    profiles: Arc<Vec<Profile>>,
+}
+
+impl OptionProfiles {
+    pub fn new(profiles: &[ProfileConfig]) -> Self {
+        let profiles = profiles
+            .iter()
+            .map(|profile| Profile {
+                classes: profile.classes.clone(),
+                paths: profile.paths.iter().map(|pattern| Glob::new(pattern)).collect(),
+                options: Arc::new(profile.options.clone()),
+            })
+            .collect();
+        Self {
+            profiles: Arc::new(profiles),
+        }
+    }
+
+    pub fn options_for(&self, path: Option<&InstancePath>, class_name: Option<&str>) -> Option<Arc<DecompileOptions>> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches(path, class_name))
+            .map(|profile| profile.options.clone())
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+
+use crate::decompiler::FailureCategory;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What `--progress-json` writes to stderr, one JSON object a line.
+/// `place` is only there when several places are processed at once.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    /// A script was sent off to be decompiled
+    ScriptStarted {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        place: Option<&'a str>,
+        path: &'a str,
+        hash: &'a str,
+    },
+    /// A script's decompilation was written
+    ScriptFinished {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        place: Option<&'a str>,
+        path: &'a str,
+        hash: &'a str,
+    },
+    /// A script's failure was written in place of its decompilation
+    ScriptFailed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        place: Option<&'a str>,
+        path: &'a str,
+        hash: &'a str,
+        error: &'a str,
+        category: FailureCategory,
+    },
+    /// Sent every so often while a place is processed
+    QueueDepth {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        place: Option<&'a str>,
+        /// Scripts found so far
+        found: u32,
+        /// Scripts written so far
+        done: u32,
+        /// Scripts found but not written yet
+        waiting: u32,
+    },
+}
+
+/// Turns on `--progress-json` for the rest of the run
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Writes `event` to stderr as a line of JSON, if `--progress-json` is on
+pub fn emit(event: &ProgressEvent) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(mut line) = serde_json::to_string(event) else {
+        return;
+    };
+    line.push('\n');
+    // in one write, so it doesn't end up in the middle of a log line
+    let _ = std::io::stderr().lock().write_all(line.as_bytes());
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+
+use tracing::{debug, info};
+
+use crate::error::Result;
+
+/// Lints that fire all over decompiled code without saying anything useful
+/// about it, since the decompiler can't know the original names and scoping
+const SELENE_TOML: &str = r#"std = "roblox"
+
+[lints]
+unused_variable = "allow"
+shadowing = "allow"
+empty_if = "allow"
+if_same_then_else = "allow"
+"#;
+
+const LUAURC: &str = r#"{
+	"languageMode": "nonstrict",
+	"lint": {
+		"LocalUnused": false,
+		"LocalShadow": false,
+		"FunctionUnused": false,
+		"ImportUnused": false
+	}
+}
+"#;
+
+/// Writes `selene.toml` and `.luaurc` into `dir`, so the decompiled sources
+/// in it can be linted and analyzed against the Roblox API straight away.
+/// Files that are already there are left alone, they may have been edited.
+pub fn write_project_files(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (name, contents) in [("selene.toml", SELENE_TOML), (".luaurc", LUAURC)] {
+        let path = dir.join(name);
+        if path.exists() {
+            debug!("{} already exists, leaving it alone", path.display());
+            continue;
+        }
+        std::fs::write(&path, contents)?;
+        info!("wrote {}", path.display());
+    }
+    Ok(())
+}
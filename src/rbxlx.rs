@@ -1,44 +1,385 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{
     atomic::{AtomicU32, AtomicU64, Ordering},
     Arc,
 };
 
+use futures::future::{FutureExt, Shared};
 use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use serde_derive::Deserialize;
 use tokio::sync::{mpsc, oneshot};
-use xml::reader::{EventReader, XmlEvent};
+use xml::name::OwnedName;
+use xml::namespace::Namespace;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
 use xml::writer::{EmitterConfig, XmlEvent as WriteXmlEvent};
+use tracing::{error, info, warn};
 
-use crate::decompiler::{DecompilationRequest, Decompiler};
+use crate::error::{Error, Result};
+use crate::compiled::find_bytecode;
+use crate::decompiler::{hash_bytecode, DecompilationRequest, DecompileResult, Decompiler};
+use crate::directives::hoist_directives;
+use crate::disasm::{debug_names, disassembly_comment, request_disassembly, write_disassembly};
+use crate::encoding::DecodingReader;
+use crate::exact::process_rbxlx_exact;
+use crate::filter::ScriptFilter;
+use crate::input::open_input;
+use crate::instance::{InstancePath, InstanceTracker};
+use crate::integrity::with_integrity_line;
+use crate::journal::write_journal;
+use crate::luau::{self, check_syntax};
+use crate::naming::ScriptFileNamer;
+use crate::obfuscation::detect_obfuscator;
+use crate::profile::OptionProfiles;
+use crate::progress::{emit, ProgressEvent};
+use crate::report::{ReportSink, Reused, ScriptReport};
+use crate::run::RunContext;
+use crate::sizes::with_size_line;
+use crate::sourcemap::Sourcemap;
+use crate::spool::{spooled, Spool, SpooledResult};
+use crate::template::{OutputTemplate, TemplateFields};
 
-enum ToWrite {
-    XmlEvent(XmlEvent),
-    DecompilationResult {
+#[derive(Default, Clone)]
+pub struct RbxlxOptions {
+    pub filter: ScriptFilter,
+    /// Mark scripts that reuse an earlier script's result with `-- duplicate of <path>`
+    pub annotate_duplicates: bool,
+    /// Folder to also write each decompiled script to, as `<instance path>.lua`
+    pub scripts_dir: Option<PathBuf>,
+    /// Check that every decompiled script parses as Luau
+    pub validate: bool,
+    /// Decompiler (set up with different options) to retry scripts that don't parse with
+    pub fallback: Option<Decompiler>,
+    /// Run decompiled scripts through StyLua
+    pub format_lua: bool,
+    /// Append a local disassembly to scripts the oracle couldn't decompile
+    pub disasm: bool,
+    /// Write a `.disasm` file next to each of the `scripts_dir` files
+    pub with_disasm: bool,
+    /// Put an integrity line in front of every decompiled script
+    pub integrity: bool,
+    /// Put a line comparing bytecode and decompiled sizes in front of every decompiled script
+    pub size_comment: bool,
+    /// Put the names kept in the bytecode's debug info in front of every
+    /// script, and name `scripts_dir` files after their chunk name
+    pub debug_names: bool,
+    /// Write `scripts_dir` files as `.luau`, with the script's `--!` directives at the top
+    pub luau: bool,
+    /// Leave scripts' `LinkedSource` and `ScriptGuid` as they were, instead of emptying them
+    pub keep_linked_source: bool,
+    /// Read past what can be repaired in a malformed place, and stop at the
+    /// first error that can't instead of failing
+    pub lenient: bool,
+    /// Copy the input byte for byte and only rewrite the scripts' sources,
+    /// instead of writing the place back out event by event
+    pub exact: bool,
+    /// Capacity of the channel into the writer, `DEFAULT_WRITE_CHANNEL_CAPACITY` if unset
+    pub write_buffer: Option<usize>,
+    /// Per-class/path decompiler options from the config's `[[profiles]]`
+    pub profiles: OptionProfiles,
+    /// Collects how every script turned out, for `--report`
+    pub report: Option<ReportSink>,
+    /// Only decompile scripts with these bytecode hashes, and pass everything
+    /// else through as is. Used to patch earlier failures into a processed place.
+    pub only_hashes: Option<Arc<HashSet<String>>>,
+    /// Compress the output file as it's written
+    pub compression: Option<OutputCompression>,
+    /// Which format the processed place is saved in
+    pub output_format: PlaceFormat,
+    /// Leave the original header and bytecode out of the written sources
+    pub strip_bytecode: bool,
+    /// How each script's source is put together, instead of the default layout
+    pub template: Option<OutputTemplate>,
+    /// What to do with decompilations too big for Studio to load comfortably
+    pub max_script_size: Option<ScriptSizeLimit>,
+    /// Where to save the decompilations that finished if writing the output fails
+    pub journal: Option<PathBuf>,
+    /// Decompilations from an earlier run's journal, used instead of asking the oracle again
+    pub resume: Option<Arc<HashMap<String, String>>>,
+    /// Where temp files, partial outputs and failed bytecode go
+    pub run: RunContext,
+    /// Which place this is, in front of progress lines and in the report,
+    /// when several are processed at once
+    pub label: Option<String>,
+    /// Results by bytecode hash, shared between places processed at once so
+    /// a script in several of them is only decompiled once
+    pub shared_results: Option<SharedResults>,
+    /// Where results too big to keep in memory until they're written wait
+    pub spool: Option<Arc<Spool>>,
+}
+
+/// What happens to a decompilation over `--max-script-size`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizePolicy {
+    /// Keep as many whole lines as fit, and say how much was cut
+    Truncate,
+    /// Write it to its own file next to the output, and say where
+    Sidecar,
+    /// Leave the decompilation out entirely
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptSizeLimit {
+    pub max_bytes: usize,
+    pub policy: OversizePolicy,
+    /// Where `OversizePolicy::Sidecar` writes the full decompilations
+    pub sidecar_dir: PathBuf,
+}
+
+/// Cuts `source` down to `max_bytes` at a line break where there is one
+fn truncate_source(source: &str, max_bytes: usize) -> &str {
+    let mut end = max_bytes.min(source.len());
+    while !source.is_char_boundary(end) {
+        end -= 1;
+    }
+    match source[..end].rfind('\n') {
+        Some(line_end) => &source[..line_end],
+        None => &source[..end],
+    }
+}
+
+/// Applies `limit` to a decompilation, `sidecars` names the files it writes
+fn limit_source(source: String, path: &InstancePath, limit: &ScriptSizeLimit, sidecars: &mut Option<ScriptFileNamer>) -> String {
+    if source.len() <= limit.max_bytes {
+        return source;
+    }
+    let size = source.len() as f64 / (1024.0 * 1024.0);
+    match limit.policy {
+        OversizePolicy::Truncate => {
+            let kept = truncate_source(&source, limit.max_bytes);
+            format!(
+                "{}\n-- truncated by --max-script-size: {} of {} bytes kept",
+                kept,
+                kept.len(),
+                source.len()
+            )
+        }
+        OversizePolicy::Sidecar => {
+            let namer = sidecars.get_or_insert_with(|| ScriptFileNamer::new(&limit.sidecar_dir, "lua"));
+            let file = namer.path_for(path);
+            let written = file
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::write(&file, &source));
+            match written {
+                Ok(()) => format!("-- decompilation is {:.2} MiB, written to {} instead", size, file.display()),
+                Err(e) => {
+                    error!("failed to write {}: {}", file.display(), e);
+                    format!("-- decompilation is {:.2} MiB and couldn't be written to {}: {}", size, file.display(), e)
+                }
+            }
+        }
+        OversizePolicy::Skip => format!("-- decompilation left out: {:.2} MiB is over --max-script-size", size),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputCompression {
+    Gzip,
+    Zstd,
+}
+
+impl OutputCompression {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputCompression::Gzip => "gz",
+            OutputCompression::Zstd => "zst",
+        }
+    }
+}
+
+/// What the processed place is written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaceFormat {
+    /// XML, streamed out as the place is read
+    #[default]
+    Rbxlx,
+    /// Roblox's binary format, much smaller and quicker for Studio to open.
+    /// The whole place is loaded into memory to convert it.
+    Rbxl,
+}
+
+impl PlaceFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            PlaceFormat::Rbxlx => "rbxlx",
+            PlaceFormat::Rbxl => "rbxl",
+        }
+    }
+}
+
+/// A decompilation result after `--validate` had a look at it
+struct Validated {
+    result: DecompileResult,
+    syntax_error: Option<String>,
+    used_fallback: bool,
+}
+
+/// Checks that a successful result parses, giving the fallback decompiler
+/// one go at the script if it doesn't
+async fn validate(result: DecompileResult, bytecode: &str, fallback: Option<&Decompiler>) -> Validated {
+    let syntax_error = match &result {
+        Ok(decompiled) => check_syntax(decompiled).err(),
+        Err(_) => None,
+    };
+    let (Some(syntax_error), Some(fallback)) = (syntax_error.clone(), fallback) else {
+        return Validated {
+            result,
+            syntax_error,
+            used_fallback: false,
+        };
+    };
+
+    let error = match fallback.decompile_single(bytecode).await {
+        Ok(Ok(decompiled)) => {
+            return Validated {
+                syntax_error: check_syntax(&decompiled).err(),
+                result: Ok(decompiled),
+                used_fallback: true,
+            };
+        }
+        Ok(Err(e)) => e.to_string(),
+        Err(e) => e.to_string(),
+    };
+
+    error!("retrying with --fallback-options failed: {}", error);
+    Validated {
+        result,
+        syntax_error: Some(syntax_error),
+        used_fallback: false,
+    }
+}
+
+pub type SharedResult = Shared<oneshot::Receiver<SpooledResult>>;
+
+pub type SharedResults = Arc<std::sync::Mutex<HashMap<String, SharedResult>>>;
+
+/// Rough time the oracle takes to work through one full in-flight window.
+/// Only used for the dry run estimate.
+const ESTIMATED_SECONDS_PER_WINDOW: f64 = 4.0;
+
+/// HTML's named entities that turn up in hand-edited places. XML only knows
+/// five, so --lenient reads these as what they stand for instead of stopping.
+const HTML_ENTITIES: &[(&str, &str)] = &[
+    ("nbsp", "\u{a0}"),
+    ("copy", "\u{a9}"),
+    ("reg", "\u{ae}"),
+    ("trade", "\u{2122}"),
+    ("deg", "\u{b0}"),
+    ("middot", "\u{b7}"),
+    ("bull", "\u{2022}"),
+    ("hellip", "\u{2026}"),
+    ("ndash", "\u{2013}"),
+    ("mdash", "\u{2014}"),
+    ("lsquo", "\u{2018}"),
+    ("rsquo", "\u{2019}"),
+    ("ldquo", "\u{201c}"),
+    ("rdquo", "\u{201d}"),
+    ("laquo", "\u{ab}"),
+    ("raquo", "\u{bb}"),
+    ("times", "\u{d7}"),
+];
+
+/// How many parsed events the reader thread may get ahead of submission
+pub const READ_CHANNEL_CAPACITY: usize = 4096;
+
+/// How many events may queue up in front of the writer before submission
+/// (and with it the reader thread) waits for the disk
+const DEFAULT_WRITE_CHANNEL_CAPACITY: usize = 16384;
+
+/// What the reader thread hands to the submission stage
+enum ReadEvent {
+    Xml(XmlEvent),
+    Script {
+        /// What comes before the bytecode in the CDATA section
         header: String,
         bytecode: Arc<str>,
         bytecode_hash: String,
-        rx: oneshot::Receiver<Result<String, String>>,
+        path: InstancePath,
+        /// Class of every instance along `path`
+        classes: Vec<String>,
+        obfuscator: Option<&'static str>,
     },
 }
 
-struct Utf8BoundaryReader<R: Read> {
-    inner: R,
+/// Writes `event` unless an earlier write already failed, and keeps the
+/// first error around so the rest of the results can still be collected
+fn write_event<'a, W: Write>(
+    writer: &mut xml::writer::EventWriter<W>,
+    event: impl Into<WriteXmlEvent<'a>>,
+    write_error: &mut Option<String>,
+) {
+    if write_error.is_some() {
+        return;
+    }
+    if let Err(e) = writer.write(event) {
+        *write_error = Some(e.to_string());
+    }
+}
+
+/// Keeps a copy of bytecode the oracle couldn't decompile in `failures_dir`, and
+/// returns its disassembly if `disasm` is set. Both decode the whole script,
+/// so this is run on the blocking pool.
+pub fn save_failure(bytecode: &str, hash: &str, disasm: bool, failures_dir: &Path) -> String {
+    use base64::{engine::general_purpose, Engine as _};
+    if let Ok(raw) = general_purpose::STANDARD.decode(bytecode) {
+        let _ = std::fs::create_dir_all(failures_dir);
+        let path = failures_dir.join(format!("{}.bin", hash));
+        if let Err(e) = std::fs::write(&path, &raw) {
+            error!("failed to save failed bytecode to {}: {}", path.display(), e);
+        }
+    }
+    if disasm {
+        disassembly_comment(bytecode)
+    } else {
+        String::new()
+    }
+}
+
+/// A script on its way to the writer, with the decompilation it's waiting on
+struct ScriptResult {
+    /// What came before the bytecode in the CDATA section, the rest of it
+    /// was let go of as soon as it was read
+    header: String,
+    /// Shared with the request, so the oracle and the output don't each need a copy
+    bytecode: Arc<str>,
+    bytecode_hash: String,
+    rx: SharedResult,
+    path: InstancePath,
+    classes: Vec<String>,
+    obfuscator: Option<&'static str>,
+    duplicate_of: Option<InstancePath>,
+    reused: Option<Reused>,
+}
+
+enum ToWrite {
+    XmlEvent(XmlEvent),
+    DecompilationResult(ScriptResult),
+}
+
+pub struct Utf8BoundaryReader<R: Read> {
+    inner: DecodingReader<R>,
     pending: Vec<u8>,
     output: VecDeque<u8>,
     eof: bool,
-    bytes_read: Arc<AtomicU64>,
 }
 
 impl<R: Read> Utf8BoundaryReader<R> {
-    fn new(inner: R, bytes_read: Arc<AtomicU64>) -> Self {
+    /// `bytes_read` counts what was read of `inner`, before it was transcoded
+    pub fn new(inner: R, bytes_read: Arc<AtomicU64>) -> Self {
         Self {
-            inner,
+            inner: DecodingReader::new(inner, bytes_read),
             pending: Vec::new(),
             output: VecDeque::new(),
             eof: false,
-            bytes_read,
         }
     }
 
@@ -52,7 +393,6 @@ impl<R: Read> Utf8BoundaryReader<R> {
         if read == 0 {
             self.eof = true;
         } else {
-            self.bytes_read.fetch_add(read as u64, Ordering::Relaxed);
             self.pending.extend_from_slice(&buf[..read]);
         }
 
@@ -133,132 +473,515 @@ pub async fn process_rbxlx_file(
     decompiler: &Decompiler,
     input_file: &str,
     output_file: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let file_size = std::fs::metadata(input_file)?.len();
-    let bytes_read = Arc::new(AtomicU64::new(0));
-    let total_scripts = Arc::new(AtomicU32::new(0));
-    let decompiled_count = Arc::new(AtomicU32::new(0));
-    let total_events = Arc::new(AtomicU32::new(0));
-    let written_events = Arc::new(AtomicU32::new(0));
-    let reader_done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    options: &RbxlxOptions,
+) -> Result<()> {
+    if options.output_format == PlaceFormat::Rbxl {
+        return process_rbxlx_to_rbxl(decompiler, input_file, output_file, options).await;
+    }
+    if options.exact {
+        process_rbxlx_exact(decompiler, input_file, output_file, options).await?;
+        if let Ok(metadata) = std::fs::metadata(output_file) {
+            info!("wrote {} KiB to {}", metadata.len() / 1024, output_file);
+        }
+        return Ok(());
+    }
 
-    let (write_tx, mut write_rx) = mpsc::unbounded_channel::<ToWrite>();
-    let decompiled_count_clone = decompiled_count.clone();
-    let written_events_clone = written_events.clone();
-    let output_file = output_file.to_string();
-    let writer_handle = tokio::spawn(async move {
-        let file = File::create(&output_file).expect("failed to create output file");
-        let mut buf_writer = BufWriter::with_capacity(8 * 1024 * 1024, file);
+    let (input, file_size) = open_input(input_file).await?;
+    let output = File::create(output_file)?;
+    match options.compression {
+        None => {
+            process_rbxlx(decompiler, input, file_size, output, options).await?;
+        }
+        Some(OutputCompression::Gzip) => {
+            let encoder = GzEncoder::new(output, flate2::Compression::default());
+            process_rbxlx(decompiler, input, file_size, encoder, options).await?.finish()?;
+        }
+        Some(OutputCompression::Zstd) => {
+            let encoder = zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            process_rbxlx(decompiler, input, file_size, encoder, options).await?.finish()?;
+        }
+    }
+
+    if let Ok(metadata) = std::fs::metadata(output_file) {
+        info!("wrote {} KiB to {}", metadata.len() / 1024, output_file);
+    } else {
+        info!("wrote output file to {}", output_file);
+    }
+    Ok(())
+}
+
+/// Processes the place as XML into a file next to `output_file`, then
+/// loads that and saves it in the binary format
+async fn process_rbxlx_to_rbxl(
+    decompiler: &Decompiler,
+    input_file: &str,
+    output_file: &str,
+    options: &RbxlxOptions,
+) -> Result<()> {
+    let (input, file_size) = open_input(input_file).await?;
+    let temp = options.run.temp_file(options.label.as_deref(), "rbxlx.converting");
+    let written = match File::create(&temp) {
+        Ok(xml_output) => process_rbxlx(decompiler, input, file_size, xml_output, options).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    info!("converting the processed place to rbxl");
+    let output = output_file.to_string();
+    let temp_clone = temp.clone();
+    let converted = tokio::task::spawn_blocking(move || -> Result<()> {
+        let dom = rbx_xml::from_reader_default(BufReader::with_capacity(8 * 1024 * 1024, File::open(&temp_clone)?))?;
+        let mut writer = BufWriter::with_capacity(8 * 1024 * 1024, File::create(&output)?);
+        rbx_binary::to_writer(&mut writer, &dom, dom.root().children())?;
+        writer.flush()?;
+        Ok(())
+    })
+    .await;
+    let _ = std::fs::remove_file(&temp);
+    converted??;
+
+    if let Ok(metadata) = std::fs::metadata(output_file) {
+        info!("wrote {} KiB to {}", metadata.len() / 1024, output_file);
+    } else {
+        info!("wrote output file to {}", output_file);
+    }
+    Ok(())
+}
+
+/// Moves `temp` over `file`, first keeping the original as `<file>.bak` if `backup` is set
+fn replace_file(file: &str, temp: &Path, backup: bool) -> Result<()> {
+    if backup {
+        let backup = format!("{}.bak", file);
+        let _ = std::fs::remove_file(&backup);
+        // a link keeps the original without copying it, where the file system has them
+        if std::fs::hard_link(file, &backup).is_err() {
+            std::fs::copy(file, &backup)?;
+        }
+    }
+    // `temp` is next to `file`, so this can't leave it half written
+    std::fs::rename(temp, file)?;
+    Ok(())
+}
+
+/// Processes `file` into a temporary file next to it, then moves that over
+/// `file`. If anything goes wrong, `file` is left as it was.
+pub async fn process_rbxlx_in_place(
+    decompiler: &Decompiler,
+    file: &str,
+    options: &RbxlxOptions,
+    backup: bool,
+) -> Result<()> {
+    let temp = PathBuf::from(format!("{}.processing", file));
+    if let Err(e) = process_rbxlx_file(decompiler, file, &temp.to_string_lossy(), options).await {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    replace_file(file, &temp, backup)?;
+    info!("replaced {}{}", file, if backup { format!(", the original is in {}.bak", file) } else { String::new() });
+    Ok(())
+}
+
+/// Decompiles the scripts in `options.only_hashes` again and patches them
+/// into an already processed place, leaving the rest of it alone. The
+/// place keeps every script's bytecode, so it is its own input.
+pub async fn patch_rbxlx_file(decompiler: &Decompiler, file: &str, options: &RbxlxOptions) -> Result<()> {
+    let input = File::open(file)?;
+    let file_size = input.metadata()?.len();
+    let patched = PathBuf::from(format!("{}.patching", file));
+    let output = File::create(&patched)?;
+    if let Err(e) = process_rbxlx(decompiler, input, file_size, output, options).await {
+        let _ = std::fs::remove_file(&patched);
+        return Err(e);
+    }
+    replace_file(file, &patched, false)?;
+    info!("patched {}", file);
+    Ok(())
+}
+
+/// The writing half of `process_rbxlx`. It takes the place's events off the
+/// channel in order and writes them out, turning every decompilation result
+/// into the script's source on the way.
+struct PlaceWriter {
+    scripts_namer: Option<ScriptFileNamer>,
+    luau_files: bool,
+    sourcemap: Option<Sourcemap>,
+    validate_results: bool,
+    fallback: Option<Decompiler>,
+    format_lua: bool,
+    disasm: bool,
+    integrity: bool,
+    size_comment: bool,
+    with_debug_names: bool,
+    report: Option<ReportSink>,
+    place: Option<String>,
+    template: OutputTemplate,
+    max_script_size: Option<ScriptSizeLimit>,
+    sidecars: Option<ScriptFileNamer>,
+    journal: Option<PathBuf>,
+    failures_dir: PathBuf,
+    disassembler: Option<Decompiler>,
+    /// validation results by bytecode hash, so duplicates aren't parsed
+    /// or retried again
+    validated: HashMap<String, Arc<Validated>>,
+    invalid_scripts: Vec<InstancePath>,
+    /// every result by bytecode hash, for the journal if writing fails
+    completed: HashMap<String, SharedResult>,
+    /// `.disasm` files still being written
+    disassemblies: Vec<tokio::task::JoinHandle<()>>,
+    decompiled_count: Arc<AtomicU32>,
+    written_events: Arc<AtomicU32>,
+}
+
+impl PlaceWriter {
+    fn new(
+        decompiler: &Decompiler,
+        options: &RbxlxOptions,
+        decompiled_count: Arc<AtomicU32>,
+        written_events: Arc<AtomicU32>,
+    ) -> Self {
+        Self {
+            scripts_namer: options
+                .scripts_dir
+                .as_deref()
+                .map(|dir| ScriptFileNamer::new(dir, if options.luau { "luau" } else { "lua" })),
+            luau_files: options.luau,
+            sourcemap: options.scripts_dir.as_deref().map(Sourcemap::new),
+            validate_results: options.validate,
+            fallback: options.fallback.clone(),
+            format_lua: options.format_lua,
+            disasm: options.disasm,
+            integrity: options.integrity,
+            size_comment: options.size_comment,
+            with_debug_names: options.debug_names,
+            report: options.report.clone(),
+            place: options.label.clone(),
+            template: options
+                .template
+                .clone()
+                .unwrap_or_else(|| OutputTemplate::default_for(options.strip_bytecode)),
+            max_script_size: options.max_script_size.clone(),
+            sidecars: None,
+            journal: options.journal.clone(),
+            failures_dir: options.run.failures_dir(),
+            disassembler: (options.with_disasm && options.scripts_dir.is_some()).then(|| decompiler.clone()),
+            validated: HashMap::new(),
+            invalid_scripts: Vec::new(),
+            completed: HashMap::new(),
+            disassemblies: Vec::new(),
+            decompiled_count,
+            written_events,
+        }
+    }
+
+    /// Writes everything that comes in on `write_rx` to `output` until the
+    /// channel is closed, then hands `output` back along with the scripts
+    /// that don't parse. If the writer is dropped halfway through (it
+    /// panicked), `write_rx` goes with it, which is how the sending side
+    /// finds out.
+    async fn run<W: Write>(mut self, output: W, mut write_rx: mpsc::Receiver<ToWrite>) -> (Vec<InstancePath>, Result<W>) {
+        let mut write_error = None;
+        let mut buf_writer = BufWriter::with_capacity(8 * 1024 * 1024, output);
         let mut writer = EmitterConfig::new()
             .create_writer(&mut buf_writer);
 
         while let Some(task) = write_rx.recv().await {
+            if write_error.is_some() {
+                // nothing more gets written, but what's still on its way is kept for the journal
+                if let ToWrite::DecompilationResult(script) = task {
+                    if self.journal.is_some() {
+                        let _ = script.rx.clone().await;
+                        self.completed.entry(script.bytecode_hash).or_insert(script.rx);
+                    }
+                }
+                self.written_events.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
             match task {
+                ToWrite::XmlEvent(XmlEvent::StartDocument { version, standalone, .. }) => {
+                    // the reader hands the place over as UTF-8, whatever it was declared as
+                    let event = WriteXmlEvent::StartDocument { version, encoding: Some("UTF-8"), standalone };
+                    write_event(&mut writer, event, &mut write_error);
+                }
                 ToWrite::XmlEvent(e) => {
-                    match e {
-                        XmlEvent::StartElement { name, attributes, .. } => {
-                            use xml::name::Name;
-                            let local_name_str = name.local_name.as_str();
-                            let elem_name = Name::local(local_name_str);
-                            let builder = WriteXmlEvent::start_element(elem_name);
-                            if attributes.is_empty() {
-                                if let Err(e) = writer.write(builder) {
-                                    panic!("Write error: {e}");
-                                }
-                            } else {
-                                let final_builder = attributes.iter().fold(builder, |b, attr| {
-                                    let attr_name = Name::local(attr.name.local_name.as_str());
-                                    b.attr(attr_name, &attr.value)
-                                });
-                                if let Err(e) = writer.write(final_builder) {
-                                    panic!("Write error: {e}");
-                                }
-                            }
-                        }
-                        XmlEvent::EndElement { name: _ } => {
-                            if let Err(e) = writer.write(WriteXmlEvent::end_element()) {
-                                panic!("Write error: {e}");
-                            }
-                        }
-                        XmlEvent::CData(text) => {
-                            let text_owned = text.clone();
-                            if let Err(e) = writer.write(WriteXmlEvent::cdata(&text_owned)) {
-                                panic!("Write error: {e}");
-                            }
-                        }
-                        XmlEvent::Characters(text) => {
-                            let text_owned = text.clone();
-                            if let Err(e) = writer.write(WriteXmlEvent::characters(&text_owned)) {
-                                panic!("Write error: {e}");
-                            }
-                        }
-                        XmlEvent::Comment(text) => {
-                            let text_owned = text.clone();
-                            if let Err(e) = writer.write(WriteXmlEvent::comment(&text_owned)) {
-                                panic!("Write error: {e}");
-                            }
-                        }
-                        XmlEvent::EndDocument | XmlEvent::ProcessingInstruction { .. } | XmlEvent::StartDocument { .. } => {
-                            written_events_clone.fetch_add(1, Ordering::Relaxed);
-                            continue
-                        }
-                        _ => {
-                            println!("unknownevent: {:?}", e);
-                            written_events_clone.fetch_add(1, Ordering::Relaxed);
-                            continue
-                        }
+                    // everything else is written back as it was read, prefixes,
+                    // namespaces, whitespace and processing instructions included
+                    if let Some(event) = e.as_writer_event() {
+                        write_event(&mut writer, event, &mut write_error);
                     }
                 }
-                ToWrite::DecompilationResult {
-                    header,
-                    bytecode,
-                    bytecode_hash,
-                    rx,
-                } => {
-                    let result = match rx.await {
-                        Ok(it) => it,
-                        Err(_) => {
-                            eprintln!("error: decompilation response never received (sender dropped)");
-                            Err("oracle-postprocess error: sender dropped".to_string())
-                        }
-                    };
-                    let result = match result {
-                        Ok(it) => format!("-- decompilation:\n{}", it),
-                        Err(it) => {
-                            use base64::{engine::general_purpose, Engine as _};
-                            if let Ok(raw) = general_purpose::STANDARD.decode(bytecode.as_bytes()) {
-                                let _ = std::fs::create_dir_all("failures");
-                                let path = format!("failures/{}.bin", bytecode_hash);
-                                if let Err(e) = std::fs::write(&path, &raw) {
-                                    eprintln!("error: failed to save failed bytecode to {}: {}", path, e);
-                                }
-                            }
-                            format!("-- decompilation failed:\n-- {}", it)
-                        }
-                    };
-                    let formatted_result = format!("{}{}\n\n{}\n", header, bytecode, result);
-                    let escaped_result = formatted_result.replace("]]>", "]]]]><![CDATA[>");
-                    let event = WriteXmlEvent::cdata(&escaped_result);
-
-                    decompiled_count_clone.fetch_add(1, Ordering::Relaxed);
-                    if let Err(e) = writer.write(event) {
-                        panic!("Write error: {e}");
-                    }
+                ToWrite::DecompilationResult(script) => {
+                    let contents = self.script_contents(script).await;
+                    let escaped = contents.replace("]]>", "]]]]><![CDATA[>");
+                    self.decompiled_count.fetch_add(1, Ordering::Relaxed);
+                    write_event(&mut writer, WriteXmlEvent::cdata(&escaped), &mut write_error);
                 }
             }
-            written_events_clone.fetch_add(1, Ordering::Relaxed);
+            self.written_events.fetch_add(1, Ordering::Relaxed);
         }
 
-        if let Err(e) = buf_writer.flush() {
-            println!("couldnt flush buffer: {:?}", e);
+        drop(writer);
+        let output = match write_error {
+            Some(e) => Err(e),
+            None => buf_writer.into_inner().map_err(|e| e.into_error().to_string()),
+        };
+        self.finish(output).await
+    }
+
+    /// What goes in a script's CDATA section once its result is in, also
+    /// writing its file (and asking for its disassembly) under `--scripts-dir`
+    async fn script_contents(&mut self, script: ScriptResult) -> String {
+        let ScriptResult {
+            header,
+            bytecode,
+            bytecode_hash,
+            rx,
+            path,
+            classes,
+            obfuscator,
+            duplicate_of,
+            reused,
+        } = script;
+        if self.journal.is_some() {
+            self.completed.entry(bytecode_hash.clone()).or_insert_with(|| rx.clone());
+        }
+        let result = match rx.await {
+            Ok(it) => it.and_then(|spooled| spooled.load()),
+            Err(_) => {
+                error!("decompilation response never received (sender dropped)");
+                Err("oracle-postprocess error: sender dropped".to_string().into())
+            }
+        };
+        let checked = if !self.validate_results {
+            None
+        } else if let Some(checked) = self.validated.get(&bytecode_hash) {
+            Some(checked.clone())
+        } else {
+            let checked = validate(result.clone(), &bytecode, self.fallback.as_ref()).await;
+            let checked = Arc::new(checked);
+            self.validated.insert(bytecode_hash.clone(), checked.clone());
+            Some(checked)
+        };
+        let result = checked
+            .as_ref()
+            .map_or(result, |checked| checked.result.clone());
+        let path_string = path.to_string();
+        emit(&match &result {
+            Ok(_) => ProgressEvent::ScriptFinished {
+                place: self.place.as_deref(),
+                path: &path_string,
+                hash: &bytecode_hash,
+            },
+            Err(e) => ProgressEvent::ScriptFailed {
+                place: self.place.as_deref(),
+                path: &path_string,
+                hash: &bytecode_hash,
+                error: &e.message,
+                category: e.category,
+            },
+        });
+        if let Some(report) = &self.report {
+            report.lock().unwrap().push(ScriptReport {
+                path: path_string,
+                class_name: classes.last().cloned().unwrap_or_default(),
+                hash: bytecode_hash.clone(),
+                bytecode_size: bytecode.len(),
+                output_size: result.as_ref().map_or(0, String::len),
+                success: result.is_ok(),
+                error: result.as_ref().err().map(ToString::to_string),
+                error_category: result.as_ref().err().map(|e| e.category),
+                obfuscator: obfuscator.map(str::to_string),
+                place: self.place.clone(),
+                reused,
+            });
         }
 
-        if let Ok(metadata) = std::fs::metadata(&output_file) {
-            println!("wrote {} KiB to {}", metadata.len() / 1024, output_file);
+        let result = result.map(|it| if self.format_lua {
+            luau::format_or_keep(it, &format_args!("game.{}", path))
         } else {
-            println!("wrote output file to {}", output_file);
+            it
+        });
+        let result = match (result, &self.max_script_size) {
+            (Ok(it), Some(limit)) => Ok(limit_source(it, &path, limit, &mut self.sidecars)),
+            (result, _) => result,
+        };
+        let result = result.map(|it| if self.size_comment { with_size_line(it, &bytecode) } else { it });
+        let result = match result {
+            Ok(it) if self.integrity => format!("-- decompilation:\n{}", with_integrity_line(it)),
+            Ok(it) => format!("-- decompilation:\n{}", it),
+            Err(it) => {
+                let failed_bytecode = bytecode.clone();
+                let failed_hash = bytecode_hash.clone();
+                let failures_dir = self.failures_dir.clone();
+                let disasm = self.disasm;
+                let disassembly = tokio::task::spawn_blocking(move || {
+                    save_failure(&failed_bytecode, &failed_hash, disasm, &failures_dir)
+                })
+                .await
+                .unwrap_or_default();
+                format!("-- decompilation failed:\n-- {}{}", it, disassembly)
+            }
+        };
+        let result = match checked.as_deref() {
+            Some(Validated { syntax_error: Some(e), used_fallback, .. }) => {
+                self.invalid_scripts.push(path.clone());
+                let note = if *used_fallback { " (even with --fallback-options)" } else { "" };
+                format!("-- warning: output does not parse as luau{}: {}\n{}", note, e, result)
+            }
+            Some(Validated { used_fallback: true, .. }) => {
+                format!("-- decompiled with --fallback-options\n{}", result)
+            }
+            _ => result,
+        };
+        let result = match duplicate_of {
+            Some(first_path) => format!("-- duplicate of game.{}\n{}", first_path, result),
+            None => result,
+        };
+        let result = match obfuscator {
+            Some(obfuscator) => format!("-- Obfuscator: {} (detected)\n{}", obfuscator, result),
+            None => result,
+        };
+        let names = self.with_debug_names.then(|| debug_names(&bytecode)).flatten();
+        let result = match names.as_ref().and_then(|names| names.header_line()) {
+            Some(line) => format!("{}\n{}", line, result),
+            None => result,
+        };
+        let decompilation = result;
+        let class_name = classes.last().map_or("", String::as_str);
+        let result = format!("-- Path: game.{}\n-- ClassName: {}\n{}", path, class_name, decompilation);
+        if let Some(namer) = &mut self.scripts_namer {
+            let script_path = match names.as_ref().and_then(|names| names.script_name()) {
+                Some(name) => namer.path_named(&path, name),
+                None => namer.path_for(&path),
+            };
+            let written = script_path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| {
+                    let contents = format!("{}\n", result);
+                    let contents = if self.luau_files { hoist_directives(&contents) } else { contents };
+                    std::fs::write(&script_path, contents)
+                });
+            if let Err(e) = written {
+                error!("failed to write {}: {}", script_path.display(), e);
+            }
+            if let Some(disassembler) = &self.disassembler {
+                match request_disassembly(disassembler, bytecode.clone(), bytecode_hash.clone()) {
+                    Ok(rx) => {
+                        let script_path = script_path.clone();
+                        self.disassemblies.push(tokio::spawn(async move {
+                            if let Err(e) = write_disassembly(&script_path, rx, None).await {
+                                error!("failed to write the disassembly of {}: {}", script_path.display(), e);
+                            }
+                        }));
+                    }
+                    Err(e) => error!("couldn't ask for the disassembly of game.{}: {}", path, e),
+                }
+            }
+            if let Some(sourcemap) = &mut self.sourcemap {
+                sourcemap.add(&path, &classes, &script_path);
+            }
         }
-    });
+        self.template.render(&TemplateFields {
+            header: &header,
+            bytecode: &bytecode,
+            decompilation: &decompilation,
+            path: &path.to_string(),
+            class_name,
+            hash: &bytecode_hash,
+        })
+    }
+
+    /// Waits for the `.disasm` files and writes the manifests. If writing the
+    /// output failed, the finished decompilations are saved to the journal
+    /// and the error says where they went.
+    async fn finish<W>(self, output: std::result::Result<W, String>) -> (Vec<InstancePath>, Result<W>) {
+        for disassembly in self.disassemblies {
+            let _ = disassembly.await;
+        }
+        if let Some(Err(e)) = self.sourcemap.map(|sourcemap| sourcemap.write()) {
+            error!("failed to write the sourcemap: {}", e);
+        }
+        if let Some(Err(e)) = self.scripts_namer.map(|namer| namer.write_manifest()) {
+            error!("failed to write the script manifest: {}", e);
+        }
+        if let Some(Err(e)) = self.sidecars.map(|namer: ScriptFileNamer| namer.write_manifest()) {
+            error!("failed to write the manifest of oversized scripts: {}", e);
+        }
+
+        let output = match (output, self.journal) {
+            (Ok(output), _) => Ok(output),
+            (Err(e), None) => Err(Error::from(format!("writing the output failed: {}", e))),
+            (Err(e), Some(journal)) => {
+                let mut sources = Vec::new();
+                for (hash, rx) in &self.completed {
+                    if let Ok(Ok(spooled)) = rx.clone().await {
+                        if let Ok(source) = spooled.load() {
+                            sources.push((hash.clone(), source));
+                        }
+                    }
+                }
+                let saved = write_journal(&journal, sources.iter().map(|(hash, source)| (hash.as_str(), source.as_str())));
+                Err(Error::from(match saved {
+                    Ok(saved) => format!(
+                        "writing the output failed: {}. {} finished decompilations were saved to {}, \
+                         run again with --resume {} to pick up from there",
+                        e,
+                        saved,
+                        journal.display(),
+                        journal.display()
+                    ),
+                    Err(journal_error) => format!(
+                        "writing the output failed: {}, and so did saving the finished decompilations to {}: {}",
+                        e,
+                        journal.display(),
+                        journal_error
+                    ),
+                }))
+            }
+        };
+        (self.invalid_scripts, output)
+    }
+}
+
+/// Stops the progress lines when `process_rbxlx` returns, however it returns
+struct ProgressTask(tokio::task::JoinHandle<()>);
+
+impl Drop for ProgressTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// `process_rbxlx_file` over any reader and writer, handing the writer
+/// back once the whole place has been written to it
+pub async fn process_rbxlx<R, W>(
+    decompiler: &Decompiler,
+    input: R,
+    file_size: u64,
+    output: W,
+    options: &RbxlxOptions,
+) -> Result<W>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    let total_scripts = Arc::new(AtomicU32::new(0));
+    let decompiled_count = Arc::new(AtomicU32::new(0));
+    let total_events = Arc::new(AtomicU32::new(0));
+    let written_events = Arc::new(AtomicU32::new(0));
+    let reader_done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    let write_capacity = options
+        .write_buffer
+        .unwrap_or(DEFAULT_WRITE_CHANNEL_CAPACITY)
+        .max(1);
+    let (write_tx, write_rx) = mpsc::channel::<ToWrite>(write_capacity);
+    let place_writer = PlaceWriter::new(decompiler, options, decompiled_count.clone(), written_events.clone());
+    let writer_handle = tokio::spawn(place_writer.run(output, write_rx));
 
     let decompiled_count_clone = decompiled_count.clone();
     let total_scripts_clone_progress = total_scripts.clone();
@@ -266,29 +989,47 @@ pub async fn process_rbxlx_file(
     let written_events_clone = written_events.clone();
     let reader_done_clone = reader_done.clone();
     let bytes_read_clone = bytes_read.clone();
-    let progress_handle = tokio::spawn(async move {
+    let decompiler_clone = decompiler.clone();
+    let label = options.label.clone();
+    let mut progress = ProgressTask(tokio::spawn(async move {
+        let place = label.clone();
         let file_size_mib = file_size as f64 / (1024.0 * 1024.0);
+        let prefix = label.map_or_else(String::new, |label| format!("{}: ", label));
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
         loop {
             interval.tick().await;
             let is_reader_done = reader_done_clone.load(Ordering::Relaxed);
+            let found = total_scripts_clone_progress.load(Ordering::Relaxed);
+            let done = decompiled_count_clone.load(Ordering::Relaxed);
+            emit(&ProgressEvent::QueueDepth {
+                place: place.as_deref(),
+                found,
+                done,
+                waiting: found.saturating_sub(done),
+            });
+            let credits = decompiler_clone
+                .credits_remaining()
+                .map_or_else(String::new, |credits| format!(" | {} credits left", credits));
 
             if !is_reader_done {
                 let read = bytes_read_clone.load(Ordering::Relaxed);
                 let read_mib = read as f64 / (1024.0 * 1024.0);
-                let pct = (read as f64 / file_size as f64) * 100.0;
+                // streamed input doesn't always say how big it is
+                let progress = if file_size > 0 {
+                    let pct = (read as f64 / file_size as f64) * 100.0;
+                    format!("{:.1}% ({:.1}/{:.1} MiB)", pct, read_mib, file_size_mib)
+                } else {
+                    format!("{:.1} MiB", read_mib)
+                };
                 let scripts = total_scripts_clone_progress.load(Ordering::Relaxed);
                 let decompiled = decompiled_count_clone.load(Ordering::Relaxed);
                 if scripts > 0 {
-                    println!(
-                        "reading: {:.1}% ({:.1}/{:.1} MiB) | {} scripts found, {} decompiled",
-                        pct, read_mib, file_size_mib, scripts, decompiled
+                    info!(
+                        "{}reading: {} | {} scripts found, {} decompiled{}",
+                        prefix, progress, scripts, decompiled, credits
                     );
                 } else {
-                    println!(
-                        "reading: {:.1}% ({:.1}/{:.1} MiB)",
-                        pct, read_mib, file_size_mib
-                    );
+                    info!("{}reading: {}{}", prefix, progress, credits);
                 }
             } else {
                 let decompiled = decompiled_count_clone.load(Ordering::Relaxed);
@@ -304,14 +1045,14 @@ pub async fn process_rbxlx_file(
                 let width = total_ev.to_string().len();
                 if total > 0 {
                     let dec_pct = (decompiled as f64 / total as f64) * 100.0;
-                    println!(
-                        "writing: {:.1}% ({:>width$}/{}) events | decompiled: {:.1}% ({}/{})",
-                        write_pct, written_ev, total_ev, dec_pct, decompiled, total
+                    info!(
+                        "{}writing: {:.1}% ({:>width$}/{}) events | decompiled: {:.1}% ({}/{}){}",
+                        prefix, write_pct, written_ev, total_ev, dec_pct, decompiled, total, credits
                     );
                 } else {
-                    println!(
-                        "writing: {:.1}% ({:>width$}/{}) events",
-                        write_pct, written_ev, total_ev
+                    info!(
+                        "{}writing: {:.1}% ({:>width$}/{}) events{}",
+                        prefix, write_pct, written_ev, total_ev, credits
                     );
                 }
 
@@ -320,93 +1061,528 @@ pub async fn process_rbxlx_file(
                 }
             }
         }
+    }));
+
+    let (read_tx, mut read_rx) = mpsc::channel::<ReadEvent>(READ_CHANNEL_CAPACITY);
+    let reader_settings = ReaderSettings {
+        filter: options.filter.clone(),
+        only_hashes: options.only_hashes.clone(),
+        clear_cloud_sources: !options.keep_linked_source,
+        lenient: options.lenient,
+    };
+    let total_events_clone = total_events.clone();
+    let bytes_read_clone = bytes_read.clone();
+    let reader_handle = tokio::task::spawn_blocking(move || {
+        read_rbxlx(
+            input,
+            bytes_read_clone,
+            total_events_clone,
+            reader_settings,
+            &read_tx,
+        )
     });
 
-    let input_file_handle = File::open(input_file)?;
-    let file = BufReader::with_capacity(8 * 1024 * 1024, input_file_handle);
-    let utf8_reader = Utf8BoundaryReader::new(file, bytes_read.clone());
-    let parser = EventReader::new(utf8_reader);
+    let mut duplicate_scripts = 0u32;
+    let mut obfuscated_scripts = 0u32;
+    let mut resumed_scripts = 0u32;
+    let mut shared_scripts = 0u32;
+    // first occurrence of every bytecode seen this run, so duplicates
+    // reuse its result instead of being decompiled again (even if
+    // a different profile would have applied to them)
+    let mut seen: HashMap<String, (SharedResult, InstancePath)> = HashMap::new();
+    let mut writer_gone = false;
+    while let Some(event) = read_rx.recv().await {
+        let (header, bytecode, bytecode_hash, path, classes, obfuscator) = match event {
+            ReadEvent::Xml(e) => {
+                if write_tx.send(ToWrite::XmlEvent(e)).await.is_err() {
+                    writer_gone = true;
+                    break;
+                }
+                continue;
+            }
+            ReadEvent::Script {
+                header,
+                bytecode,
+                bytecode_hash,
+                path,
+                classes,
+                obfuscator,
+            } => (header, bytecode, bytecode_hash, path, classes, obfuscator),
+        };
+
+        total_scripts.fetch_add(1, Ordering::Relaxed);
+        if obfuscator.is_some() {
+            obfuscated_scripts += 1;
+        }
 
+        if let Some((rx, first_path)) = seen.get(&bytecode_hash) {
+            duplicate_scripts += 1;
+            let sent = write_tx
+                .send(ToWrite::DecompilationResult(ScriptResult {
+                    header,
+                    bytecode,
+                    bytecode_hash,
+                    rx: rx.clone(),
+                    path,
+                    classes,
+                    obfuscator,
+                    duplicate_of: options.annotate_duplicates.then(|| first_path.clone()),
+                    reused: Some(Reused::Duplicate),
+                }))
+                .await;
+            if sent.is_err() {
+                writer_gone = true;
+                break;
+            }
+            continue;
+        }
+
+        let (dec_tx, dec_rx) = oneshot::channel::<DecompileResult>();
+        let mut rx = spooled(options.spool.clone(), dec_rx).shared();
+        // a place processed alongside this one may have asked for it already
+        let asked_already = options.shared_results.as_ref().and_then(|shared| {
+            let mut shared = shared.lock().unwrap();
+            let existing = shared.get(&bytecode_hash).cloned();
+            if existing.is_none() {
+                shared.insert(bytecode_hash.clone(), rx.clone());
+            }
+            existing
+        });
+
+        let reused = if let Some(existing) = asked_already {
+            shared_scripts += 1;
+            rx = existing;
+            Some(Reused::OtherPlace)
+        } else if let Some(source) = options.resume.as_ref().and_then(|resume| resume.get(&bytecode_hash)) {
+            resumed_scripts += 1;
+            let _ = dec_tx.send(Ok(source.clone()));
+            Some(Reused::Journal)
+        } else {
+            let request = DecompilationRequest::with_hash(bytecode.clone(), bytecode_hash.clone(), dec_tx)
+            .with_options(options.profiles.options_for(Some(&path), classes.last().map(String::as_str)));
+            emit(&ProgressEvent::ScriptStarted {
+                place: options.label.as_deref(),
+                path: &path.to_string(),
+                hash: &bytecode_hash,
+            });
+            decompiler.decompile_batch(vec![request]).await?;
+            None
+        };
+        seen.insert(bytecode_hash.clone(), (rx.clone(), path.clone()));
+        let sent = write_tx
+            .send(ToWrite::DecompilationResult(ScriptResult {
+                header,
+                bytecode,
+                bytecode_hash,
+                rx,
+                path,
+                classes,
+                obfuscator,
+                duplicate_of: None,
+                reused,
+            }))
+            .await;
+        if sent.is_err() {
+            writer_gone = true;
+            break;
+        }
+    }
+
+    if writer_gone {
+        // the writer only stops taking events when it panicked. Letting go of
+        // the reader's channel stops it too, and the panic is what's reported.
+        drop(read_rx);
+        drop(progress);
+        let _ = reader_handle.await;
+        return match writer_handle.await {
+            Err(e) => Err(e.into()),
+            Ok(_) => Err(Error::from("the output writer stopped early".to_string())),
+        };
+    }
+    let filtered_scripts = reader_handle.await??;
+
+    // and now we wait for the decompiler
+    // to do its thing
+    reader_done.store(true, Ordering::Relaxed);
+    (&mut progress.0).await?;
+    // and now the decompiler has done its thing
+    drop(write_tx);
+    let (invalid_scripts, output) = writer_handle.await?;
+    let output = output?;
+
+    if filtered_scripts > 0 {
+        info!("{} scripts skipped by --root/--include/--exclude/--only-class", filtered_scripts);
+    }
+
+    if duplicate_scripts > 0 {
+        info!("{} duplicate scripts reused an earlier result", duplicate_scripts);
+    }
+
+    if shared_scripts > 0 {
+        info!("{} scripts reused a result from another place", shared_scripts);
+    }
+
+    if resumed_scripts > 0 {
+        info!("{} scripts were taken from the journal", resumed_scripts);
+    }
+
+    if obfuscated_scripts > 0 {
+        info!("{} scripts look obfuscated, see their -- Obfuscator: line", obfuscated_scripts);
+    }
+
+    if !invalid_scripts.is_empty() {
+        info!("{} scripts don't parse as luau:", invalid_scripts.len());
+        for path in &invalid_scripts {
+            info!("  game.{}", path);
+        }
+    }
+
+    if total_scripts.load(Ordering::Relaxed) == 0 {
+        info!("no scripts found to decompile");
+    }
+
+    Ok(output)
+}
+
+/// What `read_rbxlx` needs from the options, taken along to its thread
+struct ReaderSettings {
+    filter: ScriptFilter,
+    only_hashes: Option<Arc<HashSet<String>>>,
+    /// Empty scripts' `LinkedSource` and `ScriptGuid`, unless --keep-linked-source
+    clear_cloud_sources: bool,
+    lenient: bool,
+}
+
+/// Parses the place on a blocking thread, finding the scripts and hashing their
+/// bytecode, so the async side only has to submit requests and forward events.
+/// Returns how many scripts the filter skipped.
+fn read_rbxlx(
+    input: impl Read,
+    bytes_read: Arc<AtomicU64>,
+    total_events: Arc<AtomicU32>,
+    settings: ReaderSettings,
+    read_tx: &mpsc::Sender<ReadEvent>,
+) -> Result<u32> {
+    let ReaderSettings { filter, only_hashes, clear_cloud_sources, lenient } = settings;
+    let only_hashes = only_hashes.as_deref();
+    let file = BufReader::with_capacity(8 * 1024 * 1024, input);
+    let utf8_reader = Utf8BoundaryReader::new(file, bytes_read);
+    let parser = if lenient {
+        ParserConfig::new()
+            .replace_unknown_entity_references(true)
+            .add_entities(HTML_ENTITIES.iter().copied())
+            .ignore_invalid_encoding_declarations(true)
+            .create_reader(utf8_reader)
+    } else {
+        EventReader::new(utf8_reader)
+    };
+
+    let mut tracker = InstanceTracker::default();
+    let mut filtered_scripts = 0u32;
     let mut event_count = 0u64;
+    // by bytecode hash, so duplicates aren't parsed again
+    let mut obfuscators: HashMap<String, Option<&'static str>> = HashMap::new();
+    let mut cloud_sources = CloudSourceClearer::default();
+    // elements passed on that haven't ended yet, for --lenient to close
+    let mut open_elements: Vec<OwnedName> = Vec::new();
     for e in parser {
         event_count += 1;
-        match e {
-            Ok(XmlEvent::CData(cdata_string)) => {
-                total_events.fetch_add(1, Ordering::Relaxed);
-                let (dec_tx, dec_rx) = oneshot::channel::<Result<String, String>>();
-
-                let bytecode_start_lf = "-- Bytecode (Base64):\n-- ";
-                let bytecode_start_crlf = "-- Bytecode (Base64):\r\n-- ";
-                
-                let bytecode_position = cdata_string
-                    .find(bytecode_start_lf)
-                    .map(|it| it + bytecode_start_lf.len())
-                    .or_else(|| cdata_string
-                        .find(bytecode_start_crlf)
-                        .map(|it| it + bytecode_start_crlf.len()));
-
-                let Some(position) = bytecode_position else {
-                    write_tx
-                        .send(ToWrite::XmlEvent(XmlEvent::CData(cdata_string)))
-                        .unwrap();
-                    continue;
-                };
+        let e = match e {
+            Ok(e) => e,
+            Err(e) if lenient => {
+                warn!(
+                    "xml parsing error at event #{}: {e}, keeping what came before it and closing the {} elements still open",
+                    event_count,
+                    open_elements.len()
+                );
+                for name in open_elements.drain(..).rev() {
+                    total_events.fetch_add(1, Ordering::Relaxed);
+                    if read_tx.blocking_send(ReadEvent::Xml(XmlEvent::EndElement { name })).is_err() {
+                        break;
+                    }
+                }
+                break;
+            }
+            Err(e) => {
+                error!("xml parsing error at event #{}: {e}", event_count);
+                return Err(e.into());
+            }
+        };
+        let e = if clear_cloud_sources {
+            // events left out of the output aren't counted, or writing would never get to 100%
+            match cloud_sources.rewrite(e) {
+                Some(e) => e,
+                None => continue,
+            }
+        } else {
+            e
+        };
+        total_events.fetch_add(1, Ordering::Relaxed);
 
-                total_scripts.fetch_add(1, Ordering::Relaxed);
+        let event = match e {
+            XmlEvent::CData(source) => {
+                let found = tracker
+                    .in_script_source()
+                    .then(|| find_bytecode(&source))
+                    .flatten();
+                let path = tracker.path();
+                match found {
+                    Some((start, end)) if filter.allows(&path, tracker.class_name()) => {
+                        let bytecode_hash = hash_bytecode(&source[start..end]);
+                        if only_hashes.is_some_and(|only| !only.contains(&bytecode_hash)) {
+                            ReadEvent::Xml(XmlEvent::CData(source))
+                        } else {
+                            let obfuscator = *obfuscators
+                                .entry(bytecode_hash.clone())
+                                .or_insert_with(|| detect_obfuscator(&source[start..end]));
+                            let bytecode = Arc::from(&source[start..end]);
+                            // only the header is kept of the CDATA, the bytecode
+                            // makes up most of it and now has a copy of its own
+                            let mut header = source;
+                            header.truncate(start);
+                            header.shrink_to_fit();
+                            ReadEvent::Script {
+                                bytecode_hash,
+                                header,
+                                bytecode,
+                                path,
+                                classes: tracker.class_names(),
+                                obfuscator,
+                            }
+                        }
+                    }
+                    found => {
+                        if found.is_some() {
+                            filtered_scripts += 1;
+                        }
+                        ReadEvent::Xml(XmlEvent::CData(source))
+                    }
+                }
+            }
+            e => {
+                tracker.observe(&e);
+                if lenient {
+                    match &e {
+                        XmlEvent::StartElement { name, .. } => open_elements.push(name.clone()),
+                        XmlEvent::EndElement { .. } => {
+                            open_elements.pop();
+                        }
+                        _ => {}
+                    }
+                }
+                ReadEvent::Xml(e)
+            }
+        };
 
-                let bytecode_end = cdata_string[position..]
-                    .find(|c| c == '\n' || c == '\r')
-                    .map(|idx| position + idx)
-                    .unwrap_or(cdata_string.len());
+        // the receiver only goes away if submission failed, and it reports that itself
+        if read_tx.blocking_send(event).is_err() {
+            break;
+        }
+    }
 
-                let header = cdata_string[..position].to_string();
-                let bytecode = &cdata_string[position..bytecode_end];
+    if cloud_sources.cleared > 0 {
+        info!("emptied {} LinkedSource and ScriptGuid properties, so Studio keeps the decompiled sources", cloud_sources.cleared);
+    }
+    Ok(filtered_scripts)
+}
 
-                let bytecode_hash = format!("{:x}", Sha256::digest(bytecode.as_bytes()));
-                let bytecode_len = bytecode.len() as u32;
+/// Script properties that make Studio load a script's source from somewhere
+/// else, a package or asset for `LinkedSource` and a Team Create draft for
+/// `ScriptGuid`, replacing the decompilation once the place is opened
+pub const CLOUD_SOURCE_PROPERTIES: &[&str] = &["LinkedSource", "ScriptGuid"];
 
-                let bytecode: Arc<str> = Arc::from(bytecode);
-                let bytecode_hash_for_writer = bytecode_hash.clone();
+/// Empties the `CLOUD_SOURCE_PROPERTIES` of every script as the place goes by.
+/// A `LinkedSource` url becomes `<null></null>`, a `ScriptGuid` an empty string.
+#[derive(Default)]
+struct CloudSourceClearer {
+    /// How far into the property being emptied the reader is, 0 outside of one
+    depth: u32,
+    cleared: u32,
+}
 
-                let request = DecompilationRequest {
-                    bytecode: bytecode.clone(),
-                    bytecode_hash,
-                    bytecode_len,
-                    tx: dec_tx,
-                };
+impl CloudSourceClearer {
+    /// `e` as it should be written, `None` when it's left out
+    fn rewrite(&mut self, e: XmlEvent) -> Option<XmlEvent> {
+        if self.depth == 0 {
+            if let XmlEvent::StartElement { attributes, .. } = &e {
+                let cloud_source = attributes.iter().any(|attr| {
+                    attr.name.local_name == "name" && CLOUD_SOURCE_PROPERTIES.contains(&attr.value.as_str())
+                });
+                if cloud_source {
+                    self.depth = 1;
+                    self.cleared += 1;
+                }
+            }
+            return Some(e);
+        }
 
-                decompiler.decompile_batch(vec![request]).await.unwrap();
-                write_tx
-                    .send(ToWrite::DecompilationResult {
-                        header,
-                        bytecode,
-                        bytecode_hash: bytecode_hash_for_writer,
-                        rx: dec_rx,
-                    })
-                    .unwrap();
+        match e {
+            XmlEvent::StartElement { .. } => {
+                self.depth += 1;
+                (self.depth == 2).then(|| XmlEvent::StartElement {
+                    name: OwnedName::local("null"),
+                    attributes: Vec::new(),
+                    namespace: Namespace::empty(),
+                })
             }
-            Ok(e) => {
-                total_events.fetch_add(1, Ordering::Relaxed);
-                write_tx.send(ToWrite::XmlEvent(e)).unwrap();
+            XmlEvent::EndElement { .. } => {
+                self.depth -= 1;
+                match self.depth {
+                    0 => Some(e),
+                    1 => Some(XmlEvent::EndElement { name: OwnedName::local("null") }),
+                    _ => None,
+                }
             }
-            Err(e) => {
-                eprintln!("xml parsing error at event #{}: {e}", event_count);
-                return Err(e.into());
+            _ => None,
+        }
+    }
+}
+
+/// Scans the place the same way `process_rbxlx_file` would, without sending
+/// anything to the oracle, and reports what a real run would cost
+pub fn dry_run_rbxlx_file(
+    input_file: &str,
+    options: &RbxlxOptions,
+    connections: usize,
+    max_bytes_in_flight: u32,
+) -> Result<()> {
+    let input_file_handle = File::open(input_file)?;
+    let file = BufReader::with_capacity(8 * 1024 * 1024, input_file_handle);
+    let utf8_reader = Utf8BoundaryReader::new(file, Arc::new(AtomicU64::new(0)));
+    let parser = EventReader::new(utf8_reader);
+
+    let mut tracker = InstanceTracker::default();
+    let mut seen_hashes = HashSet::new();
+    let mut scripts = 0u32;
+    let mut filtered_scripts = 0u32;
+    let mut duplicates = 0u32;
+    let mut too_large = 0u32;
+    let mut total_bytes = 0u64;
+    let mut unique_bytes = 0u64;
+
+    for e in parser {
+        let e = e?;
+        if let XmlEvent::CData(cdata_string) = &e {
+            if !tracker.in_script_source() {
+                continue;
+            }
+            let Some((start, end)) = find_bytecode(cdata_string) else {
+                continue;
+            };
+
+            if !options.filter.allows(&tracker.path(), tracker.class_name()) {
+                filtered_scripts += 1;
+                continue;
+            }
+
+            let bytecode = &cdata_string[start..end];
+            scripts += 1;
+            total_bytes += bytecode.len() as u64;
+
+            if bytecode.len() as u64 > max_bytes_in_flight as u64 {
+                too_large += 1;
+            }
+
+            if seen_hashes.insert(Sha256::digest(bytecode.as_bytes())) {
+                unique_bytes += bytecode.len() as u64;
+            } else {
+                duplicates += 1;
             }
         }
+        tracker.observe(&e);
     }
 
-    // and now we wait for the decompiler
-    // to do its thing
-    reader_done.store(true, Ordering::Relaxed);
-    progress_handle.await?;
-    // and now the decompiler has done its thing
-    drop(write_tx);
-    writer_handle.await?;
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    let windows = (unique_bytes as f64 / max_bytes_in_flight as f64).ceil();
+    let estimated_seconds = windows * ESTIMATED_SECONDS_PER_WINDOW / connections.max(1) as f64;
 
-    if total_scripts.load(Ordering::Relaxed) == 0 {
-        println!("no scripts found to decompile");
+    println!("dry run of {}, nothing was sent to the oracle", input_file);
+    println!("scripts: {} to decompile, {} skipped by --root/--include/--exclude/--only-class", scripts, filtered_scripts);
+    println!(
+        "bytecode: {:.2} MiB total, {:.2} MiB unique",
+        mib(total_bytes),
+        mib(unique_bytes)
+    );
+    println!(
+        "duplicates: {} scripts share bytecode with an earlier one",
+        duplicates
+    );
+    if too_large > 0 {
+        println!(
+            "too large: {} scripts exceed the {:.2} MiB in-flight limit and would fail",
+            too_large,
+            mib(max_bytes_in_flight as u64)
+        );
     }
+    println!(
+        "estimated time: ~{:.0}s ({} windows of {:.2} MiB over {} connection(s))",
+        estimated_seconds,
+        windows,
+        mib(max_bytes_in_flight as u64),
+        connections.max(1)
+    );
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A place that never ends, there's always another folder after the last one
+    struct EndlessPlace {
+        next: Vec<u8>,
+        offset: usize,
+    }
+
+    impl EndlessPlace {
+        fn new() -> Self {
+            Self {
+                next: b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<roblox version=\"4\">\n".to_vec(),
+                offset: 0,
+            }
+        }
+    }
+
+    impl Read for EndlessPlace {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.offset == self.next.len() {
+                self.next = format!(
+                    "<Item class=\"Folder\"><Properties><string name=\"Name\">{}</string></Properties></Item>\n",
+                    "x".repeat(64 * 1024)
+                )
+                .into_bytes();
+                self.offset = 0;
+            }
+            let n = buf.len().min(self.next.len() - self.offset);
+            buf[..n].copy_from_slice(&self.next[self.offset..self.offset + n]);
+            self.offset += n;
+            Ok(n)
+        }
+    }
+
+    /// Output whose first write takes the writer task down with it
+    struct BrokenOutput;
+
+    impl Write for BrokenOutput {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            panic!("the output went away");
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn a_writer_that_dies_partway_stops_the_reader() {
+        // the place never ends, so this only returns if the reader stopped too
+        let processed = tokio::time::timeout(
+            std::time::Duration::from_secs(60),
+            process_rbxlx(&Decompiler::offline(), EndlessPlace::new(), 0, BrokenOutput, &RbxlxOptions::default()),
+        )
+        .await
+        .expect("process_rbxlx kept going after the writer died");
+        assert!(processed.is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::decompiler::{FailureCategory, Latencies};
+use crate::error::Result;
+
+/// Where a script's result came from, when it wasn't asked of the oracle for that script
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reused {
+    /// An earlier script in the same place with the same bytecode
+    Duplicate,
+    /// A script with the same bytecode in another place processed alongside
+    OtherPlace,
+    /// The journal of an earlier run, see `--resume`
+    Journal,
+}
+
+/// How one script in a place turned out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptReport {
+    pub path: String,
+    pub class_name: String,
+    pub hash: String,
+    /// Size of the script's bytecode, in bytes
+    #[serde(default)]
+    pub bytecode_size: usize,
+    /// Size of the decompiled source, in bytes, 0 when it failed
+    #[serde(default)]
+    pub output_size: usize,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What kind of failure `error` is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_category: Option<FailureCategory>,
+    /// Best guess at what the script was obfuscated with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscator: Option<String>,
+    /// The place the script is in, when several were processed at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub place: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reused: Option<Reused>,
+}
+
+/// Scripts that all have the same bytecode, e.g. a loader copied around a place
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub class_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obfuscator: Option<String>,
+    /// Every instance with this bytecode, in the order they appear in the
+    /// place, as `<place>:<path>` when several places were processed at once
+    pub paths: Vec<String>,
+}
+
+/// What `--duplicates-report` writes
+#[derive(Debug, Default, Serialize)]
+pub struct DuplicatesReport {
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DuplicatesReport {
+    /// Groups the scripts in `report` by bytecode, leaving out bytecode only
+    /// one script has. The most copied bytecode comes first.
+    pub fn new(report: &Report) -> Self {
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        let mut by_hash: HashMap<&str, usize> = HashMap::new();
+        for script in &report.scripts {
+            let path = match &script.place {
+                Some(place) => format!("{}:{}", place, script.path),
+                None => script.path.clone(),
+            };
+            match by_hash.get(script.hash.as_str()) {
+                Some(&index) => groups[index].paths.push(path),
+                None => {
+                    by_hash.insert(&script.hash, groups.len());
+                    groups.push(DuplicateGroup {
+                        hash: script.hash.clone(),
+                        class_name: script.class_name.clone(),
+                        obfuscator: script.obfuscator.clone(),
+                        paths: vec![path],
+                    });
+                }
+            }
+        }
+        groups.retain(|group| group.paths.len() > 1);
+        // stable, so groups of the same size stay in the order they were first seen
+        groups.sort_by_key(|group| std::cmp::Reverse(group.paths.len()));
+        Self { groups }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("failed to serialize the duplicates report: {}", e))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+/// Filled in by `process_rbxlx` as scripts are written
+pub type ReportSink = Arc<Mutex<Vec<ScriptReport>>>;
+
+/// What `--report` writes, and `--retry-failures` reads back
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Report {
+    pub scripts: Vec<ScriptReport>,
+}
+
+impl Report {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("{} is not a report: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("failed to serialize the report: {}", e))?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn from_sink(sink: &ReportSink) -> Self {
+        Self {
+            scripts: sink.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn failed_hashes(&self) -> HashSet<String> {
+        self.scripts
+            .iter()
+            .filter(|script| !script.success)
+            .map(|script| script.hash.clone())
+            .collect()
+    }
+
+    /// Takes over the outcome of every script `retried` covers
+    pub fn update(&mut self, retried: &Report) {
+        let retried: HashMap<(&str, &str), &ScriptReport> = retried
+            .scripts
+            .iter()
+            .map(|script| ((script.path.as_str(), script.hash.as_str()), script))
+            .collect();
+        for script in &mut self.scripts {
+            if let Some(retry) = retried.get(&(script.path.as_str(), script.hash.as_str())) {
+                script.success = retry.success;
+                script.error = retry.error.clone();
+                script.error_category = retry.error_category;
+            }
+        }
+    }
+}
+
+/// What a run got through, logged as a block at the end of it
+pub struct Summary {
+    pub found: usize,
+    pub decompiled: usize,
+    pub failed: usize,
+    /// Taken from the journal or another place instead of asked for again
+    pub cached: usize,
+    /// Copies of bytecode that was already in the place
+    pub deduplicated: usize,
+    pub bytecode_bytes: usize,
+    pub output_bytes: usize,
+    pub average_latency: Option<Duration>,
+    /// The scripts the oracle took longest on, slowest first
+    pub slowest: Vec<(String, Duration)>,
+}
+
+impl Summary {
+    pub fn new(report: &Report, latencies: Latencies) -> Self {
+        let reused = |kind: &[Reused]| {
+            report
+                .scripts
+                .iter()
+                .filter(|script| script.success && script.reused.is_some_and(|reused| kind.contains(&reused)))
+                .count()
+        };
+        // the first script with each hash stands in for it
+        let mut paths: HashMap<&str, String> = HashMap::new();
+        for script in &report.scripts {
+            paths.entry(script.hash.as_str()).or_insert_with(|| match &script.place {
+                Some(place) => format!("{}:game.{}", place, script.path),
+                None => format!("game.{}", script.path),
+            });
+        }
+        let slowest = latencies
+            .slowest
+            .into_iter()
+            .map(|(hash, latency)| (paths.get(hash.as_str()).cloned().unwrap_or(hash), latency))
+            .collect();
+        Self {
+            found: report.scripts.len(),
+            decompiled: report
+                .scripts
+                .iter()
+                .filter(|script| script.success && script.reused.is_none())
+                .count(),
+            failed: report.scripts.iter().filter(|script| !script.success).count(),
+            cached: reused(&[Reused::OtherPlace, Reused::Journal]),
+            deduplicated: reused(&[Reused::Duplicate]),
+            bytecode_bytes: report.scripts.iter().map(|script| script.bytecode_size).sum(),
+            output_bytes: report.scripts.iter().map(|script| script.output_size).sum(),
+            average_latency: latencies.average,
+            slowest,
+        }
+    }
+
+    pub fn log(&self) {
+        let kib = |bytes: usize| bytes as f64 / 1024.0;
+        info!("summary:");
+        info!("  scripts found:   {}", self.found);
+        info!("  decompiled:      {}", self.decompiled);
+        info!("  failed:          {}", self.failed);
+        info!("  cached:          {}", self.cached);
+        info!("  deduplicated:    {}", self.deduplicated);
+        info!("  bytecode:        {:.1} KiB", kib(self.bytecode_bytes));
+        info!("  output:          {:.1} KiB", kib(self.output_bytes));
+        if let Some(average) = self.average_latency {
+            info!("  average latency: {:.2?}", average);
+        }
+        if !self.slowest.is_empty() {
+            info!("  slowest scripts:");
+            for (script, latency) in &self.slowest {
+                info!("    {:>8.2?}  {}", latency, script);
+            }
+        }
+    }
+}
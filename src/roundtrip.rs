@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use base64::{engine::general_purpose, Engine as _};
+use mlua::Compiler;
+use serde::Serialize;
+use tracing::info;
+
+use crate::disasm::{fingerprint, Fingerprint};
+use crate::error::Result;
+use crate::extract::{for_each_script, FoundScript};
+
+/// How many of the least faithful scripts are listed at the end
+const WORST_LISTED: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundtripStatus {
+    /// Recompiled and compared to the original
+    Compared,
+    /// The place has no decompilation for it
+    NotDecompiled,
+    /// The oracle couldn't decompile it
+    Failed,
+    /// The decompilation doesn't compile
+    CompileError,
+    /// The original bytecode couldn't be read
+    Unreadable,
+}
+
+/// One script's entry in the `roundtrip-check` report
+#[derive(Debug, Serialize)]
+pub struct RoundtripScript {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+    pub status: RoundtripStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_protos: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recompiled_protos: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_constants: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recompiled_constants: Option<usize>,
+    /// Constants the two have in common, counting repeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matching_constants: Option<usize>,
+    /// 1 when the recompiled script has the same protos and constants as
+    /// the original, down to 0 the less they have in common
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fidelity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How many of `recompiled`'s constants `original` has too, counting repeats
+fn matching_constants(original: &Fingerprint, recompiled: &Fingerprint) -> usize {
+    let mut left: HashMap<&str, usize> = HashMap::new();
+    for constant in &original.constants {
+        *left.entry(constant.as_str()).or_default() += 1;
+    }
+    let mut matching = 0;
+    for constant in &recompiled.constants {
+        if let Some(count) = left.get_mut(constant.as_str()).filter(|count| **count > 0) {
+            *count -= 1;
+            matching += 1;
+        }
+    }
+    matching
+}
+
+/// The average of how close the proto counts are and how many constants
+/// are shared out of all the distinct ones
+fn fidelity(original: &Fingerprint, recompiled: &Fingerprint, matching: usize) -> f64 {
+    let protos = match original.protos.max(recompiled.protos) {
+        0 => 1.0,
+        most => original.protos.min(recompiled.protos) as f64 / most as f64,
+    };
+    let union = original.constants.len() + recompiled.constants.len() - matching;
+    let constants = if union == 0 { 1.0 } else { matching as f64 / union as f64 };
+    (protos + constants) / 2.0
+}
+
+/// Compiles `source` with the bundled Luau compiler, at the optimization
+/// level Roblox uses for scripts
+fn compile(source: &str) -> std::result::Result<Vec<u8>, String> {
+    let bytecode = Compiler::new()
+        .set_optimization_level(1)
+        .set_debug_level(1)
+        .compile(source)
+        .map_err(|e| e.to_string())?;
+    // the compiler hands back errors as bytecode of version 0
+    match bytecode.split_first() {
+        Some((0, message)) => Err(String::from_utf8_lossy(message).into_owned()),
+        _ => Ok(bytecode),
+    }
+}
+
+fn check_script(script: &FoundScript) -> RoundtripScript {
+    let mut entry = RoundtripScript {
+        path: script.path.to_string(),
+        class_name: script.class_name.map(str::to_string),
+        status: RoundtripStatus::Compared,
+        original_protos: None,
+        recompiled_protos: None,
+        original_constants: None,
+        recompiled_constants: None,
+        matching_constants: None,
+        fidelity: None,
+        error: None,
+    };
+    let Some(source) = script.decompilation() else {
+        entry.status = if script.rest.contains("-- decompilation failed:") {
+            RoundtripStatus::Failed
+        } else {
+            RoundtripStatus::NotDecompiled
+        };
+        return entry;
+    };
+
+    let original = general_purpose::STANDARD
+        .decode(script.bytecode)
+        .map_err(|e| format!("invalid base64: {}", e))
+        .and_then(|raw| fingerprint(&raw));
+    let original = match original {
+        Ok(original) => original,
+        Err(e) => {
+            entry.status = RoundtripStatus::Unreadable;
+            entry.error = Some(e);
+            return entry;
+        }
+    };
+    entry.original_protos = Some(original.protos);
+    entry.original_constants = Some(original.constants.len());
+
+    let recompiled = compile(source).and_then(|bytecode| fingerprint(&bytecode));
+    let recompiled = match recompiled {
+        Ok(recompiled) => recompiled,
+        Err(e) => {
+            entry.status = RoundtripStatus::CompileError;
+            entry.error = Some(e);
+            entry.fidelity = Some(0.0);
+            return entry;
+        }
+    };
+    let matching = matching_constants(&original, &recompiled);
+    entry.recompiled_protos = Some(recompiled.protos);
+    entry.recompiled_constants = Some(recompiled.constants.len());
+    entry.matching_constants = Some(matching);
+    entry.fidelity = Some(fidelity(&original, &recompiled, matching));
+    entry
+}
+
+/// Recompiles every decompilation in a processed place and compares its
+/// protos and constants with the bytecode it came from, logging a summary
+/// and writing the score of each script to `report` as JSON
+pub fn roundtrip_check(input: &str, report: Option<&Path>) -> Result<()> {
+    let mut scripts = Vec::new();
+    for_each_script(input, |script: &FoundScript| {
+        scripts.push(check_script(script));
+        Ok(())
+    })?;
+
+    let count = |status: RoundtripStatus| scripts.iter().filter(|script| script.status == status).count();
+    let scores: Vec<f64> = scripts.iter().filter_map(|script| script.fidelity).collect();
+    info!("checked {} scripts:", scripts.len());
+    info!("  compared:        {}", count(RoundtripStatus::Compared));
+    info!("  don't compile:   {}", count(RoundtripStatus::CompileError));
+    info!("  failed:          {}", count(RoundtripStatus::Failed));
+    info!("  not decompiled:  {}", count(RoundtripStatus::NotDecompiled));
+    info!("  unreadable:      {}", count(RoundtripStatus::Unreadable));
+    if !scores.is_empty() {
+        info!("  average fidelity: {:.2}", scores.iter().sum::<f64>() / scores.len() as f64);
+    }
+
+    let mut worst: Vec<&RoundtripScript> = scripts.iter().filter(|script| script.fidelity.is_some_and(|it| it < 1.0)).collect();
+    worst.sort_by(|a, b| a.fidelity.partial_cmp(&b.fidelity).unwrap_or(std::cmp::Ordering::Equal));
+    if !worst.is_empty() {
+        info!("least faithful:");
+        for script in worst.iter().take(WORST_LISTED) {
+            info!("  {:.2}  game.{}", script.fidelity.unwrap_or_default(), script.path);
+        }
+    }
+
+    if let Some(report) = report {
+        let json = serde_json::to_string_pretty(&scripts)
+            .map_err(|e| format!("failed to serialize the report: {}", e))?;
+        std::fs::write(report, json)?;
+        info!("wrote the scores to {}", report.display());
+    }
+    Ok(())
+}
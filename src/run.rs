@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, info, warn};
+
+use crate::error::Result;
+
+/// Folder under the system's temp folder that runs go in, unless `--temp-dir`
+/// says otherwise. Each user gets their own, see `default_base`.
+const DEFAULT_BASE_DIR: &str = "oracle-postprocess";
+
+/// `oracle-postprocess-<user>` in the system's temp folder. On a shared
+/// machine the temp folder is everyone's, and the first user to make a
+/// folder everyone uses would own it.
+fn default_base() -> PathBuf {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty() && user.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)));
+    let name = match user {
+        Some(user) => format!("{}-{}", DEFAULT_BASE_DIR, user),
+        None => DEFAULT_BASE_DIR.to_string(),
+    };
+    std::env::temp_dir().join(name)
+}
+
+/// Makes `dir` readable by this user only, since what runs keep in it is
+/// decompiled sources and bytecode. With `recursive`, its parents are made
+/// too and it's fine if it's there already.
+#[cfg(unix)]
+fn create_private_dir(dir: &Path, recursive: bool) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new().recursive(recursive).mode(0o700).create(dir)
+}
+
+/// The temp folder is the user's own on windows already
+#[cfg(windows)]
+fn create_private_dir(dir: &Path, recursive: bool) -> std::io::Result<()> {
+    std::fs::DirBuilder::new().recursive(recursive).create(dir)
+}
+
+/// The folder one run keeps what it writes along the way in: temp files,
+/// partial outputs, journals and the bytecode of scripts that failed.
+/// It's removed once the run succeeded, and kept when it didn't (or with
+/// `--keep-temp`), since the journal and failures are what's worth a look then.
+///
+/// The default is the current directory, kept, where everything used to go.
+#[derive(Debug, Clone)]
+pub struct RunContext {
+    dir: PathBuf,
+    keep: bool,
+}
+
+impl Default for RunContext {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::new(),
+            keep: true,
+        }
+    }
+}
+
+impl RunContext {
+    /// Makes a folder for this run in `base`, or the system's temp folder
+    pub fn create(base: Option<&Path>, keep: bool) -> Result<Self> {
+        let base = base.map_or_else(default_base, Path::to_path_buf);
+        create_private_dir(&base, true)?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let dir = base.join(format!("run-{}-{}", started, std::process::id()));
+        // made fresh, so it's this user's even if `base` somehow isn't
+        create_private_dir(&dir, false)?;
+        debug!("keeping this run's files in {}", dir.display());
+        Ok(Self { dir, keep })
+    }
+
+    /// A temp file for the place called `label` (one per place when there are several)
+    pub fn temp_file(&self, label: Option<&str>, suffix: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", label.unwrap_or("place"), suffix))
+    }
+
+    /// Where the journal of the place called `label` goes unless `--journal` says otherwise
+    pub fn journal(&self, label: Option<&str>) -> PathBuf {
+        self.temp_file(label, "journal")
+    }
+
+    /// Where results too big to hold in memory wait until they're written
+    pub fn spool_dir(&self) -> PathBuf {
+        self.dir.join("spool")
+    }
+
+    /// Where bytecode the oracle couldn't decompile is kept
+    pub fn failures_dir(&self) -> PathBuf {
+        self.dir.join("failures")
+    }
+
+    /// Removes the run's folder if the run went well and it isn't being kept,
+    /// otherwise says where it is
+    pub fn finish(&self, succeeded: bool) {
+        if self.dir.as_os_str().is_empty() {
+            return;
+        }
+        let empty = std::fs::read_dir(&self.dir).map_or(true, |mut entries| entries.next().is_none());
+        if !self.keep && (succeeded || empty) {
+            if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+                warn!("failed to remove {}: {}", self.dir.display(), e);
+            }
+        } else if !empty {
+            info!("this run's temp files, journals and failed bytecode are in {}", self.dir.display());
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::future::Future;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+use crate::compiled::{load_bytecode, LoadedBytecode};
+use crate::decompiler::Decompiler;
+use crate::disasm::disassembly_comment;
+use crate::error::Result;
+use crate::folder::RenderOptions;
+use crate::integrity::with_integrity_line;
+use crate::luau;
+use crate::profile::OptionProfiles;
+use crate::rbxlx::{process_rbxlx, RbxlxOptions};
+
+pub const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:8080";
+pub const DEFAULT_CACHE_ENTRIES: usize = 10_000;
+
+/// Request bodies past this are turned away, the same limit the websocket has
+const MAX_BODY_BYTES: usize = 512 * 1024 * 1024;
+
+#[derive(Clone, Default)]
+pub struct ServeOptions {
+    pub render: RenderOptions,
+    pub profiles: OptionProfiles,
+    /// How many successful decompilations to keep around, 0 turns the cache off
+    pub cache_entries: usize,
+}
+
+/// Successful results by bytecode hash. The oldest one goes once it's full.
+#[derive(Default)]
+struct ResultCache {
+    results: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl ResultCache {
+    fn get(&self, hash: &str) -> Option<String> {
+        self.results.get(hash).cloned()
+    }
+
+    fn insert(&mut self, hash: String, source: String, capacity: usize) {
+        if capacity == 0 || self.results.contains_key(&hash) {
+            return;
+        }
+        while self.order.len() >= capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.results.remove(&oldest);
+            }
+        }
+        self.order.push_back(hash.clone());
+        self.results.insert(hash, source);
+    }
+}
+
+struct Server {
+    decompiler: Decompiler,
+    options: ServeOptions,
+    cache: Mutex<ResultCache>,
+}
+
+fn text_response(status: StatusCode, content_type: &str, body: impl Into<Bytes>) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .body(Full::new(body.into()))
+        .unwrap()
+}
+
+fn plain(status: StatusCode, body: impl Into<Bytes>) -> Response<Full<Bytes>> {
+    text_response(status, "text/plain; charset=utf-8", body)
+}
+
+fn metrics(decompiler: &Decompiler) -> Response<Full<Bytes>> {
+    text_response(StatusCode::OK, "text/plain; version=0.0.4; charset=utf-8", decompiler.metrics())
+}
+
+impl Server {
+    async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let route = (request.method().clone(), request.uri().path().to_string());
+        let body = match Limited::new(request.into_body(), MAX_BODY_BYTES).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => return plain(StatusCode::PAYLOAD_TOO_LARGE, format!("couldn't read the body: {}", e)),
+        };
+
+        match (&route.0, route.1.as_str()) {
+            (&Method::POST, "/decompile") => self.decompile(body).await,
+            (&Method::POST, "/rbxlx") => self.rbxlx(body).await,
+            (&Method::GET, "/metrics") => metrics(&self.decompiler),
+            (_, "/metrics") => plain(StatusCode::METHOD_NOT_ALLOWED, "use GET"),
+            (_, "/decompile" | "/rbxlx") => plain(StatusCode::METHOD_NOT_ALLOWED, "use POST"),
+            _ => plain(StatusCode::NOT_FOUND, "not found"),
+        }
+    }
+
+    /// The body is a bytecode file (raw or base64), the response the decompiled source
+    async fn decompile(&self, body: Bytes) -> Response<Full<Bytes>> {
+        let Ok(LoadedBytecode { bytecode, hash, .. }) = load_bytecode(body).await else {
+            return plain(StatusCode::BAD_REQUEST, "the body is not luau bytecode");
+        };
+
+        if let Some(source) = self.cache.lock().unwrap().get(&hash) {
+            return plain(StatusCode::OK, source);
+        }
+
+        let result = match self.decompiler.try_decompile_with_hash(bytecode.clone(), hash.clone()) {
+            Ok(pending) => pending.await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(Ok(source)) => {
+                let source = if self.options.render.format_lua {
+                    luau::format_or_keep(source, &hash)
+                } else {
+                    source
+                };
+                let source = if self.options.render.integrity {
+                    with_integrity_line(source)
+                } else {
+                    source
+                };
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(hash, source.clone(), self.options.cache_entries);
+                plain(StatusCode::OK, source)
+            }
+            Ok(Err(e)) => {
+                let disassembly = if self.options.render.disasm {
+                    disassembly_comment(&bytecode)
+                } else {
+                    String::new()
+                };
+                plain(StatusCode::UNPROCESSABLE_ENTITY, format!("{}{}", e, disassembly))
+            }
+            Err(e) => plain(StatusCode::BAD_GATEWAY, e.to_string()),
+        }
+    }
+
+    /// The body is a `.rbxlx` place, the response the same place with every script decompiled
+    async fn rbxlx(&self, body: Bytes) -> Response<Full<Bytes>> {
+        let options = RbxlxOptions {
+            format_lua: self.options.render.format_lua,
+            disasm: self.options.render.disasm,
+            integrity: self.options.render.integrity,
+            size_comment: self.options.render.size_comment,
+            debug_names: self.options.render.debug_names,
+            profiles: self.options.profiles.clone(),
+            ..RbxlxOptions::default()
+        };
+        let size = body.len() as u64;
+        match process_rbxlx(&self.decompiler, Cursor::new(body), size, Vec::new(), &options).await {
+            Ok(place) => text_response(StatusCode::OK, "application/xml", place),
+            Err(e) => plain(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+        }
+    }
+}
+
+async fn bind(listen: &str) -> Result<TcpListener> {
+    let listener = TcpListener::bind(listen).await?;
+    let address = listener.local_addr()?;
+    if !address.ip().is_loopback() {
+        warn!("listening on {}, which other machines can reach", address);
+    }
+    info!("listening on http://{}", address);
+    Ok(listener)
+}
+
+/// Answers every connection made to `listener` with `handle`, until accepting one fails
+async fn accept<F, R>(listener: TcpListener, handle: F) -> Result<()>
+where
+    F: Fn(Request<Incoming>) -> R + Clone + Send + Sync + 'static,
+    R: Future<Output = Response<Full<Bytes>>> + Send + 'static,
+{
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| {
+                let response = handle(request);
+                async move { Ok::<_, Infallible>(response.await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Serves the decompiler over http on `listen` until the process is stopped.
+/// There's no authentication, anyone who can reach the address gets to use the key.
+pub async fn serve(decompiler: &Decompiler, listen: &str, options: ServeOptions) -> Result<()> {
+    let listener = bind(listen).await?;
+    let server = Arc::new(Server {
+        decompiler: decompiler.clone(),
+        options,
+        cache: Mutex::new(ResultCache::default()),
+    });
+
+    accept(listener, move |request| {
+        let server = server.clone();
+        async move { server.handle(request).await }
+    })
+    .await
+}
+
+/// Serves only `GET /metrics` on `listen`, for the daemon, whose socket
+/// a Prometheus scraper can't talk to
+pub async fn serve_metrics(decompiler: &Decompiler, listen: &str) -> Result<()> {
+    let listener = bind(listen).await?;
+    let decompiler = decompiler.clone();
+    accept(listener, move |request: Request<Incoming>| {
+        let response = match (request.method(), request.uri().path()) {
+            (&Method::GET, "/metrics") => metrics(&decompiler),
+            _ => plain(StatusCode::NOT_FOUND, "not found"),
+        };
+        std::future::ready(response)
+    })
+    .await
+}
@@ -0,0 +1,26 @@
+/// Size of what `encoded` decodes to, without decoding it
+fn decoded_len(encoded: &str) -> usize {
+    let encoded = encoded.trim_end();
+    let padding = encoded.bytes().rev().take_while(|byte| *byte == b'=').count();
+    (encoded.len() / 4 * 3).saturating_sub(padding)
+}
+
+/// Puts `-- sizes: 12.3 KiB bytecode, 40.1 KiB decompiled in 1234 lines (3.3x)`
+/// in front of a decompiled script, `bytecode` being the base64 it came from.
+/// A decompilation much smaller than its bytecode usually lost something.
+pub fn with_size_line(source: String, bytecode: &str) -> String {
+    let bytecode_len = decoded_len(bytecode);
+    let ratio = if bytecode_len > 0 {
+        format!(" ({:.1}x)", source.len() as f64 / bytecode_len as f64)
+    } else {
+        String::new()
+    };
+    format!(
+        "-- sizes: {:.1} KiB bytecode, {:.1} KiB decompiled in {} lines{}\n{}",
+        bytecode_len as f64 / 1024.0,
+        source.len() as f64 / 1024.0,
+        source.lines().count(),
+        ratio,
+        source
+    )
+}
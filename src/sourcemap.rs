@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::instance::InstancePath;
+
+/// Written next to the decompiled scripts, where luau-lsp looks for it
+pub const SOURCEMAP_FILE_NAME: &str = "sourcemap.json";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    name: String,
+    class_name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    file_paths: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(name: &str, class_name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            class_name: class_name.to_string(),
+            file_paths: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+/// A Rojo-style sourcemap of the scripts written to `root`, so editors can
+/// tell which file is which instance
+pub struct Sourcemap {
+    root: PathBuf,
+    game: Node,
+}
+
+impl Sourcemap {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            game: Node::new("Game", "DataModel"),
+        }
+    }
+
+    /// Records that the script at `path` was written to `file`.
+    /// `classes` has the class of every instance along `path`; ancestors
+    /// it doesn't cover are guessed (services are named after their class,
+    /// anything deeper is taken to be a Folder).
+    pub fn add(&mut self, path: &InstancePath, classes: &[String], file: &Path) {
+        let segments = path.segments();
+        if segments.is_empty() {
+            return;
+        }
+        let offset = segments.len().saturating_sub(classes.len());
+        let class_at = |depth: usize| match depth.checked_sub(offset).and_then(|i| classes.get(i)) {
+            Some(class_name) => class_name.as_str(),
+            None if depth == 0 => segments[0].as_str(),
+            None => "Folder",
+        };
+
+        let mut node = &mut self.game;
+        for (depth, name) in segments[..segments.len() - 1].iter().enumerate() {
+            let index = match node.children.iter().position(|child| &child.name == name) {
+                Some(index) => index,
+                None => {
+                    node.children.push(Node::new(name, class_at(depth)));
+                    node.children.len() - 1
+                }
+            };
+            node = &mut node.children[index];
+        }
+
+        let depth = segments.len() - 1;
+        let name = &segments[depth];
+        let file_path = file
+            .strip_prefix(&self.root)
+            .unwrap_or(file)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        // an ancestor of an earlier script may turn out to be a script itself
+        let placeholder = node
+            .children
+            .iter()
+            .position(|child| &child.name == name && child.file_paths.is_empty());
+        let leaf = match placeholder {
+            Some(index) => &mut node.children[index],
+            None => {
+                node.children.push(Node::new(name, class_at(depth)));
+                node.children.last_mut().unwrap()
+            }
+        };
+        leaf.class_name = class_at(depth).to_string();
+        leaf.file_paths.push(file_path);
+    }
+
+    /// Writes `sourcemap.json` into the root folder
+    pub fn write(&self) -> Result<()> {
+        let json = serde_json::to_string(&self.game)
+            .map_err(|e| format!("failed to serialize the sourcemap: {}", e))?;
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(self.root.join(SOURCEMAP_FILE_NAME), json)?;
+        Ok(())
+    }
+}
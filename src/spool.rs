@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+use crate::decompiler::{DecompileError, DecompileResult};
+
+/// Results over this many MiB are spilled to disk unless `--spill-threshold` says otherwise
+pub const DEFAULT_SPILL_THRESHOLD_MIB: f64 = 8.0;
+
+/// A result on its way to the writer, in memory or spilled to disk
+pub type SpooledResult = std::result::Result<Spooled, DecompileError>;
+
+/// Where results too big to keep around in memory wait for the writer
+pub struct Spool {
+    dir: PathBuf,
+    /// In bytes, results up to this stay in memory
+    threshold: usize,
+    spilled: AtomicU64,
+}
+
+impl Spool {
+    pub fn new(dir: PathBuf, threshold: usize) -> Self {
+        Self {
+            dir,
+            threshold,
+            spilled: AtomicU64::new(0),
+        }
+    }
+
+    /// Writes `source` to a file if it's over the threshold. It stays in
+    /// memory when it isn't, or when writing it fails.
+    async fn keep(&self, source: String) -> Spooled {
+        if source.len() <= self.threshold {
+            return Spooled::Memory(source);
+        }
+        let n = self.spilled.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}.lua", n));
+        let written = match tokio::fs::create_dir_all(&self.dir).await {
+            Ok(()) => tokio::fs::write(&path, source.as_bytes()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!("failed to spill a {} KiB result to {}, keeping it in memory: {}", source.len() / 1024, path.display(), e);
+            return Spooled::Memory(source);
+        }
+        debug!("spilled a {} KiB result to {}", source.len() / 1024, path.display());
+        Spooled::File(Arc::new(SpillFile { path }))
+    }
+}
+
+/// A spilled result's file, removed once nothing holds on to it anymore
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Spooled {
+    Memory(String),
+    File(Arc<SpillFile>),
+}
+
+impl Spooled {
+    /// The decompiled source, read back from disk if it was spilled
+    pub fn load(&self) -> DecompileResult {
+        match self {
+            Spooled::Memory(source) => Ok(source.clone()),
+            Spooled::File(file) => std::fs::read_to_string(&file.path).map_err(|e| {
+                format!("failed to read back the result spilled to {}: {}", file.path.display(), e).into()
+            }),
+        }
+    }
+}
+
+/// Passes what arrives on `rx` on once it's been through `spool`, so a big
+/// result goes to disk as soon as it's back instead of waiting in memory
+/// for the writer to get to it. Without a spool everything stays in memory.
+pub fn spooled(spool: Option<Arc<Spool>>, rx: oneshot::Receiver<DecompileResult>) -> oneshot::Receiver<SpooledResult> {
+    let (tx, spooled_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        // a dropped sender is passed on as one
+        let Ok(result) = rx.await else {
+            return;
+        };
+        let result = match (result, &spool) {
+            (Ok(source), Some(spool)) => Ok(spool.keep(source).await),
+            (Ok(source), None) => Ok(Spooled::Memory(source)),
+            (Err(e), _) => Err(e),
+        };
+        let _ = tx.send(result);
+    });
+    spooled_rx
+}
@@ -0,0 +1,331 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use rusqlite::{types::Value, Connection, OpenFlags};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent as WriteXmlEvent};
+
+use crate::compiled::load_bytecode;
+use crate::decompiler::{DecompilationRequest, DecompileResult, Decompiler};
+use crate::directives::hoist_directives;
+use crate::error::{Error, Result};
+use crate::folder::{render_result, RenderOptions};
+use crate::instance::InstancePath;
+use crate::naming::ScriptFileNamer;
+use crate::profile::OptionProfiles;
+use crate::sourcemap::Sourcemap;
+
+/// Column names dumpers use, the first one a table has wins
+const NAME_COLUMNS: &[&str] = &["name", "script_name", "instance_name"];
+const PARENT_COLUMNS: &[&str] = &["parent", "parent_path", "path", "full_name", "fullname"];
+const BYTECODE_COLUMNS: &[&str] = &["bytecode", "code", "data", "source"];
+const CLASS_COLUMNS: &[&str] = &["class_name", "classname", "class"];
+
+/// Class given to scripts whose row doesn't say
+const DEFAULT_CLASS: &str = "ModuleScript";
+
+/// Where a dump database keeps its scripts
+struct ScriptTable {
+    name: String,
+    name_column: String,
+    parent_column: String,
+    bytecode_column: String,
+    class_column: Option<String>,
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn find_column(columns: &[String], candidates: &[&str]) -> Option<String> {
+    candidates
+        .iter()
+        .find_map(|candidate| columns.iter().find(|column| column.eq_ignore_ascii_case(candidate)))
+        .cloned()
+}
+
+/// Looks through the database for a table with name, parent and bytecode
+/// columns, or checks that `table` has them
+fn find_script_table(db: &Connection, table: Option<&str>) -> Result<ScriptTable> {
+    let tables: Vec<String> = match table {
+        Some(table) => vec![table.to_string()],
+        None => db
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?,
+    };
+
+    for table in tables {
+        let columns: Vec<String> = db
+            .prepare(&format!("PRAGMA table_info({})", quote(&table)))?
+            .query_map([], |row| row.get(1))?
+            .collect::<rusqlite::Result<_>>()?;
+        let (Some(name_column), Some(parent_column), Some(bytecode_column)) = (
+            find_column(&columns, NAME_COLUMNS),
+            find_column(&columns, PARENT_COLUMNS),
+            find_column(&columns, BYTECODE_COLUMNS),
+        ) else {
+            continue;
+        };
+        return Ok(ScriptTable {
+            class_column: find_column(&columns, CLASS_COLUMNS),
+            name: table,
+            name_column,
+            parent_column,
+            bytecode_column,
+        });
+    }
+
+    Err(Error::Config(match table {
+        Some(table) => format!("table {} has no name, parent and bytecode columns", table),
+        None => "no table in the database has name, parent and bytecode columns, pick one with --table".to_string(),
+    }))
+}
+
+/// One row of the script table
+struct Row {
+    path: InstancePath,
+    class_name: String,
+    /// Raw or base64, whichever the dumper stored
+    bytecode: Vec<u8>,
+}
+
+fn read_rows(database: &Path, table: Option<&str>) -> Result<Vec<Row>> {
+    let db = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let table = find_script_table(&db, table)?;
+    info!(
+        "reading scripts from table {} ({}, {}, {})",
+        table.name, table.name_column, table.parent_column, table.bytecode_column
+    );
+
+    let class_column = table.class_column.as_deref().map_or_else(|| "NULL".to_string(), quote);
+    let query = format!(
+        "SELECT {}, {}, {}, {} FROM {}",
+        quote(&table.name_column),
+        quote(&table.parent_column),
+        quote(&table.bytecode_column),
+        class_column,
+        quote(&table.name)
+    );
+    let mut statement = db.prepare(&query)?;
+    let rows = statement.query_map([], |row| {
+        let name: Option<String> = row.get(0)?;
+        let parent: Option<String> = row.get(1)?;
+        let bytecode = match row.get::<_, Value>(2)? {
+            Value::Blob(bytes) => bytes,
+            Value::Text(text) => text.into_bytes(),
+            _ => Vec::new(),
+        };
+        let class_name: Option<String> = row.get(3)?;
+
+        // the parent is a dotted path, like game.Workspace.Model
+        let mut segments: Vec<String> = parent
+            .iter()
+            .flat_map(|parent| parent.split('.'))
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_string)
+            .collect();
+        if segments.first().is_some_and(|first| first == "game") {
+            segments.remove(0);
+        }
+        segments.push(name.unwrap_or_else(|| "Script".to_string()));
+
+        Ok(Row {
+            path: InstancePath::new(segments),
+            class_name: class_name.unwrap_or_else(|| DEFAULT_CLASS.to_string()),
+            bytecode,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// An instance in the reconstructed place
+struct Node {
+    name: String,
+    class_name: String,
+    source: Option<String>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(name: &str, class_name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            class_name: class_name.to_string(),
+            source: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds a script at `path` below this node. Ancestors are made up as
+    /// needed: services are named after their class, anything deeper is a Folder.
+    fn insert(&mut self, path: &[String], depth: usize, class_name: &str, source: String) {
+        let [name, rest @ ..] = path else {
+            return;
+        };
+        if rest.is_empty() {
+            // an ancestor of an earlier script may turn out to be this script
+            match self.children.iter_mut().find(|child| &child.name == name && child.source.is_none()) {
+                Some(child) => {
+                    child.class_name = class_name.to_string();
+                    child.source = Some(source);
+                }
+                None => {
+                    let mut child = Node::new(name, class_name);
+                    child.source = Some(source);
+                    self.children.push(child);
+                }
+            }
+            return;
+        }
+
+        let index = match self.children.iter().position(|child| &child.name == name) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node::new(name, if depth == 0 { name } else { "Folder" }));
+                self.children.len() - 1
+            }
+        };
+        self.children[index].insert(rest, depth + 1, class_name, source);
+    }
+
+    fn write<W: Write>(&self, writer: &mut EventWriter<W>, referent: &mut u32) -> Result<()> {
+        let id = format!("RBX{}", *referent);
+        *referent += 1;
+        writer.write(
+            WriteXmlEvent::start_element("Item")
+                .attr("class", &self.class_name)
+                .attr("referent", &id),
+        )?;
+        writer.write(WriteXmlEvent::start_element("Properties"))?;
+        writer.write(WriteXmlEvent::start_element("string").attr("name", "Name"))?;
+        writer.write(WriteXmlEvent::characters(&self.name))?;
+        writer.write(WriteXmlEvent::end_element())?;
+        if let Some(source) = &self.source {
+            writer.write(WriteXmlEvent::start_element("ProtectedString").attr("name", "Source"))?;
+            writer.write(WriteXmlEvent::cdata(&source.replace("]]>", "]]]]><![CDATA[>")))?;
+            writer.write(WriteXmlEvent::end_element())?;
+        }
+        writer.write(WriteXmlEvent::end_element())?;
+        for child in &self.children {
+            child.write(writer, referent)?;
+        }
+        writer.write(WriteXmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Writes the scripts in `root` as a place with only them and their ancestors in it
+fn write_place(root: &Node, output: &Path) -> Result<()> {
+    let mut file = BufWriter::new(File::create(output)?);
+    {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .write_document_declaration(false)
+            .create_writer(&mut file);
+        writer.write(WriteXmlEvent::start_element("roblox").attr("version", "4"))?;
+        let mut referent = 0;
+        for child in &root.children {
+            child.write(&mut writer, &mut referent)?;
+        }
+        writer.write(WriteXmlEvent::end_element())?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+struct SqliteJob {
+    path: InstancePath,
+    class_name: String,
+    bytecode: Arc<str>,
+    rx: oneshot::Receiver<DecompileResult>,
+}
+
+/// Decompiles every script in a dumper's SQLite database into a tree that
+/// mirrors the game, or into a `.rbxlx` with the scripts put back in place
+pub async fn process_sqlite(
+    decompiler: &Decompiler,
+    database: &str,
+    table: Option<&str>,
+    output: &str,
+    options: RenderOptions,
+    profiles: &OptionProfiles,
+) -> Result<()> {
+    let database_path = Path::new(database).to_path_buf();
+    let table = table.map(str::to_string);
+    let rows = tokio::task::spawn_blocking(move || read_rows(&database_path, table.as_deref())).await??;
+    info!("found {} scripts in {}", rows.len(), database);
+
+    let mut jobs = Vec::new();
+    let mut skipped = 0u32;
+    for row in rows {
+        let loaded = match load_bytecode(row.bytecode).await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!("skipping {}: {}", row.path, e);
+                skipped += 1;
+                continue;
+            }
+        };
+        let (tx, rx) = oneshot::channel();
+        let request = DecompilationRequest::with_hash(loaded.bytecode.clone(), loaded.hash, tx)
+            .with_options(profiles.options_for(Some(&row.path), Some(&row.class_name)));
+        decompiler.decompile_batch(vec![request]).await?;
+        jobs.push(SqliteJob {
+            path: row.path,
+            class_name: row.class_name,
+            bytecode: loaded.bytecode,
+            rx,
+        });
+    }
+    info!("{} scripts queued for decompilation, {} skipped (not bytecode)", jobs.len(), skipped);
+
+    let to_place = Path::new(output)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("rbxlx"));
+    let mut namer = ScriptFileNamer::new(Path::new(output), options.extension());
+    let mut sourcemap = Sourcemap::new(Path::new(output));
+    let mut place = Node::new("Game", "DataModel");
+
+    let total = jobs.len();
+    let mut failed = 0usize;
+    for (done, job) in jobs.into_iter().enumerate() {
+        let result = job.rx.await.unwrap_or_else(|_| Err("sender dropped".to_string().into()));
+        failed += usize::from(result.is_err());
+
+        if to_place {
+            // the same layout process_rbxlx writes, so the place can be verified and retried,
+            // which needs the bytecode header left first
+            let header = "-- Bytecode (Base64):\n-- ".to_string();
+            let render = RenderOptions { luau: false, ..options };
+            let source = render_result(&job.path, &job.bytecode, Some(header), result, render);
+            place.insert(job.path.segments(), 0, &job.class_name, source);
+        } else {
+            let source = render_result(&job.path, &job.bytecode, None, result, options);
+            let file = namer.path_for(&job.path);
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let contents = format!("-- Path: game.{}\n-- ClassName: {}\n{}\n", job.path, job.class_name, source);
+            let contents = if options.luau { hoist_directives(&contents) } else { contents };
+            std::fs::write(&file, contents)?;
+            sourcemap.add(&job.path, &[job.class_name], &file);
+        }
+
+        if (done + 1) % 100 == 0 {
+            info!("decompiling: {}/{} | {} failed", done + 1, total, failed);
+        }
+    }
+
+    if to_place {
+        write_place(&place, Path::new(output))?;
+    } else {
+        sourcemap.write()?;
+        namer.write_manifest()?;
+    }
+    info!("done. {} decompiled, {} failed, written to {}", total - failed, failed, output);
+    Error::check_failures(failed, total, "scripts")
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::{engine::general_purpose, Engine as _};
+use clap::ValueEnum;
+use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::disasm;
+use crate::error::Result;
+use crate::extract::{for_each_script, FoundScript};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatsFormat {
+    Csv,
+    Parquet,
+}
+
+/// One row of the output
+struct ScriptStats {
+    path: String,
+    class_name: Option<String>,
+    status: &'static str,
+    bytecode_size: i64,
+    decompiled_size: Option<i64>,
+    protos: Option<i64>,
+    /// Scripts with the same bytecode share a group, numbered by first appearance
+    duplicate_group: i64,
+}
+
+/// Splits what a processed place has after a script's bytecode into
+/// whether it was decompiled, and how big the decompilation is
+fn decompilation_of(rest: &str) -> (&'static str, Option<i64>) {
+    if rest.contains("-- decompilation failed:") {
+        return ("failed", None);
+    }
+    match rest.find("-- decompilation:\n") {
+        Some(start) => {
+            let source = rest[start + "-- decompilation:\n".len()..].trim_end_matches('\n');
+            ("decompiled", Some(source.len() as i64))
+        }
+        None => ("not decompiled", None),
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv(rows: &[ScriptStats], with_protos: bool, mut out: impl Write) -> Result<()> {
+    let optional = |value: Option<i64>| value.map(|value| value.to_string()).unwrap_or_default();
+
+    let mut header = "path,class,status,bytecode_size,decompiled_size".to_string();
+    if with_protos {
+        header.push_str(",protos");
+    }
+    header.push_str(",duplicate_group");
+    writeln!(out, "{}", header)?;
+
+    for row in rows {
+        let mut line = format!(
+            "{},{},{},{},{}",
+            csv_field(&row.path),
+            csv_field(row.class_name.as_deref().unwrap_or("")),
+            row.status,
+            row.bytecode_size,
+            optional(row.decompiled_size)
+        );
+        if with_protos {
+            line.push_str(&format!(",{}", optional(row.protos)));
+        }
+        line.push_str(&format!(",{}", row.duplicate_group));
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_parquet(rows: &[ScriptStats], with_protos: bool, output: &Path) -> Result<()> {
+    let protos_column = if with_protos { "OPTIONAL INT64 protos;" } else { "" };
+    let schema = format!(
+        "message script {{
+            REQUIRED BYTE_ARRAY path (UTF8);
+            OPTIONAL BYTE_ARRAY class (UTF8);
+            REQUIRED BYTE_ARRAY status (UTF8);
+            REQUIRED INT64 bytecode_size;
+            OPTIONAL INT64 decompiled_size;
+            {}
+            REQUIRED INT64 duplicate_group;
+        }}",
+        protos_column
+    );
+    let parquet_error = |e: parquet::errors::ParquetError| format!("failed to write {}: {}", output.display(), e);
+    let schema = Arc::new(parse_message_type(&schema).map_err(parquet_error)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(File::create(output)?, schema, properties).map_err(parquet_error)?;
+    let mut row_group = writer.next_row_group().map_err(parquet_error)?;
+
+    // columns come out in schema order
+    let strings = |values: Vec<Option<&str>>| {
+        let definitions: Vec<i16> = values.iter().map(|value| value.is_some() as i16).collect();
+        let values: Vec<ByteArray> = values.into_iter().flatten().map(ByteArray::from).collect();
+        (values, definitions)
+    };
+    let numbers = |values: Vec<Option<i64>>| {
+        let definitions: Vec<i16> = values.iter().map(|value| value.is_some() as i16).collect();
+        let values: Vec<i64> = values.into_iter().flatten().collect();
+        (values, definitions)
+    };
+    let mut string_columns = vec![
+        (strings(rows.iter().map(|row| Some(row.path.as_str())).collect()), false),
+        (strings(rows.iter().map(|row| row.class_name.as_deref()).collect()), true),
+        (strings(rows.iter().map(|row| Some(row.status)).collect()), false),
+    ]
+    .into_iter();
+    let mut number_columns = vec![
+        (numbers(rows.iter().map(|row| Some(row.bytecode_size)).collect()), false),
+        (numbers(rows.iter().map(|row| row.decompiled_size).collect()), true),
+    ];
+    if with_protos {
+        number_columns.push((numbers(rows.iter().map(|row| row.protos).collect()), true));
+    }
+    number_columns.push((numbers(rows.iter().map(|row| Some(row.duplicate_group)).collect()), false));
+    let mut number_columns = number_columns.into_iter();
+
+    while let Some(mut column) = row_group.next_column().map_err(parquet_error)? {
+        if let Some(((values, definitions), optional)) = string_columns.next() {
+            let definitions = optional.then_some(definitions.as_slice());
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&values, definitions, None)
+                .map_err(parquet_error)?;
+        } else if let Some(((values, definitions), optional)) = number_columns.next() {
+            let definitions = optional.then_some(definitions.as_slice());
+            column
+                .typed::<Int64Type>()
+                .write_batch(&values, definitions, None)
+                .map_err(parquet_error)?;
+        }
+        column.close().map_err(parquet_error)?;
+    }
+    row_group.close().map_err(parquet_error)?;
+    writer.close().map_err(parquet_error)?;
+    Ok(())
+}
+
+/// Writes a row of statistics for every script in `input` as CSV (to stdout
+/// without an `output`) or Parquet. Processed places also say how each
+/// script's decompilation went; counting protos means parsing the bytecode,
+/// so that only happens with `with_protos`.
+pub fn write_stats(input: &str, output: Option<&str>, format: Option<StatsFormat>, with_protos: bool) -> Result<()> {
+    let mut groups: HashMap<String, i64> = HashMap::new();
+    let mut rows = Vec::new();
+
+    for_each_script(input, |script: &FoundScript| {
+        let hash = format!("{:x}", Sha256::digest(script.bytecode.as_bytes()));
+        let next_group = groups.len() as i64;
+        let duplicate_group = *groups.entry(hash).or_insert(next_group);
+
+        let raw = general_purpose::STANDARD.decode(script.bytecode).ok();
+        let protos = match (&raw, with_protos) {
+            (Some(raw), true) => disasm::strings(raw).ok().map(|protos| protos.len() as i64),
+            _ => None,
+        };
+        let (status, decompiled_size) = decompilation_of(script.rest);
+
+        rows.push(ScriptStats {
+            path: script.path.to_string(),
+            class_name: script.class_name.map(str::to_string),
+            status,
+            bytecode_size: raw.map_or(script.bytecode.len(), |raw| raw.len()) as i64,
+            decompiled_size,
+            protos,
+            duplicate_group,
+        });
+        Ok(())
+    })?;
+
+    let format = format.unwrap_or(match output {
+        Some(output) if output.to_ascii_lowercase().ends_with(".parquet") => StatsFormat::Parquet,
+        _ => StatsFormat::Csv,
+    });
+    match (format, output) {
+        (StatsFormat::Csv, None | Some("-")) => write_csv(&rows, with_protos, std::io::stdout().lock())?,
+        (StatsFormat::Csv, Some(output)) => write_csv(&rows, with_protos, std::io::BufWriter::new(File::create(output)?))?,
+        (StatsFormat::Parquet, Some(output)) if output != "-" => write_parquet(&rows, with_protos, Path::new(output))?,
+        (StatsFormat::Parquet, _) => return Err("parquet needs an output file, pass one with -o".into()),
+    }
+
+    if let Some(output) = output.filter(|output| *output != "-") {
+        info!("wrote stats for {} scripts ({} unique) to {}", rows.len(), groups.len(), output);
+    }
+    Ok(())
+}
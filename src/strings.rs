@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose, Engine as _};
+use sha2::{Digest, Sha256};
+
+use crate::disasm;
+use crate::error::Result;
+use crate::extract::for_each_script;
+use crate::instance::InstancePath;
+
+/// Lists the globals and string constants of every function in every script
+/// of `input`, decoded locally so the oracle is never contacted
+pub fn print_strings(input: &str) -> Result<()> {
+    // first script seen with each bytecode, so duplicates aren't listed twice
+    let mut seen: HashMap<String, InstancePath> = HashMap::new();
+    let mut scripts = 0u32;
+
+    for_each_script(input, |script| {
+        let (path, bytecode) = (script.path, script.bytecode);
+        scripts += 1;
+        println!("game.{}", path);
+
+        let hash = format!("{:x}", Sha256::digest(bytecode.as_bytes()));
+        if let Some(first) = seen.get(&hash) {
+            println!("  same bytecode as game.{}", first);
+            return Ok(());
+        }
+        seen.insert(hash, path.clone());
+
+        let protos = general_purpose::STANDARD
+            .decode(bytecode)
+            .map_err(|e| format!("invalid base64: {}", e))
+            .and_then(|raw| disasm::strings(&raw));
+        let protos = match protos {
+            Ok(protos) => protos,
+            Err(e) => {
+                println!("  couldn't read the bytecode: {}", e);
+                return Ok(());
+            }
+        };
+
+        for proto in protos {
+            if proto.globals.is_empty() && proto.strings.is_empty() {
+                continue;
+            }
+            println!("  {} (proto {})", proto.name, proto.id);
+            if !proto.globals.is_empty() {
+                println!("    globals: {}", proto.globals.join(", "));
+            }
+            if !proto.strings.is_empty() {
+                let strings: Vec<String> = proto.strings.iter().map(|s| format!("{:?}", s)).collect();
+                println!("    strings: {}", strings.join(", "));
+            }
+        }
+        Ok(())
+    })?;
+
+    println!("{} scripts, {} with unique bytecode", scripts, seen.len());
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tracing::{debug, info};
+
+use crate::error::{Error, Result};
+
+/// How deep below each folder places are looked for
+const MAX_DEPTH: usize = 3;
+
+/// A place file Studio saved
+pub struct StudioPlace {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+/// Where Studio puts places: the default save folder and its autosaves,
+/// including under a OneDrive-synced Documents folder on windows
+fn studio_dirs() -> Vec<PathBuf> {
+    let mut documents = Vec::new();
+    if let Some(profile) = std::env::var_os("USERPROFILE").or_else(|| std::env::var_os("HOME")) {
+        let profile = PathBuf::from(profile);
+        documents.push(profile.join("Documents"));
+        documents.push(profile.join("OneDrive").join("Documents"));
+    }
+    if let Some(one_drive) = std::env::var_os("OneDrive") {
+        documents.push(PathBuf::from(one_drive).join("Documents"));
+    }
+
+    let mut dirs: Vec<PathBuf> = documents.iter().map(|documents| documents.join("ROBLOX")).collect();
+    if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+        dirs.push(PathBuf::from(local).join("Roblox"));
+    }
+    // %OneDrive% is usually the same folder as the one under the profile
+    dirs.dedup_by(|a, b| a.to_string_lossy().eq_ignore_ascii_case(&b.to_string_lossy()));
+    dirs
+}
+
+fn is_place(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("rbxl") || extension.eq_ignore_ascii_case("rbxlx"))
+}
+
+fn collect_places(dir: &Path, depth: usize, places: &mut Vec<StudioPlace>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            // skipping Studio's installs, which ship template places
+            if depth < MAX_DEPTH && entry.file_name() != "Versions" {
+                collect_places(&path, depth + 1, places);
+            }
+        } else if is_place(&path) {
+            places.push(StudioPlace {
+                path,
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                size: metadata.len(),
+            });
+        }
+    }
+}
+
+/// Every place in Studio's folders, most recently saved first
+pub fn find_studio_places() -> Vec<StudioPlace> {
+    let mut places = Vec::new();
+    for dir in studio_dirs() {
+        debug!("looking for places in {}", dir.display());
+        collect_places(&dir, 0, &mut places);
+    }
+    places.sort_by_key(|place| std::cmp::Reverse(place.modified));
+    places.dedup_by(|a, b| a.path == b.path);
+    places
+}
+
+/// How long ago `time` was, roughly
+pub fn age(time: SystemTime) -> String {
+    let seconds = SystemTime::now().duration_since(time).map_or(0, |age| age.as_secs());
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", seconds / 60),
+        3600..=86399 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+pub fn print_places(places: &[StudioPlace]) {
+    for (i, place) in places.iter().enumerate() {
+        println!(
+            "{:>3}  {:>10}  {:>8.2} MiB  {}",
+            i + 1,
+            age(place.modified),
+            place.size as f64 / 1024.0 / 1024.0,
+            place.path.display()
+        );
+    }
+}
+
+/// Asks which of `places` to process, `None` if nothing was picked
+pub fn pick_place(places: &[StudioPlace]) -> Result<Option<&StudioPlace>> {
+    print_places(places);
+    print!("place to process (1-{}, empty to cancel): ", places.len());
+    std::io::stdout().flush()?;
+
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    match line.parse::<usize>() {
+        Ok(n) if (1..=places.len()).contains(&n) => Ok(Some(&places[n - 1])),
+        _ => Err(Error::Config(format!("{} isn't one of the listed places", line))),
+    }
+}
+
+/// Writes a binary place out as XML, so it can be processed like any other
+pub fn convert_rbxl_to_rbxlx(input: &Path, output: &Path) -> Result<()> {
+    info!("converting {} to rbxlx", input.display());
+    let dom = rbx_binary::from_reader(BufReader::new(File::open(input)?))?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    rbx_xml::to_writer_default(&mut writer, &dom, dom.root().children())?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+
+/// The placeholders a template can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// What came before the bytecode in the original source
+    Header,
+    /// The base64 bytecode, as it was in the place
+    Bytecode,
+    /// The decompilation or failure, with any notes about it
+    Decompilation,
+    Path,
+    ClassName,
+    Hash,
+    /// When the script was written, in UTC
+    Timestamp,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "header" => Self::Header,
+            "bytecode" => Self::Bytecode,
+            "decompilation" => Self::Decompilation,
+            "path" => Self::Path,
+            "class_name" => Self::ClassName,
+            "hash" => Self::Hash,
+            "timestamp" => Self::Timestamp,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// How the source of a processed script is put together, read from
+/// `--template`. Placeholders are written as `{path}`, and `{{` and `}}`
+/// stand for literal braces.
+///
+/// ```text
+/// -- {path} ({class_name}), sha256 {hash}
+/// {decompilation}
+/// ```
+#[derive(Debug, Clone)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+/// What a template is filled in with for one script
+pub struct TemplateFields<'a> {
+    pub header: &'a str,
+    pub bytecode: &'a str,
+    pub decompilation: &'a str,
+    pub path: &'a str,
+    pub class_name: &'a str,
+    pub hash: &'a str,
+}
+
+impl OutputTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(Error::Config(format!(
+                                    "unclosed template placeholder {{{}, write {{{{ for a literal {{",
+                                    name
+                                )))
+                            }
+                        }
+                    }
+                    let field = Field::from_name(name.trim()).ok_or_else(|| {
+                        Error::Config(format!(
+                            "unknown template placeholder {{{}}}, expected one of {{header}}, {{bytecode}}, \
+                             {{decompilation}}, {{path}}, {{class_name}}, {{hash}} or {{timestamp}}",
+                            name
+                        ))
+                    })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(Error::Config("unmatched } in template, write }} for a literal one".to_string())),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let template = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("failed to read template {}: {}", path.display(), e)))?;
+        Self::parse(&template)
+    }
+
+    /// The layout used without `--template`, with or without the original
+    /// header and bytecode
+    pub fn default_for(strip_bytecode: bool) -> Self {
+        let template = if strip_bytecode {
+            "-- Path: game.{path}\n-- ClassName: {class_name}\n{decompilation}\n"
+        } else {
+            "{header}{bytecode}\n\n-- Path: game.{path}\n-- ClassName: {class_name}\n{decompilation}\n"
+        };
+        Self::parse(template).unwrap()
+    }
+
+    /// Whether processed scripts keep their original header and bytecode,
+    /// which `verify` and `--retry-failures` rely on
+    pub fn keeps_bytecode(&self) -> bool {
+        matches!(
+            self.parts.as_slice(),
+            [Part::Field(Field::Header), Part::Field(Field::Bytecode), ..]
+        )
+    }
+
+    pub fn render(&self, fields: &TemplateFields) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Field(Field::Header) => rendered.push_str(fields.header),
+                Part::Field(Field::Bytecode) => rendered.push_str(fields.bytecode),
+                Part::Field(Field::Decompilation) => rendered.push_str(fields.decompilation),
+                Part::Field(Field::Path) => rendered.push_str(fields.path),
+                Part::Field(Field::ClassName) => rendered.push_str(fields.class_name),
+                Part::Field(Field::Hash) => rendered.push_str(fields.hash),
+                Part::Field(Field::Timestamp) => rendered.push_str(&utc_timestamp()),
+            }
+        }
+        rendered
+    }
+}
+
+/// The current time as an ISO 8601 UTC timestamp, e.g. `2024-05-01T12:30:00Z`
+pub fn utc_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // civil date from days since the epoch, after Howard Hinnant's civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> TemplateFields<'static> {
+        TemplateFields {
+            header: "-- header\n",
+            bytecode: "BgMBAQAAAQA=",
+            decompilation: "print(1)",
+            path: "Workspace.Script",
+            class_name: "Script",
+            hash: "abc",
+        }
+    }
+
+    fn error(template: &str) -> String {
+        match OutputTemplate::parse(template) {
+            Err(Error::Config(message)) => message,
+            Err(e) => panic!("{:?} isn't a config error: {}", template, e),
+            Ok(_) => panic!("{:?} parsed", template),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let template = OutputTemplate::parse("-- {path} ({class_name}) {hash}\n{decompilation}").unwrap();
+        assert_eq!(template.render(&fields()), "-- Workspace.Script (Script) abc\nprint(1)");
+        let template = OutputTemplate::parse("{ path }").unwrap();
+        assert_eq!(template.render(&fields()), "Workspace.Script");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let template = OutputTemplate::parse("local t = {{}} -- {{path}}").unwrap();
+        assert_eq!(template.render(&fields()), "local t = {} -- {path}");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_refused_by_name() {
+        assert!(error("{decompiled}").starts_with("unknown template placeholder {decompiled}, expected one of"));
+        assert!(error("{}").starts_with("unknown template placeholder {},"));
+        assert!(error("{Path}").starts_with("unknown template placeholder {Path},"));
+    }
+
+    #[test]
+    fn unbalanced_braces_are_refused() {
+        assert!(error("{path").starts_with("unclosed template placeholder {path"));
+        assert!(error("{").starts_with("unclosed template placeholder {"));
+        assert!(error("path}").starts_with("unmatched }"));
+    }
+
+    #[test]
+    fn only_templates_starting_with_the_bytecode_keep_it() {
+        assert!(OutputTemplate::default_for(false).keeps_bytecode());
+        assert!(!OutputTemplate::default_for(true).keeps_bytecode());
+        assert!(!OutputTemplate::parse("\n{header}{bytecode}").unwrap().keeps_bytecode());
+        assert!(!OutputTemplate::parse("{bytecode}{header}").unwrap().keeps_bytecode());
+    }
+
+    #[test]
+    fn timestamps_are_iso_8601_utc() {
+        let timestamp = utc_timestamp();
+        assert_eq!(timestamp.len(), "2024-05-01T12:30:00Z".len());
+        assert!(timestamp.ends_with('Z'));
+        assert_eq!(&timestamp[10..11], "T");
+    }
+}
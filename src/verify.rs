@@ -0,0 +1,306 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::sync::{atomic::AtomicU64, Arc};
+
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::compiled::find_bytecode;
+use crate::error::{Error, Result};
+use crate::instance::{InstancePath, InstanceTracker};
+use crate::rbxlx::Utf8BoundaryReader;
+
+/// Only the first few divergences are printed, the rest are just counted
+const MAX_REPORTED_PROBLEMS: usize = 20;
+
+/// The parts of an event that are compared. Whitespace and the document
+/// prolog are skipped, the declaration always says UTF-8 on the way out,
+/// and names are compared without their prefix.
+#[derive(Debug, PartialEq)]
+enum Node {
+    Start(String, Vec<(String, String)>),
+    End(String),
+    Characters(String),
+    CData(String),
+    Comment(String),
+}
+
+struct Item {
+    node: Node,
+    path: InstancePath,
+    in_script_source: bool,
+}
+
+struct NodeReader<R: Read> {
+    events: EventReader<Utf8BoundaryReader<BufReader<R>>>,
+    tracker: InstanceTracker,
+    peeked: Option<XmlEvent>,
+    done: bool,
+}
+
+impl<R: Read> NodeReader<R> {
+    fn new(inner: R) -> Self {
+        let file = BufReader::with_capacity(8 * 1024 * 1024, inner);
+        let utf8_reader = Utf8BoundaryReader::new(file, Arc::new(AtomicU64::new(0)));
+        Self {
+            events: EventReader::new(utf8_reader),
+            tracker: InstanceTracker::default(),
+            peeked: None,
+            done: false,
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Option<XmlEvent>> {
+        if let Some(event) = self.peeked.take() {
+            return Ok(Some(event));
+        }
+        if self.done {
+            return Ok(None);
+        }
+        match self.events.next()? {
+            XmlEvent::EndDocument => {
+                self.done = true;
+                Ok(None)
+            }
+            event => Ok(Some(event)),
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Item>> {
+        loop {
+            let Some(event) = self.next_event()? else {
+                return Ok(None);
+            };
+
+            let node = match &event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => Node::Start(
+                    name.local_name.clone(),
+                    attributes
+                        .iter()
+                        .map(|attr| (attr.name.local_name.clone(), attr.value.clone()))
+                        .collect(),
+                ),
+                XmlEvent::EndElement { name } => Node::End(name.local_name.clone()),
+                XmlEvent::Characters(text) => Node::Characters(text.clone()),
+                XmlEvent::Comment(text) => Node::Comment(text.clone()),
+                XmlEvent::CData(text) => {
+                    // `]]>` inside a script is written as two sections back to back
+                    let mut text = text.clone();
+                    loop {
+                        match self.next_event()? {
+                            Some(XmlEvent::CData(more)) => text.push_str(&more),
+                            other => {
+                                self.peeked = other;
+                                break;
+                            }
+                        }
+                    }
+                    Node::CData(text)
+                }
+                _ => continue,
+            };
+
+            let item = Item {
+                node,
+                path: self.tracker.path(),
+                in_script_source: self.tracker.in_script_source(),
+            };
+            self.tracker.observe(&event);
+            return Ok(Some(item));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    scripts: u32,
+    decompiled: u32,
+    failed: u32,
+    untouched: u32,
+    problems: usize,
+}
+
+impl Report {
+    fn problem(&mut self, path: &InstancePath, message: &str) {
+        self.problems += 1;
+        if self.problems <= MAX_REPORTED_PROBLEMS {
+            println!("mismatch at game.{}: {}", path, message);
+        }
+    }
+
+    fn check_script(&mut self, input: &str, output: &str, path: &InstancePath) {
+        self.scripts += 1;
+
+        let Some((_, bytecode_end)) = find_bytecode(input) else {
+            if input != output {
+                self.problem(path, "script without bytecode was changed");
+            }
+            return;
+        };
+
+        if input == output {
+            // skipped by --root/--include/--exclude/--only-class
+            self.untouched += 1;
+            return;
+        }
+
+        if !output.starts_with(&input[..bytecode_end]) {
+            self.problem(path, "script header or bytecode differs from the input");
+            return;
+        }
+
+        let rest = &output[bytecode_end..];
+        if rest.contains("\n-- decompilation failed:\n") {
+            self.failed += 1;
+        } else if rest.contains("\n-- decompilation:\n") {
+            self.decompiled += 1;
+        } else {
+            self.problem(path, "script has neither a decompilation nor a failure marker");
+        }
+    }
+}
+
+/// Re-reads a processed place next to the original and checks that only
+/// script sources changed, each gaining a decompilation or failure marker
+pub fn verify_rbxlx(input_file: &str, output_file: &str) -> Result<()> {
+    let mut input = NodeReader::new(File::open(input_file)?);
+    let mut output = NodeReader::new(File::open(output_file)?);
+    let mut report = Report::default();
+
+    loop {
+        let expected = input.next()?;
+        let actual = output
+            .next()
+            .map_err(|e| Error::Other(format!("{} is not well-formed xml: {}", output_file, e)))?;
+
+        let (expected, actual) = match (expected, actual) {
+            (None, None) => break,
+            (Some(expected), None) => {
+                report.problem(&expected.path, "output ends early");
+                break;
+            }
+            (None, Some(actual)) => {
+                report.problem(&actual.path, "output has content past the end of the input");
+                break;
+            }
+            (Some(expected), Some(actual)) => (expected, actual),
+        };
+
+        match (&expected.node, &actual.node) {
+            (Node::CData(input_text), Node::CData(output_text)) if expected.in_script_source => {
+                report.check_script(input_text, output_text, &expected.path);
+            }
+            (expected_node, actual_node) if expected_node != actual_node => {
+                report.problem(
+                    &expected.path,
+                    &format!("expected {:?}, found {:?}", expected_node, actual_node),
+                );
+                if matches!(expected_node, Node::Start(..) | Node::End(_))
+                    || matches!(actual_node, Node::Start(..) | Node::End(_))
+                {
+                    // the two trees no longer line up, so anything after this is noise
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    println!(
+        "checked {} scripts: {} decompiled, {} failed, {} left as is",
+        report.scripts, report.decompiled, report.failed, report.untouched
+    );
+
+    if report.problems > 0 {
+        if report.problems > MAX_REPORTED_PROBLEMS {
+            println!("... and {} more", report.problems - MAX_REPORTED_PROBLEMS);
+        }
+        return Err(Error::Other(format!(
+            "{} does not match {} ({} problems)",
+            output_file, input_file, report.problems
+        )));
+    }
+
+    println!("{} matches {}", output_file, input_file);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "-- Bytecode (Base64):\n-- BgMBAQAAAQA=";
+
+    fn path() -> InstancePath {
+        InstancePath::new(vec!["Workspace".to_string(), "Script".to_string()])
+    }
+
+    fn checked(output: &str) -> Report {
+        let mut report = Report::default();
+        report.check_script(SOURCE, output, &path());
+        report
+    }
+
+    fn place(source: &str) -> String {
+        format!(
+            "<roblox><Item class=\"Script\"><Properties><string name=\"Name\">Script</string>\
+             <ProtectedString name=\"Source\"><![CDATA[{}]]></ProtectedString></Properties></Item></roblox>",
+            source
+        )
+    }
+
+    /// Writes `input` and `output` places and verifies one against the other
+    fn verify(name: &str, input: &str, output: &str) -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("oracle-postprocess-verify-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input_file, output_file) = (dir.join("in.rbxlx"), dir.join("out.rbxlx"));
+        std::fs::write(&input_file, input).unwrap();
+        std::fs::write(&output_file, output).unwrap();
+        verify_rbxlx(&input_file.to_string_lossy(), &output_file.to_string_lossy())
+    }
+
+    #[test]
+    fn scripts_are_counted_by_their_marker() {
+        let report = checked(&format!("{}\n\n-- decompilation:\nprint(1)", SOURCE));
+        assert_eq!((report.decompiled, report.failed, report.problems), (1, 0, 0));
+        let report = checked(&format!("{}\n\n-- decompilation failed:\noops", SOURCE));
+        assert_eq!((report.decompiled, report.failed, report.problems), (0, 1, 0));
+        let report = checked(SOURCE);
+        assert_eq!((report.untouched, report.problems), (1, 0));
+    }
+
+    #[test]
+    fn changed_bytecode_or_a_missing_marker_is_a_problem() {
+        assert_eq!(checked("-- Bytecode (Base64):\n-- AAAA\n\n-- decompilation:\n").problems, 1);
+        assert_eq!(checked(&format!("{}\nprint(1)", SOURCE)).problems, 1);
+
+        let mut report = Report::default();
+        report.check_script("print(1)", "print(2)", &path());
+        assert_eq!((report.scripts, report.problems), (1, 1));
+    }
+
+    #[test]
+    fn cdata_split_around_an_end_marker_reads_as_one() {
+        let xml = "<a><![CDATA[x]]]]><![CDATA[>y]]></a>";
+        let mut reader = NodeReader::new(xml.as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap().node, Node::Start("a".to_string(), Vec::new()));
+        assert_eq!(reader.next().unwrap().unwrap().node, Node::CData("x]]>y".to_string()));
+        assert_eq!(reader.next().unwrap().unwrap().node, Node::End("a".to_string()));
+        assert!(reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn a_processed_place_matches_its_input() {
+        let output = place(&format!("{}\n\n-- decompilation:\nprint(1)", SOURCE));
+        assert!(verify("matches", &place(SOURCE), &output).is_ok());
+    }
+
+    #[test]
+    fn anything_else_changing_fails_verification() {
+        let renamed = place(SOURCE).replace(">Script<", ">Renamed<");
+        assert!(verify("renamed", &place(SOURCE), &renamed).is_err());
+        let cut = place(SOURCE).replace("</roblox>", "");
+        assert!(verify("cut", &place(SOURCE), &cut).is_err());
+    }
+}
@@ -0,0 +1,630 @@
+//! Runs the tool end to end against its own mock oracle, see the `mock-oracle` feature
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+
+use base64::{engine::general_purpose, Engine as _};
+use sha2::{Digest, Sha256};
+
+const BIN: &str = env!("CARGO_BIN_EXE_oracle-postprocess");
+
+/// A `mock-oracle` running in the background until it's dropped
+struct Oracle {
+    child: Child,
+    url: String,
+}
+
+impl Oracle {
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(BIN)
+            .arg("mock-oracle")
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start the mock oracle");
+        let mut url = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut url)
+            .unwrap();
+        Self {
+            child,
+            url: url.trim().to_string(),
+        }
+    }
+
+    /// Runs the tool in `dir` against this oracle
+    fn run(&self, dir: &Path, key: &str, args: &[&str]) -> Output {
+        Command::new(BIN)
+            .arg("--config")
+            .arg(dir.join("config.toml"))
+            .arg("--temp-dir")
+            .arg(dir.join("runs"))
+            .args(["--key", key, "--base-url", &self.url])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Oracle {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// An empty folder for one test, with an empty config so the user's isn't read
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("oracle-postprocess-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("config.toml"), "").unwrap();
+    dir
+}
+
+/// Something that passes for luau bytecode, different for every `seed`
+fn bytecode(seed: u8) -> Vec<u8> {
+    vec![6, 3, seed, 1, 0, 0, 1, 0]
+}
+
+/// The hash the oracle answers `bytecode` with, which is of its base64
+fn hash(bytecode: &[u8]) -> String {
+    let encoded = general_purpose::STANDARD.encode(bytecode);
+    format!("{:x}", Sha256::digest(encoded.as_bytes()))
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "exited with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Writes scripts with `seeds` to `dir` as <seed>.bin, returning their paths
+fn write_scripts(dir: &Path, seeds: &[u8]) -> Vec<String> {
+    seeds
+        .iter()
+        .map(|seed| {
+            let path = dir.join(format!("{}.bin", seed));
+            fs::write(&path, bytecode(*seed)).unwrap();
+            path.to_string_lossy().to_string()
+        })
+        .collect()
+}
+
+/// A scratch folder with scripts for `seeds` in it, and a mock oracle to run
+/// the tool against, which logs how it got every script to `frames.log`
+struct Setup {
+    dir: PathBuf,
+    oracle: Oracle,
+    inputs: Vec<String>,
+    seeds: Vec<u8>,
+}
+
+impl Setup {
+    fn new(name: &str, oracle_args: &[&str], seeds: &[u8]) -> Self {
+        let dir = scratch(name);
+        let frame_log = dir.join("frames.log").to_string_lossy().to_string();
+        let mut args = oracle_args.to_vec();
+        args.extend(["--frame-log", &frame_log]);
+        let oracle = Oracle::start(&args);
+        let inputs = write_scripts(&dir, seeds);
+        Self {
+            dir,
+            oracle,
+            inputs,
+            seeds: seeds.to_vec(),
+        }
+    }
+
+    /// Runs the tool in the folder with the `test` key
+    fn run(&self, args: &[&str]) -> Output {
+        self.oracle.run(&self.dir, "test", args)
+    }
+
+    /// Runs `single` over every script into `out`, with `args` in front of it
+    fn decompile(&self, args: &[&str]) -> Output {
+        let mut args = args.to_vec();
+        args.extend(["single", "-o", "out"]);
+        args.extend(self.inputs.iter().map(String::as_str));
+        self.run(&args)
+    }
+
+    /// What `decompile` wrote for the script with `seed`
+    fn decompiled(&self, seed: u8) -> String {
+        fs::read_to_string(self.dir.join("out").join(format!("{}.lua", seed))).unwrap()
+    }
+
+    /// Checks that `decompile` gave every script the answer for its own bytecode
+    fn assert_decompiled(&self) {
+        for seed in &self.seeds {
+            assert!(
+                self.decompiled(*seed).contains(&hash(&bytecode(*seed))),
+                "script {} got someone else's answer",
+                seed
+            );
+        }
+    }
+
+    /// How the oracle got each script so far: `text`, `binary` or `binary zstd`
+    fn frames(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.join("frames.log"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.rsplit_once(' ').map(|(frame, _)| frame.to_string()))
+            .collect()
+    }
+}
+
+#[test]
+fn single_decompiles_a_script() {
+    let setup = Setup::new("single", &[], &[1]);
+
+    let output = setup.run(&["single", &setup.inputs[0], "-o", "out.lua"]);
+    assert_success(&output);
+    let decompiled = fs::read_to_string(setup.dir.join("out.lua")).unwrap();
+    assert!(decompiled.contains("decompiled by the mock oracle"));
+    assert!(decompiled.contains(&hash(&bytecode(1))));
+}
+
+#[test]
+fn answers_out_of_order_and_twice_go_to_the_right_scripts() {
+    let setup = Setup::new(
+        "out-of-order",
+        &["--out-of-order", "--duplicate", "--delay-ms", "5"],
+        &[1, 2, 3, 4, 5, 6],
+    );
+    assert_success(&setup.decompile(&[]));
+    setup.assert_decompiled();
+}
+
+#[test]
+fn failed_scripts_are_retried() {
+    // the second script fails, and so does its first retry
+    let setup = Setup::new("retried", &["--fail-every", "2"], &[1, 2, 3]);
+    assert_success(&setup.decompile(&["--retries", "3"]));
+    setup.assert_decompiled();
+}
+
+#[test]
+fn failures_fail_the_run() {
+    let setup = Setup::new("failures", &["--fail-every", "1"], &[1]);
+    let output = setup.run(&["single", &setup.inputs[0], "-o", "out.lua"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn a_rejected_key_is_an_auth_error() {
+    let setup = Setup::new("rejected-key", &["--require-key", "right"], &[1]);
+    let args = ["single", &setup.inputs[0], "-o", "out.lua"];
+
+    let output = setup.oracle.run(&setup.dir, "wrong", &args);
+    assert_eq!(output.status.code(), Some(3));
+    assert_success(&setup.oracle.run(&setup.dir, "right", &args));
+}
+
+/// A place with a script for each of `seeds`, in the layout Studio saves them in
+fn place(seeds: &[u8]) -> String {
+    let items: String = seeds
+        .iter()
+        .enumerate()
+        .map(|(index, seed)| {
+            format!(
+                r#"<Item class="ModuleScript" referent="RBX{}"><Properties><string name="Name">Module{}</string><ProtectedString name="Source"><![CDATA[-- Bytecode (Base64):
+-- {}]]></ProtectedString></Properties></Item>"#,
+                index,
+                index,
+                general_purpose::STANDARD.encode(bytecode(*seed))
+            )
+        })
+        .collect();
+    format!(r#"<roblox version="4">{}</roblox>"#, items)
+}
+
+#[test]
+fn rbxlx_scripts_are_decompiled_in_place() {
+    let setup = Setup::new("rbxlx", &["--protocol", "1", "--protocol", "2", "--delay-ms", "5"], &[]);
+    // the last two share bytecode, which is only sent once
+    fs::write(setup.dir.join("place.rbxlx"), place(&[1, 2, 2])).unwrap();
+
+    let output = setup.run(&["rbxlx", "place.rbxlx", "-o", "out.rbxlx", "--report", "report.json"]);
+    assert_success(&output);
+    let processed = fs::read_to_string(setup.dir.join("out.rbxlx")).unwrap();
+    assert_eq!(processed.matches("decompiled by the mock oracle").count(), 3);
+    assert!(processed.contains(&hash(&bytecode(1))));
+    assert!(processed.contains(&hash(&bytecode(2))));
+    assert_eq!(setup.frames().len(), 2);
+    assert!(fs::read_to_string(setup.dir.join("report.json")).unwrap().contains("\"success\": true"));
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(log.contains("scripts found:   3"));
+    assert!(log.contains("deduplicated:    1"));
+    assert_success(&setup.run(&["verify", "place.rbxlx", "out.rbxlx"]));
+}
+
+#[test]
+fn a_recording_replays_without_the_oracle() {
+    let setup = Setup::new("replay", &["--fail-every", "2"], &[]);
+    let dir = &setup.dir;
+    fs::write(dir.join("place.rbxlx"), place(&[1, 2])).unwrap();
+    let recorded = setup.run(&["--record", "session.jsonl", "rbxlx", "place.rbxlx", "-o", "recorded.rbxlx"]);
+    assert_eq!(recorded.status.code(), Some(5));
+    drop(setup.oracle);
+
+    let replayed = Command::new(BIN)
+        .arg("--config")
+        .arg(dir.join("config.toml"))
+        .args(["--replay", "session.jsonl", "rbxlx", "place.rbxlx", "-o", "replayed.rbxlx"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    // the failure is replayed too
+    assert_eq!(replayed.status.code(), Some(5));
+    assert_eq!(
+        fs::read_to_string(dir.join("recorded.rbxlx")).unwrap(),
+        fs::read_to_string(dir.join("replayed.rbxlx")).unwrap()
+    );
+}
+
+#[test]
+fn scripts_wait_in_the_offline_queue_without_an_oracle() {
+    let dir = scratch("offline-queue");
+    fs::write(dir.join("place.rbxlx"), place(&[1, 2])).unwrap();
+    // a port nothing listens on anymore
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let output = Command::new(BIN)
+        .arg("--config")
+        .arg(dir.join("config.toml"))
+        .arg("--temp-dir")
+        .arg(dir.join("runs"))
+        .args(["--key", "test", "--base-url", &format!("ws://127.0.0.1:{}/ws", port)])
+        .args(["rbxlx", "place.rbxlx", "-o", "out.rbxlx", "--offline-queue", "queue.jsonl"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_success(&output);
+    // straight away, not after every script failed over http
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("queueing every script to queue.jsonl"), "{}", stderr);
+    let queue = fs::read_to_string(dir.join("queue.jsonl")).unwrap();
+    for seed in [1, 2] {
+        assert!(queue.contains(&hash(&bytecode(seed))), "script {} wasn't queued", seed);
+    }
+}
+
+#[test]
+fn bytecode_goes_in_binary_frames_when_offered() {
+    let setup = Setup::new("binary-frames", &["--protocol", "1", "--protocol", "2", "--protocol", "3"], &[1, 2]);
+    assert_success(&setup.decompile(&["--decompiler-options", r#"{"a":1}"#]));
+    setup.assert_decompiled();
+    // the oracle answers bytecode that doesn't match its hash with a failure,
+    // so these held the raw bytecode too
+    assert_eq!(setup.frames(), ["binary", "binary"]);
+
+    let setup = Setup::new("text-frames", &["--protocol", "1", "--protocol", "2"], &[1, 2]);
+    assert_success(&setup.decompile(&[]));
+    assert_eq!(setup.frames(), ["text", "text"]);
+}
+
+#[test]
+fn bytecode_and_results_are_compressed_when_offered() {
+    let setup = Setup::new(
+        "compression",
+        &["--protocol", "3", "--compression", "zstd", "--out-of-order"],
+        &[1, 2, 3],
+    );
+    assert_success(&setup.decompile(&[]));
+    setup.assert_decompiled();
+    // only bytecode that decompressed to what its hash says gets decompiled
+    assert_eq!(setup.frames(), ["binary zstd", "binary zstd", "binary zstd"]);
+
+    let setup = Setup::new("no-compression", &["--protocol", "3", "--compression", "zstd"], &[1]);
+    assert_success(&setup.decompile(&["--compression", "none"]));
+    assert_eq!(setup.frames(), ["binary"]);
+}
+
+#[test]
+fn answers_are_matched_up_by_id_when_offered() {
+    let setup = Setup::new(
+        "request-ids",
+        &["--protocol", "3", "--protocol", "4", "--out-of-order", "--duplicate"],
+        &[1, 2, 3, 4],
+    );
+    assert_success(&setup.decompile(&["--decompiler-options", r#"{"a":1}"#]));
+    setup.assert_decompiled();
+}
+
+#[test]
+fn answers_without_an_id_still_find_their_request() {
+    let setup = Setup::new("request-ids-dropped", &["--protocol", "4", "--drop-id-every", "2"], &[1, 2, 3]);
+    let mut args = vec!["--decompiler-options", r#"{"a":1}"#, "single", "--timeout", "10", "-o", "out"];
+    args.extend(setup.inputs.iter().map(String::as_str));
+    assert_success(&setup.run(&args));
+    setup.assert_decompiled();
+}
+
+#[test]
+fn spilled_results_are_written_like_any_other() {
+    let setup = Setup::new("spill", &["--out-of-order"], &[]);
+    fs::write(setup.dir.join("place.rbxlx"), place(&[1, 2, 1])).unwrap();
+
+    let output = setup.run(&["rbxlx", "place.rbxlx", "-o", "out.rbxlx", "--spill-threshold", "0"]);
+    assert_success(&output);
+    let processed = fs::read_to_string(setup.dir.join("out.rbxlx")).unwrap();
+    assert_eq!(processed.matches("decompiled by the mock oracle").count(), 3);
+    assert!(processed.contains(&hash(&bytecode(2))));
+}
+
+#[test]
+fn progress_comes_as_json_lines_when_asked_for() {
+    let setup = Setup::new("progress-json", &["--fail-every", "2"], &[]);
+    fs::write(setup.dir.join("place.rbxlx"), place(&[1, 2])).unwrap();
+
+    let output = setup.run(&["--progress-json", "-q", "--retries", "0", "rbxlx", "place.rbxlx", "-o", "out.rbxlx"]);
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let count = |event: &str| events.iter().filter(|it| it["event"] == event).count();
+    assert_eq!(count("script_started"), 2);
+    assert_eq!(count("script_finished"), 1);
+    assert_eq!(count("script_failed"), 1);
+}
+
+#[test]
+fn only_scripts_of_the_given_classes_are_decompiled() {
+    let setup = Setup::new("only-class", &[], &[]);
+    fs::write(setup.dir.join("place.rbxlx"), place(&[1, 2]).replacen("ModuleScript", "LocalScript", 1)).unwrap();
+
+    let output = setup.run(&["rbxlx", "place.rbxlx", "-o", "out.rbxlx", "--only-class", "LocalScript,Script"]);
+    assert_success(&output);
+    let processed = fs::read_to_string(setup.dir.join("out.rbxlx")).unwrap();
+    assert_eq!(processed.matches("decompiled by the mock oracle").count(), 1);
+    assert!(processed.contains(&hash(&bytecode(1))));
+    assert_eq!(setup.frames().len(), 1);
+}
+
+#[test]
+fn autotune_settles_on_a_window() {
+    let seeds: Vec<u8> = (1..=30).collect();
+    let setup = Setup::new("autotune", &["--delay-ms", "5"], &seeds);
+
+    // 1 MiB leaves three windows to try, which 30 scripts are enough for
+    let output = setup.decompile(&["--autotune", "--max-in-flight", "1"]);
+    assert_success(&output);
+    setup.assert_decompiled();
+    let log = String::from_utf8_lossy(&output.stderr);
+    let picked = log
+        .lines()
+        .find_map(|line| line.split("autotune picked ").nth(1))
+        .unwrap_or_else(|| panic!("autotune didn't pick a window: {}", log));
+    assert!(
+        ["0.25 MiB", "0.50 MiB", "1.00 MiB"].iter().any(|window| picked.starts_with(window)),
+        "{}",
+        picked
+    );
+}
+
+#[test]
+fn size_comment_goes_in_front_of_decompilations() {
+    let setup = Setup::new("size-comment", &[], &[1]);
+
+    let output = setup.run(&["--size-comment", "single", &setup.inputs[0], "-o", "out.lua"]);
+    assert_success(&output);
+    let decompiled = fs::read_to_string(setup.dir.join("out.lua")).unwrap();
+    let first_line = decompiled.lines().next().unwrap();
+    assert!(first_line.starts_with("-- sizes: 0.0 KiB bytecode"), "{}", first_line);
+    assert!(decompiled.contains("decompiled by the mock oracle"));
+}
+
+#[test]
+fn disassemblies_go_next_to_decompilations() {
+    let setup = Setup::new("with-disasm", &["--protocol", "4", "--protocol", "5", "--out-of-order"], &[1, 2]);
+    assert_success(&setup.decompile(&["--with-disasm"]));
+    setup.assert_decompiled();
+    for seed in [1, 2] {
+        let disassembly = fs::read_to_string(setup.dir.join("out").join(format!("{}.disasm", seed))).unwrap();
+        assert!(disassembly.contains("disassembled by the mock oracle"));
+        assert!(disassembly.contains(&hash(&bytecode(seed))));
+    }
+
+    // an oracle that doesn't disassemble leaves it to the built-in disassembler
+    let setup = Setup::new("local-disasm", &[], &[1]);
+    let output = setup.run(&["--with-disasm", "single", &setup.inputs[0], "-o", "local.lua"]);
+    assert_success(&output);
+    let disassembly = fs::read_to_string(setup.dir.join("local.disasm")).unwrap();
+    assert!(!disassembly.contains("disassembled by the mock oracle"));
+}
+
+#[test]
+fn lenient_keeps_what_comes_before_a_truncation() {
+    let setup = Setup::new("lenient", &[], &[]);
+    let whole = place(&[1, 2]).replacen("Module0", "Module&nbsp;0", 1);
+    let second = whole.rfind("<Item").unwrap();
+    fs::write(setup.dir.join("place.rbxlx"), &whole[..second + 150]).unwrap();
+
+    let output = setup.run(&["rbxlx", "place.rbxlx", "-o", "out.rbxlx"]);
+    assert!(!output.status.success());
+
+    let output = setup.run(&["rbxlx", "place.rbxlx", "-o", "out.rbxlx", "--lenient"]);
+    assert_success(&output);
+    let processed = fs::read_to_string(setup.dir.join("out.rbxlx")).unwrap();
+    assert_eq!(processed.matches("decompiled by the mock oracle").count(), 1);
+    assert!(processed.contains(&hash(&bytecode(1))));
+    assert!(processed.trim_end().ends_with("</roblox>"));
+}
+
+/// Bytecode for a script compiled with its chunk name, `Inventory`, and a
+/// function called `add`
+fn named_bytecode() -> Vec<u8> {
+    let ret = [22, 0, 1, 0];
+    let mut bytecode = vec![3, 2, 9];
+    bytecode.extend_from_slice(b"Inventory");
+    bytecode.push(3);
+    bytecode.extend_from_slice(b"add");
+    bytecode.push(2);
+    // add: no constants or children, defined on line 1
+    bytecode.extend_from_slice(&[0, 0, 0, 0, 1]);
+    bytecode.extend_from_slice(&ret);
+    bytecode.extend_from_slice(&[0, 0, 1, 2, 0, 0]);
+    // main, with add as its child
+    bytecode.extend_from_slice(&[0, 0, 0, 1, 1]);
+    bytecode.extend_from_slice(&ret);
+    bytecode.extend_from_slice(&[0, 1, 0, 0, 1, 0, 0]);
+    bytecode.push(1);
+    bytecode
+}
+
+#[test]
+fn debug_names_annotate_and_name_scripts() {
+    let setup = Setup::new("debug-names", &[], &[]);
+    let encoded = general_purpose::STANDARD.encode(named_bytecode());
+    let place = place(&[1]).replace(&general_purpose::STANDARD.encode(bytecode(1)), &encoded);
+    fs::write(setup.dir.join("place.rbxlx"), place).unwrap();
+
+    let output = setup.run(&["--debug-names", "rbxlx", "place.rbxlx", "-o", "out.rbxlx", "--scripts-dir", "scripts"]);
+    assert_success(&output);
+    let processed = fs::read_to_string(setup.dir.join("out.rbxlx")).unwrap();
+    assert!(processed.contains("-- Debug names: chunk Inventory, functions add"));
+    assert!(setup.dir.join("scripts").join("Inventory.lua").exists());
+    assert!(!setup.dir.join("scripts").join("Module0.lua").exists());
+}
+
+#[test]
+fn roundtrip_check_scores_each_decompilation() {
+    let setup = Setup::new("roundtrip", &[], &[]);
+    let encoded = general_purpose::STANDARD.encode(named_bytecode());
+    let place = place(&[1]).replace(&general_purpose::STANDARD.encode(bytecode(1)), &encoded);
+    fs::write(setup.dir.join("place.rbxlx"), place).unwrap();
+    assert_success(&setup.run(&["rbxlx", "place.rbxlx", "-o", "out.rbxlx"]));
+
+    let output = setup.run(&["roundtrip-check", "out.rbxlx", "--report", "roundtrip.json"]);
+    assert_success(&output);
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(setup.dir.join("roundtrip.json")).unwrap()).unwrap();
+    let script = &report[0];
+    assert_eq!(script["status"], "compared");
+    assert_eq!(script["original_protos"], 2);
+    // the mock's decompilation is a single return of a string, nothing like the original
+    let fidelity = script["fidelity"].as_f64().unwrap();
+    assert!(fidelity > 0.0 && fidelity < 1.0, "{}", fidelity);
+}
+
+/// A key-value store over http like a team's shared cache, answering in a
+/// thread until the test ends. Returns its url.
+fn kv_store() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/cache", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let mut stored: HashMap<String, Vec<u8>> = HashMap::new();
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            let _ = reader.read_line(&mut request_line);
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            let _ = reader.read_exact(&mut body);
+
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or("").to_string();
+            let path = parts.next().unwrap_or("").to_string();
+            let (status, body) = match method.as_str() {
+                "PUT" => {
+                    stored.insert(path, body);
+                    ("204 No Content", Vec::new())
+                }
+                _ => match stored.get(&path) {
+                    Some(value) => ("200 OK", value.clone()),
+                    None => ("404 Not Found", Vec::new()),
+                },
+            };
+            let mut response =
+                format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len())
+                    .into_bytes();
+            response.extend(body);
+            let _ = reader.get_mut().write_all(&response);
+        }
+    });
+    url
+}
+
+#[test]
+fn a_shared_cache_spares_the_oracle_for_the_rest_of_the_team() {
+    let url = kv_store();
+    let first = Setup::new("shared-cache-first", &[], &[1, 2]);
+    assert_success(&first.decompile(&["--cache-url", &url]));
+    drop(first);
+
+    // everything the oracle gets now fails, so these can only come from the cache
+    let second = Setup::new("shared-cache-second", &["--fail-every", "1"], &[1, 2]);
+    assert_success(&second.decompile(&["--cache-url", &url]));
+    second.assert_decompiled();
+    assert!(second.frames().is_empty());
+}
+
+#[test]
+fn cache_gc_evicts_down_to_the_max_size() {
+    let setup = Setup::new("cache-gc", &[], &[1, 2, 3]);
+    assert_success(&setup.decompile(&["--cache-dir", "cache"]));
+    let cached = || {
+        fs::read_dir(setup.dir.join("cache"))
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|extension| extension == "lua"))
+            .count()
+    };
+    assert_eq!(cached(), 3);
+
+    // room for one of the mock's decompilations, not two
+    let gc = setup.run(&["--cache-dir", "cache", "--cache-max-size", "0.00015", "cache", "gc"]);
+    assert_success(&gc);
+    assert_eq!(cached(), 1);
+    let stats = setup.run(&["--cache-dir", "cache", "cache", "stats"]);
+    assert_success(&stats);
+    assert!(String::from_utf8_lossy(&stats.stdout).contains("scripts:   1"));
+}
+
+#[test]
+fn an_encrypted_cache_only_opens_with_its_key() {
+    let setup = Setup::new("cache-encrypt", &[], &[1]);
+    let dir = &setup.dir;
+    let args = ["--cache-dir", "cache", "--cache-encrypt", "single", &setup.inputs[0], "-o", "out.lua"];
+    assert_success(&setup.run(&args));
+
+    // the decompilation only goes to disk sealed
+    let hash = hash(&bytecode(1));
+    let plain = format!("-- decompiled by the mock oracle\nreturn \"{}\"\n", hash);
+    assert!(fs::read_to_string(dir.join("out.lua")).unwrap().contains(&plain));
+    assert!(!dir.join("cache").join(format!("{}.lua", hash)).exists());
+    let sealed = fs::read(dir.join("cache").join(format!("{}.lua.enc", hash))).unwrap();
+    assert!(!sealed.windows(hash.len()).any(|window| window == hash.as_bytes()));
+    assert!(!sealed.windows(6).any(|window| window == b"return"));
+    // a 24 byte nonce, then the decompilation and a 16 byte tag
+    assert_eq!(sealed.len(), 24 + plain.len() + 16);
+    drop(setup.oracle);
+
+    // everything the oracle gets now fails, so only the cache can answer
+    let oracle = Oracle::start(&["--fail-every", "1"]);
+    assert_success(&oracle.run(dir, "test", &args));
+    assert!(!oracle.run(dir, "someone-else", &args).status.success());
+}